@@ -0,0 +1,152 @@
+//! Microbenchmarks of the CPU-side hot paths: octree inserts and culling, terrain meshing and raycasts.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use glam::{vec3, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use scanner::camera::{Camera, Viewpoint};
+use scanner::marker::{octree::Octree, Mark, MarkRaw, DEFAULT_INST_N};
+use scanner::util::geom::TriangleTest;
+use scanner::util::Ray;
+use scanner::world::{Terrain, World};
+
+const N_MARKS: usize = 100_000;
+const CLOUD_RADIUS: f32 = 400.0;
+/// Half-width of the scanner cone the coherent raycasts spread over, per unit of distance along its axis.
+const CONE_SPREAD: f32 = 0.1;
+
+fn random_marks(n: usize, seed: u64) -> Vec<Mark> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..n)
+        .map(|_| {
+            let pos = vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 * CLOUD_RADIUS - CLOUD_RADIUS;
+            Mark::scanned(pos, pos.length())
+        })
+        .collect()
+}
+
+/// Marks along a scanner sweep: each one close to the previous, like consecutive hits on a wall.
+fn sequential_marks(n: usize) -> Vec<Mark> {
+    (0..n)
+        .map(|i| {
+            let pos = vec3((i % 1000) as f32 * 0.1, (i / 1000) as f32 * 0.1, 0.0);
+            Mark::scanned(pos, 10.0)
+        })
+        .collect()
+}
+
+fn octree_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("octree_insert");
+    group.sample_size(10);
+    for (name, marks) in [("sequential", sequential_marks(N_MARKS)), ("random", random_marks(N_MARKS, 0))] {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || marks.clone(),
+                |marks| {
+                    let mut octree = Octree::new();
+                    for mark in marks {
+                        octree.insert(mark);
+                    }
+                    octree
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.bench_function("from_marks", |b| {
+        let marks = random_marks(N_MARKS, 0);
+        b.iter_batched(|| marks.clone(), Octree::from_marks, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+fn get_visible(c: &mut Criterion) {
+    let mut octree = Octree::from_marks(random_marks(1_000_000, 1));
+    let poses = [
+        ("center", Viewpoint { pos: Vec3::ZERO, yaw: 0.0, pitch: 0.0 }),
+        ("edge_inward", Viewpoint { pos: vec3(-CLOUD_RADIUS, 0.0, 0.0), yaw: 0.0, pitch: 0.0 }),
+        ("edge_outward", Viewpoint { pos: vec3(-CLOUD_RADIUS, 0.0, 0.0), yaw: std::f32::consts::PI, pitch: 0.0 }),
+        ("above", Viewpoint { pos: vec3(0.0, 2.0 * CLOUD_RADIUS, 0.0), yaw: 0.0, pitch: -1.5 }),
+    ];
+
+    let mut group = c.benchmark_group("get_visible");
+    group.sample_size(20);
+    let mut instances: Vec<MarkRaw> = Vec::with_capacity(DEFAULT_INST_N);
+    for (name, viewpoint) in poses {
+        let mut camera = Camera::new(16.0 / 9.0);
+        camera.set_viewpoint(viewpoint);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &camera, |b, camera| {
+            b.iter(|| octree.get_visible(&mut instances, DEFAULT_INST_N, camera.pos, camera.frustum()))
+        });
+    }
+    group.finish();
+}
+
+fn voxel_triangles(c: &mut Criterion) {
+    let mut group = c.benchmark_group("voxel_triangles");
+    group.sample_size(20);
+    // A fresh world every iteration, so every voxel is meshed from the noise field rather than read from the cache.
+    group.bench_function("cold_radius_25", |b| {
+        b.iter_batched(World::new, |mut world| world.retrieve_triangles(Vec3::ZERO, 25.0), BatchSize::SmallInput)
+    });
+    group.bench_function("cached_radius_25", |b| {
+        let mut world = World::new();
+        world.retrieve_triangles(Vec3::ZERO, 25.0);
+        b.iter(|| world.retrieve_triangles(Vec3::ZERO, 25.0))
+    });
+    group.finish();
+}
+
+fn raycast(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(2);
+    let rays: Vec<Ray> = (0..1000)
+        .map(|_| {
+            let dir = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            Ray { pos: scanner::camera::SPAWN_POS, dir: dir.normalize_or_zero() }
+        })
+        .collect();
+
+    let mut group = c.benchmark_group("raycast");
+    group.sample_size(10);
+    group.bench_function("cold_1000", |b| {
+        b.iter_batched(
+            World::new,
+            |mut world| rays.iter().filter(|ray| world.raycast(**ray, -1.0).is_some()).count(),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("warm_1000", |b| {
+        let mut world = World::new();
+        rays.iter().for_each(|ray| _ = world.raycast(*ray, -1.0));
+        b.iter(|| rays.iter().filter(|ray| world.raycast(**ray, -1.0).is_some()).count())
+    });
+    // One ray from the spawn point along +Z, marching the full range unless it hits a wall.
+    group.bench_function("long", |b| {
+        let mut world = World::new();
+        let ray = Ray { pos: scanner::camera::SPAWN_POS, dir: vec3(0.0, 0.0, 1.0) };
+        world.raycast(ray, -1.0);
+        b.iter(|| world.raycast(ray, -1.0))
+    });
+
+    // A scanner cone looking down ahead of the spawn point, cast one ray at a time and as one bundle.
+    let axis = vec3(0.0, -0.5, 1.0).normalize();
+    let cone: Vec<Ray> = (0..1000)
+        .map(|_| {
+            let jitter = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            Ray { pos: scanner::camera::SPAWN_POS, dir: (axis + jitter * CONE_SPREAD).normalize() }
+        })
+        .collect();
+    group.bench_function("cone_1000", |b| {
+        let mut world = World::new();
+        cone.iter().for_each(|ray| _ = world.raycast(*ray, -1.0));
+        b.iter(|| cone.iter().filter(|ray| world.raycast(**ray, -1.0).is_some()).count())
+    });
+    group.bench_function("bundle", |b| {
+        let mut world = World::new();
+        world.raycast_bundle(&cone, -1.0, TriangleTest::default());
+        b.iter(|| world.raycast_bundle(&cone, -1.0, TriangleTest::default()).iter().flatten().count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, octree_insert, get_visible, voxel_triangles, raycast);
+criterion_main!(benches);
@@ -0,0 +1,161 @@
+struct AccumulationUniform {
+    to_clip: mat4x4<f32>,
+    eye: vec4<f32>,
+    // Weight kept by history points each frame, and the weight below which they are dropped.
+    fade: f32,
+    min_weight: f32,
+    // Marks scanned later than this, in seconds of scan time, are not stamped.
+    time_limit: f32,
+    // Non-zero to stamp every mark in its own color, which holds the density heatmap.
+    heatmap: u32,
+    // Palette lookup table from the near to the far distance.
+    palette: array<vec4<f32>, 8>,
+    // Marks on the negative side of this plane are hidden.
+    clip_plane: vec4<f32>,
+    // Per layer, the color its marks are drawn in instead of their own where alpha is set.
+    layer_colors: array<vec4<f32>, 32>,
+    // Per kind of mark, the color it is stamped in instead of by distance while `by_tag` is set.
+    tag_colors: array<vec4<f32>, 4>,
+    // Non-zero to stamp marks in the color of their tag rather than their distance.
+    by_tag: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: AccumulationUniform;
+
+@group(1) @binding(0)
+var history_color: texture_2d<f32>;
+@group(1) @binding(1)
+var history_pos: texture_2d<f32>;
+
+struct MarkInput {
+    @location(1) pos: vec3<f32>,
+    // RGB and the mark tag.
+    @location(2) color: vec4<u32>,
+    @location(3) time: f32,
+    @location(4) layer: u32,
+}
+
+struct PointOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) pos: vec3<f32>,
+}
+
+struct HistoryOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) pos: vec4<f32>,
+}
+
+struct ComposeOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+// Outside the clip volume, for history texels that hold nothing.
+let CULLED = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+// Impressions are drawn dimmer than the marks currently in view.
+let IMPRESSION_BRIGHTNESS = 0.5;
+
+let DISTANCE_NEA = 100.0;
+let DISTANCE_FAR = 300.0;
+let PALETTE_SIZE = 8u;
+
+let PAINTED_TAG = 0u;
+let MISS_TAG = 1u;
+let DYNAMIC_TAG = 2u;
+let MISS_BRIGHTNESS = 0.15;
+// Painted surfels have this bit set on top of their diameter, which lies in this range.
+let PAINTED_SURFEL_BIT = 128u;
+let SURFEL_MIN_TAG = 3u;
+let SURFEL_MAX_TAG = 126u;
+
+// Color of a mark at `dist` from the camera, interpolated from the palette lookup table.
+fn palette_color(dist: f32) -> vec3<f32> {
+    let x = clamp((dist - DISTANCE_NEA) / (DISTANCE_FAR - DISTANCE_NEA), 0.0, 1.0) * f32(PALETTE_SIZE - 1u);
+    let i = min(u32(x), PALETTE_SIZE - 2u);
+    return mix(params.palette[i].rgb, params.palette[i + 1u].rgb, x - f32(i));
+}
+
+// Index into the tag colors of a mark: surface hits, painted marks, misses and marks on entities.
+fn tag_kind(tag: u32, painted: bool) -> u32 {
+    if (painted) {
+        return 1u;
+    }
+    if (tag == MISS_TAG) {
+        return 2u;
+    }
+    return select(0u, 3u, tag == DYNAMIC_TAG);
+}
+
+// One point per texel of the previous history, moved to where its position lands in the current view.
+@vertex
+fn vs_reproject(@builtin(vertex_index) index: u32) -> PointOutput {
+    let width = u32(textureDimensions(history_pos).x);
+    let texel = vec2<i32>(i32(index % width), i32(index / width));
+    let pos = textureLoad(history_pos, texel, 0);
+    let color = textureLoad(history_color, texel, 0) * params.fade;
+
+    var out: PointOutput;
+    let clipped = dot(params.clip_plane, vec4<f32>(pos.xyz, 1.0)) < 0.0;
+    let keep = pos.w > 0.0 && color.a >= params.min_weight && !clipped;
+    out.clip_position = select(CULLED, params.to_clip * vec4<f32>(pos.xyz, 1.0), keep);
+    out.color = color;
+    out.pos = pos.xyz;
+    return out;
+}
+
+// One point per visible mark, at full weight.
+@vertex
+fn vs_stamp(mark: MarkInput) -> PointOutput {
+    let dist = distance(mark.pos, params.eye.xyz);
+    let brightness = select(1.0, MISS_BRIGHTNESS, mark.color.w == MISS_TAG);
+
+    var out: PointOutput;
+    let clipped = dot(params.clip_plane, vec4<f32>(mark.pos, 1.0)) < 0.0 || mark.time > params.time_limit;
+    out.clip_position = select(params.to_clip * vec4<f32>(mark.pos, 1.0), CULLED, clipped);
+    let size_tag = mark.color.w & (PAINTED_SURFEL_BIT - 1u);
+    let painted_surfel = mark.color.w != size_tag && size_tag >= SURFEL_MIN_TAG && size_tag <= SURFEL_MAX_TAG;
+    let painted = mark.color.w == PAINTED_TAG || painted_surfel;
+    var color = select(palette_color(dist), vec3<f32>(mark.color.rgb) / 255.0, painted || params.heatmap != 0u);
+    if (params.by_tag != 0u && params.heatmap == 0u) {
+        color = params.tag_colors[tag_kind(mark.color.w, painted)].rgb;
+    }
+    let layer_color = params.layer_colors[mark.layer];
+    if (layer_color.a > 0.0 && params.heatmap == 0u) {
+        color = layer_color.rgb;
+    }
+    out.color = vec4<f32>(color * brightness, 1.0);
+    out.pos = mark.pos;
+    return out;
+}
+
+@fragment
+fn fs_history(in: PointOutput) -> HistoryOutput {
+    var out: HistoryOutput;
+    out.color = in.color;
+    out.pos = vec4<f32>(in.pos, 1.0);
+    return out;
+}
+
+@vertex
+fn vs_compose(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Draws the reprojected history into the scene, depth tested against the terrain occluders.
+@fragment
+fn fs_compose(@builtin(position) position: vec4<f32>) -> ComposeOutput {
+    let texel = vec2<i32>(position.xy);
+    let pos = textureLoad(history_pos, texel, 0);
+    if (pos.w == 0.0) {
+        discard;
+    }
+
+    let clip = params.to_clip * vec4<f32>(pos.xyz, 1.0);
+    var out: ComposeOutput;
+    out.color = textureLoad(history_color, texel, 0) * IMPRESSION_BRIGHTNESS;
+    out.depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    return out;
+}
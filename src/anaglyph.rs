@@ -0,0 +1,148 @@
+use super::camera::CameraUniform;
+use super::State;
+
+/// Distance between the eyes, in world units. Far wider than a person's, since the scan spans hundreds of units.
+const EYE_SEPARATION: f32 = 1.0;
+/// Distance at which the eyes' views coincide: nearer marks stand out of the screen, farther ones recede into it.
+const CONVERGENCE: f32 = 60.0;
+
+/// Red/cyan anaglyph stereo: the scene is rendered once per eye from cameras offset to either side, each into its
+/// own texture, and a fullscreen pass combines the left eye's brightness in red with the right eye's in green and
+/// blue.
+pub struct Anaglyph {
+    pub enabled: bool,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// The left and right eye's views at the render size, only allocated while enabled.
+    eyes: Option<([wgpu::TextureView; 2], wgpu::BindGroup)>,
+    width: u32,
+    height: u32,
+}
+
+impl Anaglyph {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("anaglyph.wgsl"));
+
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[texture_entry(0), texture_entry(1)],
+            label: Some("anaglyph_bind_group_layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Anaglyph Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Anaglyph Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        Self { enabled: false, pipeline, bind_group_layout, eyes: None, width: config.width, height: config.height }
+    }
+
+    /// Resizes the eye textures to the render size.
+    pub fn resize(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.set_enabled(device, format, self.enabled);
+    }
+
+    /// Allocates or frees the eye textures.
+    pub fn set_enabled(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, enabled: bool) {
+        self.enabled = enabled;
+        self.eyes = enabled.then(|| {
+            let eyes = [0, 1].map(|_| self.create_eye(device, format));
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&eyes[0]) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&eyes[1]) },
+                ],
+                label: Some("anaglyph_bind_group"),
+            });
+            (eyes, bind_group)
+        });
+    }
+
+    fn create_eye(&self, device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::TextureView {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Anaglyph Eye Texture"),
+            size: wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        texture.create_view(&wgpu::TextureViewDescriptor::default())
+    }
+}
+
+impl State {
+    pub fn toggle_anaglyph(&mut self) {
+        let enabled = !self.anaglyph.enabled;
+        self.anaglyph.set_enabled(&self.device, self.config.format, enabled);
+        self.notify(format!("anaglyph 3D: {}", if enabled { "on" } else { "off" }));
+    }
+
+    /// Renders the scene once per eye and combines both eyes into `view`, returning whether it did. Each eye is
+    /// submitted on its own with the camera buffer rewritten for it, so every scene pass renders stereo unchanged;
+    /// the buffer holds the centered camera again for `encoder`.
+    pub fn encode_anaglyph(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> bool {
+        let Some((eyes, bind_group)) = self.anaglyph.eyes.as_ref().filter(|_| self.anaglyph.enabled) else {
+            return false;
+        };
+
+        let camera_buffer = &self.marker.camera_buffer;
+        for (eye, side) in eyes.iter().zip([-0.5, 0.5]) {
+            let uniform = CameraUniform::stereo(&self.camera, side * EYE_SEPARATION, CONVERGENCE);
+            self.queue.write_buffer(camera_buffer, 0, bytemuck::cast_slice(&[uniform]));
+            let mut eye_encoder =
+                self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Eye Encoder") });
+            // The accumulated history is seen from the centered camera and would not line up with either eye.
+            self.encode_scene(&mut eye_encoder, eye, false);
+            self.queue.submit(std::iter::once(eye_encoder.finish()));
+        }
+        self.queue.write_buffer(camera_buffer, 0, bytemuck::cast_slice(&[self.marker.camera_uniform]));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Anaglyph Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.anaglyph.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        true
+    }
+}
@@ -0,0 +1,26 @@
+@group(0) @binding(0)
+var left_eye: texture_2d<f32>;
+@group(0) @binding(1)
+var right_eye: texture_2d<f32>;
+
+// Marks are colored by distance in pure hues, so their brightest channel stands in for luminance, which would leave
+// the blue distant marks nearly black.
+fn brightness(color: vec3<f32>) -> f32 {
+    return max(color.r, max(color.g, color.b));
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Gray anaglyph: the left eye's brightness goes to red and the right eye's to green and blue. Keeping colors would
+// hide the pure red and blue ends of the distance gradient from one eye.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    let left = brightness(textureLoad(left_eye, texel, 0).rgb);
+    let right = brightness(textureLoad(right_eye, texel, 0).rgb);
+    return vec4<f32>(left, right, right, 1.0);
+}
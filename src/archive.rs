@@ -0,0 +1,327 @@
+use super::marker::{octree::Octree, Mark, MarkRaw, MarkTag};
+use super::State;
+use glam::{DVec3, Vec3};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+const ARCHIVE_DIR: &str = "saves";
+pub const ARCHIVE_EXTENSION: &str = "scanz";
+
+const MAGIC: &[u8; 4] = b"SCNZ";
+const VERSION: u32 = 3;
+/// Oldest version still readable; version 1 archives have no origin and archives before version 3 no scan time per
+/// mark.
+const MIN_VERSION: u32 = 1;
+/// Magic, version, block count, mark count and bounds.
+const HEADER_SIZE: usize = 4 + 4 + 4 + 8 + 24;
+/// World position of the local origin, following the header since version 2.
+const ORIGIN_SIZE: usize = 24;
+/// Leaf center and half-size, mark count and compressed payload size.
+const BLOCK_HEADER_SIZE: usize = 12 + 4 + 4 + 4;
+/// Largest block a reader accepts; a leaf holds far fewer marks, so anything bigger means a corrupt file.
+const MAX_BLOCK_MARKS: usize = 1 << 16;
+/// Time per frame spent streaming an archive into the scan.
+const LOAD_BUDGET: Duration = Duration::from_millis(4);
+
+/// Writes every mark in `octree` except miss marks to `path` in the archive format: a header (magic, version, block
+/// count, mark count and bounds) and the world position of the local `origin` the marks are relative to, followed by
+/// one block per octree leaf, each a small header (leaf center, half-size,
+/// mark count and payload size) and the leaf's raw marks compressed with LZ4. Blocks are independent, so
+/// [`ArchiveReader`] can insert them one at a time. Returns the number of marks written.
+pub fn write_archive(octree: &Octree, origin: DVec3, path: &Path) -> Result<u64, String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let mut blocks = Vec::new();
+    let mut count = 0;
+    for (center, extension, marks) in octree.leaves() {
+        let marks: Vec<MarkRaw> = marks.iter().filter(|mark| mark.tag() != MarkTag::Miss).copied().collect();
+        if marks.is_empty() {
+            continue;
+        }
+        count += marks.len() as u64;
+        let bytes: Vec<u8> = marks.iter().flat_map(MarkRaw::stored_bytes).copied().collect();
+        blocks.push((center, extension, marks.len(), lz4_flex::compress(&bytes)));
+    }
+    let (min, max) = octree.bounds().unwrap_or((Vec3::ZERO, Vec3::ZERO));
+
+    let mut file = BufWriter::new(std::fs::File::create(path).map_err(|e| e.to_string())?);
+    let mut header = Vec::with_capacity(HEADER_SIZE + ORIGIN_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+    header.extend_from_slice(&count.to_le_bytes());
+    header.extend_from_slice(bytemuck::cast_slice(&[min.to_array(), max.to_array()]));
+    header.extend_from_slice(bytemuck::cast_slice(&origin.to_array()));
+    file.write_all(&header).map_err(|e| e.to_string())?;
+
+    for (center, extension, n_marks, payload) in blocks {
+        file.write_all(bytemuck::cast_slice(&center.to_array())).map_err(|e| e.to_string())?;
+        file.write_all(&extension.to_le_bytes()).map_err(|e| e.to_string())?;
+        file.write_all(&(n_marks as u32).to_le_bytes()).map_err(|e| e.to_string())?;
+        file.write_all(&(payload.len() as u32).to_le_bytes()).map_err(|e| e.to_string())?;
+        file.write_all(&payload).map_err(|e| e.to_string())?;
+    }
+    file.flush().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// Reads an archive written by [`write_archive`] block by block, so huge scans can be loaded a little every frame.
+pub struct ArchiveReader {
+    file: BufReader<std::fs::File>,
+    remaining_blocks: u32,
+    /// Bytes per mark, which depends on whether the archive has scan times.
+    mark_size: usize,
+    /// Marks in the whole archive.
+    pub count: u64,
+    /// Marks returned so far.
+    pub read: u64,
+    /// `(min, max)` corners of every mark in the archive.
+    pub bounds: (Vec3, Vec3),
+    /// World position the marks are relative to.
+    pub origin: DVec3,
+}
+
+impl ArchiveReader {
+    /// Opens `path` and reads its header.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut file = BufReader::new(std::fs::File::open(path).map_err(|e| e.to_string())?);
+
+        let mut header = [0; HEADER_SIZE];
+        file.read_exact(&mut header).map_err(|e| e.to_string())?;
+        if &header[0..4] != MAGIC {
+            return Err(format!("{} is not a scan archive", path.display()));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        if !(MIN_VERSION..=VERSION).contains(&version) {
+            return Err(format!("unsupported scan archive version {}", version));
+        }
+        let remaining_blocks = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        let count = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let corners: [f32; 6] = bytemuck::pod_read_unaligned(&header[20..44]);
+        let bounds = (Vec3::from_slice(&corners[0..3]), Vec3::from_slice(&corners[3..6]));
+        let mut origin = [0.0_f64; 3];
+        if version >= 2 {
+            file.read_exact(bytemuck::cast_slice_mut(&mut origin)).map_err(|e| e.to_string())?;
+        }
+
+        let mark_size = if version >= 3 { MarkRaw::STORED_SIZE } else { MarkRaw::UNTIMED_SIZE };
+
+        Ok(Self { file, remaining_blocks, mark_size, count, read: 0, bounds, origin: DVec3::from(origin) })
+    }
+
+    /// Decompresses the next block, or returns `None` once every block was read.
+    pub fn next_block(&mut self) -> Result<Option<Vec<Mark>>, String> {
+        if self.remaining_blocks == 0 {
+            return Ok(None);
+        }
+        self.remaining_blocks -= 1;
+
+        let mut header = [0; BLOCK_HEADER_SIZE];
+        self.file.read_exact(&mut header).map_err(|e| e.to_string())?;
+        let n_marks = u32::from_le_bytes(header[16..20].try_into().unwrap()) as usize;
+        let payload_size = u32::from_le_bytes(header[20..24].try_into().unwrap()) as usize;
+        if n_marks > MAX_BLOCK_MARKS {
+            return Err(format!("block of {} marks exceeds the limit of {}", n_marks, MAX_BLOCK_MARKS));
+        }
+        // LZ4 never grows the marks past this, so a bigger payload means a corrupt file rather than one to allocate.
+        let max_payload_size = lz4_flex::block::get_maximum_output_size(n_marks * self.mark_size);
+        if payload_size > max_payload_size {
+            return Err(format!("block payload of {} bytes for {} marks", payload_size, n_marks));
+        }
+
+        let mut payload = vec![0; payload_size];
+        self.file.read_exact(&mut payload).map_err(|e| e.to_string())?;
+        let bytes =
+            lz4_flex::decompress(&payload, n_marks * self.mark_size).map_err(|e| format!("corrupt block: {}", e))?;
+        if bytes.len() != n_marks * self.mark_size {
+            return Err("corrupt block: wrong payload size".to_string());
+        }
+
+        self.read += n_marks as u64;
+        let marks = bytes.chunks_exact(self.mark_size).map(MarkRaw::from_stored);
+        Ok(Some(marks.map(Mark::from).collect()))
+    }
+}
+
+/// An archive being streamed into the scan.
+pub struct ArchiveLoad {
+    reader: ArchiveReader,
+    name: String,
+    /// Added to every mark to move it from the archive's origin to the scan's.
+    offset: Vec3,
+    /// Layer the archive's marks go into.
+    layer: u32,
+}
+
+impl State {
+    /// Writes the scan to a new timestamped archive in the save directory.
+    pub fn export_archive(&mut self) {
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let path = Path::new(ARCHIVE_DIR).join(format!("scan-{}.{}", timestamp.as_millis(), ARCHIVE_EXTENSION));
+        match write_archive(&self.marker.octree, self.origin(), &path) {
+            Ok(count) => {
+                let size = std::fs::metadata(&path).map(|meta| meta.len()).unwrap_or_default();
+                self.notify(format!("exported {} marks ({:.1} MB)", count, size as f64 / (1024.0 * 1024.0)));
+            }
+            Err(e) => self.notify(format!("export failed: {}", e)),
+        }
+    }
+
+    /// Starts streaming the archive at `path` into the scan, keeping any marks already present.
+    pub fn load_archive(&mut self, path: &Path) {
+        match ArchiveReader::open(path) {
+            Ok(reader) => {
+                let name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                let offset = (reader.origin - self.origin()).as_vec3();
+                let layer = self.add_loaded_layer(&name);
+                self.archive_load = Some(ArchiveLoad { reader, name, offset, layer });
+            }
+            Err(e) => {
+                warn!("failed to open {}: {}", path.display(), e);
+                self.notify(format!("archive load failed: {}", e));
+            }
+        }
+    }
+
+    /// Inserts archive blocks for up to [`LOAD_BUDGET`] each frame until the archive is read.
+    pub fn update_archive(&mut self) {
+        let Some(load) = &mut self.archive_load else {
+            return;
+        };
+
+        let start = Instant::now();
+        let result = loop {
+            match load.reader.next_block() {
+                Ok(Some(marks)) => {
+                    let (offset, layer) = (load.offset, load.layer);
+                    self.playback.catch_up(marks.iter().fold(0.0, |time, mark| mark.time.max(time)));
+                    self.marker.octree.extend(marks.into_iter().map(|mark| Mark {
+                        pos: mark.pos + offset,
+                        layer,
+                        ..mark
+                    }))
+                }
+                done => break done,
+            }
+            if start.elapsed() >= LOAD_BUDGET {
+                return;
+            }
+        };
+
+        let load = self.archive_load.take().unwrap();
+        self.coverage.rebuild(&self.marker.octree);
+        self.minimap.invalidate();
+        match result {
+            Ok(_) => self.notify(format!("loaded {} marks from {}", load.reader.read, load.name)),
+            Err(e) => {
+                warn!("failed to read {}: {}", load.name, e);
+                self.notify(format!("archive load failed after {} marks: {}", load.reader.read, e));
+            }
+        }
+    }
+
+    /// Fraction of the archive being streamed in, if any.
+    pub fn archive_progress(&self) -> Option<f64> {
+        let reader = &self.archive_load.as_ref()?.reader;
+        Some(reader.read as f64 / reader.count.max(1) as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_archive, ArchiveReader, BLOCK_HEADER_SIZE, HEADER_SIZE, MAGIC, ORIGIN_SIZE};
+    use crate::marker::{octree::Octree, Mark, MarkRaw, MarkTag};
+    use glam::{vec3, DVec3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn archive_round_trips_every_saved_mark() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut octree = Octree::new();
+        for _ in 0..10_000 {
+            let pos = vec3(rng.gen_range(-300.0..300.0), rng.gen_range(-50.0..50.0), rng.gen_range(-300.0..300.0));
+            octree.insert(Mark::scanned(pos, pos.length()));
+        }
+        octree.insert(Mark::miss(vec3(1.0, 2.0, 3.0)));
+
+        let path = std::env::temp_dir().join(format!("scanner-archive-test-{}.scanz", std::process::id()));
+        let written = write_archive(&octree, DVec3::new(1e9, 0.0, -2.5), &path).unwrap();
+        let mut reader = ArchiveReader::open(&path).unwrap();
+        let mut read = Vec::new();
+        while let Some(marks) = reader.next_block().unwrap() {
+            read.extend(marks.into_iter().map(Mark::to_raw));
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let key = |mark: &MarkRaw| bytemuck::bytes_of(mark).to_vec();
+        let mut expected: Vec<MarkRaw> = octree.marks().filter(|mark| mark.pos != [1.0, 2.0, 3.0]).copied().collect();
+        expected.sort_by_key(key);
+        read.sort_by_key(key);
+        assert_eq!(written, 10_000);
+        assert_eq!((reader.count, reader.read), (10_000, 10_000));
+        assert_eq!(reader.bounds, octree.bounds().unwrap());
+        assert_eq!(reader.origin, DVec3::new(1e9, 0.0, -2.5));
+        assert_eq!(read.iter().map(key).collect::<Vec<_>>(), expected.iter().map(key).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn archives_from_before_scan_times_read_their_untimed_marks() {
+        let marks = [([1.0f32, 2.0, 3.0], [255u8, 128, 0, 255]), ([-4.0, 0.5, 6.0], [10, 20, 30, 20])];
+        let payload: Vec<u8> = marks
+            .iter()
+            .flat_map(|(pos, color)| bytemuck::cast_slice(pos).iter().chain(color).copied().collect::<Vec<u8>>())
+            .collect();
+        assert_eq!(payload.len(), 2 * MarkRaw::UNTIMED_SIZE);
+        let payload = lz4_flex::compress(&payload);
+
+        let archive = |version: u32| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend_from_slice(&version.to_le_bytes());
+            bytes.extend_from_slice(&1u32.to_le_bytes());
+            bytes.extend_from_slice(&2u64.to_le_bytes());
+            bytes.extend_from_slice(bytemuck::cast_slice(&[-4.0f32, 0.5, 3.0, 1.0, 2.0, 6.0]));
+            if version >= 2 {
+                bytes.extend_from_slice(bytemuck::cast_slice(&[1e9f64, 0.0, -2.5]));
+            }
+            bytes.extend_from_slice(bytemuck::cast_slice(&[0.0f32, 0.0, 0.0, 8.0]));
+            bytes.extend_from_slice(&2u32.to_le_bytes());
+            bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&payload);
+            bytes
+        };
+
+        let path = std::env::temp_dir().join(format!("scanner-untimed-archive-test-{}.scanz", std::process::id()));
+        for (version, origin) in [(2, DVec3::new(1e9, 0.0, -2.5)), (1, DVec3::ZERO)] {
+            std::fs::write(&path, archive(version)).unwrap();
+            let mut reader = ArchiveReader::open(&path).unwrap();
+            let read: Vec<MarkRaw> = reader.next_block().unwrap().unwrap().into_iter().map(Mark::to_raw).collect();
+            assert!(reader.next_block().unwrap().is_none());
+            assert_eq!((reader.count, reader.read, reader.origin), (2, 2, origin));
+            assert_eq!(read.iter().map(|mark| (mark.pos, mark.color)).collect::<Vec<_>>(), marks);
+            assert_eq!(read[1].tag(), MarkTag::Surfel(20));
+            assert!(read.iter().all(|mark| mark.time == 0.0 && mark.layer == 0));
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_payload_sizes_fail_to_read_instead_of_allocating() {
+        let mut octree = Octree::new();
+        octree.insert(Mark::scanned(vec3(1.0, 2.0, 3.0), 1.0));
+        let path = std::env::temp_dir().join(format!("scanner-payload-test-{}.scanz", std::process::id()));
+        write_archive(&octree, DVec3::ZERO, &path).unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        // The payload size is the last field of the only block header, right before the payload.
+        let payload_len = bytes.len() - (HEADER_SIZE + ORIGIN_SIZE + BLOCK_HEADER_SIZE);
+        bytes.truncate(bytes.len() - payload_len - 4);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let result = ArchiveReader::open(&path).and_then(|mut reader| reader.next_block());
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err_and(|e| e.contains("block payload")));
+    }
+}
@@ -0,0 +1,112 @@
+use super::bench::Scenario;
+use super::import::ImportOptions;
+use super::server::ServerOptions;
+use super::world::TerrainKind;
+use glam::Vec3;
+use std::path::PathBuf;
+
+const USAGE: &str = "usage: scanner [bench [--scenario NAME] | server [--save FILE.scan]]
+               [--terrain caves|heightfield|plane|heightmap:FILE.png|sdf:FILE.vsdf] [--seed N]
+               [--import FILE.ply|FILE.xyz [--import-scale S] [--import-offset X,Y,Z]] [--load FILE.scanz]
+               [--listen PORT] [--peer HOST:PORT]... [--trace FILE.json]";
+
+pub enum Command {
+    /// Open the interactive window.
+    Run,
+    /// Run benchmark scenarios headlessly and print the results as JSON.
+    Bench { scenarios: Vec<Scenario> },
+    /// Host a shared scan for networked clients without opening a window.
+    Server(ServerOptions),
+}
+
+/// Command line options of the scanner binary.
+pub struct Args {
+    pub command: Command,
+    pub terrain: TerrainKind,
+    /// Seed of the scanner's ray jitter, or of the benchmark scenarios.
+    pub seed: Option<u64>,
+    /// Point cloud to load into the scan on startup.
+    pub import: Option<ImportOptions>,
+    /// Scan archive to stream into the scan on startup.
+    pub archive: Option<PathBuf>,
+    /// UDP port to share marks on; a free port is picked if only peers are given. Also the port `server` listens on.
+    pub listen: Option<u16>,
+    /// Players to share marks with, as `host:port`.
+    pub peers: Vec<String>,
+    /// File to record a Chrome trace of the session's spans to.
+    pub trace: Option<PathBuf>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            command: Command::Run,
+            terrain: TerrainKind::Caves,
+            seed: None,
+            import: None,
+            archive: None,
+            listen: None,
+            peers: Vec::new(),
+            trace: None,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut args = args.peekable();
+        let mut parsed = Self::default();
+        match args.peek().map(String::as_str) {
+            Some("bench") => parsed.command = Command::Bench { scenarios: Scenario::ALL.to_vec() },
+            Some("server") => parsed.command = Command::Server(ServerOptions::default()),
+            _ => {}
+        }
+        if !matches!(parsed.command, Command::Run) {
+            args.next();
+        }
+
+        while let Some(arg) = args.next() {
+            match (arg.as_str(), &mut parsed.command) {
+                ("--terrain", _) => parsed.terrain = value(&mut args, &arg)?.parse()?,
+                ("--seed", _) => parsed.seed = Some(value(&mut args, &arg)?.parse().map_err(|e| format!("{}", e))?),
+                ("--scenario", Command::Bench { scenarios }) => *scenarios = vec![value(&mut args, &arg)?.parse()?],
+                ("--save", Command::Server(options)) => options.save = value(&mut args, &arg)?.into(),
+                ("--import", _) => parsed.import = Some(ImportOptions::new(value(&mut args, &arg)?.into())),
+                ("--import-scale", _) => {
+                    import_options(&mut parsed.import, &arg)?.scale =
+                        value(&mut args, &arg)?.parse().map_err(|e| format!("{}", e))?
+                }
+                ("--import-offset", _) => {
+                    import_options(&mut parsed.import, &arg)?.offset = parse_vec3(&value(&mut args, &arg)?)?
+                }
+                ("--load", _) => parsed.archive = Some(value(&mut args, &arg)?.into()),
+                ("--listen", _) => parsed.listen = Some(value(&mut args, &arg)?.parse().map_err(|e| format!("{}", e))?),
+                ("--peer", _) => parsed.peers.push(value(&mut args, &arg)?),
+                ("--trace", _) => parsed.trace = Some(value(&mut args, &arg)?.into()),
+                ("--help" | "-h", _) => return Err(USAGE.to_string()),
+                _ => return Err(format!("unknown argument '{}'\n{}", arg, USAGE)),
+            }
+        }
+        if let Command::Server(options) = &mut parsed.command {
+            options.terrain = parsed.terrain.clone();
+            options.port = parsed.listen.unwrap_or(options.port);
+        }
+        Ok(parsed)
+    }
+}
+
+fn import_options<'a>(import: &'a mut Option<ImportOptions>, flag: &str) -> Result<&'a mut ImportOptions, String> {
+    import.as_mut().ok_or_else(|| format!("'{}' must follow '--import'\n{}", flag, USAGE))
+}
+
+fn parse_vec3(text: &str) -> Result<Vec3, String> {
+    let values = text.split(',').map(|v| v.trim().parse::<f32>()).collect::<Result<Vec<_>, _>>();
+    match values.as_deref() {
+        Ok([x, y, z]) => Ok(Vec3::new(*x, *y, *z)),
+        _ => Err(format!("expected X,Y,Z but got '{}'", text)),
+    }
+}
+
+fn value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next().ok_or_else(|| format!("missing value for '{}'\n{}", flag, USAGE))
+}
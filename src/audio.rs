@@ -0,0 +1,174 @@
+use super::settings::AudioSettings;
+use super::util::Ray;
+use super::world::Terrain;
+use super::State;
+use glam::{vec3, Vec3};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+const TICK_INTERVAL: Duration = Duration::from_millis(30);
+const TICK_DURATION: Duration = Duration::from_millis(20);
+const MISS_DURATION: Duration = Duration::from_millis(40);
+
+const PITCH_NEAR: f32 = 1400.0;
+const PITCH_FAR: f32 = 300.0;
+const PITCH_FAR_DIST: f32 = 300.0;
+const MISS_PITCH: f32 = 120.0;
+
+const PROBE_INTERVAL: f64 = 1.0;
+const N_PROBES: usize = 16;
+const PROBE_RANGE: f32 = 150.0;
+const SPEED_OF_SOUND: f32 = 343.0;
+const MAX_REVERB_WET: f32 = 0.6;
+const MIN_REVERB_DELAY: Duration = Duration::from_millis(15);
+const MAX_REVERB_DELAY: Duration = Duration::from_millis(250);
+
+/// Scanner sound cues. Ticks are rate limited since the scanner can fire thousands of rays per second; without the
+/// `audio` feature (or without an output device) every call is a no-op.
+pub struct Audio {
+    #[cfg(feature = "audio")]
+    output: Option<(rodio::OutputStream, rodio::OutputStreamHandle)>,
+    enabled: bool,
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    volume: f32,
+    last_tick: Instant,
+
+    reverb_wet: f32,
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    reverb_delay: Duration,
+    probe_timer: f64,
+    /// Enclosure and mean hit distance of the probe rays being cast in the background, see [`State::update_audio`].
+    probe: Option<Receiver<(f32, f32)>>,
+}
+
+impl Audio {
+    pub fn new(settings: &AudioSettings) -> Self {
+        #[cfg(feature = "audio")]
+        let output = match settings.enabled.then(rodio::OutputStream::try_default) {
+            Some(Ok(output)) => Some(output),
+            Some(Err(e)) => {
+                tracing::warn!("audio disabled: {}", e);
+                None
+            }
+            None => None,
+        };
+
+        Self {
+            #[cfg(feature = "audio")]
+            output,
+            enabled: settings.enabled,
+            volume: settings.volume.clamp(0.0, 1.0),
+            last_tick: Instant::now(),
+            reverb_wet: 0.0,
+            reverb_delay: MIN_REVERB_DELAY,
+            probe_timer: 0.0,
+            probe: None,
+        }
+    }
+
+    /// Sets the reverb from an estimate of the surroundings: `enclosure` is the fraction of probe rays that hit
+    /// something and `mean_dist` their average hit distance.
+    pub fn set_environment(&mut self, enclosure: f32, mean_dist: f32) {
+        self.reverb_wet = enclosure.clamp(0.0, 1.0) * MAX_REVERB_WET;
+        let echo = Duration::from_secs_f32(2.0 * mean_dist / SPEED_OF_SOUND);
+        self.reverb_delay = echo.clamp(MIN_REVERB_DELAY, MAX_REVERB_DELAY);
+    }
+
+    /// Plays a tick whose pitch falls as the hit gets further away.
+    pub fn hit(&mut self, dist: f32) {
+        let t = (dist / PITCH_FAR_DIST).clamp(0.0, 1.0);
+        self.tick(PITCH_NEAR + (PITCH_FAR - PITCH_NEAR) * t, TICK_DURATION);
+    }
+
+    /// Plays a low thud for a ray that hit nothing.
+    pub fn miss(&mut self) {
+        self.tick(MISS_PITCH, MISS_DURATION);
+    }
+
+    fn tick(&mut self, pitch: f32, duration: Duration) {
+        if !self.enabled || self.last_tick.elapsed() < TICK_INTERVAL {
+            return;
+        }
+        self.last_tick = Instant::now();
+        self.play(pitch, duration);
+    }
+
+    #[cfg(feature = "audio")]
+    fn play(&self, pitch: f32, duration: Duration) {
+        use rodio::Source;
+
+        if let Some((_, handle)) = &self.output {
+            let source = rodio::source::SineWave::new(pitch)
+                .take_duration(duration)
+                .fade_in(duration / 4)
+                .amplify(self.volume * 0.2)
+                .buffered()
+                .reverb(self.reverb_delay, self.reverb_wet);
+            _ = handle.play_raw(source);
+        }
+    }
+
+    #[cfg(not(feature = "audio"))]
+    fn play(&self, _pitch: f32, _duration: Duration) {}
+}
+
+impl State {
+    /// Periodically casts probe rays in every direction around the camera to estimate how enclosed it is, and
+    /// adjusts the reverb of the scanner cues accordingly. The rays are cast on the rayon pool against a detached
+    /// copy of the terrain, and the reverb follows once they are done; a new probe waits for the last one.
+    pub fn update_audio(&mut self, dt: f64) {
+        if !self.audio.enabled {
+            return;
+        }
+
+        if let Some(probe) = &self.audio.probe {
+            match probe.try_recv() {
+                Ok((enclosure, mean_dist)) => {
+                    self.audio.set_environment(enclosure, mean_dist);
+                    self.audio.probe = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => self.audio.probe = None,
+            }
+        }
+
+        self.audio.probe_timer -= dt;
+        if self.audio.probe_timer > 0.0 || self.audio.probe.is_some() {
+            return;
+        }
+        self.audio.probe_timer = self.audio.probe_timer.max(0.0) + PROBE_INTERVAL;
+
+        let (sender, receiver) = mpsc::channel();
+        let (mut terrain, center) = (self.world.detached(), self.camera.pos);
+        rayon::spawn(move || {
+            // The receiver only goes away with the audio, which no longer wants the estimate then.
+            _ = sender.send(probe_enclosure(&mut *terrain, center));
+        });
+        self.audio.probe = Some(receiver);
+    }
+}
+
+/// Casts the probe rays from `center`, returning the fraction that hit something and their mean hit distance.
+fn probe_enclosure(terrain: &mut dyn Terrain, center: Vec3) -> (f32, f32) {
+    let mut hits = 0;
+    let mut dist_sum = 0.0;
+    for i in 0..N_PROBES {
+        let ray = Ray { pos: center, dir: probe_dir(i) };
+        if let Some(hit) = terrain.raycast(ray, PROBE_RANGE) {
+            hits += 1;
+            dist_sum += Vec3::distance(ray.pos, hit);
+        }
+    }
+
+    let mean_dist = if hits > 0 { dist_sum / hits as f32 } else { PROBE_RANGE };
+    (hits as f32 / N_PROBES as f32, mean_dist)
+}
+
+/// Evenly spread direction on the unit sphere (Fibonacci lattice).
+fn probe_dir(i: usize) -> Vec3 {
+    let golden_angle = std::f32::consts::PI * (3.0 - f32::sqrt(5.0));
+    let y = 1.0 - 2.0 * (i as f32 + 0.5) / N_PROBES as f32;
+    let radius = f32::sqrt(1.0 - y * y);
+    let theta = golden_angle * i as f32;
+    vec3(radius * f32::cos(theta), y, radius * f32::sin(theta))
+}
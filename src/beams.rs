@@ -0,0 +1,92 @@
+use super::lines::LineRenderer;
+use super::marker::Marker;
+use super::util::Ray;
+use super::State;
+use glam::{vec4, Vec3, Vec4};
+use std::collections::VecDeque;
+
+/// Seconds a beam takes to fade out.
+const BEAM_LIFETIME: f64 = 0.1;
+/// Beams kept at once; at high scan rates the oldest are dropped first.
+const MAX_BEAMS: usize = 4096;
+/// Length of beams drawn for rays that hit nothing.
+const MISS_LENGTH: f32 = 60.0;
+
+const HIT_COLOR: Vec4 = vec4(0.4, 1.0, 0.9, 0.8);
+const MISS_COLOR: Vec4 = vec4(0.4, 1.0, 0.9, 0.25);
+
+struct Beam {
+    start: Vec3,
+    end: Vec3,
+    color: Vec4,
+    age: f64,
+}
+
+/// Short-lived line segments from the scanner to where each ray landed, so the scanning action is visible.
+pub struct Beams {
+    recent: VecDeque<Beam>,
+    lines: LineRenderer,
+    /// Seconds until another beam may be drawn under the flash limit.
+    cooldown: f64,
+}
+
+impl Beams {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) -> Self {
+        Self {
+            recent: VecDeque::with_capacity(MAX_BEAMS),
+            lines: LineRenderer::new(device, format, &marker.camera_bind_group_layout),
+            cooldown: 0.0,
+        }
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        for beam in &mut self.recent {
+            beam.start -= shift;
+            beam.end -= shift;
+        }
+    }
+
+    /// Records a beam from `start` along `ray` to `hit`, or a short fainter one if the ray missed. With a
+    /// `flash_limit`, beams beyond that many per second are left out.
+    pub fn push(&mut self, start: Vec3, ray: Ray, hit: Option<Vec3>, flash_limit: Option<u32>) {
+        if let Some(limit) = flash_limit.filter(|&limit| limit > 0) {
+            if self.cooldown > 0.0 {
+                return;
+            }
+            self.cooldown = 1.0 / limit as f64;
+        }
+        if self.recent.len() == MAX_BEAMS {
+            self.recent.pop_front();
+        }
+        let (end, color) = match hit {
+            Some(pos) => (pos, HIT_COLOR),
+            None => (ray.pos + ray.dir * MISS_LENGTH, MISS_COLOR),
+        };
+        self.recent.push_back(Beam { start, end, color, age: 0.0 });
+    }
+}
+
+impl State {
+    pub fn update_beams(&mut self, dt: f64) {
+        let beams = &mut self.beams;
+        beams.cooldown = (beams.cooldown - dt).max(0.0);
+        for beam in &mut beams.recent {
+            beam.age += dt;
+        }
+        while beams.recent.front().is_some_and(|beam| beam.age >= BEAM_LIFETIME) {
+            beams.recent.pop_front();
+        }
+
+        for beam in &beams.recent {
+            let fade = (1.0 - beam.age / BEAM_LIFETIME) as f32;
+            let color = beam.color * vec4(1.0, 1.0, 1.0, fade);
+            beams.lines.push_gradient(beam.start, color * vec4(1.0, 1.0, 1.0, 0.2), beam.end, color);
+        }
+        beams.lines.upload(&self.queue);
+    }
+
+    pub fn render_beams<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.beams.lines.draw(render_pass, &self.marker.camera_bind_group);
+    }
+}
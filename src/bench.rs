@@ -0,0 +1,217 @@
+use super::camera::{Camera, Viewpoint};
+use super::marker::{octree::Octree, sampler::PolarSampler, Mark, MarkRaw, DEFAULT_INST_N};
+use super::settings::ScannerSettings;
+use super::util::Ray;
+use super::world::{Terrain, TerrainKind};
+use glam::{vec3, Vec3};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::Instant;
+
+const ASPECT: f32 = 16.0 / 9.0;
+const RAYS_PER_FRAME: usize = 200;
+const VIEWER_POINTS: usize = 5_000_000;
+const VIEWER_RADIUS: f32 = 400.0;
+const VIEWER_FRAMES: u32 = 120;
+
+/// Canned, deterministic workloads that exercise the scanner without a window or GPU.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scenario {
+    /// Stationary camera sweeping a cone over the spawn cave.
+    DenseRoom,
+    /// Camera flying forward while sweeping left and right.
+    CorridorSweep,
+    /// Fast flythrough over never-generated terrain, dominated by triangle generation.
+    ColdCache,
+    /// Bulk insert of a synthetic 5M point cloud followed by culling from orbiting poses.
+    ViewerLoad,
+    /// The same synthetic cloud built once with sequential inserts and once with `Octree::from_points`.
+    BulkBuild,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 5] =
+        [Scenario::DenseRoom, Scenario::CorridorSweep, Scenario::ColdCache, Scenario::ViewerLoad, Scenario::BulkBuild];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scenario::DenseRoom => "dense-room",
+            Scenario::CorridorSweep => "corridor-sweep",
+            Scenario::ColdCache => "cold-cache",
+            Scenario::ViewerLoad => "viewer-load",
+            Scenario::BulkBuild => "bulk-build",
+        }
+    }
+}
+
+impl FromStr for Scenario {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scenario::ALL.into_iter().find(|scenario| scenario.name() == s).ok_or_else(|| {
+            let names: Vec<&str> = Scenario::ALL.iter().map(Scenario::name).collect();
+            format!("unknown scenario '{}', expected one of: {}", s, names.join(", "))
+        })
+    }
+}
+
+#[derive(Default, Serialize)]
+pub struct BenchResult {
+    pub scenario: &'static str,
+    pub seed: u64,
+    pub seconds: f64,
+    pub rays: u64,
+    pub hits: u64,
+    pub rays_per_sec: f64,
+    pub marks: usize,
+    pub insert_seconds: f64,
+    pub build_seconds: f64,
+    pub cull_frames: u32,
+    pub cull_ms_avg: f64,
+    pub visible_avg: f64,
+}
+
+struct Bench {
+    world: Box<dyn Terrain>,
+    octree: Octree,
+    camera: Camera,
+    rng: StdRng,
+    instances: Vec<MarkRaw>,
+    result: BenchResult,
+    scan_seconds: f64,
+    cull_seconds: f64,
+    visible_sum: usize,
+}
+
+impl Bench {
+    fn new(scenario: Scenario, seed: u64, terrain: &TerrainKind) -> Result<Self, String> {
+        Ok(Self {
+            world: terrain.create()?,
+            octree: Octree::new(),
+            camera: Camera::new(ASPECT),
+            rng: StdRng::seed_from_u64(seed),
+            instances: Vec::with_capacity(DEFAULT_INST_N),
+            result: BenchResult { scenario: scenario.name(), seed, ..Default::default() },
+            scan_seconds: 0.0,
+            cull_seconds: 0.0,
+            visible_sum: 0,
+        })
+    }
+
+    fn scan_frame(&mut self) {
+        let start = Instant::now();
+        // Cast as one bundle and tested as by default, like the scanner's shots each frame.
+        let rays: Vec<Ray> =
+            (0..RAYS_PER_FRAME).map(|_| self.camera.cast_ray_with(&mut PolarSampler, &mut self.rng)).collect();
+        self.result.rays += rays.len() as u64;
+        let test = ScannerSettings::default().triangle_test();
+        for (ray, hit) in rays.iter().zip(self.world.raycast_bundle(&rays, -1.0, test)) {
+            if let Some(pos) = hit {
+                self.result.hits += 1;
+                self.octree.insert(Mark::scanned(pos, Vec3::distance(ray.pos, pos)));
+            }
+        }
+        self.scan_seconds += start.elapsed().as_secs_f64();
+    }
+
+    fn cull_frame(&mut self) {
+        let start = Instant::now();
+        self.octree.get_visible(&mut self.instances, DEFAULT_INST_N, self.camera.pos, self.camera.frustum());
+        self.cull_seconds += start.elapsed().as_secs_f64();
+        self.result.cull_frames += 1;
+        self.visible_sum += usize::min(self.instances.len(), DEFAULT_INST_N);
+    }
+
+    fn finish(mut self, start: Instant) -> BenchResult {
+        self.result.seconds = start.elapsed().as_secs_f64();
+        self.result.marks = self.octree.count();
+        if self.scan_seconds > 0.0 {
+            self.result.rays_per_sec = self.result.rays as f64 / self.scan_seconds;
+        }
+        if self.result.cull_frames > 0 {
+            self.result.cull_ms_avg = self.cull_seconds * 1000.0 / self.result.cull_frames as f64;
+            self.result.visible_avg = self.visible_sum as f64 / self.result.cull_frames as f64;
+        }
+        self.result
+    }
+}
+
+pub fn run(scenario: Scenario, seed: u64, terrain: &TerrainKind) -> Result<BenchResult, String> {
+    let _span = tracing::info_span!("bench", scenario = scenario.name()).entered();
+    let start = Instant::now();
+    let mut bench = Bench::new(scenario, seed, terrain)?;
+    let spawn = bench.camera.viewpoint();
+
+    match scenario {
+        Scenario::DenseRoom => {
+            for frame in 0..600 {
+                let t = frame as f32 / 600.0;
+                bench.camera.set_viewpoint(Viewpoint {
+                    yaw: spawn.yaw + f32::sin(t * 4.0 * std::f32::consts::PI) * 0.8,
+                    pitch: f32::sin(t * 6.0 * std::f32::consts::PI) * 0.4,
+                    ..spawn
+                });
+                bench.scan_frame();
+                bench.cull_frame();
+            }
+        }
+        Scenario::CorridorSweep => {
+            for frame in 0..600 {
+                let t = frame as f32 / 600.0;
+                let yaw = spawn.yaw + f32::sin(t * 8.0 * std::f32::consts::PI) * 0.8;
+                let pos = spawn.pos + vec3(f32::cos(spawn.yaw), 0.0, f32::sin(spawn.yaw)) * frame as f32;
+                bench.camera.set_viewpoint(Viewpoint { pos, yaw, pitch: 0.0 });
+                bench.scan_frame();
+                bench.cull_frame();
+            }
+        }
+        Scenario::ColdCache => {
+            for frame in 0..300 {
+                let pos = spawn.pos + vec3(f32::cos(spawn.yaw), 0.1, f32::sin(spawn.yaw)) * 10.0 * frame as f32;
+                bench.camera.set_viewpoint(Viewpoint { pos, ..spawn });
+                bench.scan_frame();
+            }
+        }
+        Scenario::ViewerLoad => {
+            let insert_start = Instant::now();
+            for pos in viewer_cloud(&mut bench.rng) {
+                bench.octree.insert(Mark::scanned(pos, pos.length()));
+            }
+            bench.result.insert_seconds = insert_start.elapsed().as_secs_f64();
+
+            for frame in 0..VIEWER_FRAMES {
+                let yaw = frame as f32 / VIEWER_FRAMES as f32 * 2.0 * std::f32::consts::PI;
+                let pos = vec3(f32::cos(yaw), 0.0, f32::sin(yaw)) * VIEWER_RADIUS * 1.5;
+                bench.camera.set_viewpoint(Viewpoint { pos, yaw: yaw + std::f32::consts::PI, pitch: 0.0 });
+                bench.cull_frame();
+            }
+        }
+        Scenario::BulkBuild => {
+            let points = viewer_cloud(&mut bench.rng);
+
+            let insert_start = Instant::now();
+            for pos in &points {
+                bench.octree.insert(Mark::scanned(*pos, pos.length()));
+            }
+            bench.result.insert_seconds = insert_start.elapsed().as_secs_f64();
+
+            let build_start = Instant::now();
+            bench.octree = Octree::from_points(&points);
+            bench.result.build_seconds = build_start.elapsed().as_secs_f64();
+        }
+    }
+
+    Ok(bench.finish(start))
+}
+
+/// `VIEWER_POINTS` points in a thick spherical shell around the origin.
+fn viewer_cloud(rng: &mut StdRng) -> Vec<Vec3> {
+    (0..VIEWER_POINTS)
+        .map(|_| {
+            let dir = vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0;
+            let radius = VIEWER_RADIUS * (0.8 + 0.2 * rng.gen::<f32>());
+            dir.normalize_or_zero() * radius
+        })
+        .collect()
+}
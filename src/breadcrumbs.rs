@@ -0,0 +1,307 @@
+use super::marker::{Mark, MarkRaw, MarkTag, MAX_TRANSIENT_MARKS};
+use super::util::Ray;
+use super::State;
+use glam::{vec3, IVec3, Vec3};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Distance the camera moves before the next breadcrumb is dropped.
+const CRUMB_SPACING: f32 = 4.0;
+/// Breadcrumbs closer than this to a new one are linked to it when nothing is in between, so crossing an old trail
+/// makes a shortcut.
+const LINK_RADIUS: f32 = 8.0;
+/// Most breadcrumbs kept; later positions are no longer recorded.
+const MAX_CRUMBS: usize = 16384;
+
+/// Seconds between replanning the way back while guiding.
+const REPLAN_TIME: f64 = 0.5;
+/// Guiding stops within this distance of the start.
+const ARRIVE_RADIUS: f32 = 3.0;
+/// Spacing of the guide marks along the path, and how far below the recorded camera positions they are drawn.
+const GUIDE_SPACING: f32 = 0.5;
+const GUIDE_DROP: f32 = 1.0;
+/// Length and speed of the bright pulses running along the path toward the start.
+const PULSE_LENGTH: f32 = 12.0;
+const PULSE_SPEED: f32 = 10.0;
+
+const GUIDE_DIM: Vec3 = vec3(0.1, 0.35, 0.3);
+const GUIDE_BRIGHT: Vec3 = vec3(0.6, 1.0, 0.9);
+
+/// Positions linked by the straight, unobstructed moves between them.
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Vec3>,
+    /// Per node, its neighbors and the distance to each.
+    edges: Vec<Vec<(usize, f32)>>,
+    /// Nodes by the cube of edge [`LINK_RADIUS`] they lie in, so the ones near a position are found without visiting
+    /// every node.
+    cells: HashMap<IVec3, Vec<usize>>,
+}
+
+/// Entry of the A* open set, ordered so the heap pops the lowest estimate first.
+struct Open {
+    estimate: f32,
+    node: usize,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    fn cmp(&self, other: &Self) -> Ordering {
+        f32::total_cmp(&other.estimate, &self.estimate)
+    }
+}
+
+impl Graph {
+    fn add(&mut self, pos: Vec3) -> usize {
+        self.nodes.push(pos);
+        self.edges.push(Vec::new());
+        self.cells.entry(cell(pos)).or_default().push(self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    /// Moves every node by `offset`.
+    fn translate(&mut self, offset: Vec3) {
+        self.cells.clear();
+        for (i, pos) in self.nodes.iter_mut().enumerate() {
+            *pos += offset;
+            self.cells.entry(cell(*pos)).or_default().push(i);
+        }
+    }
+
+    /// Nodes closer than [`LINK_RADIUS`] to `pos`.
+    fn within_link_radius(&self, pos: Vec3) -> impl Iterator<Item = usize> + '_ {
+        let center = cell(pos);
+        itertools::iproduct!(-1..=1, -1..=1, -1..=1)
+            .filter_map(move |(x, y, z)| self.cells.get(&(center + IVec3::new(x, y, z))))
+            .flatten()
+            .copied()
+            .filter(move |&i| self.nodes[i].distance(pos) < LINK_RADIUS)
+    }
+
+    fn link(&mut self, a: usize, b: usize) {
+        if a == b || self.edges[a].iter().any(|&(next, _)| next == b) {
+            return;
+        }
+        let length = self.nodes[a].distance(self.nodes[b]);
+        self.edges[a].push((b, length));
+        self.edges[b].push((a, length));
+    }
+
+    fn nearest(&self, pos: Vec3) -> Option<usize> {
+        (0..self.nodes.len())
+            .min_by(|&a, &b| f32::total_cmp(&self.nodes[a].distance_squared(pos), &self.nodes[b].distance_squared(pos)))
+    }
+
+    /// Shortest chain of linked nodes from `from` to `to`, both included, found with A* under the straight-line
+    /// distance.
+    fn path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let goal = self.nodes[to];
+        let mut cost = vec![f32::INFINITY; self.nodes.len()];
+        let mut came_from = vec![usize::MAX; self.nodes.len()];
+        let mut open = BinaryHeap::new();
+        cost[from] = 0.0;
+        open.push(Open { estimate: self.nodes[from].distance(goal), node: from });
+
+        while let Some(Open { node, .. }) = open.pop() {
+            if node == to {
+                let mut path = vec![to];
+                while *path.last().unwrap() != from {
+                    path.push(came_from[*path.last().unwrap()]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &(next, length) in &self.edges[node] {
+                let next_cost = cost[node] + length;
+                if next_cost < cost[next] {
+                    cost[next] = next_cost;
+                    came_from[next] = node;
+                    open.push(Open { estimate: next_cost + self.nodes[next].distance(goal), node: next });
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Cell of [`Graph::cells`] holding `pos`.
+fn cell(pos: Vec3) -> IVec3 {
+    (pos / LINK_RADIUS).floor().as_ivec3()
+}
+
+/// Trail of the positions the camera has visited, which can guide the player back to where the session started.
+#[derive(Default)]
+pub struct Breadcrumbs {
+    graph: Graph,
+    last: Option<usize>,
+    pub guiding: bool,
+    /// The way back from the breadcrumb nearest the camera to the start, while guiding.
+    path: Vec<Vec3>,
+    replan_timer: f64,
+    time: f64,
+}
+
+impl Breadcrumbs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        self.graph.translate(-shift);
+        for pos in &mut self.path {
+            *pos -= shift;
+        }
+    }
+
+    /// Queues marks along the path with pulses running from the camera end toward the start.
+    fn guide_marks(&self) -> Vec<MarkRaw> {
+        let mut marks = Vec::new();
+        let mut along = 0.0;
+        for pair in self.path.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let length = a.distance(b);
+            let mut t = 0.0;
+            while t < length && marks.len() < MAX_TRANSIENT_MARKS {
+                let phase = (along + t - self.time as f32 * PULSE_SPEED).rem_euclid(PULSE_LENGTH) / PULSE_LENGTH;
+                let color = GUIDE_DIM.lerp(GUIDE_BRIGHT, phase * phase);
+                let pos = a.lerp(b, t / length) - Vec3::Y * GUIDE_DROP;
+                marks.push(Mark { tag: MarkTag::Painted, ..Mark::new(pos, color) }.to_raw());
+                t += GUIDE_SPACING;
+            }
+            along += length;
+        }
+        marks
+    }
+}
+
+impl State {
+    /// Starts or stops guiding the player back along their trail to where the session started.
+    pub fn toggle_guide(&mut self) {
+        self.breadcrumbs.guiding = !self.breadcrumbs.guiding;
+        self.breadcrumbs.replan_timer = 0.0;
+        self.notify(format!("guide to start: {}", if self.breadcrumbs.guiding { "on" } else { "off" }));
+    }
+
+    /// Whether nothing in the terrain blocks the straight line from `a` to `b`.
+    fn clear_line(&mut self, a: Vec3, b: Vec3) -> bool {
+        let dist = a.distance(b);
+        dist <= 0.0 || self.world.raycast(Ray { pos: a, dir: (b - a) / dist }, dist).is_none()
+    }
+
+    /// Drops a breadcrumb once the camera moved far enough from the last one and links it to every nearby breadcrumb
+    /// it can see.
+    fn record_breadcrumb(&mut self, pos: Vec3) {
+        let graph = &self.breadcrumbs.graph;
+        let moved = self.breadcrumbs.last.is_none_or(|last| graph.nodes[last].distance(pos) >= CRUMB_SPACING);
+        if !moved || graph.nodes.len() >= MAX_CRUMBS {
+            return;
+        }
+        let last = self.breadcrumbs.last;
+        let nearby: Vec<usize> = graph.within_link_radius(pos).filter(|&i| Some(i) != last).collect();
+        let candidates = last.into_iter().chain(nearby);
+        let visible: Vec<usize> =
+            candidates.filter(|&i| self.clear_line(self.breadcrumbs.graph.nodes[i], pos)).collect();
+
+        let graph = &mut self.breadcrumbs.graph;
+        let crumb = graph.add(pos);
+        for i in visible {
+            graph.link(i, crumb);
+        }
+        self.breadcrumbs.last = Some(crumb);
+    }
+
+    /// Records the camera position and, while guiding, replans the way back and shows it with temporary marks.
+    pub fn update_breadcrumbs(&mut self, dt: f64) {
+        let pos = self.camera.pos;
+        self.record_breadcrumb(pos);
+
+        let breadcrumbs = &mut self.breadcrumbs;
+        if !breadcrumbs.guiding {
+            if !breadcrumbs.path.is_empty() {
+                breadcrumbs.path.clear();
+                self.marker.set_transient_marks(&self.queue, &[]);
+            }
+            return;
+        }
+        if pos.distance(breadcrumbs.graph.nodes[0]) < ARRIVE_RADIUS {
+            breadcrumbs.guiding = false;
+            self.notify("back at the start".to_string());
+            return;
+        }
+
+        breadcrumbs.time += dt;
+        breadcrumbs.replan_timer -= dt;
+        if breadcrumbs.replan_timer <= 0.0 {
+            breadcrumbs.replan_timer = REPLAN_TIME;
+            let graph = &breadcrumbs.graph;
+            let path = graph.nearest(pos).and_then(|nearest| graph.path(nearest, 0));
+            breadcrumbs.path = path.map_or(Vec::new(), |path| path.into_iter().map(|i| graph.nodes[i]).collect());
+            if breadcrumbs.path.is_empty() {
+                breadcrumbs.guiding = false;
+                self.marker.set_transient_marks(&self.queue, &[]);
+                self.notify("no known way back to the start".to_string());
+                return;
+            }
+        }
+        let marks = breadcrumbs.guide_marks();
+        self.marker.set_transient_marks(&self.queue, &marks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Graph;
+    use glam::{vec3, Vec3};
+
+    #[test]
+    fn path_takes_the_shortest_chain_of_links() {
+        let mut graph = Graph::default();
+        let [start, detour, near, far, goal, island] = [
+            Vec3::ZERO,
+            vec3(0.0, 10.0, 0.0),
+            vec3(3.0, 1.0, 0.0),
+            vec3(6.0, 12.0, 0.0),
+            vec3(9.0, 0.0, 0.0),
+            Vec3::ONE,
+        ]
+        .map(|pos| graph.add(pos));
+        graph.link(start, detour);
+        graph.link(detour, far);
+        graph.link(far, goal);
+        graph.link(start, near);
+        graph.link(near, goal);
+
+        assert_eq!(graph.path(start, goal), Some(vec![start, near, goal]));
+        assert_eq!(graph.path(goal, goal), Some(vec![goal]));
+        assert_eq!(graph.path(start, island), None);
+        assert_eq!(graph.nearest(vec3(5.0, 11.0, 0.0)), Some(far));
+    }
+
+    #[test]
+    fn nodes_within_the_link_radius_are_found_across_cells_and_moves() {
+        let mut graph = Graph::default();
+        let nodes = [vec3(-0.5, 0.0, 0.0), vec3(7.9, 0.0, 0.0), vec3(0.0, -7.0, 3.0), vec3(8.0, 0.0, 0.0)];
+        let ids = nodes.map(|pos| graph.add(pos));
+        let mut near: Vec<usize> = graph.within_link_radius(Vec3::ZERO).collect();
+        near.sort_unstable();
+        assert_eq!(near, ids[..3]);
+
+        graph.translate(vec3(100.0, 0.0, 0.0));
+        assert_eq!(graph.within_link_radius(Vec3::ZERO).count(), 0);
+        assert_eq!(graph.within_link_radius(vec3(108.0, 0.0, 0.0)).count(), 2);
+    }
+}
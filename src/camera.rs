@@ -1,8 +1,8 @@
-use super::util::{Frustum, Ray, Triangle};
+use super::marker::DiscSampler;
+use super::util::{geom, Frustum, Ray, ScanRng, Triangle};
 use super::State;
-use glam::{vec3, Mat4, Vec3, Vec4Swizzles};
+use glam::{vec3, Mat4, Vec2, Vec3, Vec4Swizzles};
 
-#[repr(packed)]
 pub struct Movement {
     pub forward: bool,
     pub backward: bool,
@@ -12,8 +12,29 @@ pub struct Movement {
     pub down: bool,
 }
 
+impl Movement {
+    pub fn clear(&mut self) {
+        *self = Movement { forward: false, backward: false, right: false, left: false, up: false, down: false };
+    }
+}
+
+/// A camera pose that can be stored and returned to.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+pub struct Viewpoint {
+    pub pos: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+
+struct Tween {
+    from: Viewpoint,
+    to: Viewpoint,
+    progress: f32,
+}
+
 pub struct Camera {
     aspect: f32,
+    /// Vertical field of view in radians.
     fovy: f32,
     znear: f32,
     zfar: f32,
@@ -25,8 +46,12 @@ pub struct Camera {
     dir: Vec3,
     up: Vec3,
 
+    /// Width of the scanner cone: rays stray up to half this far from the view direction, per unit of distance.
     pub ray_range: f32,
     pub mov: Movement,
+    tween: Option<Tween>,
+    /// Multiple of `CAM_SENSITIVITY` mouse motion turns the camera by.
+    sensitivity: f32,
 }
 
 const TO_WGPU_MATRIX: Mat4 = glam::mat4(
@@ -38,13 +63,30 @@ const TO_WGPU_MATRIX: Mat4 = glam::mat4(
 
 const PI: f32 = std::f32::consts::PI;
 
-const N_ITERATIONS: i32 = 5;
-
 const CAM_SIZE: f32 = 1.0;
 const CAM_SENSITIVITY: f32 = 0.0005;
+/// Scanner beam origin relative to the eye as (right, up, forward).
+const MUZZLE_OFFSET: Vec3 = vec3(0.3, -0.3, 0.6);
 const MOV_SPEED: f32 = 100.0;
+const TWEEN_TIME: f32 = 0.75;
+
+/// Vertical field of view in degrees, and the bounds the display settings allow.
+pub const DEFAULT_FOV: f32 = 60.0;
+pub const MIN_FOV: f32 = 30.0;
+pub const MAX_FOV: f32 = 120.0;
+
+/// Bounds of the look sensitivity multiplier in the controls settings.
+pub const MIN_SENSITIVITY: f32 = 0.1;
+pub const MAX_SENSITIVITY: f32 = 5.0;
+
+/// Bounds of [`Camera::ray_range`].
+pub const MIN_RAY_RANGE: f32 = 0.1;
+pub const MAX_RAY_RANGE: f32 = 1.0;
+
+pub const SPAWN_POS: Vec3 = Vec3::new(0.0, 0.0, -30.0);
 
 impl Camera {
+    /// Creates a camera looking down -Z from the default spawn point with the given viewport aspect ratio.
     pub fn new(aspect: f32) -> Self {
         let yaw = -PI / 2.0;
         let pitch = 0.0;
@@ -52,26 +94,62 @@ impl Camera {
 
         Self {
             aspect,
-            fovy: 45.0,
+            fovy: DEFAULT_FOV.to_radians(),
             znear: 0.1,
             zfar: 1000000.0,
             yaw,
             pitch,
-            pos: vec3(0.0, 0.0, -30.0),
+            pos: SPAWN_POS,
             dir,
             up: vec3(0.0, 1.0, 0.0),
             ray_range: 0.5,
             mov: Movement { forward: false, backward: false, right: false, left: false, up: false, down: false },
+            tween: None,
+            sensitivity: 1.0,
         }
     }
 
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+    }
+
+    /// Sets the vertical field of view, in degrees.
+    pub fn set_fov(&mut self, degrees: f32) {
+        self.fovy = degrees.clamp(MIN_FOV, MAX_FOV).to_radians();
+    }
+
+    /// Sets how fast mouse motion turns the camera, as a multiple of the default speed.
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity.clamp(MIN_SENSITIVITY, MAX_SENSITIVITY);
+    }
+
     fn view_matrix(&self) -> Mat4 {
         Mat4::look_to_rh(self.pos, self.dir, self.up)
     }
 
     fn projection_matrix(&self) -> Mat4 {
         let proj = Mat4::perspective_rh(self.fovy, self.aspect, self.znear, self.zfar);
-        return TO_WGPU_MATRIX * proj;
+        TO_WGPU_MATRIX * proj
+    }
+
+    /// Tangent of the angle one pixel spans at the center of a view `height` pixels tall.
+    pub fn pixel_spread(&self, height: u32) -> f32 {
+        2.0 * (self.fovy * 0.5).tan() / height.max(1) as f32
+    }
+
+    /// Distances of the near and far clip planes.
+    pub fn depth_range(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
+
+    /// Transform from local positions to clip space.
+    pub fn view_projection(&self) -> Mat4 {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// Where terrain will be needed soonest: ahead of the camera's movement, leaning towards where it looks.
+    pub fn heading(&self) -> Vec3 {
+        (self.movement_dir() * 2.0 + self.dir).normalize_or_zero()
     }
 
     fn movement_dir(&self) -> Vec3 {
@@ -89,10 +167,86 @@ impl Camera {
     }
 
     pub fn offset_view(&mut self, xrel: f32, yrel: f32) {
-        self.yaw += xrel * CAM_SENSITIVITY;
-        self.pitch -= yrel * CAM_SENSITIVITY;
-        self.pitch = self.pitch.clamp((-89.0 as f32).to_radians(), (89.0 as f32).to_radians());
+        self.yaw += xrel * CAM_SENSITIVITY * self.sensitivity;
+        self.pitch -= yrel * CAM_SENSITIVITY * self.sensitivity;
+        self.pitch = self.pitch.clamp((-89.0_f32).to_radians(), 89.0_f32.to_radians());
+        self.update_dir();
+    }
+
+    pub fn viewpoint(&self) -> Viewpoint {
+        Viewpoint { pos: self.pos, yaw: self.yaw, pitch: self.pitch }
+    }
+
+    pub fn set_viewpoint(&mut self, viewpoint: Viewpoint) {
+        self.pos = viewpoint.pos;
+        self.yaw = viewpoint.yaw;
+        self.pitch = viewpoint.pitch;
+        self.update_dir();
+    }
+
+    /// Follows the local origin to `shift`, including any tween in progress.
+    pub fn rebase(&mut self, shift: Vec3) {
+        self.pos -= shift;
+        if let Some(tween) = &mut self.tween {
+            tween.from.pos -= shift;
+            tween.to.pos -= shift;
+        }
+    }
+
+    /// Smoothly moves the camera to `viewpoint` over the next `TWEEN_TIME` seconds.
+    pub fn tween_to(&mut self, viewpoint: Viewpoint) {
+        self.tween = Some(Tween { from: self.viewpoint(), to: viewpoint, progress: 0.0 });
+    }
+
+    /// Advances the active tween, returning `false` if there is none.
+    fn update_tween(&mut self, dt: f32) -> bool {
+        let Some(tween) = &mut self.tween else {
+            return false;
+        };
+
+        tween.progress = f32::min(tween.progress + dt / TWEEN_TIME, 1.0);
+        let t = tween.progress * tween.progress * (3.0 - 2.0 * tween.progress);
+        let (from, to) = (tween.from, tween.to);
+        let yaw_delta = (to.yaw - from.yaw + PI).rem_euclid(2.0 * PI) - PI;
+
+        self.set_viewpoint(Viewpoint {
+            pos: Vec3::lerp(from.pos, to.pos, t),
+            yaw: from.yaw + yaw_delta * t,
+            pitch: from.pitch + (to.pitch - from.pitch) * t,
+        });
+        if t >= 1.0 {
+            self.tween = None;
+        }
+        true
+    }
+
+    /// Places the camera at `pos` looking towards `target`.
+    pub fn look_at(&mut self, pos: Vec3, target: Vec3) {
+        let dir = (target - pos).normalize_or_zero();
+        self.set_viewpoint(Viewpoint {
+            pos,
+            yaw: f32::atan2(dir.z, dir.x),
+            pitch: f32::asin(dir.y.clamp(-1.0, 1.0)).clamp((-89.0_f32).to_radians(), 89.0_f32.to_radians()),
+        });
+    }
+
+    /// Points `dist` units out along each corner edge of the view frustum, counter-clockwise from bottom-left.
+    pub fn frustum_corners(&self, dist: f32) -> [Vec3; 4] {
+        let inv = self.view_projection().inverse();
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, y)| {
+            let near = inv.project_point3(vec3(x, y, 0.0));
+            self.pos + (near - self.pos).normalize() * dist
+        })
+    }
+
+    /// Point just ahead of the eye, below and to the right, where scanner beams start so they are visible on screen.
+    pub fn muzzle(&self) -> Vec3 {
+        let right = Vec3::cross(self.dir, self.up).normalize();
+        let up = Vec3::cross(right, self.dir).normalize();
+        self.pos + right * MUZZLE_OFFSET.x + up * MUZZLE_OFFSET.y + self.dir * MUZZLE_OFFSET.z
+    }
 
+    fn update_dir(&mut self) {
         let dir = Vec3 {
             x: f32::cos(self.yaw) * f32::cos(self.pitch),
             y: f32::sin(self.pitch),
@@ -101,17 +255,20 @@ impl Camera {
         self.dir = dir.normalize();
     }
 
-    pub fn cast_ray(&self) -> Ray {
-        let angle = rand::random::<f32>() * 2.0 * PI;
-        let length = rand::random::<f32>() * self.ray_range * 0.5;
-
+    /// Casts a ray from the eye through `offset`, a point of the unit disc mapped onto the scanner cone.
+    pub fn cast_ray_at(&self, offset: Vec2) -> Ray {
         let right = Vec3::cross(self.dir, self.up).normalize();
         let up = Vec3::cross(self.dir, right).normalize();
 
-        let offset = length * ((right * f32::sin(angle)) + (up * f32::cos(angle)));
+        let offset = self.ray_range * 0.5 * (right * offset.x + up * offset.y);
         Ray { pos: self.pos, dir: (self.dir + offset).normalize() }
     }
 
+    /// Casts a ray from the eye, placed within the scanner cone by `sampler`.
+    pub fn cast_ray_with(&self, sampler: &mut (impl DiscSampler + ?Sized), rng: &mut dyn ScanRng) -> Ray {
+        self.cast_ray_at(sampler.sample(rng))
+    }
+
     pub fn frustum(&self) -> Frustum {
         let to_plane = |vec: glam::Vec4| {
             let mag = vec.xyz().length();
@@ -123,7 +280,7 @@ impl Camera {
             }
         };
 
-        let mat = self.projection_matrix() * self.view_matrix();
+        let mat = self.view_projection();
         [
             to_plane(mat.row(3) + mat.row(0)), // left
             to_plane(mat.row(3) - mat.row(0)), // right
@@ -157,57 +314,95 @@ impl CameraUniform {
         self.to_view = camera.view_matrix().to_cols_array_2d();
         self.to_clip = camera.projection_matrix().to_cols_array_2d();
     }
+
+    /// The view of one eye of a stereo pair, `offset` units to the right of `camera` (negative for the left eye).
+    /// The projection is shifted off-axis so both eyes' views coincide at distance `convergence`.
+    pub fn stereo(camera: &Camera, offset: f32, convergence: f32) -> Self {
+        let right = Vec3::cross(camera.dir, camera.up).normalize();
+        let pos = camera.pos + right * offset;
+        let projection = camera.projection_matrix();
+        let shift = Mat4::from_translation(vec3(projection.x_axis.x * offset / convergence, 0.0, 0.0));
+        Self {
+            pos: pos.extend(1.0).into(),
+            to_view: Mat4::look_to_rh(pos, camera.dir, camera.up).to_cols_array_2d(),
+            to_clip: (shift * projection).to_cols_array_2d(),
+        }
+    }
 }
 
 impl State {
     pub fn update_camera(&mut self, dt: f64) {
-        self.camera.pos += self.camera.movement_dir() * MOV_SPEED * dt as f32;
+        let start = self.camera.pos;
+        let mut flying = false;
+        if self.museum.active {
+            self.update_museum();
+        } else if !self.camera.update_tween(dt as f32) {
+            self.camera.pos += self.camera.movement_dir() * MOV_SPEED * dt as f32;
+            flying = !self.photo.active;
+        }
 
-        let triangle_list = self.world.retrieve_triangles(self.camera.pos, CAM_SIZE);
-        for _ in 0..N_ITERATIONS {
+        // The photo camera flies through the terrain freely.
+        if !self.museum.active && !self.photo.active {
+            let pos = self.camera.pos;
             let mut inf_dir = Vec3::ZERO;
-            for triangle in &triangle_list {
-                if let Some(dir) = self.collide_camera(triangle.clone()) {
+            self.world.for_each_triangle_in_radius(pos, CAM_SIZE, &mut |triangle| {
+                if let Some(dir) = collide_camera(pos, triangle) {
                     inf_dir += dir;
                 }
-            }
-            if inf_dir != Vec3::ZERO {
-                self.camera.pos += inf_dir;
-                break;
-            }
+            });
+            self.camera.pos += inf_dir;
+        }
+        if flying {
+            self.stats.record_movement(start, self.camera.pos);
         }
 
         self.marker.camera_uniform.update_view_proj(&self.camera);
         self.queue.write_buffer(&self.marker.camera_buffer, 0, bytemuck::cast_slice(&[self.marker.camera_uniform]));
     }
+}
+
+/// How far to push a camera at `pos` out of `triangle`, if it is within [`CAM_SIZE`] of it over its face. The push
+/// is along the face normal, to [`CAM_SIZE`] in front of the face even from behind it.
+#[inline]
+fn collide_camera(pos: Vec3, triangle: &Triangle) -> Option<Vec3> {
+    let n = geom::triangle_normal(triangle)?;
+    let dist = Vec3::dot(pos - triangle.a, n);
+    (geom::sphere_intersects_triangle(pos, CAM_SIZE, triangle) && geom::point_in_triangle(pos, triangle))
+        .then(|| (CAM_SIZE - dist) * n)
+}
 
-    #[inline]
-    fn collide_camera(&mut self, triangle: Triangle) -> Option<Vec3> {
-        let e1 = triangle.b - triangle.a;
-        let e2 = triangle.c - triangle.a;
+#[cfg(test)]
+mod tests {
+    use super::Camera;
+    use crate::marker::sampler::PolarSampler;
+    use crate::util::ScanRng;
+    use rand::{rngs::StdRng, SeedableRng};
 
-        let n = Vec3::cross(e1, e2).normalize();
-        let dist = Vec3::dot(self.camera.pos - triangle.a, n);
-        let p = self.camera.pos - dist * n;
+    /// Replays a fixed list of samples.
+    struct Scripted(std::vec::IntoIter<f32>);
 
-        if dist <= CAM_SIZE && point_in_triangle(p, triangle) {
-            Some((CAM_SIZE - dist) * n)
-        } else {
-            None
+    impl ScanRng for Scripted {
+        fn next_unit(&mut self) -> f32 {
+            self.0.next().expect("script ran out of samples")
         }
     }
-}
-
-#[inline]
-fn point_in_triangle(p: Vec3, t: Triangle) -> bool {
-    let sign = |p1: Vec3, p2: Vec3, p3: Vec3| (p1.x - p3.x) * (p2.y - p3.y) * (p1.y - p3.y);
 
-    let d1 = sign(p, t.a, t.b);
-    let d2 = sign(p, t.b, t.c);
-    let d3 = sign(p, t.c, t.a);
-
-    let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
-    let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+    #[test]
+    fn cast_ray_is_deterministic_for_a_seed() {
+        let camera = Camera::new(1.0);
+        let (mut a, mut b) = (StdRng::seed_from_u64(7), StdRng::seed_from_u64(7));
+        for _ in 0..100 {
+            let ray_a = camera.cast_ray_with(&mut PolarSampler, &mut a);
+            let ray_b = camera.cast_ray_with(&mut PolarSampler, &mut b);
+            assert_eq!(ray_a.dir, ray_b.dir);
+        }
+    }
 
-    !(has_neg && has_pos)
+    #[test]
+    fn cast_ray_without_jitter_follows_the_view() {
+        let camera = Camera::new(1.0);
+        let ray = camera.cast_ray_with(&mut PolarSampler, &mut Scripted(vec![0.25, 0.0].into_iter()));
+        assert!((ray.dir - camera.dir).length() < 1e-6);
+        assert_eq!(ray.pos, camera.pos);
+    }
 }
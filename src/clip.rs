@@ -0,0 +1,124 @@
+use super::lines::LineRenderer;
+use super::marker::Marker;
+use super::State;
+use glam::{vec4, Vec2, Vec3, Vec4};
+
+/// Distance ahead of the camera the plane starts at, and the bounds the wheel moves it within.
+const DEFAULT_DISTANCE: f32 = 50.0;
+const MIN_DISTANCE: f32 = 2.0;
+const MAX_DISTANCE: f32 = 500.0;
+/// Half the side of the outline square per unit of distance from the camera, so it stays inside the view.
+const OUTLINE_EXTENT: f32 = 0.4;
+/// Lines across the outline square in each direction.
+const OUTLINE_DIVISIONS: usize = 4;
+
+const FOLLOW_COLOR: Vec4 = vec4(1.0, 0.6, 0.2, 0.35);
+const LOCKED_COLOR: Vec4 = vec4(1.0, 0.6, 0.2, 0.8);
+
+/// A plane that hides every mark between it and the camera, for cross-section views. It stays `distance` ahead of
+/// the camera, facing along the view direction, until it is locked in place.
+pub struct Clip {
+    pub enabled: bool,
+    pub locked: bool,
+    distance: f32,
+    point: Vec3,
+    normal: Vec3,
+    /// Half the side of the outline square, fixed while the plane is locked.
+    extent: f32,
+    lines: LineRenderer,
+}
+
+impl Clip {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) -> Self {
+        Self {
+            enabled: false,
+            locked: false,
+            distance: DEFAULT_DISTANCE,
+            point: Vec3::ZERO,
+            normal: Vec3::Z,
+            extent: DEFAULT_DISTANCE * OUTLINE_EXTENT,
+            lines: LineRenderer::new(device, format, &marker.camera_bind_group_layout),
+        }
+    }
+
+    /// Rebuilds the outline renderer on a new device, keeping the plane.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) {
+        self.lines = LineRenderer::new(device, format, &marker.camera_bind_group_layout);
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        self.point -= shift;
+    }
+
+    /// Multiplies the distance from the camera by `factor`, returning the new distance. A locked plane moves along
+    /// its normal by the change.
+    pub fn scale_distance(&mut self, factor: f32) -> f32 {
+        let distance = (self.distance * factor).clamp(MIN_DISTANCE, MAX_DISTANCE);
+        if self.locked {
+            self.point += self.normal * (distance - self.distance);
+        }
+        self.distance = distance;
+        distance
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// The plane as `(normal, offset)`, negative on the hidden side for positions extended with `w = 1`.
+    fn equation(&self) -> Vec4 {
+        self.normal.extend(-self.normal.dot(self.point))
+    }
+
+    fn follow(&mut self, pos: Vec3, dir: Vec3) {
+        self.normal = dir;
+        self.point = pos + dir * self.distance;
+        self.extent = self.distance * OUTLINE_EXTENT;
+    }
+
+    fn push_outline(&mut self) {
+        let (u, v) = self.normal.any_orthonormal_pair();
+        let (u, v) = (u * self.extent, v * self.extent);
+        let color = if self.locked { LOCKED_COLOR } else { FOLLOW_COLOR };
+        for i in 0..=OUTLINE_DIVISIONS {
+            let t = i as f32 / OUTLINE_DIVISIONS as f32 * 2.0 - 1.0;
+            self.lines.push(self.point + u * t - v, self.point + u * t + v, color);
+            self.lines.push(self.point - u + v * t, self.point + u + v * t, color);
+        }
+    }
+}
+
+impl State {
+    pub fn toggle_clip(&mut self) {
+        self.clip.enabled = !self.clip.enabled;
+        self.clip.locked = false;
+        self.notify(format!("clipping plane: {}", if self.clip.enabled { "on" } else { "off" }));
+    }
+
+    /// Holds the plane where it is, or lets it follow the camera again.
+    pub fn toggle_clip_lock(&mut self) {
+        if !self.clip.enabled {
+            return;
+        }
+        self.clip.locked = !self.clip.locked;
+        self.notify(format!("clipping plane: {}", if self.clip.locked { "locked" } else { "following" }));
+    }
+
+    /// Moves an unlocked plane with the camera, passes it to the mark and occluder shaders and queues its outline.
+    pub fn update_clip(&mut self) {
+        if self.clip.enabled && !self.clip.locked {
+            let ray = self.camera.cast_ray_at(Vec2::ZERO);
+            self.clip.follow(ray.pos, ray.dir);
+        }
+        self.marker.set_clip_plane(self.clip.enabled.then(|| self.clip.equation()));
+        if self.clip.enabled {
+            self.clip.push_outline();
+        }
+        self.clip.lines.upload(&self.queue);
+    }
+
+    pub fn render_clip<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.clip.lines.draw(render_pass, &self.marker.camera_bind_group);
+    }
+}
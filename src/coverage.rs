@@ -0,0 +1,82 @@
+use super::marker::{octree::Octree, MarkTag};
+use super::State;
+use glam::Vec3;
+use std::collections::HashSet;
+
+/// Edge length of the coarse cells marks and surface are bucketed into.
+const CELL_SIZE: f32 = 10.0;
+/// Distance around the player within which surface cells are counted.
+const COVERAGE_RADIUS: f32 = 60.0;
+const UPDATE_TIME: f64 = 0.5;
+
+type Cell = (i32, i32, i32);
+
+fn cell(pos: Vec3) -> Cell {
+    let cell = (pos / CELL_SIZE).floor();
+    (cell.x as i32, cell.y as i32, cell.z as i32)
+}
+
+/// Tracks which coarse cells hold at least one mark and how many of the surface cells near the player that covers.
+pub struct Coverage {
+    scanned: HashSet<Cell>,
+    timer: f64,
+    /// Fraction of nearby surface cells with a mark, or `None` if there is no surface in range.
+    pub fraction: Option<f32>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self { scanned: HashSet::new(), timer: 0.0, fraction: None }
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        let (dx, dy, dz) = cell(shift + CELL_SIZE * 0.5);
+        self.scanned = self.scanned.drain().map(|(x, y, z)| (x - dx, y - dy, z - dz)).collect();
+    }
+
+    pub fn record(&mut self, pos: Vec3) {
+        self.scanned.insert(cell(pos));
+    }
+
+    /// Re-buckets every mark in `octree`, e.g. after a scan file was loaded.
+    pub fn rebuild(&mut self, octree: &Octree) {
+        self.scanned.clear();
+        for mark in octree.marks().filter(|mark| !matches!(mark.tag(), MarkTag::Miss | MarkTag::Dynamic)) {
+            self.record(Vec3::from(mark.pos));
+        }
+        self.timer = 0.0;
+    }
+}
+
+impl State {
+    /// Recomputes the nearby coverage every `UPDATE_TIME` seconds from the terrain surface around the camera.
+    pub fn update_coverage(&mut self, dt: f64) {
+        self.coverage.timer -= dt;
+        if self.coverage.timer > 0.0 {
+            return;
+        }
+        self.coverage.timer = UPDATE_TIME;
+
+        let center = self.camera.pos;
+        let surface: HashSet<Cell> = self
+            .world
+            .retrieve_triangles(center, COVERAGE_RADIUS)
+            .iter()
+            .map(|triangle| (triangle.a + triangle.b + triangle.c) / 3.0)
+            .filter(|pos| pos.distance_squared(center) <= COVERAGE_RADIUS * COVERAGE_RADIUS)
+            .map(cell)
+            .collect();
+
+        self.coverage.fraction = (!surface.is_empty()).then(|| {
+            let covered = surface.iter().filter(|cell| self.coverage.scanned.contains(cell)).count();
+            covered as f32 / surface.len() as f32
+        });
+    }
+}
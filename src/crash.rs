@@ -0,0 +1,176 @@
+use super::persistence::{save_scan, SAVE_DIR};
+use super::settings::Settings;
+use super::State;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+const CRASH_DIR: &str = "crashes";
+/// Log lines kept for crash reports.
+const RECENT_LOG_LINES: usize = 64;
+
+/// What a crash report says about the session besides the panic itself, kept current while it runs.
+struct Context {
+    adapter: String,
+    settings: String,
+    camera: String,
+    marks: usize,
+    /// Report written for the last panic, which the emergency save is noted in.
+    report: Option<PathBuf>,
+}
+
+static CONTEXT: Mutex<Context> = Mutex::new(Context {
+    adapter: String::new(),
+    settings: String::new(),
+    camera: String::new(),
+    marks: 0,
+    report: None,
+});
+static RECENT_LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Locks `mutex` even if a panic poisoned it; gives up instead of deadlocking if the panicking thread holds it.
+fn lock<T>(mutex: &Mutex<T>) -> Option<MutexGuard<'_, T>> {
+    match mutex.try_lock() {
+        Ok(guard) => Some(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => None,
+    }
+}
+
+/// Log sink that keeps the last lines for crash reports; each write is one formatted event.
+pub struct RecentLog;
+
+impl Write for RecentLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if let Some(mut recent) = lock(&RECENT_LOG) {
+            if recent.len() == RECENT_LOG_LINES {
+                recent.pop_front();
+            }
+            recent.push_back(String::from_utf8_lossy(buf).trim_end().to_string());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn record_adapter(info: &wgpu::AdapterInfo) {
+    if let Some(mut context) = lock(&CONTEXT) {
+        context.adapter = format!("{:?}", info);
+    }
+}
+
+pub fn record_settings(settings: &Settings) {
+    if let Some(mut context) = lock(&CONTEXT) {
+        context.settings = toml::to_string(settings).unwrap_or_else(|e| e.to_string());
+    }
+}
+
+/// Installs a panic hook that writes a crash report to the crash directory before the default hook prints the
+/// panic. The report holds the panic and its backtrace, the graphics adapter, the settings, the camera pose, the mark
+/// count and the last log lines.
+pub fn install_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let path = Path::new(CRASH_DIR).join(format!("crash-{}.txt", timestamp.as_millis()));
+        let report = report(&info.to_string(), &std::backtrace::Backtrace::force_capture().to_string());
+        match std::fs::create_dir_all(CRASH_DIR).and_then(|_| std::fs::write(&path, report)) {
+            Ok(()) => {
+                eprintln!("crash report written to {}", path.display());
+                if let Some(mut context) = lock(&CONTEXT) {
+                    context.report = Some(path);
+                }
+            }
+            Err(e) => eprintln!("failed to write crash report {}: {}", path.display(), e),
+        }
+        default_hook(info);
+    }));
+}
+
+fn report(panic: &str, backtrace: &str) -> String {
+    let mut report = format!("scanner {} crash report\n\n{}\n", env!("CARGO_PKG_VERSION"), panic);
+    if let Some(context) = lock(&CONTEXT) {
+        _ = write!(
+            report,
+            "\nadapter: {}\ncamera: {}\nmarks: {}\n\nsettings:\n{}",
+            context.adapter, context.camera, context.marks, context.settings
+        );
+    }
+    if let Some(recent) = lock(&RECENT_LOG) {
+        report += "\nrecent log:\n";
+        for line in recent.iter() {
+            report += line;
+            report += "\n";
+        }
+    }
+    report + "\nbacktrace:\n" + backtrace
+}
+
+/// Appends `line` to the last crash report, if one was written.
+fn note(line: &str) {
+    let Some(path) = lock(&CONTEXT).and_then(|context| context.report.clone()) else {
+        return;
+    };
+    let appended =
+        std::fs::OpenOptions::new().append(true).open(&path).and_then(|mut file| writeln!(file, "\n{}", line));
+    if let Err(e) = appended {
+        eprintln!("failed to update crash report {}: {}", path.display(), e);
+    }
+}
+
+impl State {
+    /// Keeps the camera pose and mark count in crash reports current.
+    pub fn update_crash_context(&self) {
+        if let Some(mut context) = lock(&CONTEXT) {
+            let viewpoint = self.camera.viewpoint();
+            context.camera = format!(
+                "world {:.2} yaw {:.3} pitch {:.3}",
+                self.to_world(viewpoint.pos),
+                viewpoint.yaw,
+                viewpoint.pitch
+            );
+            context.marks = self.marker.octree.count();
+        }
+    }
+
+    /// Saves the scan to a new timestamped file in the save directory after a panic, noting it in the crash report.
+    /// The octree may have been left mid-update, so a panic while saving is caught too.
+    pub fn emergency_save(&self) {
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let path = Path::new(SAVE_DIR).join(format!("emergency-{}.scan", timestamp.as_millis()));
+        let saved = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            save_scan(&self.marker.octree, &self.waypoints.list, self.origin(), &path)
+        }));
+        let line = match saved {
+            Ok(Ok(())) => format!("emergency save: {}", path.display()),
+            Ok(Err(e)) => format!("emergency save failed: {}", e),
+            Err(_) => "emergency save failed: panicked while saving".to_string(),
+        };
+        eprintln!("{}", line);
+        note(&line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{report, RecentLog, RECENT_LOG_LINES};
+    use std::io::Write;
+
+    #[test]
+    fn report_keeps_only_the_last_log_lines() {
+        for i in 0..RECENT_LOG_LINES + 5 {
+            RecentLog.write_all(format!("line {}\n", i).as_bytes()).unwrap();
+        }
+        let report = report("panicked at src/lib.rs:1:1", "0: main");
+        assert!(report.contains("panicked at src/lib.rs:1:1"));
+        assert!(!report.contains("line 4\n"));
+        assert!(report.contains("line 5\n"));
+        assert!(report.contains(&format!("line {}\n", RECENT_LOG_LINES + 4)));
+        assert!(report.ends_with("backtrace:\n0: main"));
+    }
+}
@@ -0,0 +1,44 @@
+use super::State;
+use std::time::Duration;
+use winit::window::Fullscreen;
+
+/// Frame rate in low power mode while the window is unfocused.
+const LOW_POWER_FPS: u32 = 30;
+
+impl State {
+    pub fn toggle_fullscreen(&mut self) {
+        self.set_fullscreen(self.window.fullscreen().is_none());
+    }
+
+    /// Switches between windowed and borderless fullscreen on the monitor chosen in the display settings (or the
+    /// current one). The surface and camera follow through the resulting `Resized` event.
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        let fullscreen = fullscreen.then(|| {
+            let monitor = self
+                .settings
+                .display
+                .monitor
+                .and_then(|i| self.window.available_monitors().nth(i))
+                .or_else(|| self.window.current_monitor());
+            Fullscreen::Borderless(monitor)
+        });
+        self.window.set_fullscreen(fullscreen);
+
+        if self.input.focused {
+            self.grab_cursor(true);
+        }
+    }
+
+    /// Shortest time between frames, from the frame rate cap in the display settings or the low power frame rate;
+    /// `None` renders as fast as possible. The event loop waits out the rest of each interval instead of polling.
+    pub fn frame_interval(&self) -> Option<Duration> {
+        let cap = self.settings.display.fps_cap.filter(|&fps| fps > 0);
+        let fps = if self.low_power() { Some(cap.map_or(LOW_POWER_FPS, |cap| cap.min(LOW_POWER_FPS))) } else { cap };
+        fps.map(|fps| Duration::from_secs_f64(1.0 / fps as f64))
+    }
+
+    /// Whether the window is unfocused in low power mode, which lowers the frame rate and pauses scanning.
+    pub fn low_power(&self) -> bool {
+        self.settings.display.low_power && !self.input.focused
+    }
+}
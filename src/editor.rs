@@ -0,0 +1,128 @@
+use super::State;
+use glam::Vec2;
+
+const DEFAULT_BRUSH_RADIUS: f32 = 12.0;
+pub const MIN_BRUSH_RADIUS: f32 = 5.0;
+pub const MAX_BRUSH_RADIUS: f32 = 60.0;
+/// Density added or removed per second at the brush center.
+const DEFAULT_BRUSH_STRENGTH: f64 = 0.5;
+pub const MIN_BRUSH_STRENGTH: f64 = 0.05;
+pub const MAX_BRUSH_STRENGTH: f64 = 5.0;
+/// Farthest surface the brush reaches.
+const BRUSH_RANGE: f32 = 300.0;
+/// Voxel sizes the resolution key cycles through.
+const VOXEL_SIZES: [f32; 3] = [2.5, 5.0, 10.0];
+/// Detail band switched on by the detail key when the settings leave it off.
+const DEFAULT_DETAIL_BAND: f32 = 40.0;
+
+/// Terrain editing mode: instead of spawning marks, the scanner button digs density away where the view center hits
+/// the terrain and the right button builds it up.
+pub struct Editor {
+    pub active: bool,
+    pub radius: f32,
+    /// Density change per second at the brush center.
+    pub strength: f64,
+    pub digging: bool,
+    pub building: bool,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            radius: DEFAULT_BRUSH_RADIUS,
+            strength: DEFAULT_BRUSH_STRENGTH,
+            digging: false,
+            building: false,
+        }
+    }
+
+    pub fn scale_radius(&mut self, factor: f32) -> f32 {
+        self.radius = (self.radius * factor).clamp(MIN_BRUSH_RADIUS, MAX_BRUSH_RADIUS);
+        self.radius
+    }
+
+    pub fn scale_strength(&mut self, factor: f64) -> f64 {
+        self.strength = (self.strength * factor).clamp(MIN_BRUSH_STRENGTH, MAX_BRUSH_STRENGTH);
+        self.strength
+    }
+}
+
+impl State {
+    pub fn toggle_editor(&mut self) {
+        self.editor.active = !self.editor.active;
+        self.editor.building = false;
+        self.notify(format!("edit mode: {}", if self.editor.active { "on" } else { "off" }));
+    }
+
+    /// Re-meshes the terrain with the next voxel size preset, keeping its seed and edits.
+    pub fn cycle_voxel_size(&mut self) {
+        let Some((size, band)) = self.world.resolution() else {
+            self.notify("terrain has no voxel resolution".to_string());
+            return;
+        };
+        let next = VOXEL_SIZES.iter().copied().find(|preset| *preset > size).unwrap_or(VOXEL_SIZES[0]);
+        self.set_resolution(next, band);
+    }
+
+    /// Switches the finer lattice near the camera on or off.
+    pub fn toggle_detail_band(&mut self) {
+        let Some((size, band)) = self.world.resolution() else {
+            self.notify("terrain has no voxel resolution".to_string());
+            return;
+        };
+        let configured = self.settings.terrain.detail_band;
+        let band = if band > 0.0 {
+            0.0
+        } else if configured > 0.0 {
+            configured
+        } else {
+            DEFAULT_DETAIL_BAND
+        };
+        self.set_resolution(size, band);
+    }
+
+    fn set_resolution(&mut self, voxel_size: f32, detail_band: f32) {
+        if let Err(e) = self.world.set_resolution(voxel_size, detail_band) {
+            self.notify(e);
+            return;
+        }
+        // Recorded edits address the old lattice.
+        self.history.clear();
+        self.occlusion.clear();
+        self.notify(format!("voxel size: {} detail: {:.0}", voxel_size, detail_band));
+    }
+
+    /// Applies the brush where the view center meets the terrain, and re-meshes the occluders it touched. Each press of
+    /// a button is recorded as one undoable stroke.
+    pub fn update_editor(&mut self, dt: f64) {
+        let editor = &mut self.editor;
+        editor.digging = editor.active && self.marker.should_cast && !self.museum.active;
+        if !editor.digging && !editor.building {
+            self.history.end_stroke();
+            return;
+        }
+
+        let ray = self.camera.cast_ray_at(Vec2::ZERO);
+        let Some(hit) = self.world.raycast(ray, BRUSH_RANGE) else {
+            return;
+        };
+        let sign = if editor.building { 1.0 } else { -1.0 };
+        // Centered a little in front of or behind the surface, so the brush works on the side it grows into.
+        let center = hit - ray.dir * sign as f32 * editor.radius * 0.25;
+        let (radius, strength) = (editor.radius, editor.strength);
+        if let Some(edit) = self.world.edit_density(center, radius, sign * strength * dt) {
+            self.occlusion.invalidate(center, radius);
+            self.history.push_density(edit);
+        } else {
+            self.editor = Editor::new();
+            self.notify("terrain cannot be edited".to_string());
+        }
+    }
+}
@@ -0,0 +1,61 @@
+struct EyeDomeParams {
+    near: f32,
+    far: f32,
+    strength: f32,
+    // Distance to the sampled neighbors, in pixels.
+    radius: f32,
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> params: EyeDomeParams;
+
+let N_NEIGHBORS = 8;
+let NEIGHBORS = array<vec2<f32>, 8>(
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(0.7071, 0.7071),
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(-0.7071, 0.7071),
+    vec2<f32>(-1.0, 0.0),
+    vec2<f32>(-0.7071, -0.7071),
+    vec2<f32>(0.0, -1.0),
+    vec2<f32>(0.7071, -0.7071),
+);
+// Scales the summed log-depth differences before the falloff, as in the original formulation.
+let RESPONSE_SCALE = 300.0;
+
+// Log2 of the view distance of the depth at `texel`, with the background as far away as the far plane.
+fn log_depth(texel: vec2<i32>) -> f32 {
+    let size = vec2<i32>(textureDimensions(depth_texture));
+    let depth = textureLoad(depth_texture, clamp(texel, vec2<i32>(0), size - 1), 0);
+    let linear = params.near * params.far / (params.far - depth * (params.far - params.near));
+    return log2(linear);
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Darkens each pixel by how far its neighbors stand in front of it, outlining every depth discontinuity.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let texel = vec2<i32>(position.xy);
+    if (textureLoad(depth_texture, texel, 0) >= 1.0) {
+        discard;
+    }
+
+    let center = log_depth(texel);
+    var response = 0.0;
+    var neighbors = NEIGHBORS;
+    for (var i = 0; i < N_NEIGHBORS; i += 1) {
+        let offset = vec2<i32>(round(neighbors[i] * params.radius));
+        response += max(0.0, center - log_depth(texel + offset));
+    }
+    response /= f32(N_NEIGHBORS);
+
+    let shade = exp(-response * RESPONSE_SCALE * params.strength);
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0 - shade);
+}
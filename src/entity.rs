@@ -0,0 +1,165 @@
+use super::util::Ray;
+use super::State;
+use glam::{vec3, Vec3};
+
+pub type EntityId = u32;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+    Sphere { radius: f32 },
+    Cube { half_size: f32 },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Motion {
+    Static,
+    /// Bobs up and down around `base`.
+    Bob {
+        base: Vec3,
+        amplitude: f32,
+        speed: f32,
+    },
+    /// Circles `center` horizontally.
+    Orbit {
+        center: Vec3,
+        radius: f32,
+        speed: f32,
+    },
+}
+
+/// A dynamic object in the world. Like the terrain it is invisible until scanned.
+pub struct Entity {
+    pub id: EntityId,
+    pub pos: Vec3,
+    pub shape: Shape,
+    pub motion: Motion,
+}
+
+impl Entity {
+    /// Distance along `ray` to the entity's surface, if the ray hits it within `max_t`.
+    fn raycast(&self, ray: Ray, max_t: f32) -> Option<f32> {
+        let t = match self.shape {
+            Shape::Sphere { radius } => {
+                let to_center = self.pos - ray.pos;
+                let along = to_center.dot(ray.dir);
+                let closest_sq = to_center.length_squared() - along * along;
+                let half_chord_sq = radius * radius - closest_sq;
+                if half_chord_sq < 0.0 {
+                    return None;
+                }
+                let half_chord = half_chord_sq.sqrt();
+                if along - half_chord >= 0.0 {
+                    along - half_chord
+                } else {
+                    along + half_chord
+                }
+            }
+            Shape::Cube { half_size } => {
+                let inv_dir = 1.0 / ray.dir;
+                let t1 = (self.pos - half_size - ray.pos) * inv_dir;
+                let t2 = (self.pos + half_size - ray.pos) * inv_dir;
+                let (t_near, t_far) = (t1.min(t2).max_element(), t1.max(t2).min_element());
+                if t_near > t_far {
+                    return None;
+                }
+                if t_near >= 0.0 {
+                    t_near
+                } else {
+                    t_far
+                }
+            }
+        };
+        (0.0..=max_t).contains(&t).then_some(t)
+    }
+}
+
+/// Entities in the world. Marks scanned on them are attached to them and kept in the octree's dynamic bucket.
+pub struct Entities {
+    pub list: Vec<Entity>,
+    next_id: EntityId,
+    time: f64,
+}
+
+impl Default for Entities {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self { list: Vec::new(), next_id: 0, time: 0.0 }
+    }
+
+    /// A floating beacon and a circling hazard near `origin`.
+    pub fn demo(origin: Vec3) -> Self {
+        let mut entities = Self::new();
+        entities.spawn(
+            Shape::Sphere { radius: 3.0 },
+            Motion::Bob { base: origin + vec3(0.0, 0.0, -25.0), amplitude: 2.0, speed: 0.5 },
+        );
+        entities.spawn(Shape::Cube { half_size: 2.0 }, Motion::Orbit { center: origin, radius: 15.0, speed: 0.3 });
+        entities
+    }
+
+    pub fn spawn(&mut self, shape: Shape, motion: Motion) -> EntityId {
+        let id = self.next_id;
+        self.next_id += 1;
+        let pos = match motion {
+            Motion::Static => Vec3::ZERO,
+            Motion::Bob { base, .. } => base,
+            Motion::Orbit { center, radius, .. } => center + vec3(radius, 0.0, 0.0),
+        };
+        self.list.push(Entity { id, pos, shape, motion });
+        id
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        for entity in &mut self.list {
+            entity.pos -= shift;
+            match &mut entity.motion {
+                Motion::Static => {}
+                Motion::Bob { base, .. } => *base -= shift,
+                Motion::Orbit { center, .. } => *center -= shift,
+            }
+        }
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.list.iter().find(|entity| entity.id == id)
+    }
+
+    /// Advances every entity along its motion.
+    pub fn update(&mut self, dt: f64) {
+        self.time += dt;
+        let t = self.time as f32;
+        for entity in &mut self.list {
+            match entity.motion {
+                Motion::Static => {}
+                Motion::Bob { base, amplitude, speed } => {
+                    entity.pos = base + Vec3::Y * amplitude * f32::sin(t * speed * std::f32::consts::TAU)
+                }
+                Motion::Orbit { center, radius, speed } => {
+                    let angle = t * speed * std::f32::consts::TAU;
+                    entity.pos = center + vec3(f32::cos(angle), 0.0, f32::sin(angle)) * radius;
+                }
+            }
+        }
+    }
+
+    /// Nearest entity hit by `ray` within `max_t`, with the hit position.
+    pub fn raycast(&self, ray: Ray, max_t: f32) -> Option<(EntityId, Vec3)> {
+        self.list
+            .iter()
+            .filter_map(|entity| entity.raycast(ray, max_t).map(|t| (entity.id, t)))
+            .min_by(|a, b| f32::total_cmp(&a.1, &b.1))
+            .map(|(id, t)| (id, ray.pos + ray.dir * t))
+    }
+}
+
+impl State {
+    pub fn update_entities(&mut self, dt: f64) {
+        self.entities.update(dt);
+    }
+}
@@ -0,0 +1,432 @@
+use super::marker::{octree::Octree, MarkRaw, MarkTag};
+use super::util::Triangle;
+use super::world::Terrain;
+use super::State;
+use glam::{DVec3, Vec3};
+use std::collections::HashMap;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+pub(crate) const EXPORT_DIR: &str = "saves";
+
+/// Distance around the camera whose terrain is exported as glTF.
+const TERRAIN_EXPORT_RADIUS: f32 = 150.0;
+/// Step of the central differences that give vertex normals.
+const NORMAL_STEP: f32 = 0.5;
+/// Vertices closer than this are welded, so neighbouring voxels share their edge vertices.
+const WELD_PRECISION: f32 = 1e-3;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_JSON_CHUNK: &[u8; 4] = b"JSON";
+const GLB_BIN_CHUNK: &[u8; 4] = b"BIN\0";
+/// glTF component type and buffer view target codes.
+const GL_FLOAT: u32 = 5126;
+const GL_UNSIGNED_INT: u32 = 5125;
+const GL_ARRAY_BUFFER: u32 = 34962;
+const GL_ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+const LAS_HEADER_SIZE: u16 = 375;
+/// Point data record format 7: position, intensity, returns, classification, GPS time and RGB.
+const LAS_POINT_FORMAT: u8 = 7;
+const LAS_POINT_SIZE: u16 = 36;
+/// Global encoding bit that LAS 1.4 requires for point formats 6 and up.
+const LAS_WKT_BIT: u16 = 1 << 4;
+/// Resolution of the stored coordinates, in world units.
+const LAS_SCALE: f64 = 0.001;
+
+/// ASPRS classes marks are exported as.
+const CLASS_UNCLASSIFIED: u8 = 1;
+const CLASS_LOW_NOISE: u8 = 7;
+/// User-definable classes, from 64 on in LAS 1.4.
+const CLASS_SURFEL: u8 = 64;
+const CLASS_DYNAMIC: u8 = 65;
+
+/// A mark described with the attributes lidar formats store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointAttributes {
+    /// Position with Z up, as GIS tools expect.
+    pub pos: DVec3,
+    /// Brightness of the mark's color.
+    pub intensity: u16,
+    pub classification: u8,
+    pub color: [u16; 3],
+}
+
+impl PointAttributes {
+    /// Maps a mark to lidar attributes: its position relative to the local `origin` to world coordinates in a
+    /// right-handed Z-up frame, the color's luminance to intensity and the tag to a classification. Misses become
+    /// noise, so tools hide them by default.
+    pub fn from_mark(mark: &MarkRaw, origin: DVec3) -> Self {
+        let pos = origin + Vec3::from(mark.pos).as_dvec3();
+        let color = mark.color();
+        let luminance = color.dot(Vec3::new(0.2126, 0.7152, 0.0722)).clamp(0.0, 1.0);
+        let classification = match mark.tag() {
+            MarkTag::Surface | MarkTag::Painted => CLASS_UNCLASSIFIED,
+            MarkTag::Miss => CLASS_LOW_NOISE,
+            MarkTag::Surfel(_) | MarkTag::PaintedSurfel(_) => CLASS_SURFEL,
+            MarkTag::Dynamic => CLASS_DYNAMIC,
+        };
+        Self {
+            pos: z_up(pos),
+            intensity: (luminance * u16::MAX as f32).round() as u16,
+            classification,
+            color: mark.color.map(|channel| channel as u16 * 257)[..3].try_into().unwrap(),
+        }
+    }
+}
+
+/// `pos` in the right-handed Z-up frame GIS tools expect.
+fn z_up(pos: DVec3) -> DVec3 {
+    DVec3::new(pos.x, -pos.z, pos.y)
+}
+
+/// Writes `points`, relative to the local `origin`, to `path` as a single Wavefront OBJ polyline in the same frame
+/// as [`PointAttributes`], so it lines up with the point cloud.
+pub fn write_polyline(points: &[Vec3], origin: DVec3, path: &Path) -> Result<(), String> {
+    let mut file = BufWriter::new(std::fs::File::create(path).map_err(|e| e.to_string())?);
+    for pos in points {
+        let pos = z_up(origin + pos.as_dvec3());
+        writeln!(file, "v {:.3} {:.3} {:.3}", pos.x, pos.y, pos.z).map_err(|e| e.to_string())?;
+    }
+    if points.len() >= 2 {
+        let indices: Vec<String> = (1..=points.len()).map(|i| i.to_string()).collect();
+        writeln!(file, "l {}", indices.join(" ")).map_err(|e| e.to_string())?;
+    }
+    file.flush().map_err(|e| e.to_string())
+}
+
+/// Writes `points` to `path` as an uncompressed LAS 1.4 file with point format 7 and millimeter resolution.
+pub fn write_las(points: &[PointAttributes], path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let (min, max) =
+        points.iter().fold((DVec3::splat(f64::INFINITY), DVec3::splat(f64::NEG_INFINITY)), |(min, max), point| {
+            (min.min(point.pos), max.max(point.pos))
+        });
+    let (min, max) = if points.is_empty() { (DVec3::ZERO, DVec3::ZERO) } else { (min, max) };
+    let offset = min.floor();
+
+    let mut header = Vec::with_capacity(LAS_HEADER_SIZE as usize);
+    header.extend_from_slice(b"LASF");
+    header.extend_from_slice(&0u16.to_le_bytes());
+    header.extend_from_slice(&LAS_WKT_BIT.to_le_bytes());
+    header.extend_from_slice(&[0; 16]);
+    header.extend_from_slice(&[1, 4]);
+    header.extend_from_slice(&fixed_string::<32>("OTHER"));
+    header.extend_from_slice(&fixed_string::<32>(concat!("scanner ", env!("CARGO_PKG_VERSION"))));
+    let (year, day) = creation_date();
+    header.extend_from_slice(&day.to_le_bytes());
+    header.extend_from_slice(&year.to_le_bytes());
+    header.extend_from_slice(&LAS_HEADER_SIZE.to_le_bytes());
+    header.extend_from_slice(&(LAS_HEADER_SIZE as u32).to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.push(LAS_POINT_FORMAT);
+    header.extend_from_slice(&LAS_POINT_SIZE.to_le_bytes());
+    // Legacy point counts, left zero as formats above 5 require.
+    header.extend_from_slice(&[0; 4 + 5 * 4]);
+    for value in [LAS_SCALE; 3].into_iter().chain(offset.to_array()) {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [max.x, min.x, max.y, min.y, max.z, min.z] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+    // No waveform data or extended VLRs.
+    header.extend_from_slice(&[0; 8 + 8 + 4]);
+    header.extend_from_slice(&(points.len() as u64).to_le_bytes());
+    // Every point is the first and only return of its pulse.
+    header.extend_from_slice(&(points.len() as u64).to_le_bytes());
+    header.extend_from_slice(&[0; 14 * 8]);
+    debug_assert_eq!(header.len(), LAS_HEADER_SIZE as usize);
+
+    let mut file = BufWriter::new(std::fs::File::create(path).map_err(|e| e.to_string())?);
+    file.write_all(&header).map_err(|e| e.to_string())?;
+
+    let mut record = Vec::with_capacity(LAS_POINT_SIZE as usize);
+    for point in points {
+        record.clear();
+        let coords = ((point.pos - offset) / LAS_SCALE).round();
+        for coord in coords.to_array() {
+            record.extend_from_slice(&(coord as i32).to_le_bytes());
+        }
+        record.extend_from_slice(&point.intensity.to_le_bytes());
+        // Return 1 of 1, no flags, scanner channel 0.
+        record.extend_from_slice(&[0x11, 0]);
+        record.push(point.classification);
+        // User data, scan angle, point source id and GPS time.
+        record.extend_from_slice(&[0; 1 + 2 + 2 + 8]);
+        for channel in point.color {
+            record.extend_from_slice(&channel.to_le_bytes());
+        }
+        file.write_all(&record).map_err(|e| e.to_string())?;
+    }
+    file.flush().map_err(|e| e.to_string())
+}
+
+/// `text` as a NUL-padded field of `N` bytes, truncated if longer.
+fn fixed_string<const N: usize>(text: &str) -> [u8; N] {
+    let mut field = [0; N];
+    let len = usize::min(text.len(), N);
+    field[..len].copy_from_slice(&text.as_bytes()[..len]);
+    field
+}
+
+/// Today's year and day of the year, starting at 1, in UTC.
+fn creation_date() -> (u16, u16) {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let mut days = now.as_secs() / 86400;
+    let mut year = 1970;
+    loop {
+        let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+        let length = if leap { 366 } else { 365 };
+        if days < length {
+            return (year as u16, days as u16 + 1);
+        }
+        days -= length;
+        year += 1;
+    }
+}
+
+/// Every mark in `octree`, positioned relative to the local `origin`, as lidar attributes.
+pub fn octree_points(octree: &Octree, origin: DVec3) -> Vec<PointAttributes> {
+    octree.marks().map(|mark| PointAttributes::from_mark(mark, origin)).collect()
+}
+
+/// An indexed triangle mesh with one normal per vertex.
+#[derive(Debug, Default)]
+pub struct TerrainMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub indices: Vec<u32>,
+}
+
+impl TerrainMesh {
+    /// Meshes the surface of `terrain` within `radius` of `center`. Vertices shared by neighbouring triangles are
+    /// welded and get their normal from the density gradient, so the mesh shades smoothly; triangles are wound
+    /// counter-clockwise seen from outside the terrain.
+    pub fn build(terrain: &mut dyn Terrain, center: Vec3, radius: f32) -> Self {
+        let mut mesh = Self::default();
+        let mut welded: HashMap<[i32; 3], u32> = HashMap::new();
+        for Triangle { a, b, c } in terrain.retrieve_triangles(center, radius) {
+            let mut corners = [a, b, c].map(|pos| {
+                let key = (pos / WELD_PRECISION).round().as_ivec3().to_array();
+                *welded.entry(key).or_insert_with(|| {
+                    mesh.positions.push(pos);
+                    mesh.normals.push(surface_normal(terrain, pos));
+                    mesh.positions.len() as u32 - 1
+                })
+            });
+
+            // Winding is checked on the welded corners, which can differ slightly from the originals.
+            let [a, b, c] = corners.map(|index| mesh.positions[index as usize]);
+            let face = Vec3::cross(b - a, c - a);
+            if face.length_squared() == 0.0 {
+                continue;
+            }
+            let outward: Vec3 = corners.iter().map(|index| mesh.normals[*index as usize]).sum();
+            if face.dot(outward) < 0.0 {
+                corners.swap(1, 2);
+            }
+            mesh.indices.extend(corners);
+        }
+        mesh
+    }
+}
+
+/// Unit vector pointing out of the terrain at `pos`, against the density gradient.
+fn surface_normal(terrain: &dyn Terrain, pos: Vec3) -> Vec3 {
+    let density = |offset: Vec3| terrain.surface_level(pos + offset * NORMAL_STEP) as f32;
+    let gradient = Vec3::new(
+        density(Vec3::X) - density(-Vec3::X),
+        density(Vec3::Y) - density(-Vec3::Y),
+        density(Vec3::Z) - density(-Vec3::Z),
+    );
+    (-gradient).normalize_or_zero()
+}
+
+/// Writes `mesh` to `path` as a binary glTF 2.0 file with a single mesh node.
+pub fn write_glb(mesh: &TerrainMesh, path: &Path) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let mut bin: Vec<u8> = Vec::new();
+    for vector in mesh.positions.iter().chain(&mesh.normals) {
+        bin.extend_from_slice(bytemuck::cast_slice(&vector.to_array()));
+    }
+    bin.extend_from_slice(bytemuck::cast_slice(&mesh.indices));
+    let vertex_bytes = mesh.positions.len() * std::mem::size_of::<Vec3>();
+    let index_bytes = mesh.indices.len() * std::mem::size_of::<u32>();
+
+    let (min, max) =
+        mesh.positions.iter().fold((Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)), |(min, max), pos| {
+            (min.min(*pos), max.max(*pos))
+        });
+    let (min, max) = if mesh.positions.is_empty() { (Vec3::ZERO, Vec3::ZERO) } else { (min, max) };
+
+    let json = serde_json::json!({
+        "asset": { "version": "2.0", "generator": concat!("scanner ", env!("CARGO_PKG_VERSION")) },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0, "name": "terrain" }],
+        "meshes": [{
+            "name": "terrain",
+            "primitives": [{ "attributes": { "POSITION": 0, "NORMAL": 1 }, "indices": 2 }],
+        }],
+        "accessors": [
+            {
+                "bufferView": 0,
+                "componentType": GL_FLOAT,
+                "count": mesh.positions.len(),
+                "type": "VEC3",
+                "min": min.to_array(),
+                "max": max.to_array(),
+            },
+            { "bufferView": 1, "componentType": GL_FLOAT, "count": mesh.normals.len(), "type": "VEC3" },
+            { "bufferView": 2, "componentType": GL_UNSIGNED_INT, "count": mesh.indices.len(), "type": "SCALAR" },
+        ],
+        "bufferViews": [
+            { "buffer": 0, "byteOffset": 0, "byteLength": vertex_bytes, "target": GL_ARRAY_BUFFER },
+            { "buffer": 0, "byteOffset": vertex_bytes, "byteLength": vertex_bytes, "target": GL_ARRAY_BUFFER },
+            {
+                "buffer": 0,
+                "byteOffset": 2 * vertex_bytes,
+                "byteLength": index_bytes,
+                "target": GL_ELEMENT_ARRAY_BUFFER,
+            },
+        ],
+        "buffers": [{ "byteLength": bin.len() }],
+    });
+
+    // Both chunks are padded to 4 bytes, the JSON one with spaces as the spec requires.
+    let mut json = serde_json::to_vec(&json).map_err(|e| e.to_string())?;
+    json.resize(json.len().next_multiple_of(4), b' ');
+    bin.resize(bin.len().next_multiple_of(4), 0);
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+
+    let mut file = BufWriter::new(std::fs::File::create(path).map_err(|e| e.to_string())?);
+    for bytes in [
+        &GLB_MAGIC[..],
+        &2u32.to_le_bytes(),
+        &(total as u32).to_le_bytes(),
+        &(json.len() as u32).to_le_bytes(),
+        GLB_JSON_CHUNK,
+        &json,
+        &(bin.len() as u32).to_le_bytes(),
+        GLB_BIN_CHUNK,
+        &bin,
+    ] {
+        file.write_all(bytes).map_err(|e| e.to_string())?;
+    }
+    file.flush().map_err(|e| e.to_string())
+}
+
+impl State {
+    /// Writes the terrain within [`TERRAIN_EXPORT_RADIUS`] of the camera to a new timestamped glTF file. The selection
+    /// does not apply to it.
+    pub fn export_terrain(&mut self) {
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let path = Path::new(EXPORT_DIR).join(format!("terrain-{}.glb", timestamp.as_millis()));
+        let mesh = TerrainMesh::build(&mut *self.world, self.camera.pos, TERRAIN_EXPORT_RADIUS);
+        match write_glb(&mesh, &path) {
+            Ok(()) => self.notify(format!("exported {} triangles to {}", mesh.indices.len() / 3, path.display())),
+            Err(e) => self.notify(format!("terrain export failed: {}", e)),
+        }
+    }
+
+    /// Writes the scan, or only the selected marks if a selection is set, to a new timestamped LAS file in the save
+    /// directory, with the whole camera trail next to it as an OBJ polyline.
+    pub fn export_las(&mut self) {
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let path = Path::new(EXPORT_DIR).join(format!("scan-{}.las", timestamp.as_millis()));
+        let points = match self.selection.bounds() {
+            Some((min, max)) => {
+                let marks = self.marker.octree.marks_in(min, max);
+                marks.iter().map(|mark| PointAttributes::from_mark(mark, self.origin())).collect()
+            }
+            None => octree_points(&self.marker.octree, self.origin()),
+        };
+        if let Err(e) = write_las(&points, &path) {
+            self.notify(format!("las export failed: {}", e));
+            return;
+        }
+        let trail: Vec<Vec3> = self.trail.polyline().collect();
+        match write_polyline(&trail, self.origin(), &path.with_extension("trail.obj")) {
+            Ok(()) if self.selection.bounds().is_some() => {
+                self.notify(format!("exported {} selected points to {}", points.len(), path.display()))
+            }
+            Ok(()) => self.notify(format!("exported {} points to {}", points.len(), path.display())),
+            Err(e) => self.notify(format!("trail export failed: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_glb, write_las, PointAttributes, TerrainMesh, CLASS_LOW_NOISE, LAS_HEADER_SIZE, LAS_POINT_SIZE};
+    use crate::marker::Mark;
+    use crate::world::World;
+    use glam::{dvec3, vec3, DVec3, Vec3};
+
+    #[test]
+    fn terrain_mesh_is_welded_outward_facing_and_written_as_glb() {
+        let mut world = World::new();
+        let mesh = TerrainMesh::build(&mut world, crate::camera::SPAWN_POS, 40.0);
+        assert!(!mesh.indices.is_empty());
+        assert!(mesh.indices.len() / 3 > mesh.positions.len() / 2, "vertices were not shared");
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.positions[triangle[i] as usize]);
+            let normal: Vec3 = triangle.iter().map(|i| mesh.normals[*i as usize]).sum();
+            assert!(Vec3::cross(b - a, c - a).dot(normal) >= 0.0);
+        }
+
+        let path = std::env::temp_dir().join(format!("scanner-terrain-test-{}.glb", std::process::id()));
+        write_glb(&mesh, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        assert_eq!((&bytes[0..4], u32_at(4), u32_at(8)), (&b"glTF"[..], 2, bytes.len()));
+        let json_len = u32_at(12);
+        let json: serde_json::Value = serde_json::from_slice(&bytes[20..20 + json_len]).unwrap();
+        assert_eq!(json["accessors"][0]["count"], mesh.positions.len());
+        assert_eq!(json["accessors"][2]["count"], mesh.indices.len());
+        let bin_len = u32_at(20 + json_len);
+        assert_eq!(&bytes[24 + json_len..28 + json_len], b"BIN\0");
+        assert!(bin_len >= json["buffers"][0]["byteLength"].as_u64().unwrap() as usize);
+    }
+
+    #[test]
+    fn las_file_has_the_header_and_records_lidar_tools_expect() {
+        let marks = [Mark::new(vec3(1.0, 2.0, 3.0), vec3(1.0, 1.0, 1.0)), Mark::miss(vec3(-4.5, 0.25, 8.0))];
+        let points: Vec<PointAttributes> =
+            marks.map(|mark| PointAttributes::from_mark(&mark.to_raw(), DVec3::ZERO)).to_vec();
+        assert_eq!(points[0].pos, dvec3(1.0, -3.0, 2.0));
+        assert_eq!((points[0].intensity, points[0].color), (u16::MAX, [u16::MAX; 3]));
+        assert_eq!(points[1].classification, CLASS_LOW_NOISE);
+
+        let path = std::env::temp_dir().join(format!("scanner-export-test-{}.las", std::process::id()));
+        write_las(&points, &path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let u16_at = |at: usize| u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap());
+        let i32_at = |at: usize| i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let f64_at = |at: usize| f64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        assert_eq!(bytes.len(), LAS_HEADER_SIZE as usize + 2 * LAS_POINT_SIZE as usize);
+        assert_eq!((&bytes[0..4], bytes[24], bytes[25]), (&b"LASF"[..], 1, 4));
+        assert_eq!((u16_at(94), bytes[104], u16_at(105)), (LAS_HEADER_SIZE, 7, LAS_POINT_SIZE));
+        assert_eq!((f64_at(179), f64_at(187)), (1.0, -4.5));
+        assert_eq!(u64_at(247), 2);
+
+        // The second point, decoded with the header's scale and offset.
+        let record = LAS_HEADER_SIZE as usize + LAS_POINT_SIZE as usize;
+        let scale = f64_at(131);
+        let offset = dvec3(f64_at(155), f64_at(163), f64_at(171));
+        let pos = dvec3(i32_at(record) as f64, i32_at(record + 4) as f64, i32_at(record + 8) as f64) * scale + offset;
+        assert!(pos.abs_diff_eq(dvec3(-4.5, -8.0, 0.25), 1e-6));
+        assert_eq!(bytes[record + 16], CLASS_LOW_NOISE);
+    }
+}
@@ -0,0 +1,284 @@
+use super::lines::LineRenderer;
+use super::marker::Marker;
+use super::world::SURFACE_THRESHOLD;
+use super::State;
+use glam::{vec3, vec4, Vec3, Vec4};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+const MAX_ENERGY: f32 = 100.0;
+/// Energy used per second of scanning.
+const DRAIN_RATE: f32 = 20.0;
+/// Energy regained per second once the scanner has been idle for `RECHARGE_DELAY`.
+const RECHARGE_RATE: f32 = 30.0;
+const RECHARGE_DELAY: f64 = 0.5;
+/// Energy needed before a drained scanner fires again, so it does not stutter at empty.
+const RESUME_ENERGY: f32 = 25.0;
+
+const BASE_RANGE: f32 = 150.0;
+const RANGE_BOOST: f32 = 50.0;
+const MAX_RANGE: f32 = 1000.0;
+
+/// Heat gained per second of scanning at `HEAT_REFERENCE_RATE` rays per second; faster scanning heats up faster.
+const HEAT_RATE: f32 = 0.15;
+const HEAT_REFERENCE_RATE: f64 = 2000.0;
+/// Heat lost per second while the scanner is idle.
+const HEAT_COOLING: f32 = 0.25;
+/// Heat an overheated scanner has to cool down to before it fires again.
+const RESUME_HEAT: f32 = 0.25;
+/// Extra width of the scanner cone at full heat, as a fraction of its width.
+const MAX_HEAT_JITTER: f32 = 1.5;
+
+/// Pickups are scattered one per cell of this size, where the terrain leaves room.
+const PICKUP_SPACING: f32 = 120.0;
+const PICKUP_RADIUS: f32 = 8.0;
+const PICKUP_SIZE: f32 = 3.0;
+/// Pickups within this distance of the camera are drawn and can be collected.
+const PICKUP_VIEW_RANGE: f32 = 300.0;
+const PICKUP_COLOR: Vec4 = vec4(1.0, 0.8, 0.2, 0.9);
+
+type Cell = (i32, i32, i32);
+
+/// Optional overdrive limit on the scanner: sustained scanning builds heat, which widens the cone and eventually
+/// forces a pause until the scanner cools down. Independent of the rest of the game layer.
+#[derive(Clone, Debug, Default)]
+pub struct Heat {
+    pub enabled: bool,
+    /// In `[0, 1]`; the scanner overheats at 1.
+    pub level: f32,
+    overheated: bool,
+}
+
+impl Heat {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, ..Self::default() }
+    }
+
+    pub fn overheated(&self) -> bool {
+        self.enabled && self.overheated
+    }
+
+    /// Factor the scanner cone is widened by at the current heat.
+    pub fn jitter_scale(&self) -> f32 {
+        if self.enabled {
+            1.0 + MAX_HEAT_JITTER * self.level * self.level
+        } else {
+            1.0
+        }
+    }
+
+    /// Heats the scanner up while it fires `rate` rays per second and cools it down otherwise. Returns whether it
+    /// just overheated.
+    fn update(&mut self, scanning: bool, rate: f64, dt: f64) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        if scanning && !self.overheated {
+            self.level += HEAT_RATE * (rate / HEAT_REFERENCE_RATE) as f32 * dt as f32;
+        } else {
+            self.level -= HEAT_COOLING * dt as f32;
+        }
+        self.level = self.level.clamp(0.0, 1.0);
+        let was_overheated = self.overheated;
+        if self.level >= 1.0 {
+            self.overheated = true;
+        } else if self.level <= RESUME_HEAT {
+            self.overheated = false;
+        }
+        self.overheated && !was_overheated
+    }
+}
+
+/// Optional game layer over the scanner: range is limited and grows with collected pickups, and scanning drains an
+/// energy meter that recharges while idle.
+pub struct Gameplay {
+    pub enabled: bool,
+    pub energy: f32,
+    pub range: f32,
+    depleted: bool,
+    idle_time: f64,
+    pub heat: Heat,
+    /// Pickup position of every cell looked at so far, `None` where the terrain fills the spot.
+    pickups: HashMap<Cell, Option<Vec3>>,
+    collected: HashSet<Cell>,
+    /// Cell of the local origin; cells are counted from the world origin so pickups survive a rebase.
+    origin_cell: Cell,
+    lines: LineRenderer,
+    time: f64,
+}
+
+impl Gameplay {
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        let cells = (shift / PICKUP_SPACING).round();
+        let origin = self.origin_cell;
+        self.origin_cell = (origin.0 + cells.x as i32, origin.1 + cells.y as i32, origin.2 + cells.z as i32);
+        for pickup in self.pickups.values_mut().flatten() {
+            *pickup -= shift;
+        }
+    }
+
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker, enabled: bool, heat: bool) -> Self {
+        Self {
+            enabled,
+            energy: MAX_ENERGY,
+            range: BASE_RANGE,
+            depleted: false,
+            idle_time: 0.0,
+            heat: Heat::new(heat),
+            pickups: HashMap::new(),
+            collected: HashSet::new(),
+            origin_cell: (0, 0, 0),
+            lines: LineRenderer::new(device, format, &marker.camera_bind_group_layout),
+            time: 0.0,
+        }
+    }
+
+    /// Rebuilds the pickup renderer on a new device, keeping the game state.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) {
+        self.lines = LineRenderer::new(device, format, &marker.camera_bind_group_layout);
+    }
+
+    /// Whether the scanner may fire this frame.
+    pub fn can_scan(&self) -> bool {
+        (!self.enabled || !self.depleted) && !self.heat.overheated()
+    }
+
+    /// Maximum scan distance to pass to [`Terrain::raycast`](super::world::Terrain::raycast), `-1` when unlimited.
+    pub fn scan_range(&self) -> f32 {
+        if self.enabled {
+            self.range
+        } else {
+            -1.0
+        }
+    }
+
+    pub fn energy_fraction(&self) -> f32 {
+        self.energy / MAX_ENERGY
+    }
+}
+
+fn pickup_cell(pos: Vec3) -> Cell {
+    let cell = (pos / PICKUP_SPACING).floor();
+    (cell.x as i32, cell.y as i32, cell.z as i32)
+}
+
+impl State {
+    pub fn toggle_gameplay(&mut self) {
+        self.gameplay.enabled = !self.gameplay.enabled;
+        self.notify(format!("gameplay: {}", if self.gameplay.enabled { "on" } else { "off" }));
+    }
+
+    pub fn toggle_heat(&mut self) {
+        self.gameplay.heat = Heat::new(!self.gameplay.heat.enabled);
+        self.notify(format!("scanner heat: {}", if self.gameplay.heat.enabled { "on" } else { "off" }));
+    }
+
+    /// Heats or cools the scanner, drains or recharges energy, collects pickups the camera touches and queues the
+    /// nearby pickups for drawing.
+    pub fn update_gameplay(&mut self, dt: f64) {
+        let scanning = self.marker.should_cast && !self.museum.active && !self.editor.active;
+        if self.gameplay.heat.update(scanning, self.marker.scan_rate(), dt) {
+            self.notify("scanner overheated".to_string());
+        }
+
+        if !self.gameplay.enabled {
+            self.gameplay.lines.upload(&self.queue);
+            return;
+        }
+
+        let gameplay = &mut self.gameplay;
+        gameplay.time += dt;
+        if self.marker.should_cast && !gameplay.depleted {
+            gameplay.idle_time = 0.0;
+            gameplay.energy = f32::max(gameplay.energy - DRAIN_RATE * dt as f32, 0.0);
+            gameplay.depleted = gameplay.energy <= 0.0;
+        } else {
+            gameplay.idle_time += dt;
+            if gameplay.idle_time >= RECHARGE_DELAY {
+                gameplay.energy = f32::min(gameplay.energy + RECHARGE_RATE * dt as f32, MAX_ENERGY);
+            }
+            if gameplay.depleted && gameplay.energy >= RESUME_ENERGY {
+                gameplay.depleted = false;
+            }
+        }
+
+        let pos = self.camera.pos;
+        let local = pickup_cell(pos);
+        let origin = gameplay.origin_cell;
+        let base = (local.0 + origin.0, local.1 + origin.1, local.2 + origin.2);
+        let reach = (PICKUP_VIEW_RANGE / PICKUP_SPACING).ceil() as i32;
+        let mut collected = 0;
+        for (x, y, z) in itertools::iproduct!(-reach..=reach, -reach..=reach, -reach..=reach) {
+            let cell = (base.0 + x, base.1 + y, base.2 + z);
+            if gameplay.collected.contains(&cell) {
+                continue;
+            }
+
+            let world = &self.world;
+            let pickup = *gameplay.pickups.entry(cell).or_insert_with(|| {
+                let seed = (cell.0 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                    ^ (cell.1 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+                    ^ (cell.2 as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+                let mut rng = StdRng::seed_from_u64(seed);
+                let offset = vec3(rng.gen(), rng.gen(), rng.gen()) * PICKUP_SPACING;
+                let local = vec3((cell.0 - origin.0) as f32, (cell.1 - origin.1) as f32, (cell.2 - origin.2) as f32);
+                let pickup = local * PICKUP_SPACING + offset;
+                (world.surface_level(pickup) < SURFACE_THRESHOLD).then_some(pickup)
+            });
+            let Some(pickup) = pickup else {
+                continue;
+            };
+
+            let dist = pickup.distance(pos);
+            if dist <= PICKUP_RADIUS {
+                gameplay.collected.insert(cell);
+                gameplay.range = f32::min(gameplay.range + RANGE_BOOST, MAX_RANGE);
+                collected += 1;
+            } else if dist <= PICKUP_VIEW_RANGE {
+                let size = PICKUP_SIZE * (1.0 + 0.2 * f32::sin(gameplay.time as f32 * 4.0));
+                let tips = [Vec3::X, Vec3::NEG_X, Vec3::Z, Vec3::NEG_Z].map(|dir| pickup + dir * size);
+                for (i, tip) in tips.iter().enumerate() {
+                    gameplay.lines.push(pickup + Vec3::Y * size, *tip, PICKUP_COLOR);
+                    gameplay.lines.push(pickup - Vec3::Y * size, *tip, PICKUP_COLOR);
+                    gameplay.lines.push(*tip, tips[[2, 3, 1, 0][i]], PICKUP_COLOR);
+                }
+            }
+        }
+        gameplay.lines.upload(&self.queue);
+
+        if collected > 0 {
+            self.notify(format!("scanner range: {:.0}", self.gameplay.range));
+        }
+    }
+
+    pub fn render_gameplay<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.gameplay.lines.draw(render_pass, &self.marker.camera_bind_group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Heat, RESUME_HEAT};
+
+    #[test]
+    fn sustained_scanning_overheats_until_the_scanner_cools_down() {
+        let mut heat = Heat::new(true);
+        let mut elapsed = 0.0;
+        while !heat.update(true, 4000.0, 0.1) {
+            elapsed += 0.1;
+            assert!(!heat.overheated() && elapsed < 10.0);
+        }
+        assert!(heat.overheated());
+        assert!(heat.jitter_scale() > 2.0);
+
+        // Holding the trigger does not heat an overheated scanner further, and it resumes once cooled enough.
+        while heat.overheated() {
+            heat.update(true, 4000.0, 0.1);
+        }
+        assert!(heat.level <= RESUME_HEAT);
+
+        assert!(!Heat::new(false).update(true, 1e9, 10.0));
+        assert_eq!(Heat::new(false).jitter_scale(), 1.0);
+    }
+}
@@ -0,0 +1,165 @@
+use super::State;
+
+/// Weight of each frame in the smoothed work time the governor watches.
+const FRAME_TIME_SMOOTHING: f64 = 0.05;
+/// Frames longer than this are one-off hitches, like loading a save, rather than load the governor could shed.
+const MAX_FRAME_TIME: f64 = 0.25;
+/// Share of the frame budget above which quality drops, and below which it comes back.
+const OVER_BUDGET: f64 = 1.1;
+const UNDER_BUDGET: f64 = 0.7;
+/// Seconds after a change before the next one, so the smoothed frame time reflects the new quality first.
+const SETTLE_TIME: f64 = 1.0;
+
+/// Fractions of the user's own visible-mark budget, render scale and point size at each quality level, from full
+/// quality down. Resolution goes first, since it is the least noticeable while moving, and fewer, smaller marks
+/// follow.
+const LEVELS: [Quality; 5] = [
+    Quality { budget: 1.0, render_scale: 1.0, point_size: 1.0 },
+    Quality { budget: 1.0, render_scale: 0.8, point_size: 1.0 },
+    Quality { budget: 0.5, render_scale: 0.8, point_size: 0.9 },
+    Quality { budget: 0.5, render_scale: 0.65, point_size: 0.8 },
+    Quality { budget: 0.25, render_scale: 0.5, point_size: 0.8 },
+];
+
+#[derive(Copy, Clone)]
+struct Quality {
+    budget: f32,
+    render_scale: f32,
+    point_size: f32,
+}
+
+/// The values the quality levels scale, as the user left them before the governor first lowered quality.
+#[derive(Copy, Clone)]
+struct Baseline {
+    instance_cap: usize,
+    render_scale: f32,
+    point_size: f32,
+}
+
+/// Keeps the frame rate near the target by stepping through quality levels: lowering the render scale, the number of
+/// visible marks and their size while frames run over budget, and restoring them once there is headroom again.
+pub struct Governor {
+    pub enabled: bool,
+    level: usize,
+    frame_time: Option<f64>,
+    settle: f64,
+    baseline: Option<Baseline>,
+}
+
+impl Governor {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled, level: 0, frame_time: None, settle: SETTLE_TIME, baseline: None }
+    }
+
+    /// Feeds the time the last frame spent working, on the CPU or the GPU, and the wall-clock time `dt` since the
+    /// one before, returning the quality level to switch to if the smoothed work time left the band around `budget`.
+    /// Frames paced by vsync or the frame rate cap take the whole budget however little work they do, so only the
+    /// work time shows the headroom to restore quality.
+    fn step(&mut self, work: f64, dt: f64, budget: f64) -> Option<usize> {
+        if work <= MAX_FRAME_TIME {
+            self.frame_time =
+                Some(self.frame_time.map_or(work, |average| average + (work - average) * FRAME_TIME_SMOOTHING));
+        }
+        self.settle -= dt;
+        let frame_time = self.frame_time?;
+        if self.settle > 0.0 {
+            return None;
+        }
+
+        let level = if frame_time > budget * OVER_BUDGET && self.level + 1 < LEVELS.len() {
+            self.level + 1
+        } else if frame_time < budget * UNDER_BUDGET && self.level > 0 {
+            self.level - 1
+        } else {
+            return None;
+        };
+        self.level = level;
+        self.settle = SETTLE_TIME;
+        Some(level)
+    }
+}
+
+impl State {
+    pub fn toggle_governor(&mut self) {
+        self.governor.enabled = !self.governor.enabled;
+        if !self.governor.enabled && self.governor.level > 0 {
+            self.governor.level = 0;
+            self.apply_quality(0);
+        }
+        self.governor.frame_time = None;
+        self.governor.settle = SETTLE_TIME;
+        self.profiler.gpu_frame = None;
+        self.notify(format!("adaptive quality: {}", if self.governor.enabled { "on" } else { "off" }));
+    }
+
+    /// Steps the quality level towards the frame budget: the target frame rate from the display settings, or the
+    /// frame interval the frame rate cap waits out if that is longer. The last frame's work is the longer of its CPU
+    /// time and the GPU time of the last frame read back, or the CPU time alone on adapters without timestamps.
+    pub fn update_governor(&mut self, dt: f64) {
+        if !self.governor.enabled {
+            return;
+        }
+        let Some(cpu) = self.profiler.cpu_frame else {
+            return;
+        };
+        let work = self.profiler.gpu_frame.map_or(cpu, |gpu| gpu.max(cpu));
+        let target = 1.0 / self.settings.display.target_fps.max(1) as f64;
+        let budget = self.frame_interval().map_or(target, |interval| target.max(interval.as_secs_f64()));
+        if let Some(level) = self.governor.step(work, dt, budget) {
+            self.apply_quality(level);
+            self.notify(format!("quality level: {}/{}", LEVELS.len() - 1 - level, LEVELS.len() - 1));
+        }
+    }
+
+    fn apply_quality(&mut self, level: usize) {
+        let baseline = self.governor.baseline.unwrap_or(Baseline {
+            instance_cap: self.marker.instance_cap(),
+            render_scale: self.render_scale(),
+            point_size: self.marker.point_size(),
+        });
+        let quality = LEVELS[level];
+        self.marker.set_instance_cap((baseline.instance_cap as f32 * quality.budget) as usize);
+        self.set_render_scale(baseline.render_scale * quality.render_scale);
+        let point_size = baseline.point_size * quality.point_size;
+        self.marker.scale_point_size(point_size / self.marker.point_size());
+        // Back at full quality, changes the user makes from here on are the new baseline.
+        self.governor.baseline = (level > 0).then_some(baseline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Governor;
+
+    fn run(governor: &mut Governor, dt: f64, seconds: f64) -> Vec<usize> {
+        run_paced(governor, dt, dt, seconds)
+    }
+
+    fn run_paced(governor: &mut Governor, work: f64, dt: f64, seconds: f64) -> Vec<usize> {
+        (0..(seconds / dt) as usize).filter_map(|_| governor.step(work, dt, 1.0 / 60.0)).collect()
+    }
+
+    #[test]
+    fn quality_drops_one_level_at_a_time_under_load_and_recovers_with_headroom() {
+        let mut governor = Governor::new(true);
+        assert_eq!(run(&mut governor, 1.0 / 40.0, 6.0), vec![1, 2, 3, 4]);
+        assert_eq!(run(&mut governor, 1.0 / 58.0, 5.0), Vec::<usize>::new());
+        assert_eq!(run(&mut governor, 1.0 / 120.0, 2.0), vec![3, 2]);
+    }
+
+    #[test]
+    fn hitches_do_not_lower_quality() {
+        let mut governor = Governor::new(true);
+        run(&mut governor, 1.0 / 60.0, 2.0);
+        assert_eq!(governor.step(2.0, 2.0, 1.0 / 60.0), None);
+        assert_eq!(run(&mut governor, 1.0 / 60.0, 2.0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn quality_recovers_while_vsync_holds_frames_at_the_budget() {
+        let mut governor = Governor::new(true);
+        assert_eq!(run(&mut governor, 1.0 / 40.0, 2.5), vec![1, 2]);
+        // Vsync holds every frame to exactly the budget; only the work time shows the headroom.
+        assert_eq!(run_paced(&mut governor, 1.0 / 200.0, 1.0 / 60.0, 3.0), vec![1, 0]);
+    }
+}
@@ -0,0 +1,182 @@
+use super::anaglyph::Anaglyph;
+use super::beams::Beams;
+use super::crash;
+use super::hud::Hud;
+use super::inspector::Inspector;
+use super::occlusion::Occlusion;
+use super::profiler::Profiler;
+use super::resample::Resampler;
+use super::State;
+use pollster::block_on;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Consecutive frames with a lost or outdated surface that reconfiguring did not fix before the device is recreated.
+const MAX_RECONFIGURE_ATTEMPTS: u32 = 3;
+
+pub(crate) struct Gpu {
+    pub surface: wgpu::Surface,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub config: wgpu::SurfaceConfiguration,
+    /// Present modes the surface supports, to switch vsync later.
+    pub present_modes: Vec<wgpu::PresentMode>,
+}
+
+/// Creates a device and a configured surface for `window`. Errors not caught by an error scope are logged instead
+/// of panicking; out-of-memory and lost-device errors set `lost` so the caller can recreate the device.
+pub(crate) fn create(window: &winit::window::Window, lost: Arc<AtomicBool>, vsync: bool) -> Result<Gpu, String> {
+    let size = window.inner_size();
+
+    let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
+    let surface = unsafe { instance.create_surface(window) };
+
+    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: Some(&surface),
+        force_fallback_adapter: false,
+    }))
+    .ok_or("no compatible graphics adapter found")?;
+    crash::record_adapter(&adapter.get_info());
+
+    let (device, queue) = block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            // Only used by the profiler, which is unavailable without it.
+            features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+            limits: wgpu::Limits::default(),
+            label: None,
+        },
+        None,
+    ))
+    .map_err(|e| format!("failed to create graphics device: {}", e))?;
+
+    device.on_uncaptured_error(move |error| {
+        error!("graphics error: {}", error);
+        let is_lost = match &error {
+            wgpu::Error::OutOfMemory { .. } => true,
+            wgpu::Error::Validation { description, .. } => description.to_lowercase().contains("lost"),
+        };
+        if is_lost {
+            lost.store(true, Ordering::Relaxed);
+        }
+    });
+
+    let present_modes = surface.get_supported_present_modes(&adapter);
+    let present_mode = select_present_mode(&present_modes, vsync);
+    info!("using present mode {:?}", present_mode);
+
+    let format = *surface.get_supported_formats(&adapter).first().ok_or("surface is incompatible with the adapter")?;
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: size.width.max(1),
+        height: size.height.max(1),
+        present_mode,
+        alpha_mode: wgpu::CompositeAlphaMode::Auto,
+    };
+    surface.configure(&device, &config);
+
+    Ok(Gpu { surface, device, queue, config, present_modes })
+}
+
+/// Picks `Fifo` with vsync, and otherwise the lowest-latency present mode available, falling back to `Fifo` which
+/// every surface supports.
+fn select_present_mode(supported: &[wgpu::PresentMode], vsync: bool) -> wgpu::PresentMode {
+    [wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox]
+        .into_iter()
+        .find(|mode| !vsync && supported.contains(mode))
+        .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+impl State {
+    /// Switches between waiting for the display's refresh and presenting frames as soon as they are done.
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.config.present_mode = select_present_mode(&self.present_modes, vsync);
+        info!("using present mode {:?}", self.config.present_mode);
+        self.surface.configure(&self.device, &self.config);
+    }
+
+    /// Recreates the device if it was reported lost since the last frame.
+    pub fn check_device(&mut self) -> Result<(), String> {
+        if self.device_lost.load(Ordering::Relaxed) {
+            warn!("graphics device lost, recreating it");
+            self.recreate_device()?;
+        }
+        Ok(())
+    }
+
+    /// Handles a failed frame: a lost or outdated surface is reconfigured, and if that keeps failing the whole device
+    /// is recreated. Returns an error only if rendering cannot continue.
+    pub fn recover(&mut self, error: wgpu::SurfaceError) -> Result<(), String> {
+        match error {
+            wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated => {
+                self.failed_frames += 1;
+                if self.failed_frames > MAX_RECONFIGURE_ATTEMPTS {
+                    warn!("surface still {:?} after reconfiguring, recreating the device", error);
+                    self.recreate_device()?;
+                } else {
+                    let size = self.window.inner_size();
+                    self.resize(size.width, size.height);
+                }
+                Ok(())
+            }
+            wgpu::SurfaceError::Timeout => {
+                warn!("{:?}", error);
+                Ok(())
+            }
+            wgpu::SurfaceError::OutOfMemory => Err("out of graphics memory".to_string()),
+        }
+    }
+
+    /// Replaces the surface, device and every GPU resource, keeping the scan and all other CPU-side state. The
+    /// visible marks are uploaded again on the next frame.
+    fn recreate_device(&mut self) -> Result<(), String> {
+        self.device_lost = Arc::new(AtomicBool::new(false));
+        let gpu = create(&self.window, self.device_lost.clone(), self.settings.display.vsync)?;
+        self.surface = gpu.surface;
+        self.device = gpu.device;
+        self.queue = gpu.queue;
+        self.config = gpu.config;
+        self.present_modes = gpu.present_modes;
+        self.failed_frames = 0;
+
+        self.marker.recreate(&self.device, &self.config, &self.camera);
+
+        self.beams = Beams::new(&self.device, self.config.format, &self.marker);
+        self.clip.recreate(&self.device, self.config.format, &self.marker);
+        self.selection.recreate(&self.device, self.config.format, &self.marker);
+        self.trail.recreate(&self.device, self.config.format, &self.marker);
+        self.picker.recreate(&self.device, self.config.format, &self.marker);
+        self.minimap.recreate(&self.device, self.config.format);
+        self.gameplay.recreate(&self.device, self.config.format, &self.marker);
+        self.photo.recreate(&self.device, self.config.format);
+
+        let view = self.inspector.view;
+        self.inspector = Inspector::new(&self.device, &self.config, &self.marker);
+        self.inspector.view = view;
+
+        let enabled = self.profiler.enabled;
+        self.profiler = Profiler::new(&self.device, &self.queue);
+        self.profiler.enabled = enabled && self.profiler.supported();
+
+        let visible = self.hud.visible;
+        self.hud = Hud::new(&self.device, self.config.format, self.hud.scale_factor());
+        self.hud.visible = visible;
+
+        let enabled = self.occlusion.enabled;
+        self.occlusion = Occlusion::new(&self.device, &self.config, &self.marker);
+        self.occlusion.enabled = enabled;
+
+        let enabled = self.anaglyph.enabled;
+        self.anaglyph = Anaglyph::new(&self.device, &self.config);
+        self.anaglyph.enabled = enabled;
+
+        self.resampler = Resampler::new(&self.device, self.config.format, self.settings.display.render_scale);
+        self.resize_scene();
+
+        self.camera.set_aspect(self.config.width as f32 / self.config.height as f32);
+        self.notify("graphics device recreated".to_string());
+        Ok(())
+    }
+}
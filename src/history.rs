@@ -0,0 +1,222 @@
+use super::marker::{Mark, MarkRaw};
+use super::world::DensityEdit;
+use super::State;
+use glam::Vec3;
+use std::collections::VecDeque;
+
+/// Memory the undo and redo stacks may hold together; the oldest commands are forgotten beyond it.
+const MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+/// A reversible change to the scene. Everything that can be undone is applied through one of these.
+pub enum Command {
+    /// A stroke of the terrain brush.
+    Density(DensityEdit),
+    /// Marks erased from the octree.
+    Erase(Vec<MarkRaw>),
+    /// Marks painted in `color`, as they were before.
+    Paint { marks: Vec<MarkRaw>, color: Vec3 },
+}
+
+impl Command {
+    fn size(&self) -> usize {
+        match self {
+            Command::Density(edit) => edit.size(),
+            Command::Erase(marks) | Command::Paint { marks, .. } => marks.len() * std::mem::size_of::<MarkRaw>(),
+        }
+    }
+
+    /// Applies the command's new state (`redo`) or its old one.
+    fn apply(&self, state: &mut State, redo: bool) {
+        match self {
+            Command::Density(edit) => {
+                let mut points = edit.points.iter().map(|(&point, &(before, after))| {
+                    let delta = if redo { after } else { before };
+                    (point, delta)
+                });
+                state.world.restore_density(&mut points);
+                let (center, radius) = edit.bounds();
+                state.occlusion.invalidate(center, radius);
+            }
+            Command::Erase(marks) => {
+                if redo {
+                    state.marker.octree.replace(marks.iter().map(|mark| (*mark, None)));
+                } else {
+                    state.marker.octree.extend(marks.iter().map(|mark| Mark::from(*mark)));
+                }
+                state.coverage.rebuild(&state.marker.octree);
+                state.minimap.invalidate();
+            }
+            Command::Paint { marks, color } => {
+                let pairs = marks.iter().map(|mark| (*mark, mark.painted(*color)));
+                if redo {
+                    state.marker.octree.replace(pairs.map(|(before, after)| (before, Some(after))));
+                } else {
+                    state.marker.octree.replace(pairs.map(|(before, after)| (after, Some(before))));
+                }
+                state.minimap.invalidate();
+            }
+        }
+    }
+}
+
+/// Undo and redo stacks of the commands applied so far.
+#[derive(Default)]
+pub struct History {
+    undo: VecDeque<Command>,
+    redo: Vec<Command>,
+    size: usize,
+    /// Whether the next density edit continues the last command's stroke.
+    stroke: bool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a command that was just applied, clearing the redo stack. Returns false if the command alone is over the
+    /// memory budget, in which case it is not recorded.
+    pub fn push(&mut self, command: Command) -> bool {
+        self.size -= self.redo.drain(..).map(|command| command.size()).sum::<usize>();
+        if command.size() > MEMORY_BUDGET {
+            return false;
+        }
+        self.size += command.size();
+        self.undo.push_back(command);
+        while self.size > MEMORY_BUDGET && self.undo.len() > 1 {
+            self.size -= self.undo.pop_front().unwrap().size();
+        }
+        true
+    }
+
+    /// Records a density edit, folding it into the previous one while the brush stays down.
+    pub fn push_density(&mut self, edit: DensityEdit) {
+        if let (true, Some(Command::Density(stroke))) = (self.stroke, self.undo.back_mut()) {
+            self.size -= stroke.size();
+            stroke.merge(edit);
+            self.size += stroke.size();
+            while self.size > MEMORY_BUDGET && self.undo.len() > 1 {
+                self.size -= self.undo.pop_front().unwrap().size();
+            }
+        } else {
+            self.push(Command::Density(edit));
+        }
+        self.stroke = true;
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        for command in self.undo.iter_mut().chain(&mut self.redo) {
+            match command {
+                Command::Density(edit) => edit.rebase(shift),
+                // Moved the way the octree moves its marks, so they still compare equal to them.
+                Command::Erase(marks) | Command::Paint { marks, .. } => {
+                    for mark in marks {
+                        mark.pos = (Vec3::from(mark.pos) + -shift).to_array();
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends the current brush stroke, so the next edit becomes a command of its own.
+    pub fn end_stroke(&mut self) {
+        self.stroke = false;
+    }
+
+    /// Forgets every command, for when the terrain they were recorded on is gone.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl State {
+    /// Erases the marks inside the box from `min` to `max` as an undoable command, returning how many were erased.
+    /// Coverage and the minimap are left for the caller to refresh.
+    pub fn erase_marks(&mut self, min: Vec3, max: Vec3) -> usize {
+        let erased = self.marker.octree.remove_in(min, max);
+        self.history.stroke = false;
+        let count = erased.len();
+        if count > 0 && !self.history.push(Command::Erase(erased)) {
+            self.notify(format!("erasing {} marks cannot be undone", count));
+        }
+        count
+    }
+
+    /// Paints the marks inside the box from `min` to `max` in `color` as an undoable command, returning how many were
+    /// painted.
+    pub fn paint_marks(&mut self, min: Vec3, max: Vec3, color: Vec3) -> usize {
+        let marks = self.marker.octree.recolor_in(min, max, color);
+        self.history.stroke = false;
+        let count = marks.len();
+        if count > 0 && !self.history.push(Command::Paint { marks, color }) {
+            self.notify(format!("painting {} marks cannot be undone", count));
+        }
+        count
+    }
+
+    pub fn undo(&mut self) {
+        self.history.stroke = false;
+        let Some(command) = self.history.undo.pop_back() else {
+            self.notify("nothing to undo".to_string());
+            return;
+        };
+        command.apply(self, false);
+        self.history.redo.push(command);
+        self.notify("undone".to_string());
+    }
+
+    pub fn redo(&mut self) {
+        self.history.stroke = false;
+        let Some(command) = self.history.redo.pop() else {
+            self.notify("nothing to redo".to_string());
+            return;
+        };
+        command.apply(self, true);
+        self.history.undo.push_back(command);
+        self.notify("redone".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, History, MEMORY_BUDGET};
+    use crate::marker::MarkRaw;
+    use crate::world::{DensityEdit, Voxel, DEFAULT_VOXEL_SIZE};
+
+    fn edit(points: &[([i64; 3], f64, f64)]) -> DensityEdit {
+        let points = points.iter().map(|&(index, before, after)| (Voxel::from_index(index), (before, after)));
+        DensityEdit { points: points.collect(), voxel_size: DEFAULT_VOXEL_SIZE }
+    }
+
+    #[test]
+    fn strokes_merge_until_ended_and_new_commands_clear_redo() {
+        let mut history = History::new();
+        history.push_density(edit(&[([0, 0, 0], 0.0, 0.1)]));
+        history.push_density(edit(&[([0, 0, 0], 0.1, 0.2), ([1, 0, 0], 0.0, 0.1)]));
+        assert_eq!(history.undo.len(), 1);
+        let Some(Command::Density(stroke)) = history.undo.back() else { panic!() };
+        assert_eq!(stroke.points[&Voxel::from_index([0, 0, 0])], (0.0, 0.2));
+        assert_eq!(stroke.points[&Voxel::from_index([1, 0, 0])], (0.0, 0.1));
+
+        history.end_stroke();
+        history.push_density(edit(&[([0, 0, 0], 0.2, 0.3)]));
+        assert_eq!(history.undo.len(), 2);
+
+        history.end_stroke();
+        history.redo.push(history.undo.pop_back().unwrap());
+        history.push_density(edit(&[([5, 0, 0], 0.0, 0.3)]));
+        assert!(history.redo.is_empty());
+        assert_eq!(history.size, history.undo.iter().map(Command::size).sum::<usize>());
+    }
+
+    #[test]
+    fn erasures_over_the_memory_budget_are_not_recorded() {
+        let mut history = History::new();
+        assert!(history.push(Command::Erase(vec![MarkRaw::default(); 16])));
+        let marks = MEMORY_BUDGET / std::mem::size_of::<MarkRaw>() + 1;
+        assert!(!history.push(Command::Erase(vec![MarkRaw::default(); marks])));
+        assert_eq!(history.undo.len(), 1);
+        assert_eq!(history.size, 16 * std::mem::size_of::<MarkRaw>());
+    }
+}
@@ -0,0 +1,572 @@
+use super::input::WheelMode;
+use super::layers::LAYER_COLORS;
+use super::menu::ENTRIES;
+use super::profiler::PHASES;
+use super::State;
+use glam::{vec2, Vec2, Vec4};
+
+const MAX_HUD_VERTICES: usize = 65536;
+
+const COLOR: Vec4 = Vec4::new(0.85, 0.9, 0.85, 0.8);
+const ACCENT: Vec4 = Vec4::new(1.0, 0.8, 0.2, 0.9);
+const BACKGROUND: Vec4 = Vec4::new(0.0, 0.0, 0.0, 0.4);
+
+/// Crosshair dimensions in device-independent pixels; unlike the rest of the HUD they ignore the UI scale setting.
+const CROSSHAIR_SIZE: f32 = 8.0;
+const CROSSHAIR_GAP: f32 = 4.0;
+const CROSSHAIR_WIDTH: f32 = 2.0;
+
+const COMPASS_WIDTH: f32 = 600.0;
+const COMPASS_HEIGHT: f32 = 36.0;
+/// Degrees of heading visible on either side of the compass center.
+const COMPASS_SPAN: f32 = 60.0;
+const COMPASS_TICK: i32 = 15;
+
+/// Seconds without a hit, while still missing, before the miss warning shows.
+const MISS_INDICATOR_TIME: f64 = 0.2;
+
+/// How long the wheel indicator stays highlighted after a wheel step.
+const WHEEL_INDICATOR_TIME: f64 = 1.5;
+
+const ENERGY_BAR_WIDTH: f32 = 240.0;
+const ENERGY_BAR_HEIGHT: f32 = 12.0;
+
+/// Side of the minimap in the top-right corner, and of the player marker and heading tick on it.
+const MINIMAP_SIZE: f32 = 192.0;
+const MINIMAP_PLAYER: f32 = 6.0;
+const MINIMAP_HEADING: f32 = 14.0;
+
+const MARGIN: f32 = 16.0;
+const GLYPH_SCALE: f32 = 3.0;
+
+/// Dims the scene behind the settings menu.
+const MENU_BACKGROUND: Vec4 = Vec4::new(0.0, 0.0, 0.0, 0.6);
+const MENU_WIDTH: f32 = 720.0;
+const MENU_LINE_HEIGHT: f32 = 10.0 * GLYPH_SCALE;
+
+/// UI scales allowed by the display settings.
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct HudVertex {
+    pos: [f32; 2],
+    color: [f32; 4],
+}
+
+impl HudVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x4];
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUniform {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+/// Screen-space overlay drawn on top of the scene: a crosshair, a compass strip and the camera position. Everything
+/// is built from solid rectangles in pixel coordinates, including text, which uses a built-in 3x5 pixel font. Layout
+/// is in logical pixels, scaled to the window's monitor and the UI scale setting.
+pub struct Hud {
+    pub visible: bool,
+    /// Physical pixels per logical pixel of the monitor the window is on, as reported by the event loop.
+    scale_factor: f64,
+    pipeline: wgpu::RenderPipeline,
+    screen_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    vertices: Vec<HudVertex>,
+    n_vertices: u32,
+    since_hit: f64,
+    since_miss: f64,
+    since_scroll: f64,
+}
+
+impl Hud {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, scale_factor: f64) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("hud.wgsl"));
+
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Screen Buffer"),
+            size: std::mem::size_of::<ScreenUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("hud_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: screen_buffer.as_entire_binding() }],
+            label: Some("hud_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("HUD Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("HUD Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[HudVertex::desc()] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("HUD Vertex Buffer"),
+            size: (MAX_HUD_VERTICES * std::mem::size_of::<HudVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            visible: true,
+            scale_factor,
+            pipeline,
+            screen_buffer,
+            bind_group,
+            vertex_buffer,
+            vertices: Vec::with_capacity(MAX_HUD_VERTICES),
+            n_vertices: 0,
+            since_hit: f64::INFINITY,
+            since_miss: f64::INFINITY,
+            since_scroll: f64::INFINITY,
+        }
+    }
+
+    /// Notes a scroll of the mouse wheel, which shows the wheel mode indicator for a moment.
+    pub fn record_scroll(&mut self) {
+        self.since_scroll = 0.0;
+    }
+
+    /// Notes the outcome of a scanner shot for the miss warning.
+    pub fn record_shot(&mut self, hit: bool) {
+        if hit {
+            self.since_hit = 0.0;
+        } else {
+            self.since_miss = 0.0;
+        }
+    }
+
+    /// Queues a filled rectangle with its top-left corner at `pos`, in pixels.
+    pub fn rect(&mut self, pos: Vec2, size: Vec2, color: Vec4) {
+        if self.vertices.len() + 6 > MAX_HUD_VERTICES {
+            return;
+        }
+        let color = color.into();
+        let (a, b) = (pos, pos + size);
+        for corner in [vec2(a.x, a.y), vec2(b.x, a.y), vec2(a.x, b.y), vec2(b.x, a.y), vec2(b.x, b.y), vec2(a.x, b.y)] {
+            self.vertices.push(HudVertex { pos: corner.into(), color });
+        }
+    }
+
+    /// Queues `text` with its top-left corner at `pos`; each font pixel is `scale` screen pixels. Characters without
+    /// a glyph are drawn as blanks. Returns the width of the text in pixels.
+    pub fn text(&mut self, pos: Vec2, scale: f32, text: &str, color: Vec4) -> f32 {
+        let mut x = pos.x;
+        for c in text.chars() {
+            let bits = glyph(c.to_ascii_uppercase());
+            for row in 0..5 {
+                for col in 0..3 {
+                    if bits & (1 << (14 - row * 3 - col)) != 0 {
+                        let pixel = vec2(x + col as f32 * scale, pos.y + row as f32 * scale);
+                        self.rect(pixel, Vec2::splat(scale), color);
+                    }
+                }
+            }
+            x += 4.0 * scale;
+        }
+        x - pos.x
+    }
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// Uploads the queued geometry and the screen size in logical pixels, and clears the queue for the next frame.
+    fn upload(&mut self, queue: &wgpu::Queue, size: Vec2) {
+        let screen = ScreenUniform { size: size.into(), _padding: [0.0; 2] };
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[screen]));
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.n_vertices = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+}
+
+/// 3x5 bitmap for `c`, rows top to bottom, three bits per row with the most significant bit on the left.
+fn glyph(c: char) -> u16 {
+    match c {
+        '0' => 0b111_101_101_101_111,
+        '1' => 0b010_110_010_010_111,
+        '2' => 0b111_001_111_100_111,
+        '3' => 0b111_001_111_001_111,
+        '4' => 0b101_101_111_001_001,
+        '5' | 'S' => 0b111_100_111_001_111,
+        '6' => 0b111_100_111_101_111,
+        '7' => 0b111_001_001_001_001,
+        '8' => 0b111_101_111_101_111,
+        '9' => 0b111_101_111_001_111,
+        '-' => 0b000_000_111_000_000,
+        '.' => 0b000_000_000_000_010,
+        '%' => 0b101_001_010_100_101,
+        ':' => 0b000_010_000_010_000,
+        '<' => 0b001_010_100_010_001,
+        '>' => 0b100_010_001_010_100,
+        '/' => 0b001_001_010_100_100,
+        'A' => 0b010_101_111_101_101,
+        'B' => 0b110_101_110_101_110,
+        'C' => 0b111_100_100_100_111,
+        'D' => 0b110_101_101_101_110,
+        'E' => 0b111_100_111_100_111,
+        'F' => 0b111_100_110_100_100,
+        'G' => 0b111_100_101_101_111,
+        'H' => 0b101_101_111_101_101,
+        'I' => 0b111_010_010_010_111,
+        'J' => 0b001_001_001_101_111,
+        'K' => 0b101_101_110_101_101,
+        'L' => 0b100_100_100_100_111,
+        'M' => 0b101_111_111_101_101,
+        'N' => 0b110_101_101_101_101,
+        'O' => 0b010_101_101_101_010,
+        'P' => 0b111_101_111_100_100,
+        'Q' => 0b010_101_101_110_011,
+        'R' => 0b110_101_110_101_101,
+        'T' => 0b111_010_010_010_010,
+        'U' => 0b101_101_101_101_111,
+        'V' => 0b101_101_101_101_010,
+        'W' => 0b101_101_111_111_101,
+        'X' => 0b101_101_010_101_101,
+        'Y' => 0b101_101_010_010_010,
+        'Z' => 0b111_001_010_100_111,
+        _ => 0,
+    }
+}
+
+/// The crosshair's four arms as top-left corners and sizes in physical pixels, for a surface of `size` physical pixels.
+/// The arms are whole pixels wide and long and the center sits on a pixel corner, so the lines stay crisp at any scale
+/// factor instead of smearing across pixel boundaries.
+fn crosshair(size: Vec2, scale_factor: f32) -> [(Vec2, Vec2); 4] {
+    let center = (size * 0.5).floor();
+    let whole = |length: f32| (length * scale_factor).round().max(1.0);
+    let (size, gap) = (whole(CROSSHAIR_SIZE), whole(CROSSHAIR_GAP));
+    // Even, so the arms straddle the center evenly.
+    let thick = (CROSSHAIR_WIDTH * scale_factor * 0.5).round().max(1.0) * 2.0;
+    [
+        (center + vec2(-gap - size, -thick * 0.5), vec2(size, thick)),
+        (center + vec2(gap, -thick * 0.5), vec2(size, thick)),
+        (center + vec2(-thick * 0.5, -gap - size), vec2(thick, size)),
+        (center + vec2(-thick * 0.5, gap), vec2(thick, size)),
+    ]
+}
+
+/// Compass heading in degrees for a camera yaw: 0 looks down -Z (north), 90 down +X (east).
+fn heading(yaw: f32) -> f32 {
+    (yaw.to_degrees() + 90.0).rem_euclid(360.0)
+}
+
+impl State {
+    pub fn toggle_hud(&mut self) {
+        self.hud.visible = !self.hud.visible;
+    }
+
+    /// Follows the window to a monitor with another scale factor.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.hud.scale_factor = scale_factor;
+    }
+
+    /// Physical pixels per logical HUD pixel: the monitor's scale factor times the UI scale setting.
+    pub fn ui_scale(&self) -> f32 {
+        self.hud.scale_factor as f32 * self.settings.display.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+    }
+
+    /// Top-left corner and side of the minimap in logical pixels, while it is shown.
+    pub fn minimap_rect(&self) -> Option<(Vec2, f32)> {
+        if !self.minimap.enabled || !self.hud.visible || self.menu.open {
+            return None;
+        }
+        let width = self.config.width as f32 / self.ui_scale();
+        Some((vec2(width - MARGIN - MINIMAP_SIZE, MARGIN), MINIMAP_SIZE))
+    }
+
+    pub fn update_hud(&mut self, dt: f64) {
+        self.hud.since_hit += dt;
+        self.hud.since_miss += dt;
+        self.hud.since_scroll += dt;
+        if !self.hud.visible && !self.menu.open {
+            return;
+        }
+
+        let scale = self.ui_scale();
+        let surface = vec2(self.config.width as f32, self.config.height as f32);
+        let (width, height) = (surface.x / scale, surface.y / scale);
+        // The menu takes the place of the rest of the HUD while it is open.
+        if self.menu.open {
+            self.draw_menu(vec2(width, height));
+            self.hud.upload(&self.queue, vec2(width, height));
+            return;
+        }
+        let minimap = self.minimap_rect();
+        let hud = &mut self.hud;
+
+        let center = vec2(width, height) * 0.5;
+        let arms = crosshair(surface, hud.scale_factor as f32);
+        for (pos, size) in arms {
+            hud.rect(pos / scale, size / scale, COLOR);
+        }
+
+        let missing = hud.since_miss < MISS_INDICATOR_TIME && hud.since_hit >= MISS_INDICATOR_TIME;
+        if missing && self.settings.scanner.miss_indicator {
+            let label = self.locale.get("hud.no_hit");
+            let label_x = center.x - label.len() as f32 * 2.0 * GLYPH_SCALE;
+            let (bottom_pos, bottom_size) = arms[3];
+            let label_y = (bottom_pos.y + bottom_size.y) / scale + 8.0;
+            hud.text(vec2(label_x, label_y), GLYPH_SCALE, label, ACCENT);
+        }
+
+        let heading = heading(self.camera.viewpoint().yaw);
+        let strip = vec2(center.x - COMPASS_WIDTH * 0.5, MARGIN);
+        hud.rect(strip, vec2(COMPASS_WIDTH, COMPASS_HEIGHT), BACKGROUND);
+        let first = ((heading - COMPASS_SPAN) / COMPASS_TICK as f32).ceil() as i32 * COMPASS_TICK;
+        for degrees in (first..=(heading + COMPASS_SPAN) as i32).step_by(COMPASS_TICK as usize) {
+            let x = center.x + (degrees as f32 - heading) / COMPASS_SPAN * COMPASS_WIDTH * 0.5;
+            let label = match degrees.rem_euclid(360) {
+                0 => Some("N"),
+                90 => Some("E"),
+                180 => Some("S"),
+                270 => Some("W"),
+                _ => None,
+            };
+            match label {
+                Some(label) => {
+                    hud.rect(vec2(x - 1.0, strip.y), vec2(2.0, 10.0), COLOR);
+                    hud.text(vec2(x - 1.5 * GLYPH_SCALE, strip.y + 14.0), GLYPH_SCALE, label, COLOR);
+                }
+                None => hud.rect(vec2(x - 1.0, strip.y), vec2(2.0, 6.0), COLOR),
+            }
+        }
+        hud.rect(vec2(center.x - 1.0, strip.y), vec2(2.0, COMPASS_HEIGHT), ACCENT);
+        let label = format!("{:03.0}", heading.round() % 360.0);
+        let label_x = center.x - label.len() as f32 * 2.0 * 2.0;
+        hud.text(vec2(label_x, strip.y + COMPASS_HEIGHT + 6.0), 2.0, &label, ACCENT);
+
+        // The map itself is drawn by the minimap pipeline underneath; only the frame and the player go on top.
+        if let Some((corner, size)) = minimap {
+            for (pos, edge) in [
+                (corner, vec2(size, 1.0)),
+                (corner + vec2(0.0, size - 1.0), vec2(size, 1.0)),
+                (corner, vec2(1.0, size)),
+                (corner + vec2(size - 1.0, 0.0), vec2(1.0, size)),
+            ] {
+                hud.rect(pos, edge, COLOR);
+            }
+            let player = corner + self.minimap.locate(self.camera.pos).clamp(Vec2::ZERO, Vec2::ONE) * size;
+            let half = vec2(MINIMAP_PLAYER, MINIMAP_PLAYER) * 0.5;
+            hud.rect(player - half, half * 2.0, ACCENT);
+            // A dotted tick along the view direction, with map rows running south like the world's Z.
+            let (sin, cos) = self.camera.viewpoint().yaw.sin_cos();
+            for step in (2..=MINIMAP_HEADING as i32).step_by(2) {
+                hud.rect(player + vec2(cos, sin) * step as f32 - 1.0, vec2(2.0, 2.0), ACCENT);
+            }
+        }
+
+        if self.gameplay.enabled {
+            let bar = vec2(ENERGY_BAR_WIDTH, ENERGY_BAR_HEIGHT);
+            let corner = vec2(center.x - bar.x * 0.5, height - MARGIN - bar.y);
+            hud.rect(corner, bar, BACKGROUND);
+            let fill = vec2(bar.x * self.gameplay.energy_fraction(), bar.y);
+            hud.rect(corner, fill, if self.gameplay.can_scan() { COLOR } else { ACCENT });
+            let range = format!("{:.0}", self.gameplay.range);
+            let label = self.locale.format("hud.energy", &[("range", &range)]);
+            hud.text(corner - vec2(0.0, 7.0 * GLYPH_SCALE), GLYPH_SCALE, &label, COLOR);
+        }
+
+        // Above the energy bar when both are shown.
+        let heat = &self.gameplay.heat;
+        if heat.enabled {
+            let bar = vec2(ENERGY_BAR_WIDTH, ENERGY_BAR_HEIGHT);
+            let stacked = if self.gameplay.enabled { bar.y + 10.0 * GLYPH_SCALE } else { 0.0 };
+            let corner = vec2(center.x - bar.x * 0.5, height - MARGIN - bar.y - stacked);
+            hud.rect(corner, bar, BACKGROUND);
+            let color = if heat.overheated() { ACCENT } else { COLOR };
+            hud.rect(corner, vec2(bar.x * heat.level, bar.y), color);
+            let label = self.locale.get(if heat.overheated() { "hud.overheat" } else { "hud.heat" });
+            hud.text(corner - vec2(0.0, 7.0 * GLYPH_SCALE), GLYPH_SCALE, label, color);
+        }
+
+        // The wheel's current target, in the bottom-right corner; lit up while a modifier picks another target or
+        // right after scrolling.
+        let (mode, value) = self.wheel_value();
+        let hud = &mut self.hud;
+        let active = mode != WheelMode::Cone || hud.since_scroll < WHEEL_INDICATOR_TIME;
+        let value_x = width - MARGIN - value.len() as f32 * 4.0 * GLYPH_SCALE;
+        let value_y = height - MARGIN - 5.0 * GLYPH_SCALE;
+        hud.text(vec2(value_x, value_y), GLYPH_SCALE, &value, if active { ACCENT } else { COLOR });
+
+        if self.layers.panel_open {
+            self.draw_layer_panel();
+        }
+
+        let hud = &mut self.hud;
+        let pos = self.camera.pos;
+        let coverage = match self.coverage.fraction {
+            Some(fraction) => format!("{:.1}%", fraction * 100.0),
+            None => "-".to_string(),
+        };
+        let locale = &self.locale;
+        let mut lines = vec![
+            format!("X {:.0}", pos.x),
+            format!("Y {:.0}", pos.y),
+            format!("Z {:.0}", pos.z),
+            locale.format("hud.depth", &[("depth", &format!("{:.0}", -pos.y))]),
+            locale.format("hud.coverage", &[("coverage", &coverage)]),
+        ];
+        // Share of terrain density lookups served from cached lattice points, and how many points are cached.
+        if let Some(stats) = self.world.cache_stats() {
+            let rate = stats.hit_rate().map_or("-".to_string(), |rate| format!("{:.1}%", rate * 100.0));
+            lines.push(locale.format("hud.cache", &[("rate", &rate), ("cached", &stats.cached)]));
+        }
+        // GPU time of each part of the frame and CPU time per frame, in milliseconds.
+        let profiler = &self.profiler;
+        if profiler.enabled {
+            let ms = |ms: Option<f32>| ms.map_or("-".to_string(), |ms| format!("{:.2}", ms));
+            for ((key, _, _), gpu_ms) in PHASES.iter().zip(profiler.gpu_ms) {
+                lines.push(locale.format(key, &[("ms", &ms(gpu_ms))]));
+            }
+            lines.push(locale.format("hud.cpu", &[("ms", &ms(profiler.cpu_ms))]));
+        }
+        let line_height = 7.0 * GLYPH_SCALE;
+        let mut y = height - MARGIN - line_height * lines.len() as f32;
+        for line in &lines {
+            hud.text(vec2(MARGIN, y), GLYPH_SCALE, line, COLOR);
+            y += line_height;
+        }
+
+        hud.upload(&self.queue, vec2(width, height));
+    }
+
+    /// Lists the layers in the top-left corner: a swatch of the color override, whether the layer is shown and its
+    /// name, with the selected line highlighted and the layer being recorded into marked.
+    fn draw_layer_panel(&mut self) {
+        let hud = &mut self.hud;
+        let layers = &self.layers;
+        let mut y = MARGIN;
+        hud.text(vec2(MARGIN, y), GLYPH_SCALE, "LAYERS", ACCENT);
+        y += MENU_LINE_HEIGHT;
+
+        let swatch = Vec2::splat(5.0 * GLYPH_SCALE);
+        for (i, layer) in layers.list.iter().enumerate() {
+            let color = if i == layers.selected { ACCENT } else { COLOR };
+            if let Some(override_color) = layer.color {
+                hud.rect(vec2(MARGIN, y), swatch, LAYER_COLORS[override_color].extend(1.0));
+            }
+            let shown = if layer.visible { "ON " } else { "OFF" };
+            let recording = if i as u32 == layers.current { " - REC" } else { "" };
+            let line = format!("{} {}{}", shown, layer.name, recording);
+            hud.text(vec2(MARGIN + swatch.x + 2.0 * GLYPH_SCALE, y), GLYPH_SCALE, &line, color);
+            y += MENU_LINE_HEIGHT;
+        }
+        let color = if layers.selected == layers.list.len() { ACCENT } else { COLOR };
+        hud.text(vec2(MARGIN + swatch.x + 2.0 * GLYPH_SCALE, y), GLYPH_SCALE, "NEW LAYER", color);
+    }
+
+    /// Lays out the settings menu in the middle of a screen of `size` logical pixels: a label and a value per entry,
+    /// with the selected entry highlighted and its value between arrows.
+    fn draw_menu(&mut self, size: Vec2) {
+        let hud = &mut self.hud;
+        hud.rect(Vec2::ZERO, size, MENU_BACKGROUND);
+
+        let left = (size.x - MENU_WIDTH) * 0.5;
+        let mut y = (size.y - MENU_LINE_HEIGHT * (ENTRIES.len() + 4) as f32) * 0.5;
+        hud.text(vec2(left, y), GLYPH_SCALE, self.locale.get("menu.title"), ACCENT);
+        y += MENU_LINE_HEIGHT * 2.0;
+
+        for (i, entry) in ENTRIES.iter().enumerate() {
+            let selected = i == self.menu.selected;
+            let color = if selected { ACCENT } else { COLOR };
+            hud.text(vec2(left, y), GLYPH_SCALE, &entry.label(&self.locale), color);
+            let value = entry.value(&self.settings, &self.locale);
+            let value = if selected && !value.is_empty() { format!("< {} >", value) } else { value };
+            let value_x = left + MENU_WIDTH - value.len() as f32 * 4.0 * GLYPH_SCALE;
+            hud.text(vec2(value_x, y), GLYPH_SCALE, &value, color);
+            y += MENU_LINE_HEIGHT;
+        }
+
+        y += MENU_LINE_HEIGHT;
+        hud.text(vec2(left, y), 2.0, self.locale.get("menu.hint"), COLOR);
+    }
+
+    pub fn render_hud<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !(self.hud.visible || self.menu.open) || self.hud.n_vertices == 0 {
+            return;
+        }
+
+        let (width, height) = (self.config.width, self.config.height);
+        render_pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+        render_pass.set_scissor_rect(0, 0, width, height);
+
+        render_pass.set_pipeline(&self.hud.pipeline);
+        render_pass.set_vertex_buffer(0, self.hud.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, &self.hud.bind_group, &[]);
+        render_pass.draw(0..self.hud.n_vertices, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crosshair, CROSSHAIR_SIZE, CROSSHAIR_WIDTH};
+    use glam::vec2;
+
+    #[test]
+    fn crosshair_is_pixel_aligned_at_fractional_scale_factors() {
+        for scale_factor in [1.0, 1.25, 1.5, 1.75, 2.0, 3.0] {
+            for (pos, size) in crosshair(vec2(1921.0, 1079.0), scale_factor) {
+                assert_eq!(pos, pos.round(), "arm at {} for scale factor {}", pos, scale_factor);
+                assert_eq!(size, size.round(), "arm of {} for scale factor {}", size, scale_factor);
+            }
+        }
+    }
+
+    #[test]
+    fn crosshair_grows_with_scale_factor() {
+        let [(_, normal), ..] = crosshair(vec2(800.0, 600.0), 1.0);
+        let [(_, retina), ..] = crosshair(vec2(1600.0, 1200.0), 2.0);
+        assert_eq!(normal, vec2(CROSSHAIR_SIZE, CROSSHAIR_WIDTH));
+        assert_eq!(retina, normal * 2.0);
+    }
+}
@@ -0,0 +1,318 @@
+use super::marker::{octree::Octree, Mark};
+use super::State;
+use glam::{vec3, Vec3};
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// Color given to imported points that carry none.
+const DEFAULT_COLOR: Vec3 = Vec3::new(0.8, 0.8, 0.8);
+
+/// Where to read external points from and how to place them in the world.
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    pub path: PathBuf,
+    pub scale: f32,
+    pub offset: Vec3,
+}
+
+impl ImportOptions {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, scale: 1.0, offset: Vec3::ZERO }
+    }
+}
+
+/// Reads a PLY (ASCII or binary) or ASCII XYZ point cloud, picking the format from the file extension, and returns
+/// its points as marks transformed by `options`.
+pub fn import_points(options: &ImportOptions) -> Result<Vec<Mark>, String> {
+    let path = &options.path;
+    let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut reader = BufReader::new(file);
+
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_ascii_lowercase();
+    let mut marks = match extension.as_str() {
+        "ply" => read_ply(&mut reader),
+        "xyz" | "txt" => read_xyz(&mut reader),
+        _ => Err(format!("unsupported point cloud format '{}', expected .ply or .xyz", extension)),
+    }
+    .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    for mark in &mut marks {
+        mark.pos = mark.pos * options.scale + options.offset;
+    }
+    Ok(marks)
+}
+
+/// One point per line: `x y z` optionally followed by `r g b`, separated by spaces or commas. Colors are read as
+/// 0-255 if any channel exceeds 1. Blank lines and lines starting with `#` or `//` are skipped.
+fn read_xyz(reader: &mut impl BufRead) -> Result<Vec<Mark>, String> {
+    let mut marks = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+
+        let values = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|field| !field.is_empty())
+            .map(str::parse::<f32>)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("line {}: {}", i + 1, e))?;
+        if values.len() < 3 {
+            return Err(format!("line {}: expected at least 3 coordinates", i + 1));
+        }
+
+        let pos = vec3(values[0], values[1], values[2]);
+        let color = match values.get(3..6) {
+            Some(rgb) => {
+                let color = vec3(rgb[0], rgb[1], rgb[2]);
+                if color.max_element() > 1.0 {
+                    color / 255.0
+                } else {
+                    color
+                }
+            }
+            None => DEFAULT_COLOR,
+        };
+        marks.push(Mark::new(pos, color));
+    }
+    Ok(marks)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return Err(format!("unknown property type '{}'", name)),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+
+    fn is_float(self) -> bool {
+        matches!(self, PlyType::F32 | PlyType::F64)
+    }
+}
+
+struct PlyProperty {
+    name: String,
+    ty: PlyType,
+    /// Type of the length prefix for list properties.
+    list: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+/// Reads the `vertex` element of a PLY file: `x`, `y`, `z` and, if present, `red`, `green`, `blue`. Integer colors
+/// are scaled from their type's range; float colors are used as-is. Elements after the vertices are ignored.
+fn read_ply(reader: &mut impl BufRead) -> Result<Vec<Mark>, String> {
+    let (format, elements) = read_ply_header(reader)?;
+
+    let mut marks = Vec::new();
+    for element in &elements {
+        let is_vertex = element.name == "vertex";
+        let index = |name: &str| element.properties.iter().position(|property| property.name == name);
+        let position = [index("x"), index("y"), index("z")];
+        let color = [index("red"), index("green"), index("blue")];
+        if is_vertex && position.iter().any(Option::is_none) {
+            return Err("vertex element has no x, y and z properties".to_string());
+        }
+
+        let mut values = vec![0.0; element.properties.len()];
+        let mut tokens = Vec::new();
+        for _ in 0..element.count {
+            if format == PlyFormat::Ascii {
+                let mut line = String::new();
+                reader.read_line(&mut line).map_err(|e| e.to_string())?;
+                tokens = line.split_whitespace().rev().map(str::to_string).collect();
+            }
+
+            for (value, property) in values.iter_mut().zip(&element.properties) {
+                let len = match property.list {
+                    Some(ty) => read_ply_value(reader, format, ty, &mut tokens)? as usize,
+                    None => 1,
+                };
+                for _ in 0..len {
+                    *value = read_ply_value(reader, format, property.ty, &mut tokens)?;
+                }
+            }
+
+            if is_vertex {
+                let pos = position.map(|i| values[i.unwrap()] as f32);
+                let rgb = match color {
+                    [Some(r), Some(g), Some(b)] => {
+                        let scale = |i: usize| match element.properties[i].ty {
+                            ty if ty.is_float() => 1.0,
+                            ty => ((1u64 << (ty.size() * 8)) - 1) as f64,
+                        };
+                        vec3(
+                            (values[r] / scale(r)) as f32,
+                            (values[g] / scale(g)) as f32,
+                            (values[b] / scale(b)) as f32,
+                        )
+                    }
+                    _ => DEFAULT_COLOR,
+                };
+                marks.push(Mark::new(Vec3::from(pos), rgb));
+            }
+        }
+
+        if is_vertex {
+            break;
+        }
+    }
+    Ok(marks)
+}
+
+fn read_ply_header(reader: &mut impl BufRead) -> Result<(PlyFormat, Vec<PlyElement>), String> {
+    let mut line = String::new();
+    let mut next_line = |reader: &mut dyn BufRead| -> Result<String, String> {
+        line.clear();
+        match reader.read_line(&mut line).map_err(|e| e.to_string())? {
+            0 => Err("unexpected end of header".to_string()),
+            _ => Ok(line.trim().to_string()),
+        }
+    };
+
+    if next_line(reader)? != "ply" {
+        return Err("missing 'ply' magic".to_string());
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    loop {
+        let line = next_line(reader)?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            ["end_header"] => break,
+            ["format", name, _] => {
+                format = Some(match *name {
+                    "ascii" => PlyFormat::Ascii,
+                    "binary_little_endian" => PlyFormat::LittleEndian,
+                    "binary_big_endian" => PlyFormat::BigEndian,
+                    _ => return Err(format!("unknown format '{}'", name)),
+                })
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| format!("invalid element count '{}'", count))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", len_ty, ty, name] => {
+                elements.last_mut().ok_or("property before any element")?.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    list: Some(PlyType::parse(len_ty)?),
+                })
+            }
+            ["property", ty, name] => elements
+                .last_mut()
+                .ok_or("property before any element")?
+                .properties
+                .push(PlyProperty { name: name.to_string(), ty: PlyType::parse(ty)?, list: None }),
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(format!("unexpected header line '{}'", line)),
+        }
+    }
+
+    Ok((format.ok_or("missing format line")?, elements))
+}
+
+/// Reads one value, from `tokens` (the current line, reversed) in ASCII files or from `reader` in binary ones.
+fn read_ply_value(
+    reader: &mut impl Read,
+    format: PlyFormat,
+    ty: PlyType,
+    tokens: &mut Vec<String>,
+) -> Result<f64, String> {
+    if format == PlyFormat::Ascii {
+        let token = tokens.pop().ok_or("too few values on line")?;
+        return token.parse().map_err(|_| format!("invalid value '{}'", token));
+    }
+
+    let mut bytes = [0; 8];
+    let bytes = &mut bytes[..ty.size()];
+    reader.read_exact(bytes).map_err(|e| e.to_string())?;
+    if format == PlyFormat::BigEndian {
+        bytes.reverse();
+    }
+    let bytes: &[u8] = bytes;
+    Ok(match ty {
+        PlyType::I8 => bytes[0] as i8 as f64,
+        PlyType::U8 => bytes[0] as f64,
+        PlyType::I16 => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        PlyType::U16 => u16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        PlyType::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        PlyType::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        PlyType::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        PlyType::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+    })
+}
+
+impl State {
+    /// Imports the point cloud described by `options` into the scan, keeping any marks already present.
+    pub fn import_points(&mut self, options: &ImportOptions) {
+        match import_points(options) {
+            Ok(marks) => {
+                let n_marks = marks.len();
+                let layer = self.add_loaded_layer(&file_name(&options.path));
+                let marks: Vec<Mark> = marks.into_iter().map(|mark| Mark { layer, ..mark }).collect();
+                if self.marker.octree.count() == 0 {
+                    self.marker.octree = Octree::from_marks(marks);
+                } else {
+                    self.marker.octree.extend(marks);
+                }
+                self.coverage.rebuild(&self.marker.octree);
+                self.minimap.invalidate();
+                self.notify(format!("imported {} points from {}", n_marks, file_name(&options.path)));
+            }
+            Err(e) => {
+                warn!("import failed: {}", e);
+                self.notify(format!("import failed: {}", e));
+            }
+        }
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default()
+}
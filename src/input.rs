@@ -0,0 +1,338 @@
+use super::camera::{MAX_RAY_RANGE, MIN_RAY_RANGE};
+use super::State;
+use glam::{vec2, Vec2};
+use std::collections::HashMap;
+use winit::event::{ModifiersState, Touch, TouchPhase};
+use winit::window::CursorGrabMode;
+
+const CONE_SCROLL_SPEED: f32 = 0.0005;
+const RATE_SCROLL_SPEED: f64 = 0.1;
+const SIZE_SCROLL_SPEED: f32 = 0.1;
+
+/// Mouse motion events dropped after regaining focus; the first deltas include all cursor travel made while the
+/// window was in the background.
+const REFOCUS_SKIPPED_MOTIONS: u32 = 3;
+
+/// Fraction of the window width, from the left edge, where a touch starts the movement joystick.
+const JOYSTICK_REGION: f32 = 0.35;
+/// Joystick deflection in physical pixels below which no movement is applied.
+const JOYSTICK_DEAD_ZONE: f32 = 24.0;
+/// Look sensitivity of touch drags relative to mouse motion.
+const TOUCH_LOOK_SCALE: f32 = 1.5;
+/// How long a look touch must rest before it starts scanning.
+const HOLD_TIME: f64 = 0.4;
+/// Distance in physical pixels a resting touch may drift and still count as held.
+const HOLD_SLOP: f32 = 12.0;
+
+/// Scanner control the mouse wheel adjusts, selected by the held modifier keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WheelMode {
+    /// No modifier: the scan cone angle.
+    Cone,
+    /// Ctrl: shots per second.
+    Rate,
+    /// Alt: splat size.
+    PointSize,
+    /// No modifier in edit mode: the brush radius.
+    BrushRadius,
+    /// Ctrl in edit mode: the brush strength.
+    BrushStrength,
+    /// Ctrl and Alt with the clipping plane on: its distance from the camera.
+    ClipDistance,
+    /// No modifier during scan playback: the scrub time.
+    Scrub,
+}
+
+impl WheelMode {
+    pub fn from_modifiers(modifiers: ModifiersState, editing: bool, clipping: bool, scrubbing: bool) -> Self {
+        if editing {
+            if modifiers.ctrl() {
+                WheelMode::BrushStrength
+            } else {
+                WheelMode::BrushRadius
+            }
+        } else if clipping && modifiers.ctrl() && modifiers.alt() {
+            WheelMode::ClipDistance
+        } else if modifiers.ctrl() {
+            WheelMode::Rate
+        } else if modifiers.alt() {
+            WheelMode::PointSize
+        } else if scrubbing {
+            WheelMode::Scrub
+        } else {
+            WheelMode::Cone
+        }
+    }
+}
+
+/// How the cursor is kept inside the window while looking around.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorMode {
+    Confined,
+    Locked,
+    /// Neither grab mode is supported: the cursor is hidden and re-centered after every motion event.
+    Virtual,
+    Released,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TouchRole {
+    /// Started in the joystick region: the offset from the start point drives movement.
+    Joystick,
+    /// Started elsewhere: dragging looks around, resting in place scans.
+    Look,
+}
+
+struct TouchPoint {
+    role: TouchRole,
+    start: Vec2,
+    last: Vec2,
+    /// Time spent within `HOLD_SLOP` of the start point, or `None` once it moved away.
+    held: Option<f64>,
+}
+
+/// Active touches by finger id.
+#[derive(Default)]
+pub struct Touches {
+    points: HashMap<u64, TouchPoint>,
+    /// Whether touches currently control movement and scanning, so releasing them stops both without overriding
+    /// the keyboard and mouse otherwise.
+    driving: bool,
+}
+
+impl Touches {
+    /// Combined deflection of the joystick touches.
+    fn joystick(&self) -> Vec2 {
+        self.points
+            .values()
+            .filter(|point| point.role == TouchRole::Joystick)
+            .map(|point| point.last - point.start)
+            .sum()
+    }
+
+    fn scanning(&self) -> bool {
+        self.points.values().any(|point| point.held.is_some_and(|held| held >= HOLD_TIME))
+    }
+}
+
+pub struct Input {
+    pub modifiers: ModifiersState,
+    pub focused: bool,
+    pub cursor_mode: CursorMode,
+    pub touches: Touches,
+    skipped_motions: u32,
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            modifiers: ModifiersState::empty(),
+            focused: true,
+            cursor_mode: CursorMode::Released,
+            touches: Touches::default(),
+            skipped_motions: 0,
+        }
+    }
+}
+
+impl State {
+    /// Releases the cursor and drops all held input when the window loses focus, and grabs it back on return.
+    pub fn set_focused(&mut self, focused: bool) {
+        self.input.focused = focused;
+        self.input.modifiers = ModifiersState::empty();
+        self.marker.should_cast = false;
+        self.camera.mov.clear();
+        self.input.touches.points.clear();
+
+        if focused {
+            self.input.skipped_motions = REFOCUS_SKIPPED_MOTIONS;
+        }
+        self.museum.dragging = false;
+        self.grab_cursor(focused && !self.museum.active);
+    }
+
+    /// Grabs the cursor with the best mode the platform supports, trying confined, then locked, then a virtual
+    /// cursor, and returns the mode in use.
+    pub fn grab_cursor(&mut self, grab: bool) -> CursorMode {
+        self.input.cursor_mode = if !grab {
+            _ = self.window.set_cursor_grab(CursorGrabMode::None);
+            CursorMode::Released
+        } else if self.window.set_cursor_grab(CursorGrabMode::Confined).is_ok() {
+            CursorMode::Confined
+        } else if self.window.set_cursor_grab(CursorGrabMode::Locked).is_ok() {
+            CursorMode::Locked
+        } else {
+            CursorMode::Virtual
+        };
+
+        self.window.set_cursor_visible(!grab);
+        self.input.cursor_mode
+    }
+
+    /// Follows the scan button: scanning while it is held, or with toggle scanning on, flipping on each press.
+    pub fn scan_button(&mut self, pressed: bool) {
+        if !self.settings.accessibility.toggle_scan {
+            self.marker.should_cast = pressed;
+        } else if pressed {
+            self.marker.should_cast = !self.marker.should_cast;
+        }
+    }
+
+    pub fn mouse_motion(&mut self, dx: f32, dy: f32) {
+        if !self.input.focused {
+            return;
+        }
+        if self.input.skipped_motions > 0 {
+            self.input.skipped_motions -= 1;
+            return;
+        }
+        if self.museum.active {
+            if self.museum.dragging {
+                self.museum.rotate(dx, dy);
+            }
+            return;
+        }
+        self.camera.offset_view(dx, dy);
+
+        if self.input.cursor_mode == CursorMode::Virtual {
+            let size = self.window.inner_size();
+            let center = winit::dpi::PhysicalPosition::new(size.width / 2, size.height / 2);
+            _ = self.window.set_cursor_position(center);
+        }
+    }
+
+    /// Maps a mouse wheel step to the scanner control selected by the held modifiers: plain wheel changes the cone
+    /// angle (or the scrub time during scan playback), Ctrl the scan rate, Alt the splat size and Ctrl+Alt the clipping
+    /// plane distance; in edit mode they set the brush radius and strength instead. Rate and size scale
+    /// multiplicatively so each step stays proportional to the current value. In museum mode the wheel zooms the orbit
+    /// camera instead.
+    pub fn scroll(&mut self, y: f32) {
+        if !self.input.focused {
+            return;
+        }
+
+        if self.museum.active {
+            self.museum.zoom(y);
+            return;
+        }
+
+        match self.wheel_mode() {
+            WheelMode::BrushRadius => _ = self.editor.scale_radius(1.0 + y * SIZE_SCROLL_SPEED),
+            WheelMode::BrushStrength => _ = self.editor.scale_strength(1.0 + y as f64 * RATE_SCROLL_SPEED),
+            WheelMode::Rate => _ = self.marker.scale_cooldown(1.0 - y as f64 * RATE_SCROLL_SPEED),
+            WheelMode::PointSize => _ = self.marker.scale_point_size(1.0 + y * SIZE_SCROLL_SPEED),
+            WheelMode::ClipDistance => _ = self.clip.scale_distance(1.0 + y * SIZE_SCROLL_SPEED),
+            WheelMode::Scrub => self.scrub(y),
+            WheelMode::Cone => {
+                let range = self.camera.ray_range - y * CONE_SCROLL_SPEED;
+                self.camera.ray_range = range.clamp(MIN_RAY_RANGE, MAX_RAY_RANGE);
+                self.marker.retarget_adaptive_cone(self.camera.ray_range);
+            }
+        }
+        self.hud.record_scroll();
+        if !self.hud.visible {
+            let (_, value) = self.wheel_value();
+            self.notify(value.to_lowercase());
+        }
+    }
+
+    fn wheel_mode(&self) -> WheelMode {
+        let scrubbing = self.playback.scrub.is_some();
+        WheelMode::from_modifiers(self.input.modifiers, self.editor.active, self.clip.enabled, scrubbing)
+    }
+
+    /// The control the wheel currently adjusts and its value, as shown on the HUD.
+    pub fn wheel_value(&self) -> (WheelMode, String) {
+        let mode = self.wheel_mode();
+        let value = match mode {
+            WheelMode::Cone if self.marker.adaptive_cone.is_some() => {
+                format!("CONE A {:.0}%", self.camera.ray_range * 100.0)
+            }
+            WheelMode::Cone => format!("CONE {:.0}%", self.camera.ray_range * 100.0),
+            WheelMode::Rate => format!("RATE {:.0}", self.marker.scan_rate()),
+            WheelMode::PointSize => format!("SIZE {:.2}", self.marker.point_size()),
+            WheelMode::BrushRadius => format!("EDIT SIZE {:.0}", self.editor.radius),
+            WheelMode::BrushStrength => format!("EDIT POWER {:.2}", self.editor.strength),
+            WheelMode::ClipDistance => format!("CLIP {:.0}", self.clip.distance()),
+            WheelMode::Scrub => {
+                let scrub = self.playback.scrub.unwrap_or_default();
+                format!("TIME {:.0}/{:.0}S", scrub, self.playback.now())
+            }
+        };
+        (mode, value)
+    }
+
+    /// Tracks a finger: touches starting in the left joystick region move the camera by their offset from where they
+    /// began, other touches look around while dragged and scan while held in place. In museum mode every drag
+    /// rotates the orbit camera.
+    pub fn touch(&mut self, touch: &Touch) {
+        let pos = vec2(touch.location.x as f32, touch.location.y as f32);
+        let touches = &mut self.input.touches;
+
+        match touch.phase {
+            TouchPhase::Started => {
+                let in_joystick = pos.x < self.config.width as f32 * JOYSTICK_REGION && !self.museum.active;
+                let role = if in_joystick { TouchRole::Joystick } else { TouchRole::Look };
+                let held = (role == TouchRole::Look && !self.museum.active).then_some(0.0);
+                touches.points.insert(touch.id, TouchPoint { role, start: pos, last: pos, held });
+            }
+            TouchPhase::Moved => {
+                let Some(point) = touches.points.get_mut(&touch.id) else {
+                    return;
+                };
+                let delta = pos - point.last;
+                point.last = pos;
+                if point.held.is_some() && pos.distance(point.start) > HOLD_SLOP {
+                    point.held = None;
+                }
+
+                if point.role == TouchRole::Look && point.held.is_none() {
+                    if self.museum.active {
+                        self.museum.rotate(delta.x, delta.y);
+                    } else {
+                        self.camera.offset_view(delta.x * TOUCH_LOOK_SCALE, delta.y * TOUCH_LOOK_SCALE);
+                    }
+                }
+            }
+            TouchPhase::Ended | TouchPhase::Cancelled => {
+                touches.points.remove(&touch.id);
+            }
+        }
+        self.apply_touches();
+    }
+
+    /// Advances the hold timers of resting touches.
+    pub fn update_touch(&mut self, dt: f64) {
+        let touches = &mut self.input.touches;
+        if touches.points.is_empty() {
+            return;
+        }
+        for held in touches.points.values_mut().filter_map(|point| point.held.as_mut()) {
+            *held += dt;
+        }
+        self.apply_touches();
+    }
+
+    fn apply_touches(&mut self) {
+        let touches = &mut self.input.touches;
+        let active = touches.points.values().any(|point| point.role == TouchRole::Joystick) || touches.scanning();
+        if !active && !touches.driving {
+            return;
+        }
+        touches.driving = active;
+
+        let stick = touches.joystick();
+        let mov = &mut self.camera.mov;
+        mov.forward = stick.y < -JOYSTICK_DEAD_ZONE;
+        mov.backward = stick.y > JOYSTICK_DEAD_ZONE;
+        mov.left = stick.x < -JOYSTICK_DEAD_ZONE;
+        mov.right = stick.x > JOYSTICK_DEAD_ZONE;
+        self.marker.should_cast = touches.scanning();
+    }
+}
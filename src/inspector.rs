@@ -0,0 +1,188 @@
+use super::camera::{Camera, CameraUniform, Viewpoint};
+use super::lines::LineRenderer;
+use super::marker::{create_instance_buffer, octree::VisibleCache, MarkRaw, Marker};
+use super::State;
+use glam::{vec3, vec4, Vec3};
+
+const INSPECTOR_INST_N: usize = 250000;
+const VIEWPORT_FRACTION: f32 = 0.3;
+const VIEWPORT_MARGIN: f32 = 16.0;
+
+const TOP_DOWN_HEIGHT: f32 = 400.0;
+const ORBIT_RADIUS: f32 = 250.0;
+const ORBIT_HEIGHT: f32 = 120.0;
+const ORBIT_SPEED: f32 = 0.3;
+const FRUSTUM_LENGTH: f32 = 150.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InspectorView {
+    Off,
+    TopDown,
+    Orbit,
+}
+
+/// Secondary camera rendered into a corner viewport, showing the point cloud from above or orbiting the player,
+/// with the player's view frustum outlined.
+pub struct Inspector {
+    pub view: InspectorView,
+    camera: Camera,
+    camera_uniform: CameraUniform,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+
+    instances: Vec<MarkRaw>,
+    visible_cache: VisibleCache,
+    instance_buffer: wgpu::Buffer,
+    n_visible: u32,
+
+    backdrop_pipeline: wgpu::RenderPipeline,
+    lines: LineRenderer,
+    orbit_angle: f32,
+}
+
+impl Inspector {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, marker: &Marker) -> Self {
+        let camera = Camera::new(1.0);
+        let camera_uniform = CameraUniform::new(&camera);
+        let camera_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Inspector Camera Buffer"),
+            size: std::mem::size_of::<CameraUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let camera_bind_group = marker.create_camera_bind_group(device, &camera_buffer);
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("inspector.wgsl"));
+        let backdrop_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Inspector Backdrop Layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let backdrop_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Inspector Backdrop Pipeline"),
+            layout: Some(&backdrop_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_backdrop", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_backdrop",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        Self {
+            view: InspectorView::Off,
+            camera,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            instances: Vec::with_capacity(INSPECTOR_INST_N),
+            visible_cache: VisibleCache::new(),
+            instance_buffer: create_instance_buffer(device, INSPECTOR_INST_N),
+            n_visible: 0,
+            backdrop_pipeline,
+            lines: LineRenderer::new(device, config.format, &marker.camera_bind_group_layout),
+            orbit_angle: 0.0,
+        }
+    }
+
+    pub fn cycle_view(&mut self) -> InspectorView {
+        self.view = match self.view {
+            InspectorView::Off => InspectorView::TopDown,
+            InspectorView::TopDown => InspectorView::Orbit,
+            InspectorView::Orbit => InspectorView::Off,
+        };
+        self.view
+    }
+}
+
+/// Corner viewport as `(x, y, width, height)` in physical pixels.
+fn viewport(config: &wgpu::SurfaceConfiguration) -> (f32, f32, f32, f32) {
+    let size = f32::min(config.width as f32 * VIEWPORT_FRACTION, config.height as f32 - 2.0 * VIEWPORT_MARGIN);
+    let size = size.max(1.0);
+    (config.width as f32 - size - VIEWPORT_MARGIN, config.height as f32 - size - VIEWPORT_MARGIN, size, size)
+}
+
+impl State {
+    pub fn cycle_inspector(&mut self) {
+        let view = self.inspector.cycle_view();
+        self.notify(format!("inspector: {:?}", view));
+    }
+
+    pub fn update_inspector(&mut self, dt: f64) {
+        let inspector = &mut self.inspector;
+        let player = self.camera.viewpoint();
+
+        match inspector.view {
+            InspectorView::Off => return,
+            InspectorView::TopDown => inspector.camera.set_viewpoint(Viewpoint {
+                pos: player.pos + vec3(0.0, TOP_DOWN_HEIGHT, 0.0),
+                yaw: player.yaw,
+                pitch: -std::f32::consts::FRAC_PI_2,
+            }),
+            InspectorView::Orbit => {
+                inspector.orbit_angle += ORBIT_SPEED * dt as f32;
+                let offset = vec3(f32::cos(inspector.orbit_angle), 0.0, f32::sin(inspector.orbit_angle));
+                let pos = player.pos + offset * ORBIT_RADIUS + vec3(0.0, ORBIT_HEIGHT, 0.0);
+                inspector.camera.look_at(pos, player.pos);
+            }
+        }
+
+        let (_, _, width, height) = viewport(&self.config);
+        inspector.camera.set_aspect(width / height);
+        inspector.camera_uniform.update_view_proj(&inspector.camera);
+        self.queue.write_buffer(&inspector.camera_buffer, 0, bytemuck::cast_slice(&[inspector.camera_uniform]));
+
+        let frustum = inspector.camera.frustum();
+        self.marker.octree.get_visible_cached(
+            &mut inspector.visible_cache,
+            &mut inspector.instances,
+            INSPECTOR_INST_N,
+            inspector.camera.pos,
+            frustum,
+        );
+        let n_total = inspector.instances.len();
+        let visible = &inspector.instances[n_total.saturating_sub(INSPECTOR_INST_N)..];
+        self.queue.write_buffer(&inspector.instance_buffer, 0, bytemuck::cast_slice(visible));
+        inspector.n_visible = visible.len() as u32;
+
+        let color = vec4(1.0, 0.9, 0.2, 1.0);
+        let corners = self.camera.frustum_corners(FRUSTUM_LENGTH);
+        for (i, corner) in corners.iter().enumerate() {
+            inspector.lines.push(player.pos, *corner, color);
+            inspector.lines.push(*corner, corners[(i + 1) % corners.len()], color);
+        }
+        inspector.lines.push(player.pos, player.pos + Vec3::Y * 10.0, vec4(1.0, 1.0, 1.0, 1.0));
+        inspector.lines.upload(&self.queue);
+    }
+
+    pub fn render_inspector<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let inspector = &self.inspector;
+        if inspector.view == InspectorView::Off {
+            return;
+        }
+
+        let (x, y, width, height) = viewport(&self.config);
+        render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+        render_pass.set_scissor_rect(x as u32, y as u32, width as u32, height as u32);
+
+        render_pass.set_pipeline(&inspector.backdrop_pipeline);
+        render_pass.draw(0..3, 0..1);
+
+        self.marker.draw(
+            render_pass,
+            &inspector.camera_bind_group,
+            &inspector.instance_buffer,
+            inspector.n_visible,
+            false,
+        );
+        inspector.lines.draw(render_pass, &inspector.camera_bind_group);
+    }
+}
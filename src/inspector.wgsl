@@ -0,0 +1,12 @@
+let BACKDROP_COLOR = vec4<f32>(0.03, 0.03, 0.05, 1.0);
+
+@vertex
+fn vs_backdrop(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_backdrop() -> @location(0) vec4<f32> {
+    return BACKDROP_COLOR;
+}
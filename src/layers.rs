@@ -0,0 +1,173 @@
+use super::marker::{Mark, MAX_LAYERS};
+use super::State;
+use glam::{vec3, Vec3};
+use tracing::warn;
+use winit::event::VirtualKeyCode;
+
+/// Colors a layer's marks can be drawn in instead of their own, in the order the panel steps through them.
+pub const LAYER_COLORS: [Vec3; 6] = [
+    vec3(1.0, 0.35, 0.35),
+    vec3(1.0, 0.85, 0.2),
+    vec3(0.35, 1.0, 0.45),
+    vec3(0.3, 0.85, 1.0),
+    vec3(0.75, 0.45, 1.0),
+    vec3(1.0, 1.0, 1.0),
+];
+
+/// A named group of marks, e.g. everything scanned between two saves or loaded from one file.
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    /// Index into [`LAYER_COLORS`] of the color the layer's marks are drawn in, or `None` for their own colors.
+    pub color: Option<usize>,
+}
+
+impl Layer {
+    fn new(name: String) -> Self {
+        Self { name, visible: true, color: None }
+    }
+}
+
+/// The layers of the session and the panel that manages them.
+pub struct Layers {
+    pub list: Vec<Layer>,
+    /// Layer new marks are recorded in.
+    pub current: u32,
+    /// Scanning sessions started so far, which number the layers they record into.
+    sessions: usize,
+    pub panel_open: bool,
+    /// Selected line of the panel: a layer, or one past the last for the new layer action.
+    pub selected: usize,
+}
+
+impl Layers {
+    pub fn new() -> Self {
+        Self {
+            list: vec![Layer::new("session 1".to_string())],
+            current: 0,
+            sessions: 1,
+            panel_open: false,
+            selected: 0,
+        }
+    }
+
+    /// Adds a layer named `name`, or returns `None` once there are [`MAX_LAYERS`].
+    fn add(&mut self, name: String) -> Option<u32> {
+        if self.list.len() >= MAX_LAYERS {
+            warn!("no room for layer {}", name);
+            return None;
+        }
+        self.list.push(Layer::new(name));
+        Some(self.list.len() as u32 - 1)
+    }
+
+    /// Records new marks into a new session layer, or keeps the current one if there is no room.
+    fn next_session(&mut self) -> Option<u32> {
+        self.sessions += 1;
+        let layer = self.add(format!("session {}", self.sessions))?;
+        self.current = layer;
+        Some(layer)
+    }
+
+    /// Bit per visible layer.
+    pub fn mask(&self) -> u32 {
+        self.list.iter().enumerate().filter(|(_, layer)| layer.visible).fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    /// Stamps a mark recorded now with the scan time and the current layer.
+    pub fn stamp(&self, mark: Mark) -> Mark {
+        mark.stamp(self.playback.now(), self.layers.current)
+    }
+
+    /// Starts recording new marks into a new layer.
+    pub fn new_layer(&mut self) {
+        match self.layers.next_session() {
+            Some(layer) => {
+                let name = &self.layers.list[layer as usize].name;
+                self.notify(format!("recording into {}", name));
+            }
+            None => self.notify(format!("at most {} layers", MAX_LAYERS)),
+        }
+    }
+
+    /// Adds a layer for marks loaded from `name`, or falls back to the current layer if there is no room.
+    pub fn add_loaded_layer(&mut self, name: &str) -> u32 {
+        self.layers.add(name.to_string()).unwrap_or(self.layers.current)
+    }
+
+    /// Starts over with the layers of a scan that replaced every mark: one layer named `name` holding the loaded
+    /// marks, which are all in the first layer, and a new session layer for what is scanned from now on.
+    pub fn reset_layers(&mut self, name: &str) {
+        let sessions = self.layers.sessions;
+        self.layers = Layers { list: vec![Layer::new(name.to_string())], current: 0, sessions, ..Layers::new() };
+        self.layers.next_session();
+    }
+
+    pub fn toggle_layer_panel(&mut self) {
+        // The panel is drawn by the HUD, so it can't be used without it.
+        if !cfg!(feature = "ui") {
+            self.notify("the layer panel is not available in this build".to_string());
+            return;
+        }
+        self.layers.panel_open = !self.layers.panel_open;
+        self.layers.selected = self.layers.selected.min(self.layers.list.len());
+        self.hud.visible |= self.layers.panel_open;
+    }
+
+    /// Handles a key press while the layer panel is open: up and down pick a layer, return shows or hides it and left
+    /// and right step through its color overrides. Return on the last line starts a new layer.
+    pub fn layer_key(&mut self, keycode: VirtualKeyCode) {
+        let layers = &mut self.layers;
+        let lines = layers.list.len() + 1;
+        match keycode {
+            VirtualKeyCode::Up => layers.selected = (layers.selected + lines - 1) % lines,
+            VirtualKeyCode::Down => layers.selected = (layers.selected + 1) % lines,
+            VirtualKeyCode::Return if layers.selected == layers.list.len() => self.new_layer(),
+            VirtualKeyCode::Return => layers.list[layers.selected].visible ^= true,
+            VirtualKeyCode::Left | VirtualKeyCode::Right if layers.selected < layers.list.len() => {
+                // `None` sits between the last color and the first.
+                let n = LAYER_COLORS.len() + 1;
+                let step = if keycode == VirtualKeyCode::Right { 1 } else { n - 1 };
+                let layer = &mut layers.list[layers.selected];
+                layer.color = match (layer.color.map_or(0, |color| color + 1) + step) % n {
+                    0 => None,
+                    index => Some(index - 1),
+                };
+            }
+            _ => {}
+        }
+    }
+
+    /// Hides the marks of hidden layers from culling and passes the color overrides on to the mark shaders.
+    pub fn update_layers(&mut self) {
+        self.marker.octree.set_layer_mask(self.layers.mask());
+        let colors = self.layers.list.iter().map(|layer| layer.color.map(|color| LAYER_COLORS[color]));
+        self.marker.set_layer_colors(colors);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Layers;
+    use crate::marker::MAX_LAYERS;
+
+    #[test]
+    fn layers_stop_at_the_mask_width_and_hidden_ones_leave_the_mask() {
+        let mut layers = Layers::new();
+        while layers.next_session().is_some() {}
+        assert_eq!(layers.list.len(), MAX_LAYERS);
+        assert_eq!(layers.current, MAX_LAYERS as u32 - 1);
+        assert_eq!(layers.mask(), u32::MAX);
+
+        layers.list[3].visible = false;
+        assert_eq!(layers.mask(), !(1 << 3));
+    }
+}
@@ -0,0 +1,427 @@
+//! Point cloud scanning engine: a procedural [`Terrain`] (marching-cubes [`world::World`] by default), an
+//! octree-backed [`Marker`] renderer and a free-fly [`Camera`]. The `scanner` binary is a thin winit front-end over
+//! [`State`].
+
+use anaglyph::Anaglyph;
+use archive::ArchiveLoad;
+use args::Args;
+use audio::Audio;
+use beams::Beams;
+use breadcrumbs::Breadcrumbs;
+use camera::Camera;
+use clip::Clip;
+use coverage::Coverage;
+use editor::Editor;
+use entity::Entities;
+use gameplay::Gameplay;
+use governor::Governor;
+use history::History;
+use hud::Hud;
+use input::Input;
+use inspector::Inspector;
+use layers::Layers;
+use locale::Locale;
+use marker::{Marker, RayQueue};
+use menu::Menu;
+use minimap::Minimap;
+use museum::Museum;
+use net::Net;
+use occlusion::Occlusion;
+use persistence::Autosave;
+use photo::Photo;
+use picker::Picker;
+use playback::Playback;
+use profiler::{Profiler, Stamp};
+use rand::{rngs::StdRng, SeedableRng};
+use resample::Resampler;
+use scripting::Scripting;
+use selection::Selection;
+use session::Session;
+use settings::{Settings, TerrainSettings};
+use stats::Stats;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use system::System;
+use tracing::{info, warn};
+use trail::Trail;
+use waypoints::Waypoints;
+use world::Terrain;
+
+pub mod anaglyph;
+pub mod archive;
+pub mod args;
+pub mod audio;
+pub mod beams;
+pub mod bench;
+pub mod breadcrumbs;
+pub mod camera;
+pub mod clip;
+pub mod coverage;
+pub mod crash;
+mod display;
+pub mod editor;
+pub mod entity;
+#[cfg_attr(not(feature = "export"), path = "stubs/export.rs")]
+pub mod export;
+pub mod gameplay;
+pub mod governor;
+mod gpu;
+pub mod history;
+#[cfg_attr(not(feature = "ui"), path = "stubs/hud.rs")]
+pub mod hud;
+pub mod import;
+pub mod input;
+pub mod inspector;
+pub mod layers;
+pub mod lines;
+pub mod locale;
+pub mod marker;
+pub mod menu;
+pub mod minimap;
+pub mod museum;
+#[cfg_attr(not(feature = "net"), path = "stubs/net.rs")]
+pub mod net;
+pub mod occlusion;
+pub mod origin;
+pub mod persistence;
+pub mod photo;
+pub mod picker;
+pub mod playback;
+pub mod profiler;
+pub mod resample;
+pub mod scripting;
+pub mod selection;
+#[cfg_attr(not(feature = "net"), path = "stubs/server.rs")]
+pub mod server;
+pub mod session;
+pub mod settings;
+pub mod stats;
+pub mod system;
+pub mod trace;
+pub mod trail;
+pub mod util;
+pub mod waypoints;
+pub mod world;
+
+const TITLE_UPDATE_TIME: f64 = 1.0;
+const NOTIFICATION_TIME: f64 = 2.0;
+
+pub struct State {
+    pub surface: wgpu::Surface,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub config: wgpu::SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
+
+    pub camera: Camera,
+    pub marker: Marker,
+    pub beams: Beams,
+    pub clip: Clip,
+    pub selection: Selection,
+    pub trail: Trail,
+    pub picker: Picker,
+    pub minimap: Minimap,
+    pub gameplay: Gameplay,
+    pub inspector: Inspector,
+    pub hud: Hud,
+    pub menu: Menu,
+    pub museum: Museum,
+    pub photo: Photo,
+    pub net: Net,
+    pub occlusion: Occlusion,
+    pub resampler: Resampler,
+    pub profiler: Profiler,
+    pub governor: Governor,
+    pub anaglyph: Anaglyph,
+    pub coverage: Coverage,
+    pub editor: Editor,
+    pub history: History,
+    pub world: Box<dyn Terrain>,
+    pub entities: Entities,
+    pub input: Input,
+    pub session: Session,
+    pub settings: Settings,
+    pub locale: Locale,
+    pub stats: Stats,
+    pub audio: Audio,
+    pub autosave: Autosave,
+    pub waypoints: Waypoints,
+    pub breadcrumbs: Breadcrumbs,
+    pub playback: Playback,
+    pub layers: Layers,
+    pub scripting: Scripting,
+    /// Updated and rendered in order every frame, see [`system::builtin_systems`].
+    systems: Vec<Box<dyn System>>,
+    archive_load: Option<ArchiveLoad>,
+    /// World position of the local origin, see [`origin`].
+    origin: glam::DVec3,
+
+    title_timer: f64,
+    title_update: bool,
+    notification: Option<(String, f64)>,
+    minimized: bool,
+    /// Set from the device's error handler when it reports the device lost.
+    device_lost: Arc<AtomicBool>,
+    /// Consecutive frames that failed with a lost or outdated surface.
+    failed_frames: u32,
+
+    pub window: winit::window::Window,
+}
+
+impl State {
+    /// Creates the wgpu device and surface for `window` and sets up the camera, renderer and world.
+    pub fn new(window: winit::window::Window, args: &Args) -> Result<State, String> {
+        let mut settings = Settings::load();
+        menu::validate(&mut settings);
+        crash::record_settings(&settings);
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let gpu::Gpu { surface, device, queue, config, present_modes } =
+            gpu::create(&window, device_lost.clone(), settings.display.vsync)?;
+
+        let mut camera = Camera::new(config.width as f32 / config.height as f32);
+        camera.set_fov(settings.display.fov);
+        camera.set_sensitivity(settings.controls.sensitivity);
+        let mut marker = Marker::new(&device, &config, &camera);
+        marker.set_palette(settings.display.palette);
+        let beams = Beams::new(&device, config.format, &marker);
+        let clip = Clip::new(&device, config.format, &marker);
+        let selection = Selection::new(&device, config.format, &marker);
+        let trail = Trail::new(&device, config.format, &marker);
+        let picker = Picker::new(&device, config.format, &marker);
+        let minimap = Minimap::new(&device, config.format);
+        let inspector = Inspector::new(&device, &config, &marker);
+        let hud = Hud::new(&device, config.format, window.scale_factor());
+        let photo = Photo::new(&device, config.format);
+        let anaglyph = Anaglyph::new(&device, &config);
+        let occlusion = Occlusion::new(&device, &config, &marker);
+        let mut world = args.terrain.create()?;
+        let input = Input::new();
+        let session = Session::load();
+        if world.resolution().is_some() {
+            let TerrainSettings { voxel_size, detail_band, lod_levels, lod_distance } = settings.terrain;
+            let applied =
+                world.set_resolution(voxel_size, detail_band).and_then(|_| world.set_lod(lod_levels, lod_distance));
+            if let Err(e) = applied {
+                warn!("ignoring terrain settings: {}", e);
+            }
+        }
+        let locale = Locale::load(&settings.display.language);
+        let profiler = Profiler::new(&device, &queue);
+        let resampler = Resampler::new(&device, config.format, settings.display.render_scale);
+        let audio = Audio::new(&settings.audio);
+        let gameplay =
+            Gameplay::new(&device, config.format, &marker, settings.gameplay.enabled, settings.gameplay.heat);
+        let net = match args.listen {
+            Some(port) => Net::bind(port, &args.peers)?,
+            None if !args.peers.is_empty() => Net::bind(0, &args.peers)?,
+            None => Net::disabled(),
+        };
+
+        let seed = args.seed.or(settings.scanner.seed).unwrap_or_else(rand::random);
+        info!("scan seed {}", seed);
+        marker.set_rng(StdRng::seed_from_u64(seed));
+        marker.queue = RayQueue::new(settings.scanner.ray_budget);
+
+        let systems = system::builtin_systems(net.enabled());
+        let mut state = Self {
+            surface,
+            device,
+            queue,
+            config,
+            present_modes,
+            camera,
+            marker,
+            beams,
+            clip,
+            selection,
+            trail,
+            picker,
+            minimap,
+            gameplay,
+            inspector,
+            hud,
+            menu: Menu::new(),
+            museum: Museum::new(),
+            photo,
+            net,
+            occlusion,
+            resampler,
+            profiler,
+            governor: Governor::new(settings.display.adaptive_quality),
+            anaglyph,
+            coverage: Coverage::new(),
+            editor: Editor::new(),
+            history: History::new(),
+            world,
+            entities: Entities::demo(camera::SPAWN_POS),
+            input,
+            session,
+            settings,
+            locale,
+            stats: Stats::new(),
+            audio,
+            autosave: Autosave::new(),
+            waypoints: Waypoints::new(),
+            breadcrumbs: Breadcrumbs::new(),
+            playback: Playback::new(),
+            layers: Layers::new(),
+            scripting: Scripting::new(),
+            systems,
+            archive_load: None,
+            origin: glam::DVec3::ZERO,
+            title_timer: 0.0,
+            title_update: false,
+            notification: None,
+            minimized: false,
+            device_lost,
+            failed_frames: 0,
+            window,
+        };
+
+        state.resize_scene();
+        if let Some(options) = &args.import {
+            state.import_points(options);
+        }
+        if let Some(path) = &args.archive {
+            state.load_archive(path);
+        }
+        state.load_scripts();
+        Ok(state)
+    }
+
+    /// Reconfigures the surface and camera for a new window size. A zero-sized (minimized) window keeps the old
+    /// configuration and rendering is skipped until it is restored.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.minimized = width == 0 || height == 0;
+        if self.minimized {
+            return;
+        }
+
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(&self.device, &self.config);
+        self.resize_scene();
+        self.camera.set_aspect(width as f32 / height as f32);
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        let _span = tracing::info_span!("update").entered();
+        self.profiler.begin_cpu();
+        // Photo mode and the settings menu pause everything that moves or scans on its own.
+        let paused = self.photo.active || self.menu.open;
+        self.update_systems(dt, paused);
+
+        if let Some((_, ref mut timer)) = self.notification {
+            *timer -= dt;
+            if *timer <= 0.0 {
+                self.notification = None;
+                self.title_timer = 0.0;
+            }
+        }
+
+        self.title_timer -= dt;
+        self.title_update = false;
+        if self.title_timer <= 0.0 {
+            self.title_timer += TITLE_UPDATE_TIME;
+            self.title_update = true;
+        }
+    }
+
+    /// Shows a short-lived message next to the stats in the window title.
+    pub fn notify(&mut self, text: String) {
+        self.notification = Some((text, NOTIFICATION_TIME));
+        self.title_timer = 0.0;
+    }
+
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        if self.minimized {
+            return Ok(());
+        }
+        let _span = tracing::info_span!("render").entered();
+
+        self.prepare_markers();
+
+        // Waiting for the next texture is vsync or the compositor, not work the frame could shed.
+        self.profiler.pause_cpu();
+        let output = self.surface.get_current_texture()?;
+        self.profiler.resume_cpu();
+        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut encoder =
+            self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Render Encoder") });
+        self.begin_profile(&mut encoder);
+        let scene_view = self.resampler.target_view().unwrap_or(&view);
+        self.reproject_history(&mut encoder);
+        if !self.encode_anaglyph(&mut encoder, scene_view) {
+            self.encode_scene(&mut encoder, scene_view, true);
+        }
+        self.stamp_history(&mut encoder);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.render_resampled(&mut render_pass);
+            if self.photo.active {
+                self.render_photo(&mut render_pass);
+            } else {
+                self.render_systems(&mut render_pass);
+            }
+        }
+
+        self.resolve_profile(&mut encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.map_profile();
+        output.present();
+        self.failed_frames = 0;
+
+        Ok(())
+    }
+
+    /// Splats the marks, draws the terrain occluders and marks into `view` and shades them with eye-dome lighting.
+    /// `view` must match the size of the occlusion depth buffer and the splat frame, see [`State::render_size`].
+    /// `with_history` draws the accumulated history behind the marks, which only lines up with views of the render
+    /// size; it is only set for the frame shown in the window, whose passes are also timed by the profiler.
+    fn encode_scene(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, with_history: bool) {
+        let stamp = |encoder: &mut wgpu::CommandEncoder, stamp| {
+            if with_history {
+                self.profiler.stamp(encoder, stamp);
+            }
+        };
+        stamp(encoder, Stamp::SplatStart);
+        self.dispatch_splats(encoder);
+        stamp(encoder, Stamp::SplatEnd);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Scene Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.occlusion.depth_view(),
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.0), store: true }),
+                    stencil_ops: None,
+                }),
+            });
+            self.render_occlusion(&mut render_pass);
+            if with_history {
+                self.render_history(&mut render_pass);
+            }
+            self.render_markers(&mut render_pass);
+        }
+        stamp(encoder, Stamp::SceneEnd);
+        self.encode_eye_dome(encoder, view);
+    }
+}
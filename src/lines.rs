@@ -0,0 +1,114 @@
+use glam::{Vec3, Vec4};
+
+const MAX_LINE_VERTICES: usize = 16384;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LineVertex {
+    pos: [f32; 3],
+    color: [f32; 4],
+}
+
+impl LineVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4];
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+/// Immediate-mode world-space line list: segments pushed during a frame are uploaded and drawn with whichever camera
+/// bind group the caller provides, then cleared.
+pub struct LineRenderer {
+    pipeline: wgpu::RenderPipeline,
+    vertices: Vec<LineVertex>,
+    vertex_buffer: wgpu::Buffer,
+    n_vertices: u32,
+}
+
+impl LineRenderer {
+    /// `camera_bind_group_layout` must expose a `CameraUniform` at binding 0, as the marker layout does.
+    pub fn new(
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        camera_bind_group_layout: &wgpu::BindGroupLayout,
+    ) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("lines.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Line Pipeline Layout"),
+            bind_group_layouts: &[camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Line Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[LineVertex::desc()] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Line Vertex Buffer"),
+            size: (MAX_LINE_VERTICES * std::mem::size_of::<LineVertex>()) as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self { pipeline, vertices: Vec::with_capacity(MAX_LINE_VERTICES), vertex_buffer, n_vertices: 0 }
+    }
+
+    /// Queues a segment for this frame; segments beyond the buffer capacity are dropped.
+    pub fn push(&mut self, a: Vec3, b: Vec3, color: Vec4) {
+        self.push_gradient(a, color, b, color);
+    }
+
+    pub fn push_gradient(&mut self, a: Vec3, color_a: Vec4, b: Vec3, color_b: Vec4) {
+        if self.vertices.len() + 2 > MAX_LINE_VERTICES {
+            return;
+        }
+        self.vertices.push(LineVertex { pos: a.into(), color: color_a.into() });
+        self.vertices.push(LineVertex { pos: b.into(), color: color_b.into() });
+    }
+
+    /// Uploads the queued segments and clears the queue for the next frame.
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+        self.n_vertices = self.vertices.len() as u32;
+        self.vertices.clear();
+    }
+
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup) {
+        if self.n_vertices == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.draw(0..self.n_vertices, 0..1);
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use tracing::warn;
+
+/// Directory translations are read from, one `<language>.json` file per language.
+const LOCALE_DIR: &str = "locales";
+
+/// Built-in English strings, which every translation falls back to. `{name}` placeholders are filled in by
+/// [`Locale::format`]. HUD and menu strings are drawn with the HUD's pixel font, which only has glyphs for digits, a
+/// few symbols and capital letters; anything else is drawn blank.
+const ENGLISH: &[(&str, &str)] = &[
+    ("title", "Scanner Demo"),
+    ("title.marks", "marks: {marks}({stored}) | cap: {cap}"),
+    ("title.players", "players: {players}"),
+    ("title.loading", "loading {percent}%"),
+    ("hud.no_hit", "NO HIT"),
+    ("hud.energy", "ENERGY  RANGE {range}"),
+    ("hud.heat", "HEAT"),
+    ("hud.overheat", "OVERHEAT"),
+    ("hud.depth", "DEPTH {depth}"),
+    ("hud.coverage", "COVERAGE {coverage}"),
+    ("hud.cache", "CACHE {rate} {cached}"),
+    ("hud.gpu_splat", "GPU SPLAT {ms}"),
+    ("hud.gpu_scene", "GPU SCENE {ms}"),
+    ("hud.gpu_post", "GPU POST {ms}"),
+    ("hud.gpu_total", "GPU TOTAL {ms}"),
+    ("hud.cpu", "CPU {ms}"),
+    ("menu.title", "SETTINGS"),
+    ("menu.hint", "ARROWS SELECT AND CHANGE   RETURN CONFIRM   ESC CLOSE"),
+    ("menu.on", "ON"),
+    ("menu.off", "OFF"),
+    ("menu.controls.sensitivity", "MOUSE SENSITIVITY"),
+    ("menu.display.fov", "FIELD OF VIEW"),
+    ("menu.display.vsync", "VSYNC"),
+    ("menu.display.fps_cap", "FRAME RATE CAP"),
+    ("menu.display.low_power", "LOW POWER IN BACKGROUND"),
+    ("menu.display.render_scale", "RENDER SCALE"),
+    ("menu.display.ui_scale", "UI SCALE"),
+    ("menu.display.palette", "PALETTE"),
+    ("menu.display.adaptive_quality", "ADAPTIVE QUALITY"),
+    ("menu.scanner.miss_marks", "MISS MARKS"),
+    ("menu.scanner.miss_indicator", "MISS WARNING"),
+    ("menu.scanner.ray_budget", "RAY BUDGET"),
+    ("menu.scanner.auto_stop", "AUTO-STOP DENSITY"),
+    ("menu.scanner.watertight", "WATERTIGHT HITS"),
+    ("menu.accessibility.toggle_scan", "CLICK TO TOGGLE SCANNING"),
+    ("menu.accessibility.reduced_motion", "REDUCED MOTION"),
+    ("menu.accessibility.flash_limit", "BEAM FLASHES PER SECOND"),
+    ("menu.restore_defaults", "RESTORE DEFAULTS"),
+    ("summary.title", "session summary"),
+    ("summary.time_played", "time played"),
+    ("summary.rays", "rays cast"),
+    ("summary.hit_ratio", "hit ratio"),
+    ("summary.marks", "marks created"),
+    ("summary.distance", "distance"),
+    ("summary.deepest", "deepest point"),
+];
+
+/// Table of user-facing strings in the configured language. Translations are flat JSON objects from string keys to
+/// text; keys they leave out keep their English text.
+pub struct Locale {
+    strings: HashMap<String, String>,
+}
+
+impl Locale {
+    pub fn english() -> Self {
+        Self { strings: ENGLISH.iter().map(|&(key, text)| (key.to_string(), text.to_string())).collect() }
+    }
+
+    /// Loads the translation for `language` from the locale directory, falling back to English if it is missing or
+    /// unreadable. `en` needs no file.
+    pub fn load(language: &str) -> Self {
+        if language == "en" {
+            return Self::english();
+        }
+        let path = Path::new(LOCALE_DIR).join(format!("{}.json", language));
+        let result = std::fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|text| Self::parse(&text));
+        result.unwrap_or_else(|e| {
+            warn!("failed to load {}: {}", path.display(), e);
+            Self::english()
+        })
+    }
+
+    /// English overridden by the translation in `json`; unknown keys are reported and ignored.
+    fn parse(json: &str) -> Result<Self, String> {
+        let translation: HashMap<String, String> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut locale = Self::english();
+        for (key, text) in translation {
+            match locale.strings.get_mut(&key) {
+                Some(entry) => *entry = text,
+                None => warn!("ignoring unknown string {:?}", key),
+            }
+        }
+        Ok(locale)
+    }
+
+    /// The text for `key`, or the key itself if there is none.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        self.strings.get(key).map_or(key, String::as_str)
+    }
+
+    /// The text for `key` with each `{name}` placeholder replaced by the matching argument.
+    pub fn format(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        let mut text = self.get(key).to_string();
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), &value.to_string());
+        }
+        text
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::english()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Locale;
+
+    #[test]
+    fn translation_falls_back_to_english() {
+        let locale = Locale::parse(r#"{ "hud.heat": "HITZE", "hud.nonexistent": "X" }"#).unwrap();
+        assert_eq!(locale.get("hud.heat"), "HITZE");
+        assert_eq!(locale.get("hud.overheat"), "OVERHEAT");
+        assert_eq!(locale.get("hud.nonexistent"), "hud.nonexistent");
+    }
+
+    #[test]
+    fn format_fills_placeholders() {
+        let locale = Locale::english();
+        let text = locale.format("title.marks", &[("marks", &12), ("stored", &34), ("cap", &"56")]);
+        assert_eq!(text, "marks: 12(34) | cap: 56");
+    }
+}
@@ -1,178 +1,111 @@
+use std::panic::AssertUnwindSafe;
 use std::time::Instant;
 
-use camera::Camera;
-use marker::Marker;
-use pollster::block_on;
+use scanner::{
+    args::{Args, Command},
+    bench, crash, server, trace, State,
+};
+use tracing::{error, info};
 use winit::{
     dpi::{LogicalPosition, LogicalSize},
     event::*,
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
-use world::World;
-
-mod camera;
-mod marker;
-pub mod util;
-mod world;
-
-const TITLE_UPDATE_TIME: f64 = 1.0;
-
-pub struct State {
-    surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-
-    camera: Camera,
-    marker: Marker,
-    world: World,
-
-    title_timer: f64,
-    title_update: bool,
-
-    window: winit::window::Window,
-}
-
-impl State {
-    fn new(window: winit::window::Window) -> State {
-        let size = window.inner_size();
-
-        let instance = wgpu::Instance::new(wgpu::Backends::PRIMARY);
-        let surface = unsafe { instance.create_surface(&window) };
-
-        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        }))
-        .unwrap();
-
-        let (device, queue) = block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor { features: wgpu::Features::empty(), limits: wgpu::Limits::default(), label: None },
-            None,
-        ))
-        .unwrap();
-
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_supported_formats(&adapter)[0],
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Immediate,
-            alpha_mode: wgpu::CompositeAlphaMode::Auto,
-        };
-
-        surface.configure(&device, &config);
-
-        let camera = Camera::new(config.width as f32 / config.height as f32);
-        let marker = Marker::new(&device, &config, &camera);
-        let world = World::new();
-
-        Self { surface, device, queue, config, camera, marker, world, title_timer: 0.0, title_update: false, window }
-    }
-
-    fn resize(&mut self, width: u32, height: u32) {
-        self.config.width = width as u32;
-        self.config.height = height as u32;
-        self.surface.configure(&self.device, &self.config);
-    }
 
-    fn update(&mut self, dt: f64) {
-        self.update_camera(dt);
-        self.update_marker(dt);
-
-        self.title_timer -= dt;
-        self.title_update = false;
-        if self.title_timer <= 0.0 {
-            self.title_timer += TITLE_UPDATE_TIME;
-            self.title_update = true;
-        }
+fn main() -> Result<(), String> {
+    let args = Args::parse(std::env::args().skip(1))?;
+    let mut trace = trace::init(args.trace.as_deref());
+    crash::install_hook();
+
+    if let Command::Bench { scenarios } = &args.command {
+        let results = scenarios
+            .iter()
+            .map(|scenario| bench::run(*scenario, args.seed.unwrap_or(0), &args.terrain))
+            .collect::<Result<Vec<_>, _>>()?;
+        println!("{}", serde_json::to_string_pretty(&results).map_err(|e| e.to_string())?);
+        return Ok(());
     }
-
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let mut encoder =
-            self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Render Encoder") });
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color { r: 0.0, g: 0.0, b: 0.0, a: 1.0 }),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-            self.render_markers(&mut render_pass);
-        }
-
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
-
-        Ok(())
+    if let Command::Server(options) = &args.command {
+        return server::run(options);
     }
-}
 
-fn main() -> Result<(), String> {
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let window =
+        WindowBuilder::new().with_inner_size(LogicalSize { width: 1600, height: 900 }).build(&event_loop).unwrap();
 
-    env_logger::init();
-    let mut app_state = State::new(window);
+    let mut app_state = State::new(window, &args)?;
 
-    app_state.window.set_cursor_position(LogicalPosition { x: 0, y: 0 }).unwrap();
-    app_state.window.set_cursor_grab(winit::window::CursorGrabMode::Confined).unwrap();
-    app_state.window.set_cursor_visible(false);
-
-    app_state.window.set_inner_size(LogicalSize { width: 1600, height: 900 });
-    app_state.window.set_resizable(false);
+    _ = app_state.window.set_cursor_position(LogicalPosition { x: 0, y: 0 });
+    let cursor_mode = app_state.grab_cursor(true);
+    if app_state.settings.display.fullscreen {
+        app_state.set_fullscreen(true);
+    }
+    info!("using cursor mode {:?}", cursor_mode);
 
     let mut now = Instant::now();
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::DeviceEvent { ref event, .. } => device_event(&mut app_state, event),
-        Event::WindowEvent { ref event, window_id } if window_id == app_state.window.id() => {
-            window_event(&mut app_state, event, control_flow)
-        }
-        Event::RedrawRequested(window_id) if window_id == app_state.window.id() => {
-            let dt = now.elapsed().as_secs_f64();
-            now = Instant::now();
-
-            app_state.update(dt);
-            match app_state.render() {
-                Ok(_) => {}
-                Err(wgpu::SurfaceError::Lost) => {
-                    let size = app_state.window.inner_size();
-                    app_state.resize(size.width, size.height);
+    let mut next_frame = now;
+    event_loop.run(move |event, _, control_flow| {
+        let handled = std::panic::catch_unwind(AssertUnwindSafe(|| match event {
+            Event::DeviceEvent { ref event, .. } => device_event(&mut app_state, event),
+            Event::WindowEvent { ref event, window_id } if window_id == app_state.window.id() => {
+                window_event(&mut app_state, event, control_flow)
+            }
+            Event::RedrawRequested(window_id) if window_id == app_state.window.id() => {
+                let dt = now.elapsed().as_secs_f64();
+                now = Instant::now();
+                next_frame = app_state.frame_interval().map_or(now, |interval| now + interval);
+
+                let result = app_state.check_device().and_then(|_| {
+                    app_state.update(dt);
+                    app_state.render().or_else(|e| app_state.recover(e))
+                });
+                if let Err(e) = result {
+                    error!("{}", e);
+                    *control_flow = ControlFlow::Exit;
                 }
-                Err(wgpu::SurfaceError::OutOfMemory) => *control_flow = ControlFlow::Exit,
-                Err(e) => eprintln!("{:?}", e),
             }
+            // Sleeps until the next frame is due rather than spinning when the frame rate is capped.
+            Event::MainEventsCleared if matches!(control_flow, ControlFlow::ExitWithCode(_)) => {}
+            Event::MainEventsCleared if Instant::now() < next_frame => {
+                *control_flow = ControlFlow::WaitUntil(next_frame)
+            }
+            Event::MainEventsCleared => {
+                *control_flow = ControlFlow::Poll;
+                app_state.window.request_redraw();
+            }
+            Event::LoopDestroyed => {
+                app_state.finish_session();
+                // The event loop exits the process without unwinding, so the trace is flushed here.
+                drop(trace.take());
+            }
+            _ => {}
+        }));
+        // The panic hook has written a crash report by now; save what can be saved and stop before the scan is
+        // touched again in a broken state.
+        if handled.is_err() {
+            app_state.emergency_save();
+            drop(trace.take());
+            std::process::abort();
         }
-        Event::MainEventsCleared => app_state.window.request_redraw(),
-        _ => {}
     });
 }
 
 fn device_event(app_state: &mut State, event: &DeviceEvent) {
     match &event {
-        DeviceEvent::MouseMotion { delta } => app_state.camera.offset_view(delta.0 as f32, delta.1 as f32),
-        DeviceEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_, y) } => {
-            let delta = y * 0.0005;
-            app_state.camera.ray_range = f32::clamp(app_state.camera.ray_range - delta, 0.1, 1.0);
-        }
+        _ if app_state.menu.open => {}
+        DeviceEvent::MouseMotion { delta } => app_state.mouse_motion(delta.0 as f32, delta.1 as f32),
+        DeviceEvent::MouseWheel { delta: MouseScrollDelta::LineDelta(_, y) } => app_state.scroll(*y),
         _ => {}
     }
 }
 
 fn window_event(app_state: &mut State, event: &WindowEvent, control_flow: &mut ControlFlow) {
     match event {
+        WindowEvent::KeyboardInput {
+            input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(VirtualKeyCode::Escape), .. },
+            ..
+        } if app_state.menu.open => app_state.toggle_menu(),
         WindowEvent::CloseRequested
         | WindowEvent::KeyboardInput {
             input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(VirtualKeyCode::Escape), .. },
@@ -180,21 +113,142 @@ fn window_event(app_state: &mut State, event: &WindowEvent, control_flow: &mut C
         } => *control_flow = ControlFlow::Exit,
 
         WindowEvent::Resized(size) => app_state.resize(size.width, size.height),
-        WindowEvent::ScaleFactorChanged { new_inner_size: size, .. } => app_state.resize(size.width, size.height),
+        WindowEvent::ScaleFactorChanged { scale_factor, new_inner_size: size } => {
+            app_state.set_scale_factor(*scale_factor);
+            app_state.resize(size.width, size.height);
+        }
 
+        WindowEvent::ModifiersChanged(modifiers) => app_state.input.modifiers = *modifiers,
+        WindowEvent::Focused(focused) => app_state.set_focused(*focused),
+
+        WindowEvent::MouseInput { .. } | WindowEvent::Touch(_) if app_state.menu.open => {}
         WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
-            app_state.marker.should_cast = state == &ElementState::Pressed
+            let pressed = state == &ElementState::Pressed;
+            if app_state.museum.active {
+                app_state.museum.dragging = pressed;
+            } else if !app_state.photo.active {
+                app_state.scan_button(pressed);
+            }
+        }
+        WindowEvent::MouseInput { state, button: MouseButton::Right, .. } => {
+            app_state.editor.building =
+                app_state.editor.active && !app_state.photo.active && state == &ElementState::Pressed;
         }
+        WindowEvent::Touch(touch) => app_state.touch(touch),
         WindowEvent::KeyboardInput { input: KeyboardInput { state, virtual_keycode, .. }, .. } => {
             let val = state == &ElementState::Pressed;
+            if app_state.menu.open {
+                match virtual_keycode {
+                    Some(VirtualKeyCode::F1) if val => app_state.toggle_menu(),
+                    Some(keycode) if val => app_state.menu_key(*keycode),
+                    _ => {}
+                }
+                return;
+            }
+            if let Some(slot) = virtual_keycode.and_then(viewpoint_slot) {
+                if val && app_state.input.modifiers.alt() {
+                    // Waypoints are numbered from 1 in drop order, with 0 standing in for the tenth.
+                    app_state.teleport_to_waypoint((slot + 9) % 10);
+                } else if val && app_state.input.modifiers.ctrl() {
+                    app_state.store_viewpoint(slot);
+                } else if val {
+                    app_state.recall_viewpoint(slot);
+                }
+                return;
+            }
+
             if let Some(keycode) = virtual_keycode {
                 match keycode {
+                    _ if app_state.museum.active && is_movement_key(*keycode) => {}
+                    VirtualKeyCode::Up
+                    | VirtualKeyCode::Down
+                    | VirtualKeyCode::Left
+                    | VirtualKeyCode::Right
+                    | VirtualKeyCode::Return
+                        if val && app_state.layers.panel_open && !app_state.input.modifiers.alt() =>
+                    {
+                        app_state.layer_key(*keycode)
+                    }
                     VirtualKeyCode::W => app_state.camera.mov.forward = val,
                     VirtualKeyCode::S => app_state.camera.mov.backward = val,
                     VirtualKeyCode::A => app_state.camera.mov.left = val,
                     VirtualKeyCode::D => app_state.camera.mov.right = val,
                     VirtualKeyCode::Space => app_state.camera.mov.up = val,
                     VirtualKeyCode::LShift => app_state.camera.mov.down = val,
+                    VirtualKeyCode::R if val && app_state.input.modifiers.ctrl() => app_state.measure(),
+                    VirtualKeyCode::R if val && app_state.input.modifiers.alt() => app_state.clear_measurement(),
+                    VirtualKeyCode::R if val => app_state.marker.toggle_ruler(),
+                    VirtualKeyCode::P if val && app_state.input.modifiers.ctrl() => app_state.toggle_playback(),
+                    VirtualKeyCode::P if val && app_state.input.modifiers.alt() => app_state.toggle_playback_pause(),
+                    VirtualKeyCode::P if val => app_state.toggle_scan_pattern(),
+                    VirtualKeyCode::V if val => app_state.toggle_adaptive_cone(),
+                    VirtualKeyCode::B if val => app_state.cycle_sampler(),
+                    VirtualKeyCode::Tab if val && app_state.input.modifiers.ctrl() => app_state.toggle_layer_panel(),
+                    VirtualKeyCode::Tab if val => app_state.cycle_inspector(),
+                    VirtualKeyCode::E if val => app_state.toggle_editor(),
+                    VirtualKeyCode::T if val && app_state.input.modifiers.ctrl() => app_state.toggle_detail_band(),
+                    VirtualKeyCode::T if val => app_state.cycle_voxel_size(),
+                    VirtualKeyCode::Z if val && app_state.input.modifiers.ctrl() => app_state.undo(),
+                    VirtualKeyCode::Y if val && app_state.input.modifiers.ctrl() => app_state.redo(),
+                    VirtualKeyCode::M if val => app_state.toggle_museum(),
+                    VirtualKeyCode::H if val && app_state.input.modifiers.ctrl() => app_state.toggle_minimap(),
+                    VirtualKeyCode::H if val => app_state.toggle_hud(),
+                    VirtualKeyCode::O if val => app_state.toggle_occlusion(),
+                    VirtualKeyCode::C if val => app_state.toggle_splatting(),
+                    VirtualKeyCode::F if val => app_state.toggle_accumulation(),
+                    VirtualKeyCode::L if val && app_state.input.modifiers.ctrl() => app_state.toggle_trail(),
+                    VirtualKeyCode::L if val => app_state.toggle_eye_dome(),
+                    VirtualKeyCode::J if val => app_state.toggle_anaglyph(),
+                    VirtualKeyCode::K if val => app_state.toggle_profiler(),
+                    VirtualKeyCode::Q if val => app_state.toggle_governor(),
+                    VirtualKeyCode::U if val && app_state.input.modifiers.ctrl() => app_state.toggle_heatmap(),
+                    VirtualKeyCode::U if val && app_state.input.modifiers.alt() => app_state.toggle_tag_colors(),
+                    VirtualKeyCode::U if val => app_state.cycle_palette(),
+                    VirtualKeyCode::X if val && app_state.input.modifiers.ctrl() => app_state.toggle_clip_lock(),
+                    VirtualKeyCode::X if val => app_state.toggle_clip(),
+                    VirtualKeyCode::I if val && app_state.input.modifiers.ctrl() => app_state.clear_selection(),
+                    VirtualKeyCode::I if val => app_state.place_selection_corner(),
+                    VirtualKeyCode::Delete if val => app_state.delete_selection(),
+                    VirtualKeyCode::Insert if val => app_state.paint_selection(),
+                    VirtualKeyCode::G if val && app_state.input.modifiers.ctrl() => app_state.toggle_heat(),
+                    VirtualKeyCode::G if val => app_state.toggle_gameplay(),
+                    VirtualKeyCode::N if val && app_state.input.modifiers.ctrl() => app_state.drop_waypoint_at_mark(),
+                    VirtualKeyCode::N if val => app_state.drop_waypoint(),
+                    VirtualKeyCode::PageUp if val => app_state.cycle_waypoint(-1),
+                    VirtualKeyCode::PageDown if val => app_state.cycle_waypoint(1),
+                    VirtualKeyCode::Home if val && app_state.input.modifiers.ctrl() => app_state.toggle_guide(),
+                    VirtualKeyCode::Home if val => app_state.return_to_origin(),
+                    VirtualKeyCode::F11 if val => app_state.toggle_fullscreen(),
+                    VirtualKeyCode::Return if val && app_state.input.modifiers.alt() => app_state.toggle_fullscreen(),
+                    VirtualKeyCode::F1 if val => app_state.toggle_menu(),
+                    VirtualKeyCode::F5
+                        if val && app_state.input.modifiers.ctrl() && app_state.input.modifiers.shift() =>
+                    {
+                        app_state.approve_scripts()
+                    }
+                    VirtualKeyCode::F5 if val && app_state.input.modifiers.ctrl() => app_state.reload_scripts(),
+                    VirtualKeyCode::F5 if val => app_state.quicksave(),
+                    VirtualKeyCode::F6 if val => app_state.export_archive(),
+                    VirtualKeyCode::F7 if val => app_state.export_las(),
+                    VirtualKeyCode::F8 if val => app_state.export_terrain(),
+                    VirtualKeyCode::F9 if val => app_state.quickload(),
+                    VirtualKeyCode::F10 if val && app_state.input.modifiers.ctrl() => app_state.toggle_vignette(),
+                    VirtualKeyCode::F10 if val => app_state.toggle_photo_mode(),
+                    VirtualKeyCode::F12 if val => app_state.take_screenshot(),
+                    VirtualKeyCode::Period if val => app_state.step_scan_rate(true),
+                    VirtualKeyCode::Comma if val => app_state.step_scan_rate(false),
+                    VirtualKeyCode::Apostrophe if val => app_state.adjust_rays_per_tick(1),
+                    VirtualKeyCode::Semicolon if val => app_state.adjust_rays_per_tick(-1),
+                    VirtualKeyCode::LBracket if val => app_state.marker.scale_ruler_spacing(0.5),
+                    VirtualKeyCode::RBracket if val => app_state.marker.scale_ruler_spacing(2.0),
+                    VirtualKeyCode::Equals if val => {
+                        let cap = app_state.marker.instance_cap();
+                        app_state.marker.set_instance_cap(cap.saturating_mul(2));
+                    }
+                    VirtualKeyCode::Minus if val => {
+                        let cap = app_state.marker.instance_cap();
+                        app_state.marker.set_instance_cap(cap / 2);
+                    }
                     _ => {}
                 }
             }
@@ -203,3 +257,31 @@ fn window_event(app_state: &mut State, event: &WindowEvent, control_flow: &mut C
         _ => {}
     }
 }
+
+fn is_movement_key(keycode: VirtualKeyCode) -> bool {
+    matches!(
+        keycode,
+        VirtualKeyCode::W
+            | VirtualKeyCode::S
+            | VirtualKeyCode::A
+            | VirtualKeyCode::D
+            | VirtualKeyCode::Space
+            | VirtualKeyCode::LShift
+    )
+}
+
+fn viewpoint_slot(keycode: VirtualKeyCode) -> Option<usize> {
+    match keycode {
+        VirtualKeyCode::Key0 => Some(0),
+        VirtualKeyCode::Key1 => Some(1),
+        VirtualKeyCode::Key2 => Some(2),
+        VirtualKeyCode::Key3 => Some(3),
+        VirtualKeyCode::Key4 => Some(4),
+        VirtualKeyCode::Key5 => Some(5),
+        VirtualKeyCode::Key6 => Some(6),
+        VirtualKeyCode::Key7 => Some(7),
+        VirtualKeyCode::Key8 => Some(8),
+        VirtualKeyCode::Key9 => Some(9),
+        _ => None,
+    }
+}
@@ -0,0 +1,371 @@
+use super::super::occlusion::DEPTH_FORMAT;
+use super::super::State;
+use super::{MarkRaw, MAX_LAYERS, PALETTE_SIZE, TAG_KINDS};
+
+const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+/// Local position of the mark each history texel holds, with `w` set where there is one.
+const POSITION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+/// Seconds over which an impression fades to about a third of its brightness.
+const FADE_TIME: f64 = 20.0;
+/// Impressions fainter than this are dropped.
+const MIN_WEIGHT: f32 = 0.05;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct AccumulationUniform {
+    to_clip: [[f32; 4]; 4],
+    eye: [f32; 4],
+    fade: f32,
+    min_weight: f32,
+    time_limit: f32,
+    heatmap: u32,
+    palette: [[f32; 4]; PALETTE_SIZE],
+    clip_plane: [f32; 4],
+    layer_colors: [[f32; 4]; MAX_LAYERS],
+    tag_colors: [[f32; 4]; TAG_KINDS],
+    by_tag: u32,
+    _padding: [u32; 3],
+}
+
+/// One frame of accumulated marks: their colors faded by age, and their positions for reprojection.
+struct History {
+    color_view: wgpu::TextureView,
+    position_view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+struct Targets {
+    histories: [History; 2],
+    depth_view: wgpu::TextureView,
+}
+
+/// Temporal accumulation: every visible mark is stamped as a point into a persistent history at render resolution,
+/// which is carried from frame to frame by moving each point to where its position lands in the new view, slowly
+/// fading. The history is drawn into the scene behind the current marks, so marks that dropped out of the instance
+/// budget stay visible as a faded impression. Points that leave the view are forgotten.
+pub struct Accumulator {
+    pub enabled: bool,
+    reproject_pipeline: wgpu::RenderPipeline,
+    stamp_pipeline: wgpu::RenderPipeline,
+    compose_pipeline: wgpu::RenderPipeline,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    history_layout: wgpu::BindGroupLayout,
+    /// Only allocated while enabled.
+    targets: Option<Targets>,
+    /// History written this frame; the other one holds the previous frame.
+    current: usize,
+    /// Set when the history no longer lines up with the scene, so the next frame starts from scratch.
+    reset: bool,
+    width: u32,
+    height: u32,
+}
+
+impl Accumulator {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../accumulate.wgsl"));
+
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("accumulation_uniform_layout"),
+        });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let history_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[texture_entry(0), texture_entry(1)],
+            label: Some("accumulation_history_layout"),
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Accumulation Uniform Buffer"),
+            size: std::mem::size_of::<AccumulationUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &uniform_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: uniform_buffer.as_entire_binding() }],
+            label: Some("accumulation_uniform_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Accumulation Pipeline Layout"),
+            bind_group_layouts: &[&uniform_layout, &history_layout],
+            push_constant_ranges: &[],
+        });
+        let stamp_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Stamp Pipeline Layout"),
+            bind_group_layouts: &[&uniform_layout],
+            push_constant_ranges: &[],
+        });
+        let history_targets = [
+            Some(wgpu::ColorTargetState { format: COLOR_FORMAT, blend: None, write_mask: wgpu::ColorWrites::ALL }),
+            Some(wgpu::ColorTargetState { format: POSITION_FORMAT, blend: None, write_mask: wgpu::ColorWrites::ALL }),
+        ];
+        let history_depth = |depth_compare| wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+        let points = wgpu::PrimitiveState { topology: wgpu::PrimitiveTopology::PointList, ..Default::default() };
+        let multisample = wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false };
+
+        let reproject_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Reproject Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_reproject", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_history",
+                targets: &history_targets,
+            }),
+            primitive: points,
+            depth_stencil: Some(history_depth(wgpu::CompareFunction::Less)),
+            multisample,
+            multiview: None,
+        });
+
+        // Marks already carried over from the last frame sit at the same depth and are refreshed to full weight.
+        let stamp_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Stamp Pipeline"),
+            layout: Some(&stamp_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_stamp", buffers: &[MarkRaw::desc()] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_history",
+                targets: &history_targets,
+            }),
+            primitive: points,
+            depth_stencil: Some(history_depth(wgpu::CompareFunction::LessEqual)),
+            multisample,
+            multiview: None,
+        });
+
+        let compose_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Compose Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_compose", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_compose",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample,
+            multiview: None,
+        });
+
+        Self {
+            enabled: false,
+            reproject_pipeline,
+            stamp_pipeline,
+            compose_pipeline,
+            uniform_buffer,
+            uniform_bind_group,
+            history_layout,
+            targets: None,
+            current: 0,
+            reset: true,
+            width: config.width,
+            height: config.height,
+        }
+    }
+
+    /// Resizes the history to the render size, which starts it over.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.set_enabled(device, self.enabled);
+    }
+
+    /// Allocates or frees the history. Either way the next frame starts from scratch.
+    pub fn set_enabled(&mut self, device: &wgpu::Device, enabled: bool) {
+        self.enabled = enabled;
+        self.targets = enabled.then(|| Targets {
+            histories: [0, 1].map(|_| self.create_history(device)),
+            depth_view: create_view(device, "Accumulation Depth Texture", DEPTH_FORMAT, self.width, self.height),
+        });
+        self.reset = true;
+    }
+
+    /// Forgets the history, e.g. after the local origin moved and the stored positions no longer hold.
+    pub fn clear(&mut self) {
+        self.reset = true;
+    }
+
+    fn create_history(&self, device: &wgpu::Device) -> History {
+        let color_view = create_view(device, "Accumulation Color Texture", COLOR_FORMAT, self.width, self.height);
+        let position_view =
+            create_view(device, "Accumulation Position Texture", POSITION_FORMAT, self.width, self.height);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.history_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&color_view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&position_view) },
+            ],
+            label: Some("accumulation_history_bind_group"),
+        });
+        History { color_view, position_view, bind_group }
+    }
+}
+
+fn create_view(
+    device: &wgpu::Device,
+    label: &str,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+impl State {
+    pub fn toggle_accumulation(&mut self) {
+        let enabled = !self.marker.accumulator.enabled;
+        self.marker.accumulator.set_enabled(&self.device, enabled);
+        self.notify(format!("accumulation: {}", if enabled { "on" } else { "off" }));
+    }
+
+    pub fn update_accumulation(&mut self, dt: f64) {
+        let palette = self.marker.palette_table();
+        let clip_plane = self.marker.clip_plane();
+        let time_limit = self.marker.time_limit();
+        let layer_colors = self.marker.layer_colors();
+        let heatmap = self.marker.heatmap() as u32;
+        let tag_colors = self.marker.tag_colors();
+        let by_tag = self.marker.by_tag() as u32;
+        let accumulator = &mut self.marker.accumulator;
+        if !accumulator.enabled {
+            return;
+        }
+
+        accumulator.current = 1 - accumulator.current;
+        let uniform = AccumulationUniform {
+            to_clip: self.camera.view_projection().to_cols_array_2d(),
+            eye: self.camera.pos.extend(1.0).into(),
+            fade: if accumulator.reset { 0.0 } else { (-dt / FADE_TIME).exp() as f32 },
+            min_weight: MIN_WEIGHT,
+            time_limit,
+            heatmap,
+            palette,
+            clip_plane,
+            layer_colors,
+            tag_colors,
+            by_tag,
+            _padding: [0; 3],
+        };
+        accumulator.reset = false;
+        self.queue.write_buffer(&accumulator.uniform_buffer, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Carries the previous frame's history over into this frame's, moved into the current view.
+    pub fn reproject_history(&self, encoder: &mut wgpu::CommandEncoder) {
+        let accumulator = &self.marker.accumulator;
+        let Some(targets) = accumulator.targets.as_ref().filter(|_| accumulator.enabled) else {
+            return;
+        };
+        let next = &targets.histories[accumulator.current];
+        let previous = &targets.histories[1 - accumulator.current];
+
+        let mut render_pass = begin_history_pass(encoder, next, &targets.depth_view, "Reproject Pass", true);
+        render_pass.set_pipeline(&accumulator.reproject_pipeline);
+        render_pass.set_bind_group(0, &accumulator.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &previous.bind_group, &[]);
+        render_pass.draw(0..accumulator.width * accumulator.height, 0..1);
+    }
+
+    /// Draws this frame's history into the scene pass, behind the marks and in front of nothing else.
+    pub fn render_history<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let accumulator = &self.marker.accumulator;
+        let Some(targets) = accumulator.targets.as_ref().filter(|_| accumulator.enabled) else {
+            return;
+        };
+        render_pass.set_pipeline(&accumulator.compose_pipeline);
+        render_pass.set_bind_group(0, &accumulator.uniform_bind_group, &[]);
+        render_pass.set_bind_group(1, &targets.histories[accumulator.current].bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Stamps the marks in view into this frame's history, after it has been drawn.
+    pub fn stamp_history(&self, encoder: &mut wgpu::CommandEncoder) {
+        let accumulator = &self.marker.accumulator;
+        let Some(targets) = accumulator.targets.as_ref().filter(|_| accumulator.enabled) else {
+            return;
+        };
+        let next = &targets.histories[accumulator.current];
+
+        let mut render_pass = begin_history_pass(encoder, next, &targets.depth_view, "Stamp Pass", false);
+        render_pass.set_pipeline(&accumulator.stamp_pipeline);
+        render_pass.set_bind_group(0, &accumulator.uniform_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.marker.instance_ring.current().slice(..));
+        render_pass.draw(0..1, 0..self.marker.n_visible);
+    }
+}
+
+fn begin_history_pass<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    history: &'a History,
+    depth_view: &'a wgpu::TextureView,
+    label: &str,
+    clear: bool,
+) -> wgpu::RenderPass<'a> {
+    let load = |color| if clear { wgpu::LoadOp::Clear(color) } else { wgpu::LoadOp::Load };
+    let attachment = |view| {
+        Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations { load: load(wgpu::Color::TRANSPARENT), store: true },
+        })
+    };
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(label),
+        color_attachments: &[attachment(&history.color_view), attachment(&history.position_view)],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth_view,
+            depth_ops: Some(wgpu::Operations {
+                load: if clear { wgpu::LoadOp::Clear(1.0) } else { wgpu::LoadOp::Load },
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    })
+}
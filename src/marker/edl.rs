@@ -0,0 +1,186 @@
+use super::super::occlusion::DEPTH_FORMAT;
+use super::super::State;
+use super::{MarkRaw, Vertex};
+
+/// How strongly depth discontinuities darken the marks behind them.
+const EDL_STRENGTH: f32 = 1.0;
+/// Distance to the neighbors each pixel is compared with, in pixels.
+const EDL_RADIUS: f32 = 1.5;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct EyeDomeParams {
+    near: f32,
+    far: f32,
+    strength: f32,
+    radius: f32,
+}
+
+/// Eye-dome lighting, the screen-space shading of point cloud viewers: the marks' discs are written to the scene
+/// depth buffer on top of the terrain occluders, and a fullscreen pass darkens every pixel by how far its neighbors
+/// stand in front of it in log depth. This outlines ridges, openings and cave walls without any normals or lights.
+pub struct EyeDome {
+    pub enabled: bool,
+    depth_pipeline: wgpu::RenderPipeline,
+    shade_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+}
+
+impl EyeDome {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        mark_shader: &wgpu::ShaderModule,
+        mark_layout: &wgpu::PipelineLayout,
+    ) -> Self {
+        let depth_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Mark Depth Pipeline"),
+            layout: Some(mark_layout),
+            vertex: wgpu::VertexState {
+                module: mark_shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), MarkRaw::desc()],
+            },
+            fragment: Some(wgpu::FragmentState { module: mark_shader, entry_point: "fs_depth", targets: &[] }),
+            primitive: wgpu::PrimitiveState { cull_mode: Some(wgpu::Face::Back), ..Default::default() },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../edl.wgsl"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("edl_bind_group_layout"),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("EDL Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shade_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("EDL Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("EDL Params Buffer"),
+            size: std::mem::size_of::<EyeDomeParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self { enabled: false, depth_pipeline, shade_pipeline, bind_group_layout, params_buffer }
+    }
+}
+
+impl State {
+    pub fn toggle_eye_dome(&mut self) {
+        let enabled = !self.marker.eye_dome.enabled;
+        self.marker.eye_dome.enabled = enabled;
+        self.notify(format!("eye-dome lighting: {}", if enabled { "on" } else { "off" }));
+    }
+
+    /// Writes the mark depths into the scene depth buffer and shades `view` from it, after the scene pass.
+    pub fn encode_eye_dome(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let marker = &self.marker;
+        let eye_dome = &marker.eye_dome;
+        if !eye_dome.enabled {
+            return;
+        }
+
+        let (near, far) = self.camera.depth_range();
+        let params = EyeDomeParams { near, far, strength: EDL_STRENGTH, radius: EDL_RADIUS };
+        self.queue.write_buffer(&eye_dome.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Mark Depth Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.occlusion.depth_view(),
+                    depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: true }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&eye_dome.depth_pipeline);
+            render_pass.set_bind_group(0, &marker.camera_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, marker.vertex_buffer.slice(..));
+            for (buffer, n_marks) in
+                [(marker.instance_ring.current(), marker.n_visible), (&marker.dynamic_buffer, marker.n_dynamic)]
+            {
+                if n_marks > 0 {
+                    render_pass.set_vertex_buffer(1, buffer.slice(..));
+                    render_pass.draw(0..6, 0..n_marks);
+                }
+            }
+        }
+
+        // Rebuilt every frame since the depth buffer is replaced on resize.
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &eye_dome.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(self.occlusion.depth_view()),
+                },
+                wgpu::BindGroupEntry { binding: 1, resource: eye_dome.params_buffer.as_entire_binding() },
+            ],
+            label: Some("edl_bind_group"),
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("EDL Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&eye_dome.shade_pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -1,9 +1,22 @@
-use super::camera::{Camera, CameraUniform};
+use super::camera::{Camera, CameraUniform, MAX_RAY_RANGE, MIN_RAY_RANGE};
+use super::entity::EntityId;
+use super::occlusion::DEPTH_FORMAT;
+use super::util::{Ray, ScanRng};
 use super::State;
-use glam::Vec3;
+use glam::{vec3, Vec3, Vec4};
+use rand::{rngs::StdRng, SeedableRng};
 use wgpu::util::DeviceExt;
 
-mod octree;
+pub use sampler::{DiscSampler, SamplerKind};
+pub use scan::{RayQueue, ScanPattern, DEFAULT_RAY_BUDGET};
+
+mod accumulate;
+mod edl;
+pub mod octree;
+mod ring;
+pub mod sampler;
+mod scan;
+mod splat;
 
 pub const VERTICES: &[Vertex] = &[
     Vertex { position: [-0.5, 0.5] },
@@ -14,8 +27,74 @@ pub const VERTICES: &[Vertex] = &[
     Vertex { position: [0.5, 0.5] },
 ];
 
-pub const INST_N: usize = 1000000;
-const MARKER_COOLDOWN: f64 = 0.0005;
+pub const DEFAULT_INST_N: usize = 1000000;
+pub const MIN_INST_N: usize = 1000;
+const DEFAULT_MARKER_COOLDOWN: f64 = 0.0005;
+const MIN_MARKER_COOLDOWN: f64 = 0.00001;
+const MAX_MARKER_COOLDOWN: f64 = 0.1;
+/// Most temporary marks drawn at once, e.g. for the guide path.
+pub const MAX_TRANSIENT_MARKS: usize = 4096;
+/// Rays cast every time the scanner timer fires.
+const MAX_RAYS_PER_TICK: usize = 64;
+/// Weight of each new hit distance in the running average the adaptive cone follows.
+const HIT_DISTANCE_SMOOTHING: f32 = 0.02;
+/// Spot radius the adaptive cone keeps when turned on before anything was hit.
+const DEFAULT_SPOT_RADIUS: f32 = 10.0;
+/// Factor the scan rate keys change the cooldown by.
+const RATE_STEP: f64 = 1.25;
+
+const DEFAULT_POINT_SIZE: f32 = 1.0;
+const MIN_POINT_SIZE: f32 = 0.1;
+const MAX_POINT_SIZE: f32 = 10.0;
+
+const DISTANCE_NEA: f32 = 100.0;
+const DISTANCE_MID: f32 = 200.0;
+const DISTANCE_FAR: f32 = 300.0;
+
+const COLOR_NEA: Vec3 = glam::vec3(1.0, 0.0, 0.0);
+const COLOR_MID: Vec3 = glam::vec3(0.0, 1.0, 0.0);
+const COLOR_FAR: Vec3 = glam::vec3(0.0, 0.2, 1.0);
+const MISS_COLOR: Vec3 = glam::vec3(0.5, 0.5, 0.5);
+
+const DEFAULT_RULER_SPACING: f32 = 10.0;
+const MIN_RULER_SPACING: f32 = 1.0;
+const MAX_RULER_SPACING: f32 = 500.0;
+
+/// Clip plane no position lies on the negative side of.
+const NO_CLIP: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+/// Most layers marks can be grouped into, one per bit of a layer mask.
+pub const MAX_LAYERS: usize = 32;
+/// Time limit no mark is scanned after.
+const NO_TIME_LIMIT: f32 = f32::MAX;
+
+/// Which marks the renderer draws, for queries on the CPU that should only find marks the player can see.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Visibility {
+    /// Marks on the negative side of this plane are hidden.
+    pub clip_plane: Vec4,
+    /// Marks scanned later than this, in seconds of scan time, are hidden.
+    pub time_limit: f32,
+    /// Marks of the layers whose bit is clear are hidden.
+    pub layer_mask: u32,
+}
+
+impl Visibility {
+    /// Shows every mark.
+    pub const ALL: Self =
+        Self { clip_plane: Vec4::from_array(NO_CLIP), time_limit: NO_TIME_LIMIT, layer_mask: u32::MAX };
+
+    /// Whether the renderer draws `mark`, with the same tests as the shaders.
+    pub fn shows(&self, mark: &MarkRaw) -> bool {
+        self.clip_plane.dot(Vec3::from(mark.pos).extend(1.0)) >= 0.0
+            && mark.time <= self.time_limit
+            && self.layer_mask & (1 << mark.layer) != 0
+    }
+}
+
+/// Leaf densities, in marks per square unit, at the sparse and dense ends of the heatmap ramp. Surfaces scanned once
+/// in passing land near the sparse end, while the leaves a spot was held on reach the dense one.
+const DENSITY_SPARSE: f32 = 0.1;
+const DENSITY_DENSE: f32 = 100.0;
 
 #[repr(C)]
 #[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
@@ -35,25 +114,190 @@ impl Vertex {
     }
 }
 
+/// What a mark stands for. Stored in the last byte of [`MarkRaw::color`], which held an always opaque alpha before
+/// tags existed, so marks from older scan files read back as surface marks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarkTag {
+    /// A point on a scanned or imported surface.
+    Surface,
+    /// Placed at the end of a ray that hit nothing; drawn faintly and left out of saved scans.
+    Miss,
+    /// Scanned on an entity and moving with it.
+    Dynamic,
+    /// Recolored by the user; drawn in its own color rather than shaded by distance.
+    Painted,
+    /// Stands for a merged cluster of surface marks and is drawn at least as large as the cluster. Holds the
+    /// cluster's diameter in `1 / SURFEL_BYTES_PER_UNIT` world units, which doubles as its tag byte.
+    Surfel(u8),
+    /// A surfel recolored by the user, holding its diameter like [`MarkTag::Surfel`]. Its tag byte has
+    /// `PAINTED_SURFEL_BIT` set on top of the diameter.
+    PaintedSurfel(u8),
+}
+
+impl MarkTag {
+    const PAINTED_BYTE: u8 = 0;
+    const MISS_BYTE: u8 = 1;
+    const DYNAMIC_BYTE: u8 = 2;
+    const SURFEL_MIN_BYTE: u8 = 3;
+    /// Merged cells are at most `2 * SURFEL_MAX_EXTENSION / SURFEL_GRID` across, so surfels never come near this.
+    const SURFEL_MAX_BYTE: u8 = 126;
+    const PAINTED_SURFEL_BIT: u8 = 0x80;
+    const SURFEL_BYTES_PER_UNIT: f32 = 16.0;
+
+    /// Tag of a surfel `diameter` world units across, rounded to the nearest size the tag byte can hold.
+    pub fn surfel(diameter: f32) -> Self {
+        let byte = (diameter * Self::SURFEL_BYTES_PER_UNIT).round();
+        MarkTag::Surfel(byte.clamp(Self::SURFEL_MIN_BYTE as f32, Self::SURFEL_MAX_BYTE as f32) as u8)
+    }
+
+    /// Diameter of a surfel in world units, painted or not, or `None` for other marks.
+    pub fn surfel_size(self) -> Option<f32> {
+        match self {
+            MarkTag::Surfel(byte) | MarkTag::PaintedSurfel(byte) => Some(byte as f32 / Self::SURFEL_BYTES_PER_UNIT),
+            _ => None,
+        }
+    }
+
+    /// The tag of a mark recolored by the user. Surfels keep their size, and misses stay misses.
+    pub fn painted(self) -> Self {
+        match self {
+            MarkTag::Surface | MarkTag::Dynamic | MarkTag::Painted => MarkTag::Painted,
+            MarkTag::Surfel(byte) | MarkTag::PaintedSurfel(byte) => MarkTag::PaintedSurfel(byte),
+            MarkTag::Miss => MarkTag::Miss,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            MarkTag::Surface => 255,
+            MarkTag::Miss => Self::MISS_BYTE,
+            MarkTag::Dynamic => Self::DYNAMIC_BYTE,
+            MarkTag::Painted => Self::PAINTED_BYTE,
+            MarkTag::Surfel(byte) => byte,
+            MarkTag::PaintedSurfel(byte) => byte | Self::PAINTED_SURFEL_BIT,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            Self::PAINTED_BYTE => MarkTag::Painted,
+            Self::MISS_BYTE => MarkTag::Miss,
+            Self::DYNAMIC_BYTE => MarkTag::Dynamic,
+            Self::SURFEL_MIN_BYTE..=Self::SURFEL_MAX_BYTE => MarkTag::Surfel(byte),
+            _ if (Self::SURFEL_MIN_BYTE..=Self::SURFEL_MAX_BYTE).contains(&(byte & !Self::PAINTED_SURFEL_BIT)) => {
+                MarkTag::PaintedSurfel(byte & !Self::PAINTED_SURFEL_BIT)
+            }
+            _ => MarkTag::Surface,
+        }
+    }
+}
+
+/// Entity a mark moves with, and where on it the mark sits.
+#[derive(Copy, Clone, Debug)]
+pub struct MarkParent {
+    pub entity: EntityId,
+    /// Position relative to the entity.
+    pub offset: Vec3,
+}
+
 #[derive(Copy, Clone)]
 pub struct Mark {
-    pos: Vec3,
+    pub pos: Vec3,
+    pub color: Vec3,
+    pub tag: MarkTag,
+    pub parent: Option<MarkParent>,
+    /// Seconds of scan time the mark was recorded at, for replaying the scan in order.
+    pub time: f32,
+    /// Layer the mark was recorded in, below [`MAX_LAYERS`].
+    pub layer: u32,
 }
 
 impl Mark {
-    fn to_raw(&self) -> MarkRaw {
-        MarkRaw { pos: self.pos.into() }
+    pub fn new(pos: Vec3, color: Vec3) -> Self {
+        Self { pos, color, tag: MarkTag::Surface, parent: None, time: 0.0, layer: 0 }
+    }
+
+    /// Attaches the mark to `entity`, currently at `entity_pos`, so it follows the entity from now on.
+    pub fn attach(self, entity: EntityId, entity_pos: Vec3) -> Self {
+        let parent = MarkParent { entity, offset: self.pos - entity_pos };
+        Self { tag: MarkTag::Dynamic, parent: Some(parent), ..self }
+    }
+
+    /// Stamps the mark with the scan time it was recorded at and the layer it was recorded in.
+    pub fn stamp(self, time: f32, layer: u32) -> Self {
+        Self { time, layer, ..self }
+    }
+
+    /// Creates a mark colored by the distance it was scanned from, using the same ramp as the shader.
+    pub fn scanned(pos: Vec3, dist: f32) -> Self {
+        Self::new(pos, scan_color(dist))
+    }
+
+    /// Creates a mark for a ray that hit nothing, placed where it gave up.
+    pub fn miss(pos: Vec3) -> Self {
+        Self { pos, color: MISS_COLOR, tag: MarkTag::Miss, parent: None, time: 0.0, layer: 0 }
+    }
+
+    pub fn to_raw(self) -> MarkRaw {
+        let color = (self.color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round();
+        let color = [color.x as u8, color.y as u8, color.z as u8, self.tag.to_byte()];
+        MarkRaw { pos: self.pos.into(), color, time: self.time, layer: self.layer }
+    }
+}
+
+impl From<MarkRaw> for Mark {
+    fn from(raw: MarkRaw) -> Self {
+        Self { pos: raw.pos.into(), color: raw.color(), tag: raw.tag(), parent: None, time: raw.time, layer: raw.layer }
     }
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct MarkRaw {
-    pos: [f32; 3],
+    pub pos: [f32; 3],
+    pub color: [u8; 4],
+    pub time: f32,
+    pub layer: u32,
 }
 
 impl MarkRaw {
-    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![1 => Float32x3];
+    /// Size of a mark in files and packets, which leave out the layer since layers only exist in one session.
+    pub const STORED_SIZE: usize = 20;
+    /// Size of a mark in files written before marks had a scan time.
+    pub const UNTIMED_SIZE: usize = 16;
+
+    /// The mark as stored in files and packets.
+    pub fn stored_bytes(&self) -> &[u8] {
+        &bytemuck::bytes_of(self)[..Self::STORED_SIZE]
+    }
+
+    /// Reads a mark of [`MarkRaw::STORED_SIZE`] or [`MarkRaw::UNTIMED_SIZE`] bytes. It is put in the first layer,
+    /// and taken to have been scanned at the start if it has no time.
+    pub fn from_stored(bytes: &[u8]) -> Self {
+        let mut raw = Self::default();
+        bytemuck::bytes_of_mut(&mut raw)[..bytes.len()].copy_from_slice(bytes);
+        raw
+    }
+
+    #[inline]
+    pub fn color(&self) -> Vec3 {
+        glam::vec3(self.color[0] as f32, self.color[1] as f32, self.color[2] as f32) / 255.0
+    }
+
+    #[inline]
+    pub fn tag(&self) -> MarkTag {
+        MarkTag::from_byte(self.color[3])
+    }
+
+    /// The mark recolored by the user with `color`, see [`MarkTag::painted`].
+    pub fn painted(self, color: Vec3) -> Self {
+        let color = (color.clamp(Vec3::ZERO, Vec3::ONE) * 255.0).round();
+        let color = [color.x as u8, color.y as u8, color.z as u8, self.tag().painted().to_byte()];
+        Self { color, ..self }
+    }
+
+    const ATTRIBS: [wgpu::VertexAttribute; 4] =
+        wgpu::vertex_attr_array![1 => Float32x3, 2 => Uint8x4, 3 => Float32, 4 => Uint32];
 
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
         wgpu::VertexBufferLayout {
@@ -64,38 +308,265 @@ impl MarkRaw {
     }
 }
 
+/// Entries of the palette lookup table the mark shaders interpolate between, evenly spread from the near to the far
+/// distance.
+pub const PALETTE_SIZE: usize = 8;
+/// Kinds of marks shading by tag tells apart: surface hits, painted marks, misses and marks on entities.
+pub const TAG_KINDS: usize = 4;
+
+/// Perceptually uniform colormaps from matplotlib, bright end first so near marks stand out against the black
+/// background.
+const VIRIDIS: [u32; 8] = [0xfde725, 0x9fda3a, 0x4ac16d, 0x1fa187, 0x277f8e, 0x365c8d, 0x46337e, 0x440154];
+/// Viridis adjusted to look the same with and without red-green color blindness.
+const CIVIDIS: [u32; 6] = [0xffea46, 0xcbba69, 0x958f78, 0x666970, 0x31446b, 0x00204d];
+/// ColorBrewer's orange to purple diverging scheme, safe for deuteranopia and protanopia.
+const COLORBLIND: [u32; 7] = [0xb35806, 0xf1a340, 0xfee0b6, 0xf7f7f7, 0xd8daeb, 0x998ec3, 0x542788];
+/// ColorBrewer's blue to red diverging scheme, for the density heatmap: under-scanned areas blue, over-scanned red.
+const DENSITY: [u32; 5] = [0x2c7bb6, 0xabd9e9, 0xffffbf, 0xfdae61, 0xd7191c];
+
+/// Colors marks are shaded with by their distance from the camera.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    #[default]
+    Spectrum,
+    Thermal,
+    Ice,
+    Mono,
+    /// Bright, strongly separated colors for low vision.
+    Contrast,
+    /// Orange to purple, distinguishable with red-green color blindness.
+    #[serde(alias = "deuteranopia")]
+    Colorblind,
+    Viridis,
+    Cividis,
+}
+
+/// How a palette's colors spread over the distance range.
+enum Ramp {
+    /// Blends from the near to the middle color and on to the far one, easing in and out of each.
+    Blend([Vec3; 3]),
+    /// Evenly spaced `0xrrggbb` colors, interpolated linearly.
+    Map(&'static [u32]),
+}
+
+impl Palette {
+    pub const ALL: [Palette; 8] = [
+        Palette::Spectrum,
+        Palette::Thermal,
+        Palette::Ice,
+        Palette::Mono,
+        Palette::Contrast,
+        Palette::Colorblind,
+        Palette::Viridis,
+        Palette::Cividis,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Palette::Spectrum => "spectrum",
+            Palette::Thermal => "thermal",
+            Palette::Ice => "ice",
+            Palette::Mono => "mono",
+            Palette::Contrast => "contrast",
+            Palette::Colorblind => "colorblind",
+            Palette::Viridis => "viridis",
+            Palette::Cividis => "cividis",
+        }
+    }
+
+    fn ramp(self) -> Ramp {
+        match self {
+            Palette::Spectrum => Ramp::Blend([COLOR_NEA, COLOR_MID, COLOR_FAR]),
+            Palette::Thermal => Ramp::Blend([vec3(1.0, 1.0, 0.6), vec3(1.0, 0.35, 0.0), vec3(0.45, 0.0, 0.35)]),
+            Palette::Ice => Ramp::Blend([vec3(1.0, 1.0, 1.0), vec3(0.3, 0.8, 1.0), vec3(0.1, 0.15, 0.6)]),
+            Palette::Mono => Ramp::Blend([vec3(1.0, 1.0, 1.0), vec3(0.6, 0.6, 0.6), vec3(0.3, 0.3, 0.3)]),
+            Palette::Contrast => Ramp::Blend([vec3(1.0, 1.0, 0.0), vec3(0.0, 1.0, 1.0), vec3(1.0, 0.2, 1.0)]),
+            Palette::Colorblind => Ramp::Map(&COLORBLIND),
+            Palette::Viridis => Ramp::Map(&VIRIDIS),
+            Palette::Cividis => Ramp::Map(&CIVIDIS),
+        }
+    }
+
+    /// The palette's color at `dist` from the camera.
+    fn color(self, dist: f32) -> Vec3 {
+        match self.ramp() {
+            Ramp::Blend([near, mid, far]) => {
+                let color = Vec3::lerp(near, mid, smoothstep(DISTANCE_NEA, DISTANCE_MID, dist));
+                Vec3::lerp(color, far, smoothstep(DISTANCE_MID, DISTANCE_FAR, dist))
+            }
+            Ramp::Map(colors) => map_color(colors, (dist - DISTANCE_NEA) / (DISTANCE_FAR - DISTANCE_NEA)),
+        }
+    }
+
+    /// The lookup table the mark shaders interpolate: the palette sampled at evenly spaced distances.
+    fn table(self) -> [[f32; 4]; PALETTE_SIZE] {
+        std::array::from_fn(|i| {
+            let dist = DISTANCE_NEA + (DISTANCE_FAR - DISTANCE_NEA) * i as f32 / (PALETTE_SIZE - 1) as f32;
+            self.color(dist).extend(1.0).into()
+        })
+    }
+
+    /// Colors of surface hits, painted marks, misses and dynamic marks when marks are shaded by their tag: the
+    /// palette at evenly spaced distances, so the colorblind-safe palettes keep them apart as well.
+    fn tag_colors(self) -> [[f32; 4]; TAG_KINDS] {
+        std::array::from_fn(|i| {
+            let dist = DISTANCE_NEA + (DISTANCE_FAR - DISTANCE_NEA) * i as f32 / (TAG_KINDS - 1) as f32;
+            self.color(dist).extend(1.0).into()
+        })
+    }
+}
+
+/// Interpolates the evenly spaced `0xrrggbb` `colors` at `x` between 0 and 1.
+fn map_color(colors: &[u32], x: f32) -> Vec3 {
+    let x = x.clamp(0.0, 1.0) * (colors.len() - 1) as f32;
+    let i = (x as usize).min(colors.len() - 2);
+    Vec3::lerp(unpack_rgb(colors[i]), unpack_rgb(colors[i + 1]), x - i as f32)
+}
+
+/// Heatmap color of a leaf `density` marks per square unit, on a log scale from [`DENSITY_SPARSE`] to
+/// [`DENSITY_DENSE`].
+pub fn density_color(density: f32) -> Vec3 {
+    let x = (density.max(f32::MIN_POSITIVE) / DENSITY_SPARSE).ln() / (DENSITY_DENSE / DENSITY_SPARSE).ln();
+    map_color(&DENSITY, x)
+}
+
+fn unpack_rgb(rgb: u32) -> Vec3 {
+    vec3((rgb >> 16) as f32, (rgb >> 8 & 0xff) as f32, (rgb & 0xff) as f32) / 255.0
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GlobalsUniform {
+    ruler_spacing: f32,
+    ruler_enabled: u32,
+    point_size: f32,
+    /// Marks scanned later than this, in seconds of scan time, are hidden; [`NO_TIME_LIMIT`] shows every mark.
+    time_limit: f32,
+    /// Palette lookup table from the near to the far distance, with unused alpha.
+    palette: [[f32; 4]; PALETTE_SIZE],
+    /// Marks on the negative side of this plane are hidden; [`NO_CLIP`] keeps every mark.
+    clip_plane: [f32; 4],
+    /// Per layer, the color its marks are drawn in instead of their own where alpha is set.
+    layer_colors: [[f32; 4]; MAX_LAYERS],
+    /// Per kind of mark, the color it is shaded with instead of by distance while `by_tag` is set.
+    tag_colors: [[f32; 4]; TAG_KINDS],
+    /// Non-zero to draw every mark in its own color, which culling sets to the density heatmap.
+    heatmap: u32,
+    /// Non-zero to shade marks by their tag rather than their distance.
+    by_tag: u32,
+    _padding: [u32; 2],
+}
+
+impl GlobalsUniform {
+    pub fn new() -> Self {
+        Self {
+            ruler_spacing: DEFAULT_RULER_SPACING,
+            ruler_enabled: 0,
+            point_size: DEFAULT_POINT_SIZE,
+            time_limit: NO_TIME_LIMIT,
+            palette: Palette::Spectrum.table(),
+            clip_plane: NO_CLIP,
+            layer_colors: [[0.0; 4]; MAX_LAYERS],
+            tag_colors: Palette::Spectrum.tag_colors(),
+            heatmap: 0,
+            by_tag: 0,
+            _padding: [0; 2],
+        }
+    }
+}
+
+impl Default for GlobalsUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct Marker {
+    /// Depth tested against the terrain occluders, for the main scene pass.
     render_pipeline: wgpu::RenderPipeline,
+    /// Without a depth attachment, for overlays such as the inspector.
+    overlay_pipeline: wgpu::RenderPipeline,
 
     instances: Vec<MarkRaw>,
     vertex_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
+    instance_ring: ring::InstanceRing,
+    n_visible: u32,
+    /// Marks attached to entities, re-uploaded every frame.
+    dynamic_buffer: wgpu::Buffer,
+    n_dynamic: u32,
+    /// Marks that are drawn but never stored, set by tools every frame.
+    transient_buffer: wgpu::Buffer,
+    n_transient: u32,
+    inst_n: usize,
+    pending_inst_n: Option<usize>,
 
     pub camera_uniform: CameraUniform,
     pub camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
+    pub camera_bind_group_layout: wgpu::BindGroupLayout,
+    pub camera_bind_group: wgpu::BindGroup,
+
+    pub globals_uniform: GlobalsUniform,
+    globals_buffer: wgpu::Buffer,
 
-    octree: octree::Octree,
+    pub octree: octree::Octree,
+    visible_cache: octree::VisibleCache,
+    pub splatter: splat::Splatter,
+    pub accumulator: accumulate::Accumulator,
+    pub eye_dome: edl::EyeDome,
 
     pub should_cast: bool,
     marker_timer: f64,
+    cooldown: f64,
+    /// Radius of the spot the cone should cover on the surface, if the cone adapts to the hit distance.
+    pub adaptive_cone: Option<f32>,
+    /// Running average of recent hit distances.
+    hit_distance: Option<f32>,
+    /// Shots fired each time the timer runs out, multiplying the spray density at the same cooldown.
+    rays_per_tick: usize,
+    /// Shots that came due but were left for a later frame by the ray budget.
+    pub queue: RayQueue,
+    pub pattern: ScanPattern,
+    /// Jitter for every ray the scanner casts, seeded at startup so runs can be replayed.
+    pub rng: Box<dyn ScanRng>,
+    /// Spreads the rays over the scanner cone.
+    pub sampler: Box<dyn DiscSampler>,
+    pub sampler_kind: SamplerKind,
 }
 
 impl Marker {
+    /// Builds the mark render pipeline for the surface format in `config`, with an empty octree.
     pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, camera: &Camera) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("../shader.wgsl"));
 
         let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
             label: Some("camera_bind_group_layout"),
         });
 
@@ -105,36 +576,17 @@ impl Marker {
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[Vertex::desc(), MarkRaw::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
-            multiview: None,
-        });
+        let depth_stencil = wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        };
+        let render_pipeline =
+            create_mark_pipeline(device, &render_pipeline_layout, &shader, config.format, Some(depth_stencil));
+        let overlay_pipeline = create_mark_pipeline(device, &render_pipeline_layout, &shader, config.format, None);
+        let eye_dome = edl::EyeDome::new(device, config, &shader, &render_pipeline_layout);
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -142,14 +594,9 @@ impl Marker {
             usage: wgpu::BufferUsages::VERTEX,
         });
 
-        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Instance Buffer"),
-            size: (INST_N * std::mem::size_of::<MarkRaw>()) as u64,
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+        let inst_n = usize::min(DEFAULT_INST_N, max_instances(device));
 
-        let camera_uniform = CameraUniform::new(&camera);
+        let camera_uniform = CameraUniform::new(camera);
 
         let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Buffer"),
@@ -157,9 +604,20 @@ impl Marker {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
+        let globals_uniform = GlobalsUniform::new();
+
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Globals Buffer"),
+            contents: bytemuck::cast_slice(&[globals_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
         let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &camera_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: globals_buffer.as_entire_binding() },
+            ],
             label: Some("camera_bind_group"),
         });
 
@@ -167,58 +625,532 @@ impl Marker {
 
         Self {
             render_pipeline,
-            instances: Vec::with_capacity(INST_N),
+            overlay_pipeline,
+            instances: Vec::with_capacity(inst_n),
             vertex_buffer,
-            instance_buffer,
+            instance_ring: ring::InstanceRing::new(device, inst_n),
+            n_visible: 0,
+            dynamic_buffer: create_instance_buffer(device, octree::MAX_DYNAMIC_MARKS),
+            n_dynamic: 0,
+            transient_buffer: create_instance_buffer(device, MAX_TRANSIENT_MARKS),
+            n_transient: 0,
+            inst_n,
+            pending_inst_n: None,
             camera_uniform,
             camera_buffer,
+            camera_bind_group_layout,
             camera_bind_group,
+            globals_uniform,
+            globals_buffer,
             octree,
+            visible_cache: octree::VisibleCache::new(),
+            splatter: splat::Splatter::new(device, config),
+            accumulator: accumulate::Accumulator::new(device, config),
+            eye_dome,
             marker_timer: 0.0,
+            cooldown: DEFAULT_MARKER_COOLDOWN,
+            rays_per_tick: 1,
+            queue: RayQueue::default(),
+            adaptive_cone: None,
+            hit_distance: None,
+            pattern: ScanPattern::Spray,
             should_cast: false,
+            rng: Box::new(StdRng::seed_from_u64(0)),
+            sampler: SamplerKind::R2.create(),
+            sampler_kind: SamplerKind::R2,
         }
     }
+
+    /// Rebuilds every GPU resource on a new device, keeping the octree and scanner settings. The instance cap is
+    /// reapplied on the next update.
+    pub fn recreate(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, camera: &Camera) {
+        let old = std::mem::replace(self, Marker::new(device, config, camera));
+        self.set_instance_cap(old.instance_cap());
+        self.octree = old.octree;
+        self.globals_uniform = old.globals_uniform;
+        self.should_cast = old.should_cast;
+        self.marker_timer = old.marker_timer;
+        self.cooldown = old.cooldown;
+        self.rays_per_tick = old.rays_per_tick;
+        self.queue = old.queue;
+        self.adaptive_cone = old.adaptive_cone;
+        self.hit_distance = old.hit_distance;
+        self.pattern = old.pattern;
+        self.rng = old.rng;
+        self.sampler = old.sampler;
+        self.sampler_kind = old.sampler_kind;
+        self.splatter.enabled = old.splatter.enabled;
+        self.accumulator.set_enabled(device, old.accumulator.enabled);
+        self.eye_dome.enabled = old.eye_dome.enabled;
+    }
+
+    /// Binds `camera_buffer` together with the shared globals, so the mark pipeline can render from another camera.
+    pub fn create_camera_bind_group(&self, device: &wgpu::Device, camera_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.camera_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: self.globals_buffer.as_entire_binding() },
+            ],
+            label: Some("camera_bind_group"),
+        })
+    }
+
+    /// Draws the first `n_marks` instances of `instance_buffer` with the mark pipeline. `depth_tested` selects the
+    /// pipeline for passes with a [`DEPTH_FORMAT`] attachment.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        camera_bind_group: &'a wgpu::BindGroup,
+        instance_buffer: &'a wgpu::Buffer,
+        n_marks: u32,
+        depth_tested: bool,
+    ) {
+        render_pass.set_pipeline(if depth_tested { &self.render_pipeline } else { &self.overlay_pipeline });
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.draw(0..6, 0..n_marks);
+    }
+
+    #[inline]
+    pub fn instance_cap(&self) -> usize {
+        self.pending_inst_n.unwrap_or(self.inst_n)
+    }
+
+    /// Replaces the scanner's jitter source, e.g. with a generator seeded for a replay.
+    pub fn set_rng(&mut self, rng: impl ScanRng + 'static) {
+        self.rng = Box::new(rng);
+    }
+
+    pub fn set_sampler(&mut self, kind: SamplerKind) {
+        self.sampler = kind.create();
+        self.sampler_kind = kind;
+    }
+
+    /// Requests a new instance cap. The change is deferred to the next frame boundary so no buffer in use by the
+    /// current frame is ever replaced.
+    pub fn set_instance_cap(&mut self, inst_n: usize) {
+        self.pending_inst_n = Some(usize::max(inst_n, MIN_INST_N));
+    }
+
+    pub fn toggle_ruler(&mut self) {
+        self.globals_uniform.ruler_enabled ^= 1;
+    }
+
+    /// Multiplies the spacing between distance rings, in world units, by `factor`.
+    pub fn scale_ruler_spacing(&mut self, factor: f32) {
+        let spacing = self.globals_uniform.ruler_spacing * factor;
+        self.globals_uniform.ruler_spacing = spacing.clamp(MIN_RULER_SPACING, MAX_RULER_SPACING);
+    }
+
+    /// Multiplies the delay between rays by `factor`, returning the new delay in seconds.
+    pub fn scale_cooldown(&mut self, factor: f64) -> f64 {
+        self.cooldown = (self.cooldown * factor).clamp(MIN_MARKER_COOLDOWN, MAX_MARKER_COOLDOWN);
+        self.cooldown
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        self.octree.translate(-shift);
+        self.queue.rebase(shift);
+        self.accumulator.clear();
+    }
+
+    /// Changes the number of shots per timer tick by `delta`, returning the new count.
+    pub fn adjust_rays_per_tick(&mut self, delta: isize) -> usize {
+        self.rays_per_tick = self.rays_per_tick.saturating_add_signed(delta).clamp(1, MAX_RAYS_PER_TICK);
+        self.rays_per_tick
+    }
+
+    /// Feeds a hit distance from the raycaster into the average the adaptive cone follows.
+    pub fn record_hit_distance(&mut self, dist: f32) {
+        self.hit_distance = Some(match self.hit_distance {
+            Some(average) => average + (dist - average) * HIT_DISTANCE_SMOOTHING,
+            None => dist,
+        });
+    }
+
+    /// Makes the adaptive cone keep the spot that `ray_range` covers at the current hit distance.
+    pub fn retarget_adaptive_cone(&mut self, ray_range: f32) {
+        if let (Some(spot), Some(dist)) = (&mut self.adaptive_cone, self.hit_distance) {
+            *spot = ray_range * 0.5 * dist;
+        }
+    }
+
+    /// Cone width that covers the target spot at the average hit distance, if the cone adapts.
+    fn adapted_ray_range(&self) -> Option<f32> {
+        let (spot, dist) = (self.adaptive_cone?, self.hit_distance?);
+        Some((2.0 * spot / dist.max(f32::EPSILON)).clamp(MIN_RAY_RANGE, MAX_RAY_RANGE))
+    }
+
+    /// Shots fired per second at the current cooldown and rays per tick.
+    pub fn scan_rate(&self) -> f64 {
+        self.rays_per_tick as f64 / (self.cooldown * self.pattern.rays_per_shot() as f64)
+    }
+
+    pub fn point_size(&self) -> f32 {
+        self.globals_uniform.point_size
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.globals_uniform.palette = palette.table();
+        self.globals_uniform.tag_colors = palette.tag_colors();
+    }
+
+    /// Lookup table of the current palette, for other passes that shade marks by distance.
+    pub fn palette_table(&self) -> [[f32; 4]; PALETTE_SIZE] {
+        self.globals_uniform.palette
+    }
+
+    /// Colors of each kind of mark while shading by tag, for other passes that shade marks.
+    pub fn tag_colors(&self) -> [[f32; 4]; TAG_KINDS] {
+        self.globals_uniform.tag_colors
+    }
+
+    /// Shades marks by their tag rather than their distance, or stops doing so.
+    pub fn set_by_tag(&mut self, by_tag: bool) {
+        self.globals_uniform.by_tag = by_tag as u32;
+    }
+
+    pub fn by_tag(&self) -> bool {
+        self.globals_uniform.by_tag != 0
+    }
+
+    /// Replaces the temporary marks, keeping the first `MAX_TRANSIENT_MARKS`.
+    pub fn set_transient_marks(&mut self, queue: &wgpu::Queue, marks: &[MarkRaw]) {
+        let marks = &marks[..marks.len().min(MAX_TRANSIENT_MARKS)];
+        if !marks.is_empty() {
+            queue.write_buffer(&self.transient_buffer, 0, bytemuck::cast_slice(marks));
+        }
+        self.n_transient = marks.len() as u32;
+    }
+
+    /// Hides the marks on the negative side of `plane`, a normal and offset, or none.
+    pub fn set_clip_plane(&mut self, plane: Option<Vec4>) {
+        self.globals_uniform.clip_plane = plane.map_or(NO_CLIP, Vec4::into);
+    }
+
+    pub fn clip_plane(&self) -> [f32; 4] {
+        self.globals_uniform.clip_plane
+    }
+
+    /// Draws the marks of each layer in its color, or their own.
+    pub fn set_layer_colors(&mut self, colors: impl IntoIterator<Item = Option<Vec3>>) {
+        self.globals_uniform.layer_colors = [[0.0; 4]; MAX_LAYERS];
+        for (uniform, color) in self.globals_uniform.layer_colors.iter_mut().zip(colors) {
+            *uniform = color.map_or([0.0; 4], |color| color.extend(1.0).into());
+        }
+    }
+
+    pub fn layer_colors(&self) -> [[f32; 4]; MAX_LAYERS] {
+        self.globals_uniform.layer_colors
+    }
+
+    /// Draws the marks in the color of their leaf's density rather than by distance, or stops doing so.
+    pub fn set_heatmap(&mut self, heatmap: bool) {
+        self.octree.set_heatmap(heatmap);
+        self.globals_uniform.heatmap = heatmap as u32;
+    }
+
+    pub fn heatmap(&self) -> bool {
+        self.globals_uniform.heatmap != 0
+    }
+
+    /// Hides the marks scanned after `time`, or none.
+    pub fn set_time_limit(&mut self, time: Option<f32>) {
+        self.globals_uniform.time_limit = time.unwrap_or(NO_TIME_LIMIT);
+    }
+
+    pub fn time_limit(&self) -> f32 {
+        self.globals_uniform.time_limit
+    }
+
+    /// Which marks are drawn under the current clip plane, time limit and hidden layers.
+    pub fn visibility(&self) -> Visibility {
+        Visibility {
+            clip_plane: self.globals_uniform.clip_plane.into(),
+            time_limit: self.globals_uniform.time_limit,
+            layer_mask: self.octree.layer_mask(),
+        }
+    }
+
+    /// Multiplies the world-space size of mark splats by `factor`, returning the new size.
+    pub fn scale_point_size(&mut self, factor: f32) -> f32 {
+        let size = self.globals_uniform.point_size * factor;
+        self.globals_uniform.point_size = size.clamp(MIN_POINT_SIZE, MAX_POINT_SIZE);
+        self.globals_uniform.point_size
+    }
+
+    fn apply_instance_cap(&mut self, device: &wgpu::Device) {
+        let Some(inst_n) = self.pending_inst_n.take() else {
+            return;
+        };
+
+        let inst_n = usize::min(inst_n, max_instances(device));
+        if inst_n == self.inst_n {
+            return;
+        }
+
+        self.instance_ring = ring::InstanceRing::new(device, inst_n);
+        self.instances = Vec::with_capacity(inst_n);
+        self.inst_n = inst_n;
+    }
+}
+
+fn create_mark_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[Vertex::desc(), MarkRaw::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: Some(wgpu::Face::Back),
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil,
+        multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+        multiview: None,
+    })
+}
+
+fn max_instances(device: &wgpu::Device) -> usize {
+    (device.limits().max_buffer_size / std::mem::size_of::<MarkRaw>() as u64) as usize
+}
+
+pub(crate) fn create_instance_buffer(device: &wgpu::Device, inst_n: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Instance Buffer"),
+        size: (inst_n * std::mem::size_of::<MarkRaw>()) as u64,
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Color stored with scanned marks, for exports; the renderer shades marks with the current palette instead.
+fn scan_color(dist: f32) -> Vec3 {
+    Palette::Spectrum.color(dist)
 }
 
 impl State {
-    pub fn render_markers<'a>(&'a mut self, render_pass: &mut wgpu::RenderPass<'a>) {
+    /// Culls the octree against the camera and uploads the visible marks for this frame.
+    pub fn prepare_markers(&mut self) {
         let frustum = self.camera.frustum();
-        self.marker.octree.get_visible(&mut self.marker.instances, self.camera.pos, frustum);
+        let inst_n = self.marker.inst_n;
+        let marker = &mut self.marker;
+        let cull_span = tracing::info_span!("octree_cull").entered();
+        let changed = marker.octree.get_visible_cached(
+            &mut marker.visible_cache,
+            &mut marker.instances,
+            inst_n,
+            self.camera.pos,
+            frustum,
+        );
+        drop(cull_span);
 
         let n_total = self.marker.instances.len();
-        let n_marks = usize::min(self.marker.instances.len(), INST_N);
+        let n_marks = usize::min(self.marker.instances.len(), inst_n);
 
-        self.queue.write_buffer(
-            &self.marker.instance_buffer,
-            0 as wgpu::BufferAddress,
-            bytemuck::cast_slice(&self.marker.instances[usize::saturating_sub(n_total, INST_N)..]),
-        );
+        if changed {
+            let visible = &self.marker.instances[usize::saturating_sub(n_total, inst_n)..];
+            self.marker.instance_ring.upload(&self.queue, visible);
+        }
 
-        render_pass.set_pipeline(&self.marker.render_pipeline);
-        render_pass.set_vertex_buffer(0, self.marker.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.marker.instance_buffer.slice(..));
-        render_pass.set_bind_group(0, &self.marker.camera_bind_group, &[]);
-        render_pass.draw(0..6, 0..n_marks as _);
+        self.marker.n_visible = n_marks as u32;
+        self.prepare_splats();
+
+        let entities = &self.entities;
+        let dynamic_span = tracing::info_span!("octree_dynamic").entered();
+        let dynamic =
+            self.marker.octree.update_dynamic(|id| entities.get(id).map(|entity| entity.pos), self.camera.pos);
+        drop(dynamic_span);
+        if self.marker.n_dynamic > 0 || !dynamic.is_empty() {
+            self.queue.write_buffer(&self.marker.dynamic_buffer, 0, bytemuck::cast_slice(dynamic));
+            self.marker.n_dynamic = dynamic.len() as u32;
+        }
 
         if self.title_update {
-            let title = format!("Scanner Demo | marks: {}({})", n_marks, self.marker.octree.count());
-            _ = self.window.set_title(title.as_str());
+            let locale = &self.locale;
+            let marks = locale.format(
+                "title.marks",
+                &[("marks", &n_marks), ("stored", &self.marker.octree.count()), ("cap", &self.marker.instance_cap())],
+            );
+            let mut title = format!("{} | {}", locale.get("title"), marks);
+            if self.net.enabled() {
+                title = format!(
+                    "{} | {}",
+                    title,
+                    locale.format("title.players", &[("players", &(self.net.players() + 1))])
+                );
+            }
+            if let Some(progress) = self.archive_progress() {
+                let percent = format!("{:.0}", progress * 100.0);
+                title = format!("{} | {}", title, locale.format("title.loading", &[("percent", &percent)]));
+            }
+            if let Some((text, _)) = &self.notification {
+                title = format!("{} | {}", title, text);
+            }
+            self.window.set_title(title.as_str());
+        }
+    }
+
+    pub fn render_markers<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let marker = &self.marker;
+        if !marker.resolve_splats(render_pass) {
+            let instance_buffer = marker.instance_ring.current();
+            marker.draw(render_pass, &marker.camera_bind_group, instance_buffer, marker.n_visible, true);
+        }
+        if marker.n_dynamic > 0 {
+            marker.draw(render_pass, &marker.camera_bind_group, &marker.dynamic_buffer, marker.n_dynamic, true);
+        }
+        if marker.n_transient > 0 {
+            marker.draw(render_pass, &marker.camera_bind_group, &marker.transient_buffer, marker.n_transient, true);
         }
     }
 
     pub fn update_marker(&mut self, dt: f64) {
-        if self.marker.marker_timer < 0.0 {
+        self.marker.apply_instance_cap(&self.device);
+        self.queue.write_buffer(&self.marker.globals_buffer, 0, bytemuck::cast_slice(&[self.marker.globals_uniform]));
+
+        if let Some(range) = self.marker.adapted_ray_range() {
+            self.camera.ray_range = range;
+        }
+
+        if self.museum.active || self.editor.active {
             self.marker.marker_timer = 0.0;
-        } else {
-            self.marker.marker_timer -= dt;
+            self.marker.queue.clear();
+            return;
         }
 
-        while self.marker.marker_timer <= 0.0 && self.marker.should_cast {
-            self.marker.marker_timer += MARKER_COOLDOWN;
-            let ray = self.camera.cast_ray();
-            if let Some(pos) = self.world.raycast(ray, -1.0) {
-                self.marker.octree.insert(Mark { pos });
+        // The timer carries the time left over from each tick into the next frame, so the number of shots per second
+        // depends only on the cooldown and not on the frame rate.
+        if self.marker.should_cast {
+            self.marker.marker_timer -= dt;
+            while self.marker.marker_timer <= 0.0 && self.gameplay.can_scan() {
+                self.marker.marker_timer += self.marker.cooldown * self.marker.pattern.rays_per_shot() as f64;
+                for _ in 0..self.marker.rays_per_tick {
+                    let ray = self.sample_ray();
+                    self.marker.queue.push(ray, self.marker.pattern.rays_per_shot());
+                }
             }
+            self.marker.marker_timer = self.marker.marker_timer.max(0.0);
+        } else {
+            self.marker.marker_timer = 0.0;
         }
+
+        // Shots already fired land even after the button is released.
+        let rays = self.marker.queue.take(self.marker.pattern.rays_per_shot());
+        let _span = tracing::info_span!("raycast", rays = rays.len()).entered();
+        self.scan_batch(&rays);
+    }
+
+    /// Casts a ray from the eye in a direction picked by the sampler within the scanner cone.
+    fn sample_ray(&mut self) -> Ray {
+        let offset = self.marker.sampler.sample(&mut *self.marker.rng) * self.gameplay.heat.jitter_scale();
+        self.camera.cast_ray_at(offset)
+    }
+
+    /// Switches between a fixed cone and one that narrows on distant surfaces and widens up close, keeping the spot
+    /// it covers, and so the density of new marks, roughly constant. The spot starts as the one the cone covers now.
+    pub fn toggle_adaptive_cone(&mut self) {
+        let marker = &mut self.marker;
+        marker.adaptive_cone = match marker.adaptive_cone {
+            Some(_) => None,
+            None => Some(marker.hit_distance.map_or(DEFAULT_SPOT_RADIUS, |dist| self.camera.ray_range * 0.5 * dist)),
+        };
+        let state = if marker.adaptive_cone.is_some() { "on" } else { "off" };
+        self.notify(format!("adaptive cone: {}", state));
+    }
+
+    /// Switches between shading marks with the palette and the density heatmap, which shows where the scan is still
+    /// sparse and where it has been gone over again and again.
+    pub fn toggle_heatmap(&mut self) {
+        let heatmap = !self.marker.heatmap();
+        self.marker.set_heatmap(heatmap);
+        self.marker.accumulator.clear();
+        self.notify(format!("density heatmap: {}", if heatmap { "on" } else { "off" }));
+    }
+
+    /// Switches between shading marks by their distance and by their tag, which sets painted marks, misses and marks
+    /// on entities apart in the palette's colors.
+    pub fn toggle_tag_colors(&mut self) {
+        let by_tag = !self.marker.by_tag();
+        self.marker.set_by_tag(by_tag);
+        self.marker.accumulator.clear();
+        self.notify(format!("tag colors: {}", if by_tag { "on" } else { "off" }));
+    }
+
+    /// Multiplies the scan rate by [`RATE_STEP`] (`faster`) or divides it.
+    pub fn step_scan_rate(&mut self, faster: bool) {
+        self.marker.scale_cooldown(if faster { 1.0 / RATE_STEP } else { RATE_STEP });
+        self.notify(format!("scan rate: {:.0} rays/s", self.marker.scan_rate()));
+    }
+
+    /// Changes the number of shots per timer tick by `delta`, returning the new count.
+    pub fn adjust_rays_per_tick(&mut self, delta: isize) {
+        let rays = self.marker.adjust_rays_per_tick(delta);
+        self.notify(format!("rays per tick: {} ({:.0} rays/s)", rays, self.marker.scan_rate()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        density_color, scan_color, unpack_rgb, Palette, CIVIDIS, COLORBLIND, COLOR_FAR, COLOR_NEA, DENSITY,
+        DENSITY_DENSE, DENSITY_SPARSE, PALETTE_SIZE, TAG_KINDS,
+    };
+    use glam::{vec3, Vec3};
+
+    #[test]
+    fn palette_tables_span_the_ramp_from_near_to_far() {
+        let ends = |palette: Palette| {
+            let table = palette.table();
+            (Vec3::from_slice(&table[0]), Vec3::from_slice(&table[PALETTE_SIZE - 1]))
+        };
+        let (near, far) = ends(Palette::Spectrum);
+        assert!(near.abs_diff_eq(COLOR_NEA, 1e-6) && far.abs_diff_eq(COLOR_FAR, 1e-6));
+        assert_eq!(Palette::Spectrum.color(250.0), scan_color(250.0));
+
+        let (near, far) = ends(Palette::Cividis);
+        assert!(near.abs_diff_eq(unpack_rgb(CIVIDIS[0]), 1e-6));
+        assert!(far.abs_diff_eq(unpack_rgb(CIVIDIS[CIVIDIS.len() - 1]), 1e-6));
+        assert_eq!(unpack_rgb(0xff8000), vec3(1.0, 128.0 / 255.0, 0.0));
+
+        let tags = Palette::Colorblind.tag_colors().map(|color| Vec3::from_slice(&color));
+        assert!(tags[0].abs_diff_eq(unpack_rgb(COLORBLIND[0]), 1e-6));
+        assert!(tags[TAG_KINDS - 1].abs_diff_eq(unpack_rgb(COLORBLIND[COLORBLIND.len() - 1]), 1e-6));
+        assert!(tags.windows(2).all(|pair| pair[0].distance(pair[1]) > 0.2));
+        assert_eq!(serde_json::from_str::<Palette>("\"deuteranopia\"").unwrap(), Palette::Colorblind);
+    }
+
+    #[test]
+    fn density_colors_run_from_sparse_to_dense_on_a_log_scale() {
+        let at = |density: f32, index: usize| density_color(density).abs_diff_eq(unpack_rgb(DENSITY[index]), 1e-5);
+        assert!(at(0.0, 0) && at(DENSITY_SPARSE, 0));
+        assert!(at((DENSITY_SPARSE * DENSITY_DENSE).sqrt(), 2));
+        assert!(at(DENSITY_DENSE, 4) && at(DENSITY_DENSE * 10.0, 4));
     }
 }
@@ -1,36 +1,224 @@
-use super::super::util::{Frustum, SVec};
-use super::{Mark, MarkRaw};
+use super::super::entity::EntityId;
+use super::super::util::{Frustum, Ray, SVec};
+use super::{density_color, Mark, MarkRaw, MarkTag, Visibility};
 use glam::{vec3, Vec3};
+use rayon::prelude::*;
+use std::collections::{HashMap, VecDeque};
 
 const BUCKET_SIZE: usize = 256;
 const BASE_EXTENSION: f32 = 50.0;
+/// Bits per axis of the Morton codes used by bulk construction, i.e. its maximum depth.
+const MORTON_BITS: u32 = 21;
+/// Trees with fewer marks are culled on the calling thread; below this the thread pool overhead dominates.
+const PARALLEL_MIN_MARKS: usize = 200_000;
+/// Number of subtrees handed to the thread pool per worker thread, to balance uneven subtrees.
+const SUBTREES_PER_THREAD: usize = 4;
+
+/// How far the camera may move, in world units, before a [`VisibleCache`] is rebuilt.
+const CACHE_MAX_MOVE: f32 = 2.0;
+/// How far the camera may turn, in radians, before a [`VisibleCache`] is rebuilt.
+const CACHE_MAX_TURN: f32 = 0.02;
+
+/// Largest half-size of a leaf whose marks are merged into surfels once it fills up, rather than split. A full leaf
+/// this small holds at least `BUCKET_SIZE / 4^3` marks per cubic unit.
+const SURFEL_MAX_EXTENSION: f32 = 2.0;
+/// Cells per axis a full leaf is divided into for merging; each cell's marks become one surfel.
+const SURFEL_GRID: usize = 2;
+/// Fewest marks a cell needs to be merged.
+const SURFEL_MIN_MARKS: usize = 8;
+
+/// Marks attached to entities kept at once; the oldest are dropped first.
+pub const MAX_DYNAMIC_MARKS: usize = 65536;
+
+static NEXT_TREE_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+fn next_tree_id() -> u64 {
+    NEXT_TREE_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
 
 pub struct Octree {
+    /// Distinguishes trees so a [`VisibleCache`] built for one is never replayed against another.
+    id: u64,
+    /// Incremented on every insertion, and whenever the layer mask changes.
+    revision: u64,
+    /// Bit per layer whose marks are returned by the culling functions.
+    layer_mask: u32,
+    /// Whether the culling functions color marks by the density of their leaf, see [`Octant::density`].
+    heatmap: bool,
     root: u32,
     octants: Vec<Octant>,
+    bounds: Option<(Vec3, Vec3)>,
+    /// Per-subtree output buffers of the parallel traversal, kept between frames to avoid reallocating.
+    buffers: Vec<Vec<MarkRaw>>,
+    /// Marks attached to entities. They move every frame, so rather than being filed into octants they are kept in
+    /// one bucket that [`Octree::update_dynamic`] re-positions and re-sorts.
+    dynamic: VecDeque<Mark>,
+    dynamic_raw: Vec<MarkRaw>,
+}
+
+impl Default for Octree {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Octree {
+    /// Creates an octree with a single empty leaf around the origin; the root grows outwards as marks are inserted.
     pub fn new() -> Self {
         Self {
+            id: next_tree_id(),
+            revision: 0,
+            layer_mask: u32::MAX,
+            heatmap: false,
             root: 0,
-            octants: vec![Octant {
-                center: vec3(0.0, 0.0, 0.0),
-                extension: BASE_EXTENSION,
-                content: Content::Leaf(SVec::new()),
-            }],
+            octants: vec![Octant::leaf(vec3(0.0, 0.0, 0.0), BASE_EXTENSION, SVec::new())],
+            bounds: None,
+            buffers: Vec::new(),
+            dynamic: VecDeque::new(),
+            dynamic_raw: Vec::new(),
         }
     }
 
+    /// Inserts `mark` into the tree, or into the dynamic bucket if it is attached to an entity.
     pub fn insert(&mut self, mark: Mark) {
-        while !self[self.root].contains(mark) {
+        if mark.parent.is_some() {
+            if self.dynamic.len() == MAX_DYNAMIC_MARKS {
+                self.dynamic.pop_front();
+            }
+            self.dynamic.push_back(mark);
+            return;
+        }
+
+        self.bounds = match self.bounds {
+            Some((min, max)) => Some((min.min(mark.pos), max.max(mark.pos))),
+            None => Some((mark.pos, mark.pos)),
+        };
+        self.grow_to(mark.pos);
+        self.insert_contained(mark);
+    }
+
+    /// Inserts many marks at once. The root is grown to the batch's bounding box up front, so each mark descends
+    /// straight to its leaf without re-checking the root bounds.
+    pub fn extend(&mut self, marks: impl IntoIterator<Item = Mark>) {
+        let marks: Vec<Mark> = marks.into_iter().collect();
+        let Some(first) = marks.first() else {
+            return;
+        };
+
+        let (min, max) =
+            marks.iter().fold((first.pos, first.pos), |(min, max), mark| (min.min(mark.pos), max.max(mark.pos)));
+        self.bounds = match self.bounds {
+            Some((old_min, old_max)) => Some((old_min.min(min), old_max.max(max))),
+            None => Some((min, max)),
+        };
+        self.grow_to(min);
+        self.grow_to(max);
+
+        for mark in marks {
+            self.insert_contained(mark);
+        }
+    }
+
+    /// Builds a tree holding `points`, all with the same neutral color. See [`Octree::from_marks`].
+    pub fn from_points(points: &[Vec3]) -> Self {
+        Self::from_marks(points.iter().map(|pos| Mark::new(*pos, Vec3::ONE)).collect())
+    }
+
+    /// Builds a tree holding `marks` bottom-up: the marks are sorted by Morton code within a root cube fitted to
+    /// their bounds, so every octant's marks form a contiguous run that is split by code bits without re-testing
+    /// positions. Marks exactly on a split plane may land one step (1/2^21 of the root size) on the other side
+    /// compared to [`Octree::insert`], which only matters for marks on an octant's boundary.
+    pub fn from_marks(marks: Vec<Mark>) -> Self {
+        let Some(first) = marks.first() else {
+            return Self::new();
+        };
+
+        let (min, max) =
+            marks.iter().fold((first.pos, first.pos), |(min, max), mark| (min.min(mark.pos), max.max(mark.pos)));
+        let center = (min + max) * 0.5;
+        let extension = f32::max((max - min).max_element() * 0.5 * (1.0 + 1e-5), 1e-3);
+        let origin = center - extension;
+        let cells = (1u32 << MORTON_BITS) as f32;
+
+        let mut coded: Vec<(u64, MarkRaw)> = marks
+            .into_iter()
+            .map(|mark| {
+                let cell =
+                    ((mark.pos - origin) / (2.0 * extension) * cells).clamp(Vec3::ZERO, Vec3::splat(cells - 1.0));
+                (morton(cell.x as u32, cell.y as u32, cell.z as u32), mark.to_raw())
+            })
+            .collect();
+        coded.sort_unstable_by_key(|(code, _)| *code);
+
+        let mut octree = Self {
+            id: next_tree_id(),
+            revision: 0,
+            layer_mask: u32::MAX,
+            heatmap: false,
+            root: 0,
+            octants: Vec::new(),
+            bounds: Some((min, max)),
+            buffers: Vec::new(),
+            dynamic: VecDeque::new(),
+            dynamic_raw: Vec::new(),
+        };
+        let mut overflow = Vec::new();
+        octree.root = octree.build(&coded, center, extension, MORTON_BITS, &mut overflow);
+        for mark in overflow {
+            octree.insert_contained(Mark::from(mark));
+        }
+        octree
+    }
+
+    /// Recursively builds the octant for `coded`, whose codes all agree above `bits` bits per axis, and returns its
+    /// id. Marks that still overflow a bucket once the code bits run out are left in `overflow`.
+    fn build(
+        &mut self,
+        coded: &[(u64, MarkRaw)],
+        center: Vec3,
+        extension: f32,
+        bits: u32,
+        overflow: &mut Vec<MarkRaw>,
+    ) -> u32 {
+        if coded.len() <= BUCKET_SIZE || bits == 0 {
+            let (fits, rest) = coded.split_at(usize::min(coded.len(), BUCKET_SIZE));
+            overflow.extend(rest.iter().map(|(_, mark)| *mark));
+            let data = SVec::try_from_iter(fits.iter().map(|(_, mark)| *mark)).unwrap();
+            self.octants.push(Octant::leaf(center, extension, data));
+            return self.octants.len() as u32 - 1;
+        }
+
+        let shift = 3 * (bits - 1);
+        let mut children = [0; 8];
+        let mut rest = coded;
+        for (i, child) in children.iter_mut().enumerate() {
+            let len = rest.partition_point(|(code, _)| (code >> shift) & 7 == i as u64);
+            let (run, tail) = rest.split_at(len);
+            rest = tail;
+
+            let mut child_center = center;
+            for j in 0..3 {
+                child_center[j] += if i & 1 << j != 0 { extension / 2.0 } else { -extension / 2.0 };
+            }
+            *child = self.build(run, child_center, extension / 2.0, bits - 1, overflow);
+        }
+
+        let count = children.iter().map(|id| self[*id].count).sum();
+        let color_sum = children.iter().map(|id| self[*id].color_sum).sum();
+        self.octants.push(Octant { center, extension, count, color_sum, hits: 0, content: Content::Parent(children) });
+        self.octants.len() as u32 - 1
+    }
+
+    /// Adds parent octants above the root until it contains `pos`.
+    fn grow_to(&mut self, pos: Vec3) {
+        while !self[self.root].contains(pos) {
             let center = self[self.root].center;
             let extension = self[self.root].extension;
 
             let mut child_id = 0;
             let mut new_center = center;
             for i in 0..3 {
-                if mark.pos[i] > center[i] {
+                if pos[i] > center[i] {
                     child_id |= 1 << i;
                     new_center[i] += extension;
                 } else {
@@ -52,24 +240,39 @@ impl Octree {
                         }
                     }
                     children_id.push(self.octants.len() as u32);
-                    self.octants.push(Octant { center, extension, content: Content::Leaf(SVec::new()) });
+                    self.octants.push(Octant::leaf(center, extension, SVec::new()));
                 }
             }
 
+            let (count, color_sum) = (self[self.root].count, self[self.root].color_sum);
             self.root = self.octants.len() as u32;
             self.octants.push(Octant {
                 center: new_center,
                 extension: extension * 2.0,
+                count,
+                color_sum,
+                hits: 0,
                 content: Content::Parent(children_id.try_into().unwrap()),
             });
         }
+    }
 
+    /// Inserts `mark`, which must lie inside the root octant. A full leaf that is small enough has its marks merged
+    /// into surfels to make room before it is split.
+    fn insert_contained(&mut self, mark: Mark) {
+        self.revision += 1;
         let mark = mark.to_raw();
+        let color = mark.color();
         let mut id = self.root;
         loop {
+            if self[id].is_full_leaf() && self[id].extension <= SURFEL_MAX_EXTENSION && self.merge_surfels(id) {
+                continue;
+            }
+
             let center = self[id].center;
             let mut children = match self[id].content {
                 Content::Parent(children) => {
+                    self[id].accumulate(color);
                     let mut child_id = 0;
                     for i in 0..3 {
                         if mark.pos[i] > self[id].center[i] {
@@ -81,6 +284,8 @@ impl Octree {
                 }
                 Content::Leaf(ref mut data) => {
                     if data.push(mark) {
+                        self[id].accumulate(color);
+                        self[id].hits += 1;
                         return;
                     }
 
@@ -88,16 +293,19 @@ impl Octree {
                     let mut children_data = Vec::with_capacity(8);
                     (0..8).for_each(|_| children_data.push(SVec::new()));
 
-                    for mark in data.into_iter() {
+                    for mark in data.iter() {
                         let mut child_id = 0;
                         for i in 0..3 {
                             if mark.pos[i] > center[i] {
                                 child_id |= 1 << i;
                             }
                         }
-                        children_data[child_id].push(mark.clone());
+                        children_data[child_id].push(*mark);
                     }
 
+                    // The leaf's hits are shared out by how many of its marks each child gets, as merged surfels
+                    // don't record how many hits they stand for.
+                    let hits = self[id].hits as u64;
                     for i in 0..8 {
                         let extension = self[id].extension / 2.0;
                         let mut center = self[id].center;
@@ -108,11 +316,9 @@ impl Octree {
                                 center[j] -= extension;
                             }
                         }
-                        children.push(Octant {
-                            center,
-                            extension,
-                            content: Content::Leaf(children_data.pop().unwrap()),
-                        });
+                        let mut child = Octant::leaf(center, extension, children_data.pop().unwrap());
+                        child.hits = (hits * child.count as u64 / BUCKET_SIZE as u64) as u32;
+                        children.push(child);
                     }
 
                     children
@@ -120,56 +326,667 @@ impl Octree {
             };
 
             let mut children_ids = [0; 8];
-            for i in 0..8 {
-                children_ids[i] = self.octants.len() as u32;
+            for child_id in &mut children_ids {
+                *child_id = self.octants.len() as u32;
                 self.octants.push(children.pop().unwrap());
             }
             self[id].content = Content::Parent(children_ids);
+            self[id].hits = 0;
         }
     }
 
+    /// Replaces each cluster of at least `SURFEL_MIN_MARKS` surface marks of one layer sharing a cell of the leaf `id`
+    /// with one surfel at their centroid, with their average color and wide enough to cover them. The merged marks'
+    /// slots are freed for the following inserts. Returns whether anything was merged.
+    fn merge_surfels(&mut self, id: u32) -> bool {
+        let (center, extension) = (self[id].center, self[id].extension);
+        let Content::Leaf(ref mut data) = self[id].content else {
+            return false;
+        };
+
+        let min = center - extension;
+        let cell_size = 2.0 * extension / SURFEL_GRID as f32;
+        let mut cells: Vec<((u32, usize), usize)> = data
+            .iter()
+            .enumerate()
+            .filter(|(_, mark)| mark.tag() == MarkTag::Surface)
+            .map(|(index, mark)| {
+                let cell = ((Vec3::from(mark.pos) - min) / cell_size).floor();
+                let cell = cell.clamp(Vec3::ZERO, Vec3::splat(SURFEL_GRID as f32 - 1.0));
+                let cell = cell.x as usize + SURFEL_GRID * (cell.y as usize + SURFEL_GRID * cell.z as usize);
+                ((mark.layer, cell), index)
+            })
+            .collect();
+        cells.sort_unstable();
+
+        let mut merged = Vec::new();
+        let mut surfels = Vec::new();
+        for cluster in cells.chunk_by(|a, b| a.0 == b.0).filter(|cluster| cluster.len() >= SURFEL_MIN_MARKS) {
+            let marks = cluster.iter().map(|(_, index)| data[*index]);
+            let centroid = marks.clone().map(|mark| Vec3::from(mark.pos)).sum::<Vec3>() / cluster.len() as f32;
+            let color = marks.clone().map(|mark| mark.color()).sum::<Vec3>() / cluster.len() as f32;
+            let radius = marks.clone().map(|mark| centroid.distance(mark.pos.into())).fold(0.0, f32::max);
+            // Shown from when the first of its marks was scanned.
+            let time = marks.map(|mark| mark.time).fold(f32::INFINITY, f32::min);
+            let surfel = Mark { tag: MarkTag::surfel(2.0 * radius), ..Mark::new(centroid, color) };
+            surfels.push(surfel.stamp(time, cluster[0].0 .0).to_raw());
+            merged.extend(cluster.iter().map(|(_, index)| *index));
+        }
+        if merged.is_empty() {
+            return false;
+        }
+
+        // Highest first, so every slot swapped in from the end holds a mark that stays.
+        merged.sort_unstable_by(|a, b| b.cmp(a));
+        let removed_color: Vec3 = merged.iter().map(|index| data.swap_remove(*index).color()).sum();
+        for surfel in &surfels {
+            data.push(*surfel);
+        }
+
+        let removed = merged.len() as u32 - surfels.len() as u32;
+        let color_delta = surfels.iter().map(MarkRaw::color).sum::<Vec3>() - removed_color;
+        let mut ancestor = self.root;
+        loop {
+            self[ancestor].count -= removed;
+            self[ancestor].color_sum += color_delta;
+            match self[ancestor].content {
+                Content::Parent(children) => ancestor = children[self[ancestor].child_index(center)],
+                Content::Leaf(_) => break,
+            }
+        }
+        true
+    }
+
+    /// Only returns marks of the layers whose bit is set in `mask` from the culling functions and
+    /// [`Octree::update_dynamic`].
+    pub fn set_layer_mask(&mut self, mask: u32) {
+        if mask != self.layer_mask {
+            self.layer_mask = mask;
+            self.revision += 1;
+        }
+    }
+
+    pub fn layer_mask(&self) -> u32 {
+        self.layer_mask
+    }
+
+    /// Colors the marks returned by the culling functions by the density of their leaf instead of their own colors,
+    /// or stops doing so. Misses keep their color.
+    pub fn set_heatmap(&mut self, heatmap: bool) {
+        if heatmap != self.heatmap {
+            self.heatmap = heatmap;
+            self.revision += 1;
+        }
+    }
+
+    /// Appends the marks of the leaf `id` in a shown layer to `vec`.
+    fn extend_shown(&self, vec: &mut Vec<MarkRaw>, id: u32) {
+        let Content::Leaf(ref leaf) = self[id].content else {
+            return;
+        };
+        let start = vec.len();
+        if self.layer_mask == u32::MAX {
+            vec.extend_from_slice(leaf);
+        } else {
+            vec.extend(leaf.iter().filter(|mark| self.layer_mask & (1 << mark.layer) != 0));
+        }
+
+        if self.heatmap {
+            let color = (density_color(self[id].density()) * 255.0).round();
+            for mark in vec[start..].iter_mut().filter(|mark| mark.tag() != MarkTag::Miss) {
+                mark.color = [color.x as u8, color.y as u8, color.z as u8, mark.color[3]];
+            }
+        }
+    }
+
+    /// Density of the leaf holding `pos`, see [`Octant::density`], or 0 outside the tree.
+    pub fn density_at(&self, pos: Vec3) -> f32 {
+        if !self[self.root].contains(pos) {
+            return 0.0;
+        }
+        let mut id = self.root;
+        while let Content::Parent(children) = self[id].content {
+            id = children[self[id].child_index(pos)];
+        }
+        self[id].density()
+    }
+
+    /// Number of marks in the tree, not counting the dynamic bucket.
     pub fn count(&self) -> usize {
-        let mut sum = 0;
-        for oct in &self.octants {
-            if let Content::Leaf(ref data) = oct.content {
-                sum += data.len();
+        self[self.root].count as usize
+    }
+
+    /// Moves every dynamic mark to its entity's position from `entity_pos`, dropping those whose entity is gone,
+    /// and returns them sorted farthest from `pos` first like the culled marks.
+    pub fn update_dynamic(&mut self, entity_pos: impl Fn(EntityId) -> Option<Vec3>, pos: Vec3) -> &[MarkRaw] {
+        self.dynamic.retain(|mark| mark.parent.is_some_and(|parent| entity_pos(parent.entity).is_some()));
+
+        self.dynamic_raw.clear();
+        for mark in self.dynamic.iter().filter(|mark| self.layer_mask & (1 << mark.layer) != 0) {
+            let Some(parent) = mark.parent else {
+                continue;
+            };
+            let Some(entity_pos) = entity_pos(parent.entity) else {
+                continue;
+            };
+            self.dynamic_raw.push(Mark { pos: entity_pos + parent.offset, ..*mark }.to_raw());
+        }
+        self.dynamic_raw.sort_unstable_by(|a, b| {
+            let dist_a = Vec3::from(a.pos).distance_squared(pos);
+            let dist_b = Vec3::from(b.pos).distance_squared(pos);
+            f32::total_cmp(&dist_b, &dist_a)
+        });
+        &self.dynamic_raw
+    }
+
+    /// Moves every mark by `offset`, e.g. when the local origin is rebased. The structure is kept as it is, which is
+    /// far cheaper than rebuilding the tree.
+    pub fn translate(&mut self, offset: Vec3) {
+        for octant in &mut self.octants {
+            octant.center += offset;
+            if let Content::Leaf(data) = &mut octant.content {
+                for mark in data.as_mut_slice() {
+                    mark.pos = (Vec3::from(mark.pos) + offset).to_array();
+                }
             }
         }
-        sum
+        for mark in &mut self.dynamic {
+            mark.pos += offset;
+        }
+        self.bounds = self.bounds.map(|(min, max)| (min + offset, max + offset));
+        // Cached culls describe the tree where it was.
+        self.id = next_tree_id();
+        self.revision += 1;
+    }
+
+    /// Axis-aligned `(min, max)` corners enclosing every mark, or `None` if the tree is empty. They are refitted when
+    /// marks are removed, but merging marks into surfels at their centroid may leave them a little loose.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        self.bounds
+    }
+
+    /// Iterates over every mark in the tree, in no particular order.
+    pub fn marks(&self) -> impl Iterator<Item = &MarkRaw> {
+        self.octants
+            .iter()
+            .filter_map(|octant| match octant.content {
+                Content::Leaf(ref data) => Some(data.iter()),
+                Content::Parent(_) => None,
+            })
+            .flatten()
     }
 
-    pub fn get_visible(&mut self, vec: &mut Vec<MarkRaw>, pos: Vec3, frustum: Frustum) {
+    /// Iterates over the non-empty leaves as their center, half-size and marks, in no particular order.
+    pub fn leaves(&self) -> impl Iterator<Item = (Vec3, f32, &[MarkRaw])> {
+        self.octants.iter().filter_map(|octant| match octant.content {
+            Content::Leaf(ref data) if !data.is_empty() => Some((octant.center, octant.extension, data.as_slice())),
+            _ => None,
+        })
+    }
+
+    /// Copies of the marks inside the axis-aligned box from `min` to `max`, skipping octants outside it.
+    pub fn marks_in(&self, min: Vec3, max: Vec3) -> Vec<MarkRaw> {
+        let mut marks = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            let octant = &self[id];
+            if octant.count == 0 || !octant.overlaps(min, max) {
+                continue;
+            }
+            match octant.content {
+                Content::Parent(children) => stack.extend(children),
+                Content::Leaf(ref data) => marks.extend(data.iter().filter(|mark| in_box(mark, min, max))),
+            }
+        }
+        marks
+    }
+
+    /// The mark `visibility` shows closest in angle to `ray` within `max_dist` along it, if any lies within the cone
+    /// whose half-angle has the tangent `tolerance`. Octants whose bounding sphere cannot hold a closer mark are
+    /// skipped.
+    pub fn pick(&self, ray: Ray, tolerance: f32, max_dist: f32, visibility: Visibility) -> Option<MarkRaw> {
+        // Smallest perpendicular offset per unit of distance along the ray found so far, and its mark.
+        let mut best: Option<(f32, MarkRaw)> = None;
+        let mut stack = vec![self.root];
+        while let Some(id) = stack.pop() {
+            let octant = &self[id];
+            let radius = octant.extension * 3.0_f32.sqrt();
+            let to_center = octant.center - ray.pos;
+            let along = to_center.dot(ray.dir);
+            let offset = (to_center - ray.dir * along).length();
+            let limit = best.map_or(tolerance, |(spread, _)| spread);
+            if octant.count == 0 || along + radius <= 0.0 || along - radius > max_dist {
+                continue;
+            }
+            if (offset - radius).max(0.0) > limit * (along + radius) {
+                continue;
+            }
+
+            match octant.content {
+                Content::Parent(children) => stack.extend(children),
+                Content::Leaf(ref data) => {
+                    for mark in data.iter().filter(|mark| mark.tag() != MarkTag::Miss && visibility.shows(mark)) {
+                        let to_mark = Vec3::from(mark.pos) - ray.pos;
+                        let along = to_mark.dot(ray.dir);
+                        if along <= 0.0 || along > max_dist {
+                            continue;
+                        }
+                        let spread = (to_mark - ray.dir * along).length() / along;
+                        if spread <= best.map_or(tolerance, |(spread, _)| spread) {
+                            best = Some((spread, *mark));
+                        }
+                    }
+                }
+            }
+        }
+        best.map(|(_, mark)| mark)
+    }
+
+    /// Removes the marks inside the box from `min` to `max` and returns them.
+    pub fn remove_in(&mut self, min: Vec3, max: Vec3) -> Vec<MarkRaw> {
+        let mut removed = Vec::new();
+        self.edit_in(self.root, min, max, &mut |mark| {
+            removed.push(*mark);
+            false
+        });
+        if !removed.is_empty() {
+            self.fit_bounds();
+        }
+        self.revision += 1;
+        removed
+    }
+
+    /// Paints the marks inside the box from `min` to `max` in `color`, see [`MarkRaw::painted`], and returns them as
+    /// they were before. Misses are left as they are.
+    pub fn recolor_in(&mut self, min: Vec3, max: Vec3, color: Vec3) -> Vec<MarkRaw> {
+        let mut changed = Vec::new();
+        self.edit_in(self.root, min, max, &mut |mark| {
+            if mark.tag() != MarkTag::Miss {
+                changed.push(*mark);
+                *mark = mark.painted(color);
+            }
+            true
+        });
+        self.revision += 1;
+        changed
+    }
+
+    /// Replaces each mark equal to the first of a pair in `replacements` by the second, or removes it where that is
+    /// `None`. A pair replaces at most one mark. Returns how many marks were replaced or removed.
+    pub fn replace(&mut self, replacements: impl IntoIterator<Item = (MarkRaw, Option<MarkRaw>)>) -> usize {
+        let key = |mark: &MarkRaw| bytemuck::cast::<MarkRaw, [u32; 6]>(*mark);
+        let mut pending: HashMap<[u32; 6], Vec<Option<MarkRaw>>> = HashMap::new();
+        let (mut min, mut max) = (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY));
+        for (old, new) in replacements {
+            min = min.min(old.pos.into());
+            max = max.max(old.pos.into());
+            pending.entry(key(&old)).or_default().push(new);
+        }
+
+        let mut replaced = 0;
+        let (removed, _) =
+            self.edit_in(self.root, min, max, &mut |mark| match pending.get_mut(&key(mark)).and_then(Vec::pop) {
+                Some(new) => {
+                    replaced += 1;
+                    new.map(|new| *mark = new).is_some()
+                }
+                None => true,
+            });
+        // Replacements keep their marks in place, as painting does, so only removals change the bounds.
+        if removed > 0 {
+            self.fit_bounds();
+        }
+        self.revision += 1;
+        replaced
+    }
+
+    /// Shrinks the bounds back to the marks left after some were removed.
+    fn fit_bounds(&mut self) {
+        self.bounds = self.marks().map(|mark| Vec3::from(mark.pos)).fold(None, |bounds, pos| match bounds {
+            Some((min, max)) => Some((pos.min(min), pos.max(max))),
+            None => Some((pos, pos)),
+        });
+    }
+
+    /// Passes every mark inside the box under octant `id` to `edit`, which may change it and returns whether to keep
+    /// it, and updates the counts and color sums on the way back up. Returns the octant's count and color sum
+    /// changes.
+    fn edit_in(&mut self, id: u32, min: Vec3, max: Vec3, edit: &mut impl FnMut(&mut MarkRaw) -> bool) -> (u32, Vec3) {
+        if self[id].count == 0 || !self[id].overlaps(min, max) {
+            return (0, Vec3::ZERO);
+        }
+
+        let (removed, color_delta) = match self[id].content {
+            Content::Parent(children) => children.iter().fold((0, Vec3::ZERO), |(removed, color_delta), child| {
+                let (child_removed, child_delta) = self.edit_in(*child, min, max, edit);
+                (removed + child_removed, color_delta + child_delta)
+            }),
+            Content::Leaf(ref mut data) => {
+                let before = data.len() as u64;
+                let mut removed = 0;
+                let mut color_delta = Vec3::ZERO;
+                let mut i = 0;
+                while i < data.len() {
+                    let mark = &mut data.as_mut_slice()[i];
+                    if !in_box(mark, min, max) {
+                        i += 1;
+                        continue;
+                    }
+                    let old_color = mark.color();
+                    if edit(mark) {
+                        color_delta += mark.color() - old_color;
+                        i += 1;
+                    } else {
+                        color_delta -= data.swap_remove(i).color();
+                        removed += 1;
+                    }
+                }
+                // As on a split, removed surfels take their share of the hits along.
+                let hits = self[id].hits as u64;
+                self[id].hits = (hits * (before - removed as u64) / before) as u32;
+                (removed, color_delta)
+            }
+        };
+        self[id].count -= removed;
+        self[id].color_sum += color_delta;
+        (removed, color_delta)
+    }
+
+    /// Average color of every mark in the tree, or `None` if it is empty.
+    pub fn average_color(&self) -> Option<Vec3> {
+        self[self.root].average_color()
+    }
+
+    /// Fills `vec` with the marks in octants intersecting `frustum`, far to near, stopping once `budget` marks are
+    /// collected. Large trees are traversed on the rayon thread pool when there is more than one worker; see
+    /// [`Octree::get_visible_parallel`].
+    pub fn get_visible(&mut self, vec: &mut Vec<MarkRaw>, budget: usize, pos: Vec3, frustum: Frustum) {
+        if self.count() >= PARALLEL_MIN_MARKS && rayon::current_num_threads() > 1 {
+            self.get_visible_parallel(vec, budget, pos, frustum);
+        } else {
+            self.get_visible_sequential(vec, budget, pos, frustum);
+        }
+    }
+
+    pub fn get_visible_sequential(&self, vec: &mut Vec<MarkRaw>, budget: usize, pos: Vec3, frustum: Frustum) {
         vec.truncate(0);
-        self.get_visible_rec(vec, self.root, pos, frustum);
+        self.get_visible_rec(vec, budget, self.root, pos, frustum);
     }
 
-    fn get_visible_rec(&mut self, vec: &mut Vec<MarkRaw>, id: u32, pos: Vec3, frustum: Frustum) {
-        if vec.len() == vec.capacity() {
+    /// Splits the top of the tree into visible subtrees in traversal order, culls each into its own buffer on the
+    /// thread pool and concatenates the buffers in order. Without a budget this yields exactly what
+    /// [`Octree::get_visible_sequential`] does; with one, the subtree that crosses the budget is kept whole, so up to
+    /// one subtree's worth of extra marks may be returned.
+    pub fn get_visible_parallel(&mut self, vec: &mut Vec<MarkRaw>, budget: usize, pos: Vec3, frustum: Frustum) {
+        vec.truncate(0);
+        let subtrees = self.split_visible(rayon::current_num_threads() * SUBTREES_PER_THREAD, pos, frustum);
+
+        let mut buffers = std::mem::take(&mut self.buffers);
+        buffers.resize_with(subtrees.len(), Vec::new);
+        buffers.par_iter_mut().zip(&subtrees).for_each(|(buffer, id)| {
+            buffer.truncate(0);
+            self.get_visible_rec(buffer, budget, *id, pos, frustum);
+        });
+
+        for buffer in &buffers {
+            if vec.len() >= budget {
+                break;
+            }
+            vec.extend_from_slice(buffer);
+        }
+        self.buffers = buffers;
+    }
+
+    /// Expands the root level by level into at least `target` visible octants (fewer if the tree runs out of
+    /// parents), listed in the order the sequential traversal would visit them.
+    fn split_visible(&self, target: usize, pos: Vec3, frustum: Frustum) -> Vec<u32> {
+        let mut subtrees = vec![self.root];
+        while subtrees.len() < target {
+            let mut expanded = false;
+            let mut next = Vec::with_capacity(subtrees.len() * 8);
+            for id in subtrees {
+                match self[id].content {
+                    Content::Leaf(_) => next.push(id),
+                    Content::Parent(children) => {
+                        expanded = true;
+                        next.extend(self.visible_children(children, pos, frustum));
+                    }
+                }
+            }
+            subtrees = next;
+            if !expanded {
+                break;
+            }
+        }
+        subtrees
+    }
+
+    /// `children` that intersect `frustum`, farthest from `pos` first.
+    fn visible_children(&self, mut children: [u32; 8], pos: Vec3, frustum: Frustum) -> impl Iterator<Item = u32> + '_ {
+        children.sort_unstable_by(|a, b| {
+            let dist_a = Vec3::distance_squared(self[*a].center, pos);
+            let dist_b = Vec3::distance_squared(self[*b].center, pos);
+            f32::total_cmp(&dist_b, &dist_a)
+        });
+        children.into_iter().filter(move |child_id| frustum.iter().all(|plane| self[*child_id].collide(*plane)))
+    }
+
+    /// Like [`Octree::get_visible`], but reuses the classification stored in `cache` while the camera stays within
+    /// `CACHE_MAX_MOVE` and `CACHE_MAX_TURN` of the pose it was built for. Octants that were inside the frustum with
+    /// enough margin to stay inside over that range are collected without any tests; only octants that straddled an
+    /// edge are re-tested. Marks inserted since are picked up as long as the root is unchanged. If neither the tree
+    /// nor the pose changed since the last call, `vec` is assumed to still hold that call's output and is left as is,
+    /// which is signalled by returning false.
+    pub fn get_visible_cached(
+        &mut self,
+        cache: &mut VisibleCache,
+        vec: &mut Vec<MarkRaw>,
+        budget: usize,
+        pos: Vec3,
+        frustum: Frustum,
+    ) -> bool {
+        let last = (self.id, self.revision, budget, pos, frustum);
+        if cache.last == Some(last) {
+            return false;
+        }
+        cache.last = Some(last);
+
+        vec.truncate(0);
+        if cache.reusable(self, pos, frustum) {
+            for entry in &cache.entries {
+                if vec.len() >= budget {
+                    break;
+                }
+                match *entry {
+                    CachedOctant::Inside(id) => self.collect_all(vec, budget, id),
+                    CachedOctant::Boundary(id) => {
+                        if frustum.iter().all(|plane| self[id].collide(*plane)) {
+                            self.get_visible_rec(vec, budget, id, pos, frustum);
+                        }
+                    }
+                }
+            }
+            return true;
+        }
+
+        cache.reset(self, pos, frustum);
+        if self.count() >= PARALLEL_MIN_MARKS && rayon::current_num_threads() > 1 {
+            let subtrees = self.split_visible(rayon::current_num_threads() * SUBTREES_PER_THREAD, pos, frustum);
+            let mut buffers = std::mem::take(&mut self.buffers);
+            buffers.resize_with(subtrees.len(), Vec::new);
+            cache.entry_buffers.resize_with(subtrees.len(), Vec::new);
+
+            let this = &*self;
+            buffers.par_iter_mut().zip(cache.entry_buffers.par_iter_mut()).zip(&subtrees).for_each(
+                |((buffer, entries), id)| {
+                    buffer.truncate(0);
+                    entries.truncate(0);
+                    this.classify_rec(buffer, entries, budget, *id, pos, frustum);
+                },
+            );
+
+            for (buffer, entries) in buffers.iter().zip(&cache.entry_buffers) {
+                if vec.len() >= budget {
+                    break;
+                }
+                vec.extend_from_slice(buffer);
+                cache.entries.extend_from_slice(entries);
+            }
+            self.buffers = buffers;
+        } else {
+            self.classify_rec(vec, &mut cache.entries, budget, self.root, pos, frustum);
+        }
+        true
+    }
+
+    /// Collects the visible marks under `id`, which is known to intersect the frustum, recording the largest
+    /// octants that lie inside it with margin to spare and the leaves that straddle its edges.
+    fn classify_rec(
+        &self,
+        vec: &mut Vec<MarkRaw>,
+        entries: &mut Vec<CachedOctant>,
+        budget: usize,
+        id: u32,
+        pos: Vec3,
+        frustum: Frustum,
+    ) {
+        if vec.len() >= budget {
+            return;
+        }
+
+        let octant = &self[id];
+        let distance = Vec3::distance(octant.center, pos) + octant.extension * 3.0_f32.sqrt();
+        let margin = CACHE_MAX_MOVE + CACHE_MAX_TURN * distance;
+        if frustum.iter().all(|plane| octant.inside(*plane, margin)) {
+            entries.push(CachedOctant::Inside(id));
+            self.collect_all(vec, budget, id);
+            return;
+        }
+
+        match octant.content {
+            Content::Leaf(_) => {
+                entries.push(CachedOctant::Boundary(id));
+                self.extend_shown(vec, id);
+            }
+            Content::Parent(children) => {
+                for child_id in self.visible_children(children, pos, frustum) {
+                    self.classify_rec(vec, entries, budget, child_id, pos, frustum);
+                }
+            }
+        }
+    }
+
+    /// Collects every mark under `id` without frustum tests.
+    fn collect_all(&self, vec: &mut Vec<MarkRaw>, budget: usize, id: u32) {
+        if vec.len() >= budget {
             return;
         }
 
         match self[id].content {
-            Content::Leaf(ref mut data) => vec.extend(data.into_iter()),
+            Content::Leaf(_) => self.extend_shown(vec, id),
             Content::Parent(children) => {
-                let mut children = children.clone();
-                children.sort_unstable_by(|a, b| {
-                    let dist_a = Vec3::distance_squared(self[*a].center, pos);
-                    let dist_b = Vec3::distance_squared(self[*b].center, pos);
-                    f32::total_cmp(&dist_b, &dist_a)
-                });
-                'outer: for child_id in children {
-                    for plane in frustum {
-                        if !self[child_id].collide(plane) {
-                            continue 'outer;
-                        }
-                    }
-                    self.get_visible_rec(vec, child_id, pos, frustum);
+                for child_id in children {
+                    self.collect_all(vec, budget, child_id);
+                }
+            }
+        }
+    }
+
+    fn get_visible_rec(&self, vec: &mut Vec<MarkRaw>, budget: usize, id: u32, pos: Vec3, frustum: Frustum) {
+        if vec.len() >= budget {
+            return;
+        }
+
+        match self[id].content {
+            Content::Leaf(_) => self.extend_shown(vec, id),
+            Content::Parent(children) => {
+                for child_id in self.visible_children(children, pos, frustum) {
+                    self.get_visible_rec(vec, budget, child_id, pos, frustum);
                 }
             }
         }
     }
 }
 
+/// Interleaves the low `MORTON_BITS` bits of each coordinate, x in the lowest bit of every triple, matching the
+/// child numbering of [`Octant`]s.
+fn morton(x: u32, y: u32, z: u32) -> u64 {
+    let spread = |v: u32| {
+        let mut v = v as u64 & 0x1f_ffff;
+        v = (v | v << 32) & 0x1f_0000_0000_ffff;
+        v = (v | v << 16) & 0x1f_0000_ff00_00ff;
+        v = (v | v << 8) & 0x100f_00f0_0f00_f00f;
+        v = (v | v << 4) & 0x10c3_0c30_c30c_30c3;
+        v = (v | v << 2) & 0x1249_2492_4924_9249;
+        v
+    };
+    spread(x) | spread(y) << 1 | spread(z) << 2
+}
+
+#[derive(Clone, Copy, Debug)]
+enum CachedOctant {
+    /// Inside the frustum with margin; collected without tests.
+    Inside(u32),
+    /// Straddling a frustum edge when the cache was built; re-tested on every reuse.
+    Boundary(u32),
+}
+
+/// Culling classification from an earlier frame, kept by each camera that culls an [`Octree`].
+#[derive(Default)]
+pub struct VisibleCache {
+    valid: bool,
+    tree: u64,
+    root: u32,
+    pos: Vec3,
+    frustum: Frustum,
+    entries: Vec<CachedOctant>,
+    /// Per-subtree entries of a parallel rebuild.
+    entry_buffers: Vec<Vec<CachedOctant>>,
+    /// Tree id, revision, budget and view of the most recent call.
+    last: Option<(u64, u64, usize, Vec3, Frustum)>,
+}
+
+impl VisibleCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forces the next cached cull to start from the root.
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+        self.last = None;
+    }
+
+    fn reset(&mut self, octree: &Octree, pos: Vec3, frustum: Frustum) {
+        self.valid = true;
+        self.tree = octree.id;
+        self.root = octree.root;
+        self.pos = pos;
+        self.frustum = frustum;
+        self.entries.truncate(0);
+    }
+
+    /// Whether the entries still describe `octree` as seen from `pos` through `frustum`: same tree and root, and
+    /// the camera within `CACHE_MAX_MOVE` and `CACHE_MAX_TURN` of the cached pose with an unchanged projection.
+    fn reusable(&self, octree: &Octree, pos: Vec3, frustum: Frustum) -> bool {
+        if !self.valid || self.tree != octree.id || self.root != octree.root {
+            return false;
+        }
+        if Vec3::distance(self.pos, pos) > CACHE_MAX_MOVE {
+            return false;
+        }
+
+        let min_cos = f32::cos(CACHE_MAX_TURN);
+        frustum.iter().zip(&self.frustum).all(|(plane, cached)| {
+            // Offset of the plane from the camera; it only changes with the projection, not with the pose.
+            let offset = plane.truncate().dot(pos) - plane.w;
+            let cached_offset = cached.truncate().dot(self.pos) - cached.w;
+            plane.truncate().dot(cached.truncate()) >= min_cos
+                && (offset - cached_offset).abs() <= 1e-3 * f32::max(1.0, cached_offset.abs())
+        })
+    }
+}
+
 impl std::ops::Index<u32> for Octree {
     type Output = Octant;
     fn index(&self, index: u32) -> &Self::Output {
@@ -183,7 +1000,13 @@ impl std::ops::IndexMut<u32> for Octree {
     }
 }
 
+fn in_box(mark: &MarkRaw, min: Vec3, max: Vec3) -> bool {
+    let pos = Vec3::from(mark.pos);
+    pos.cmpge(min).all() && pos.cmple(max).all()
+}
+
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 enum Content {
     Parent([u32; 8]),
     Leaf(SVec<MarkRaw, BUCKET_SIZE>),
@@ -193,23 +1016,81 @@ enum Content {
 pub struct Octant {
     center: Vec3,
     extension: f32,
+    count: u32,
+    color_sum: Vec3,
+    /// Marks scanned into this leaf, including those merged into surfels, which the marks it holds stand for.
+    /// Always 0 for parents.
+    hits: u32,
     content: Content,
 }
 
 impl Octant {
+    fn leaf(center: Vec3, extension: f32, data: SVec<MarkRaw, BUCKET_SIZE>) -> Self {
+        let color_sum = data.iter().map(MarkRaw::color).sum();
+        let count = data.len() as u32;
+        Self { center, extension, count, color_sum, hits: count, content: Content::Leaf(data) }
+    }
+
+    #[inline]
+    fn accumulate(&mut self, color: Vec3) {
+        self.count += 1;
+        self.color_sum += color;
+    }
+
+    /// Number of marks stored under this octant.
     #[inline]
-    fn contains(&self, mark: Mark) -> bool {
-        let under = mark.pos.x < self.center.x - self.extension
-            || mark.pos.y < self.center.y - self.extension
-            || mark.pos.z < self.center.z - self.extension;
+    pub fn count(&self) -> usize {
+        self.count as usize
+    }
 
-        let above = mark.pos.x >= self.center.x + self.extension
-            || mark.pos.y >= self.center.y + self.extension
-            || mark.pos.z >= self.center.z + self.extension;
+    /// Average color of the marks stored under this octant, or `None` if it is empty.
+    #[inline]
+    pub fn average_color(&self) -> Option<Vec3> {
+        (self.count > 0).then(|| self.color_sum / self.count as f32)
+    }
+
+    /// Marks scanned into this leaf per square unit of its cross-section, since marks lie on surfaces rather than
+    /// filling the volume.
+    pub fn density(&self) -> f32 {
+        self.hits as f32 / (2.0 * self.extension).powi(2)
+    }
+
+    fn is_full_leaf(&self) -> bool {
+        matches!(self.content, Content::Leaf(ref data) if data.len() == BUCKET_SIZE)
+    }
+
+    /// Which of the children `pos` belongs to, with the same tie-breaking as insertion.
+    #[inline]
+    fn child_index(&self, pos: Vec3) -> usize {
+        (0..3).filter(|i| pos[*i] > self.center[*i]).map(|i| 1 << i).sum()
+    }
+
+    #[inline]
+    fn contains(&self, pos: Vec3) -> bool {
+        let under = pos.x < self.center.x - self.extension
+            || pos.y < self.center.y - self.extension
+            || pos.z < self.center.z - self.extension;
+
+        let above = pos.x >= self.center.x + self.extension
+            || pos.y >= self.center.y + self.extension
+            || pos.z >= self.center.z + self.extension;
 
         !(above || under)
     }
 
+    /// Whether the octant lies at least `margin` inside `plane`.
+    #[inline]
+    fn inside(&self, plane: glam::Vec4, margin: f32) -> bool {
+        let r = self.extension * (plane.x.abs() + plane.y.abs() + plane.z.abs());
+        let s = Vec3::dot(plane.truncate(), self.center) - plane.w;
+        s - r >= margin
+    }
+
+    #[inline]
+    fn overlaps(&self, min: Vec3, max: Vec3) -> bool {
+        (self.center - self.extension).cmple(max).all() && (self.center + self.extension).cmpge(min).all()
+    }
+
     #[inline]
     fn collide(&self, plane: glam::Vec4) -> bool {
         let r = self.extension * (plane.x.abs() + plane.y.abs() + plane.z.abs());
@@ -217,3 +1098,192 @@ impl Octant {
         -r <= s
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Content, Octant, Octree, BUCKET_SIZE};
+    use crate::marker::{Mark, MarkRaw, MarkTag, Visibility};
+    use crate::util::Ray;
+    use glam::{vec3, vec4, Vec3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn dense_marks_merge_into_surfels_with_consistent_totals() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut octree = Octree::new();
+        let n = 20 * BUCKET_SIZE;
+        for _ in 0..n {
+            // A 2x2 patch of wall, as if scanned from standing still.
+            let pos = vec3(rng.gen_range(0.0..2.0), rng.gen_range(0.0..2.0), 0.5);
+            octree.insert(Mark::new(pos, Vec3::splat(0.5)));
+        }
+
+        assert!(octree.count() < n / 4, "{} marks left of {}", octree.count(), n);
+        assert_eq!(octree.count(), octree.marks().count());
+        assert!(octree.average_color().unwrap().abs_diff_eq(Vec3::splat(0.5), 0.01));
+
+        let surfels: Vec<f32> = octree.marks().filter_map(|mark| mark.tag().surfel_size()).collect();
+        assert!(!surfels.is_empty());
+        // A surfel covers its cluster, which lies within one cell of a leaf no wider than the patch.
+        assert!(surfels.iter().all(|size| *size > 0.0 && *size <= 2.0 * 2.0_f32.sqrt() + 0.1));
+        assert!(octree.marks().all(|mark| mark.pos[2] == 0.5 && mark.tag() != MarkTag::Miss));
+    }
+
+    #[test]
+    fn leaf_hits_outlast_merging_and_follow_removals() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let mut octree = Octree::new();
+        let n = 20 * BUCKET_SIZE;
+        for _ in 0..n {
+            let pos = vec3(rng.gen_range(0.0..2.0), rng.gen_range(0.0..2.0), 0.5);
+            octree.insert(Mark::new(pos, Vec3::ONE));
+        }
+        // A sparse wall next to the patch.
+        for _ in 0..BUCKET_SIZE {
+            octree.insert(Mark::new(vec3(rng.gen_range(-40.0..-2.0), rng.gen_range(0.0..40.0), 0.5), Vec3::ONE));
+        }
+
+        let hits = |octree: &Octree| octree.octants.iter().map(|octant| octant.hits as usize).sum::<usize>();
+        let leaves = octree.leaves().count();
+        // Splits may round down one hit per child.
+        assert!(hits(&octree) <= n + BUCKET_SIZE && hits(&octree) + 8 * leaves >= n + BUCKET_SIZE);
+        let densest = |octree: &Octree, x: f32| {
+            let leaves = octree.octants.iter().filter(|octant| matches!(octant.content, Content::Leaf(_)));
+            leaves.filter(|octant| octant.contains(vec3(x, 1.0, 0.5))).map(Octant::density).fold(0.0, f32::max)
+        };
+        assert!(densest(&octree, 1.0) > 100.0 * densest(&octree, -20.0));
+        assert_eq!(octree.density_at(vec3(1.0, 1.0, 0.5)), densest(&octree, 1.0));
+        assert_eq!(octree.density_at(vec3(1e4, 0.0, 0.0)), 0.0);
+
+        octree.remove_in(vec3(-50.0, -50.0, -50.0), vec3(50.0, 50.0, 50.0));
+        assert_eq!(hits(&octree), 0);
+    }
+
+    #[test]
+    fn box_edits_touch_only_marks_inside_and_keep_totals() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut octree = Octree::new();
+        for _ in 0..5000 {
+            let pos = vec3(rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0));
+            octree.insert(Mark::new(pos, Vec3::ZERO));
+        }
+        let (min, max) = (vec3(-20.0, -50.0, 0.0), vec3(30.0, 10.0, 40.0));
+        let inside = |mark: &MarkRaw| Vec3::from(mark.pos).cmpge(min).all() && Vec3::from(mark.pos).cmple(max).all();
+        let expected = octree.marks().filter(|mark| inside(mark)).count();
+        assert!(expected > 0);
+        assert_eq!(octree.marks_in(min, max).len(), expected);
+
+        let unpainted = octree.recolor_in(min, max, Vec3::ONE);
+        assert_eq!(unpainted.len(), expected);
+        assert!(octree.marks().all(|mark| inside(mark) == (mark.tag() == MarkTag::Painted)));
+        let painted = octree.average_color().unwrap().x * octree.count() as f32;
+        assert!((painted - expected as f32).abs() < 0.5);
+
+        // Undoing the paint restores every mark as it was.
+        let restore = unpainted.iter().map(|mark| (mark.painted(Vec3::ONE), Some(*mark)));
+        assert_eq!(octree.replace(restore), expected);
+        assert!(octree.marks().all(|mark| mark.tag() == MarkTag::Surface));
+        assert!(octree.average_color().unwrap().abs_diff_eq(Vec3::ZERO, 1e-4));
+
+        let removed = octree.remove_in(min, max);
+        assert_eq!(removed.len(), expected);
+        assert_eq!(octree.count(), 5000 - expected);
+        assert_eq!(octree.marks().count(), octree.count());
+        assert!(octree.marks_in(min, max).is_empty());
+        assert!(octree.average_color().unwrap().abs_diff_eq(Vec3::ZERO, 1e-4));
+
+        // Removing by value takes exactly the given marks, one per pair.
+        octree.extend(removed.iter().map(|mark| Mark::from(*mark)));
+        assert_eq!(octree.marks_in(min, max).len(), expected);
+        assert_eq!(octree.replace(removed.iter().map(|mark| (*mark, None))), expected);
+        assert_eq!(octree.count(), 5000 - expected);
+    }
+
+    #[test]
+    fn bounds_shrink_back_when_marks_are_removed() {
+        let mut octree = Octree::new();
+        octree.extend((0..100).map(|i| Mark::new(vec3(i as f32, 0.0, 0.0), Vec3::ONE)));
+        let far = Mark::new(vec3(500.0, 20.0, -30.0), Vec3::ONE);
+        octree.insert(far);
+        assert_eq!(octree.bounds(), Some((vec3(0.0, 0.0, -30.0), vec3(500.0, 20.0, 0.0))));
+
+        octree.remove_in(vec3(400.0, 0.0, -50.0), vec3(600.0, 50.0, 0.0));
+        assert_eq!(octree.bounds(), Some((Vec3::ZERO, vec3(99.0, 0.0, 0.0))));
+        assert_eq!(
+            octree.replace((50..100).map(|i| (Mark::new(vec3(i as f32, 0.0, 0.0), Vec3::ONE).to_raw(), None))),
+            50
+        );
+        assert_eq!(octree.bounds(), Some((Vec3::ZERO, vec3(49.0, 0.0, 0.0))));
+        octree.remove_in(Vec3::splat(-1.0), Vec3::splat(100.0));
+        assert_eq!(octree.bounds(), None);
+    }
+
+    #[test]
+    fn painted_surfels_keep_their_size() {
+        let surfel = Mark { tag: MarkTag::surfel(3.0), ..Mark::new(Vec3::ZERO, Vec3::ZERO) }.to_raw();
+        let painted = surfel.painted(Vec3::X);
+        assert_eq!(painted.tag(), MarkTag::PaintedSurfel(48));
+        assert_eq!(painted.tag().surfel_size(), Some(3.0));
+        assert_eq!(painted.painted(Vec3::Y).tag(), painted.tag());
+        assert_eq!(Mark::from(painted).to_raw().color, painted.color);
+        assert_eq!(Mark::miss(Vec3::ZERO).to_raw().painted(Vec3::X).tag(), MarkTag::Miss);
+    }
+
+    #[test]
+    fn picks_the_mark_closest_in_angle_within_the_tolerance() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut octree = Octree::new();
+        for _ in 0..5000 {
+            let pos = vec3(rng.gen_range(-200.0..200.0), rng.gen_range(-200.0..200.0), rng.gen_range(-200.0..-20.0));
+            octree.insert(Mark::new(pos, Vec3::ONE));
+        }
+        octree.insert(Mark::miss(vec3(0.0, 0.0, -50.0)));
+        let ray = Ray { pos: Vec3::ZERO, dir: Vec3::NEG_Z };
+        let spread = |pos: Vec3| pos.truncate().length() / -pos.z;
+
+        let expected = octree.marks().filter(|mark| mark.tag() != MarkTag::Miss).map(|mark| Vec3::from(mark.pos));
+        let expected = expected.min_by(|a, b| spread(*a).total_cmp(&spread(*b))).unwrap();
+        let pick = |tolerance, max_dist, visibility| octree.pick(ray, tolerance, max_dist, visibility);
+        let picked = pick(1.0, 1000.0, Visibility::ALL).unwrap();
+        assert_eq!(Vec3::from(picked.pos), expected);
+        assert!(pick(spread(expected) * 0.99, 1000.0, Visibility::ALL).is_none());
+        assert!(pick(1.0, -expected.z * 0.99, Visibility::ALL).map(|mark| Vec3::from(mark.pos)) != Some(expected));
+
+        // Marks the renderer hides are not picked.
+        let clip_plane = vec4(-expected.x.signum(), 0.0, 0.0, expected.x.abs() * 0.5);
+        let clipped = pick(1.0, 1000.0, Visibility { clip_plane, ..Visibility::ALL }).unwrap();
+        assert!(clipped.pos != expected.to_array() && clip_plane.dot(Vec3::from(clipped.pos).extend(1.0)) >= 0.0);
+        assert!(pick(1.0, 1000.0, Visibility { time_limit: -1.0, ..Visibility::ALL }).is_none());
+        assert!(pick(1.0, 1000.0, Visibility { layer_mask: !1, ..Visibility::ALL }).is_none());
+    }
+
+    #[test]
+    fn translated_trees_hold_shifted_marks_and_accept_new_ones() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let random_pos = |rng: &mut StdRng| vec3(rng.gen_range(-500.0..500.0), rng.gen_range(-50.0..50.0), 0.0);
+        let offset = vec3(-960.0, 0.0, 1920.0);
+        let mut octree = Octree::new();
+        let mut expected = Octree::new();
+        for _ in 0..5000 {
+            let pos = random_pos(&mut rng);
+            octree.insert(Mark::new(pos, Vec3::ONE));
+            expected.insert(Mark::new(pos + offset, Vec3::ONE));
+        }
+
+        octree.translate(offset);
+        for _ in 0..5000 {
+            let pos = random_pos(&mut rng) + offset;
+            octree.insert(Mark::new(pos, Vec3::ONE));
+            expected.insert(Mark::new(pos, Vec3::ONE));
+        }
+
+        let key = |octree: &Octree| {
+            let mut marks: Vec<[u32; 3]> = octree.marks().map(|mark| mark.pos.map(f32::to_bits)).collect();
+            marks.sort_unstable();
+            marks
+        };
+        assert_eq!(key(&octree), key(&expected));
+        assert_eq!(octree.bounds(), expected.bounds());
+        assert_eq!(octree.count(), 10_000);
+    }
+}
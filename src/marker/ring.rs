@@ -0,0 +1,80 @@
+use super::{create_instance_buffer, MarkRaw};
+use std::ops::Range;
+
+/// Number of instance buffers cycled through; a buffer is rewritten only after the frames drawing from the others.
+const RING_SIZE: usize = 3;
+
+/// Ring of instance buffers written round-robin, so an upload never targets the buffer the previous frames are still
+/// drawing from. Only the marks that changed since a buffer was last written are uploaded to it.
+pub struct InstanceRing {
+    buffers: Vec<wgpu::Buffer>,
+    current: usize,
+    /// Per buffer, the range of marks that changed since it was last written.
+    dirty: [Option<Range<usize>>; RING_SIZE],
+    /// Copy of the marks of the last upload, to find what changed in the next.
+    uploaded: Vec<MarkRaw>,
+}
+
+impl InstanceRing {
+    pub fn new(device: &wgpu::Device, inst_n: usize) -> Self {
+        Self {
+            buffers: (0..RING_SIZE).map(|_| create_instance_buffer(device, inst_n)).collect(),
+            current: 0,
+            dirty: Default::default(),
+            uploaded: Vec::with_capacity(inst_n),
+        }
+    }
+
+    /// The buffer holding the marks of the last upload.
+    pub fn current(&self) -> &wgpu::Buffer {
+        &self.buffers[self.current]
+    }
+
+    /// Makes the next buffer of the ring hold `marks`, writing only the part that differs from its old contents. If
+    /// `marks` equals the last upload the current buffer is kept.
+    pub fn upload(&mut self, queue: &wgpu::Queue, marks: &[MarkRaw]) {
+        let Some(changed) = changed_range(&self.uploaded, marks) else {
+            return;
+        };
+        for dirty in &mut self.dirty {
+            *dirty = Some(match dirty.take() {
+                Some(range) => usize::min(range.start, changed.start)..usize::max(range.end, changed.end),
+                None => changed.clone(),
+            });
+        }
+
+        self.current = (self.current + 1) % RING_SIZE;
+        if let Some(range) = self.dirty[self.current].take() {
+            let range = range.start.min(marks.len())..range.end.min(marks.len());
+            let offset = (range.start * std::mem::size_of::<MarkRaw>()) as wgpu::BufferAddress;
+            queue.write_buffer(&self.buffers[self.current], offset, bytemuck::cast_slice(&marks[range]));
+        }
+
+        self.uploaded.clear();
+        self.uploaded.extend_from_slice(marks);
+    }
+}
+
+/// Smallest range of indices outside which `old` and `new` agree, or `None` if they are equal.
+fn changed_range(old: &[MarkRaw], new: &[MarkRaw]) -> Option<Range<usize>> {
+    let old_bytes: &[u8] = bytemuck::cast_slice(old);
+    let new_bytes: &[u8] = bytemuck::cast_slice(new);
+    if old_bytes == new_bytes {
+        return None;
+    }
+
+    let size = std::mem::size_of::<MarkRaw>();
+    let prefix = old_bytes.chunks_exact(size).zip(new_bytes.chunks_exact(size)).take_while(|(a, b)| a == b).count();
+    let end = if old.len() == new.len() {
+        let suffix = old_bytes
+            .chunks_exact(size)
+            .rev()
+            .zip(new_bytes.chunks_exact(size).rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        new.len() - suffix
+    } else {
+        usize::max(old.len(), new.len())
+    };
+    Some(prefix..end)
+}
@@ -0,0 +1,116 @@
+use crate::util::ScanRng;
+use crate::State;
+use glam::{vec2, Vec2};
+use std::f32::consts::TAU;
+
+/// Plastic number, the basis of the R2 sequence.
+const PLASTIC: f64 = 1.324_717_957_244_746;
+
+/// Picks where in the scanner cone each ray goes, as a point of the unit disc.
+pub trait DiscSampler {
+    fn sample(&mut self, rng: &mut dyn ScanRng) -> Vec2;
+}
+
+/// Built-in samplers, cycled at runtime to compare how evenly they fill a surface.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerKind {
+    /// Uniform angle and radius, the original pattern. Half the rays land within the inner quarter of the disc's
+    /// area, so scans clump at the center.
+    Polar,
+    /// Uniform over the disc's area.
+    Area,
+    /// The R2 low-discrepancy sequence: evenly spread like blue noise, with no two rays close together.
+    R2,
+    /// The Halton sequence in bases 2 and 3, another low-discrepancy pattern.
+    Halton,
+}
+
+impl SamplerKind {
+    pub fn create(self) -> Box<dyn DiscSampler> {
+        match self {
+            SamplerKind::Polar => Box::new(PolarSampler),
+            SamplerKind::Area => Box::new(AreaSampler),
+            SamplerKind::R2 => Box::new(R2Sampler::default()),
+            SamplerKind::Halton => Box::new(HaltonSampler::default()),
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            SamplerKind::Polar => SamplerKind::Area,
+            SamplerKind::Area => SamplerKind::R2,
+            SamplerKind::R2 => SamplerKind::Halton,
+            SamplerKind::Halton => SamplerKind::Polar,
+        }
+    }
+}
+
+/// Maps a point of the unit square onto the unit disc, keeping uniform inputs uniform over its area.
+fn square_to_disc(u: f32, v: f32) -> Vec2 {
+    let (sin, cos) = f32::sin_cos(v * TAU);
+    vec2(sin, cos) * u.sqrt()
+}
+
+pub struct PolarSampler;
+
+impl DiscSampler for PolarSampler {
+    fn sample(&mut self, rng: &mut dyn ScanRng) -> Vec2 {
+        let angle = rng.next_unit() * TAU;
+        let length = rng.next_unit();
+        vec2(f32::sin(angle), f32::cos(angle)) * length
+    }
+}
+
+pub struct AreaSampler;
+
+impl DiscSampler for AreaSampler {
+    fn sample(&mut self, rng: &mut dyn ScanRng) -> Vec2 {
+        square_to_disc(rng.next_unit(), rng.next_unit())
+    }
+}
+
+/// Deterministic; the sequence continues across shots, so even a still camera keeps filling the gaps.
+#[derive(Default)]
+pub struct R2Sampler {
+    index: u64,
+}
+
+impl DiscSampler for R2Sampler {
+    fn sample(&mut self, _: &mut dyn ScanRng) -> Vec2 {
+        self.index += 1;
+        let n = self.index as f64;
+        let u = (0.5 + n / PLASTIC).fract();
+        let v = (0.5 + n / (PLASTIC * PLASTIC)).fract();
+        square_to_disc(u as f32, v as f32)
+    }
+}
+
+#[derive(Default)]
+pub struct HaltonSampler {
+    index: u64,
+}
+
+impl DiscSampler for HaltonSampler {
+    fn sample(&mut self, _: &mut dyn ScanRng) -> Vec2 {
+        self.index += 1;
+        square_to_disc(radical_inverse(self.index, 2), radical_inverse(self.index, 3))
+    }
+}
+
+fn radical_inverse(mut index: u64, base: u64) -> f32 {
+    let (mut result, mut scale) = (0.0, 1.0 / base as f64);
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale /= base as f64;
+    }
+    result as f32
+}
+
+impl State {
+    pub fn cycle_sampler(&mut self) {
+        let kind = self.marker.sampler_kind.next();
+        self.marker.set_sampler(kind);
+        self.notify(format!("ray sampler: {:?}", kind));
+    }
+}
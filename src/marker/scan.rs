@@ -0,0 +1,337 @@
+use super::octree::Octree;
+use super::{Mark, Visibility};
+use crate::util::Ray;
+use crate::world::MAX_RAY_LENGTH;
+use crate::State;
+use glam::Vec3;
+use std::collections::VecDeque;
+
+const PRECISION_SAMPLES: usize = 8;
+const PRECISION_SPREAD: f32 = 0.002;
+const PRECISION_TOLERANCE: f32 = 0.02;
+const PRECISION_MIN_TOLERANCE: f32 = 0.25;
+/// Other directions in the cone a shot that lands on a densely scanned surface tries before auto-stop drops it.
+const MAX_REDIRECTS: usize = 4;
+/// Directions a redirect samples in the cone, of which it casts the one that looks at the least scanned spot.
+const REDIRECT_CANDIDATES: usize = 8;
+/// Tangent of the half-angle around a redirect candidate within which the mark it looks at is picked.
+const REDIRECT_PICK_TOLERANCE: f32 = 0.01;
+/// Rays cast per frame unless the settings say otherwise.
+pub const DEFAULT_RAY_BUDGET: usize = 1000;
+/// Frames' worth of rays the backlog holds before the oldest are dropped, so sustained overload costs marks rather
+/// than ever growing latency.
+const BACKLOG_FRAMES: usize = 4;
+
+/// How each scanner shot turns rays into marks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScanPattern {
+    /// One ray per shot, one mark per hit.
+    Spray,
+    /// Several nearly parallel rays per shot, averaged into a single mark after rejecting outliers.
+    Precision { samples: usize },
+}
+
+impl ScanPattern {
+    pub fn precision() -> Self {
+        ScanPattern::Precision { samples: PRECISION_SAMPLES }
+    }
+
+    /// Number of rays a single shot casts, used to scale the scanner cooldown.
+    pub fn rays_per_shot(&self) -> usize {
+        match self {
+            ScanPattern::Spray => 1,
+            ScanPattern::Precision { samples } => *samples,
+        }
+    }
+}
+
+/// Scanner shots that came due but were not cast yet. Each frame casts at most `budget` rays' worth of shots from the
+/// front and leaves the rest for the next one, so bursts of shots are spread over a few frames instead of stalling one.
+pub struct RayQueue {
+    shots: VecDeque<Ray>,
+    pub budget: usize,
+    /// Rays dropped from a full backlog so far.
+    pub dropped: u64,
+    /// Rays cast on top of the shots taken, like auto-stop redirects, which the next frames' budgets pay back.
+    debt: usize,
+}
+
+impl Default for RayQueue {
+    fn default() -> Self {
+        Self::new(DEFAULT_RAY_BUDGET)
+    }
+}
+
+impl RayQueue {
+    pub fn new(budget: usize) -> Self {
+        Self { shots: VecDeque::new(), budget: budget.max(1), dropped: 0, debt: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shots.is_empty()
+    }
+
+    /// Queues a shot casting `rays_per_shot` rays, dropping the oldest ones once the backlog holds more rays than
+    /// [`BACKLOG_FRAMES`] budgets, but always keeping the newest shot.
+    pub fn push(&mut self, ray: Ray, rays_per_shot: usize) {
+        let rays_per_shot = rays_per_shot.max(1);
+        self.shots.push_back(ray);
+        while self.shots.len() > (self.budget * BACKLOG_FRAMES / rays_per_shot).max(1) {
+            self.shots.pop_front();
+            self.dropped += rays_per_shot as u64;
+        }
+    }
+
+    /// Shots to cast this frame: as many as fit the budget, less the rays still owed, when each casts
+    /// `rays_per_shot` rays, but at least one.
+    pub fn take(&mut self, rays_per_shot: usize) -> Vec<Ray> {
+        let repaid = self.debt.min(self.budget);
+        self.debt -= repaid;
+        let shots = ((self.budget - repaid) / rays_per_shot.max(1)).max(1).min(self.shots.len());
+        self.shots.drain(..shots).collect()
+    }
+
+    /// Takes `rays` cast outside of the queue out of the next frames' budgets.
+    pub fn charge(&mut self, rays: usize) {
+        self.debt += rays;
+    }
+
+    pub fn clear(&mut self) {
+        self.shots.clear();
+        self.debt = 0;
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        for ray in &mut self.shots {
+            ray.pos -= shift;
+        }
+    }
+}
+
+impl State {
+    pub fn toggle_scan_pattern(&mut self) {
+        self.marker.pattern = match self.marker.pattern {
+            ScanPattern::Spray => ScanPattern::precision(),
+            ScanPattern::Precision { .. } => ScanPattern::Spray,
+        };
+        self.notify(format!("scan pattern: {:?}", self.marker.pattern));
+    }
+
+    /// Casts this frame's `shots` together and records what each of them hit.
+    pub(super) fn scan_batch(&mut self, shots: &[Ray]) {
+        let hits = self.cast_shots(shots);
+        for (shot, hit) in self.aim(shots.iter().copied().zip(hits).collect()) {
+            self.scan(shot, hit);
+        }
+    }
+
+    /// Records the shot `ray`, which hit the terrain at `hit`.
+    fn scan(&mut self, ray: Ray, hit: Option<Vec3>) {
+        let range = if self.gameplay.enabled { self.gameplay.range } else { MAX_RAY_LENGTH };
+        let max_t = hit.map_or(range, |pos| Vec3::distance(ray.pos, pos));
+        let entity_hit = self.entities.raycast(ray, max_t);
+        let hit = entity_hit.map(|(_, pos)| pos).or(hit);
+        self.beams.push(self.camera.muzzle(), ray, hit, self.settings.accessibility.flash_limit);
+
+        match (entity_hit, hit) {
+            (Some((entity, pos)), _) => {
+                let dist = Vec3::distance(ray.pos, pos);
+                if let Some(entity_pos) = self.entities.get(entity).map(|entity| entity.pos) {
+                    self.marker.octree.insert(self.stamp(Mark::scanned(pos, dist)).attach(entity, entity_pos));
+                    self.stats.record_mark();
+                }
+                self.audio.hit(dist);
+            }
+            (None, Some(pos)) => {
+                let dist = Vec3::distance(ray.pos, pos);
+                let mark = self.stamp(Mark::scanned(pos, dist));
+                self.marker.octree.insert(mark);
+                self.net.share(self.to_world(pos), mark);
+                self.stats.record_mark();
+                self.coverage.record(pos);
+                self.minimap.record(pos);
+                self.audio.hit(dist);
+            }
+            (None, None) => {
+                if self.settings.scanner.miss_marks {
+                    self.marker.octree.insert(self.stamp(Mark::miss(ray.pos + ray.dir * range)));
+                    self.stats.record_mark();
+                }
+                self.audio.miss();
+            }
+        }
+        if let Some(pos) = hit {
+            self.marker.record_hit_distance(Vec3::distance(ray.pos, pos));
+        }
+        self.hud.record_shot(hit.is_some());
+        self.stats.record_shot(self.marker.pattern.rays_per_shot(), hit.is_some());
+    }
+
+    /// Returns the shots to record out of `shots`, each with where it hit. With auto-stop on, shots that land on a
+    /// surface scanned at least as densely as the setting asks for are aimed at other directions in the cone instead,
+    /// cast as one bundle per round, up to [`MAX_REDIRECTS`] rounds, and dropped if those land on dense surfaces too.
+    /// The redirected rays come out of the next frames' ray budget.
+    fn aim(&mut self, shots: Vec<(Ray, Option<Vec3>)>) -> Vec<(Ray, Option<Vec3>)> {
+        let Some(density) = self.settings.scanner.auto_stop else {
+            return shots;
+        };
+        let dense =
+            |octree: &Octree, hit: Option<Vec3>| hit.is_some_and(|pos| octree.density_at(pos) >= density as f32);
+        let (mut dense_shots, mut aimed): (Vec<_>, Vec<_>) =
+            shots.into_iter().partition(|&(_, hit)| dense(&self.marker.octree, hit));
+        for _ in 0..MAX_REDIRECTS {
+            if dense_shots.is_empty() {
+                break;
+            }
+            let rays: Vec<Ray> = dense_shots.iter().map(|&(ray, _)| self.redirect(ray)).collect();
+            let hits = self.cast_shots(&rays);
+            self.marker.queue.charge(rays.len() * self.marker.pattern.rays_per_shot());
+            let (dense_again, landed): (Vec<_>, Vec<_>) =
+                rays.into_iter().zip(hits).partition(|&(_, hit)| dense(&self.marker.octree, hit));
+            aimed.extend(landed);
+            dense_shots = dense_again;
+        }
+        aimed
+    }
+
+    /// Another direction in the cone for `ray`, which landed on a densely scanned surface, from where the shot was
+    /// fired since the camera may have moved on while it was queued. Of [`REDIRECT_CANDIDATES`] sampled directions,
+    /// it takes the one that looks at the least scanned spot.
+    fn redirect(&mut self, ray: Ray) -> Ray {
+        let candidates: Vec<Ray> =
+            (0..REDIRECT_CANDIDATES).map(|_| Ray { pos: ray.pos, ..self.sample_ray() }).collect();
+        let range = if self.gameplay.enabled { self.gameplay.range } else { MAX_RAY_LENGTH };
+        least_scanned(&self.marker.octree, candidates, range).unwrap_or(ray)
+    }
+
+    /// Where each of `shots` hits the terrain, casting every ray they take as one bundle.
+    fn cast_shots(&mut self, shots: &[Ray]) -> Vec<Option<Vec3>> {
+        let (range, test) = (self.gameplay.scan_range(), self.settings.scanner.triangle_test());
+        let ScanPattern::Precision { samples } = self.marker.pattern else {
+            return self.world.raycast_bundle(shots, range, test);
+        };
+
+        let mut rays = Vec::with_capacity(shots.len() * samples);
+        for shot in shots {
+            let (u, v) = shot.dir.any_orthonormal_pair();
+            for _ in 0..samples {
+                let (du, dv) = (self.marker.rng.next_unit() - 0.5, self.marker.rng.next_unit() - 0.5);
+                let offset = PRECISION_SPREAD * (u * du + v * dv);
+                rays.push(Ray { pos: shot.pos, dir: (shot.dir + offset).normalize() });
+            }
+        }
+        let hits = self.world.raycast_bundle(&rays, range, test);
+        shots.iter().zip(hits.chunks(samples.max(1))).map(|(shot, hits)| precise_hit(*shot, hits)).collect()
+    }
+}
+
+/// The ray of `candidates` that looks at the least densely scanned spot of `octree` within `range`, judged by the
+/// mark each picks before any of them is cast. Rays that pick no mark look at unscanned surface.
+fn least_scanned(octree: &Octree, candidates: impl IntoIterator<Item = Ray>, range: f32) -> Option<Ray> {
+    let density = |ray: Ray| {
+        octree
+            .pick(ray, REDIRECT_PICK_TOLERANCE, range, Visibility::ALL)
+            .map_or(0.0, |mark| octree.density_at(Vec3::from(mark.pos)))
+    };
+    candidates.into_iter().map(|ray| (density(ray), ray)).min_by(|a, b| f32::total_cmp(&a.0, &b.0)).map(|(_, ray)| ray)
+}
+
+/// Averages the hits of the rays jittered around `ray` that agree with the median distance. Shots where fewer than
+/// half the samples agree are discarded as noise.
+fn precise_hit(ray: Ray, samples: &[Option<Vec3>]) -> Option<Vec3> {
+    let mut hits: Vec<(f32, Vec3)> =
+        samples.iter().flatten().map(|pos| (Vec3::distance(ray.pos, *pos), *pos)).collect();
+    let samples = samples.len();
+    if hits.len() * 2 < samples {
+        return None;
+    }
+
+    hits.sort_unstable_by(|a, b| f32::total_cmp(&a.0, &b.0));
+    let median = hits[hits.len() / 2].0;
+    let tolerance = f32::max(median * PRECISION_TOLERANCE, PRECISION_MIN_TOLERANCE);
+
+    let inliers: Vec<Vec3> = hits.iter().filter(|hit| (hit.0 - median).abs() <= tolerance).map(|hit| hit.1).collect();
+    if inliers.len() * 2 < samples {
+        return None;
+    }
+
+    Some(inliers.iter().sum::<Vec3>() / inliers.len() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{least_scanned, RayQueue};
+    use crate::marker::octree::Octree;
+    use crate::marker::Mark;
+    use crate::util::Ray;
+    use crate::world::{Plane, Terrain, MAX_RAY_LENGTH};
+    use glam::{vec3, Vec3};
+
+    #[test]
+    fn bursts_carry_over_within_the_budget_and_overflow_drops_the_oldest() {
+        let ray = |i: usize| Ray { pos: vec3(i as f32, 0.0, 0.0), dir: Vec3::Z };
+        let mut queue = RayQueue::new(10);
+        (0..25).for_each(|i| queue.push(ray(i), 1));
+        assert_eq!(queue.take(1).len(), 10);
+        assert_eq!(queue.take(4)[0].pos.x, 10.0);
+        assert_eq!(queue.len(), 13);
+        assert_eq!(queue.take(100).len(), 1);
+
+        (0..60).for_each(|i| queue.push(ray(i), 1));
+        assert_eq!((queue.len(), queue.dropped), (40, 32));
+        assert_eq!(queue.take(1)[0].pos.x, 20.0);
+
+        queue.rebase(vec3(960.0, 0.0, 0.0));
+        assert_eq!(queue.take(1)[0].pos.x, 30.0 - 960.0);
+
+        // Precision shots of eight rays each fill the backlog of 40 rays with five shots.
+        queue.clear();
+        (0..20).for_each(|i| queue.push(ray(i), 8));
+        assert_eq!((queue.len(), queue.dropped), (5, 32 + 15 * 8));
+        let mut tiny = RayQueue::new(1);
+        (0..3).for_each(|i| tiny.push(ray(i), 8));
+        assert_eq!(tiny.take(8)[0].pos.x, 2.0);
+    }
+
+    #[test]
+    fn redirected_rays_are_paid_from_the_next_budgets() {
+        let mut queue = RayQueue::new(10);
+        (0..40).for_each(|_| queue.push(Ray { pos: Vec3::ZERO, dir: Vec3::Z }, 1));
+        queue.charge(14);
+        assert_eq!(queue.take(1).len(), 1);
+        assert_eq!(queue.take(1).len(), 6);
+        assert_eq!(queue.take(2).len(), 5);
+        queue.charge(3);
+        queue.clear();
+        (0..40).for_each(|_| queue.push(Ray { pos: Vec3::ZERO, dir: Vec3::Z }, 1));
+        assert_eq!(queue.take(1).len(), 10);
+    }
+
+    #[test]
+    fn redirects_steer_towards_the_unscanned_side_of_a_plane() {
+        let mut plane = Plane::new();
+        let eye = Vec3::ZERO;
+        let ray = |x: f32| Ray { pos: eye, dir: vec3(x, -1.0, 0.0).normalize() };
+        let hit = |plane: &mut Plane, x: f32| plane.raycast(ray(x), MAX_RAY_LENGTH).unwrap();
+
+        // The side of the plane below the eye and towards -x is scanned densely, +x not at all.
+        let mut octree = Octree::new();
+        for i in 0..4000 {
+            let (x, z) = ((i % 80) as f32 * -0.5, (i / 80) as f32 * 0.5 - 12.5);
+            let pos = hit(&mut plane, 0.0) + vec3(x, 0.0, z);
+            octree.insert(Mark::scanned(pos, Vec3::distance(eye, pos)));
+        }
+        let dense = hit(&mut plane, -0.2);
+        assert!(octree.density_at(dense) > 0.0);
+
+        let candidates = [-0.4, -0.2, -0.1, 0.3].map(ray);
+        let redirect = least_scanned(&octree, candidates, MAX_RAY_LENGTH).unwrap();
+        assert_eq!(redirect.dir, ray(0.3).dir);
+        assert_eq!(octree.density_at(hit(&mut plane, 0.3)), 0.0);
+    }
+}
@@ -0,0 +1,211 @@
+use super::super::occlusion::DEPTH_FORMAT;
+use super::super::State;
+use super::Marker;
+
+const WORKGROUP_SIZE: u32 = 256;
+const MAX_WORKGROUPS_X: u32 = 65535;
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct SplatParams {
+    width: u32,
+    height: u32,
+    n_marks: u32,
+    _padding: u32,
+}
+
+/// Alternative to the instanced quad pipeline: a compute shader splats every visible mark straight into a per-pixel
+/// depth and color buffer, one thread per mark, and a fullscreen pass resolves that buffer into the frame.
+pub struct Splatter {
+    pub enabled: bool,
+    splat_pipeline: wgpu::ComputePipeline,
+    resolve_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Rebuilt every frame since the instance buffer is replaced when the instance cap changes.
+    bind_group: Option<wgpu::BindGroup>,
+    params_buffer: wgpu::Buffer,
+    frame_buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+}
+
+impl Splatter {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("../splat.wgsl"));
+
+        let uniform_entry = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let storage_entry = |binding, visibility, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let compute_fragment = wgpu::ShaderStages::COMPUTE | wgpu::ShaderStages::FRAGMENT;
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                uniform_entry(0, compute_fragment),
+                uniform_entry(1, wgpu::ShaderStages::COMPUTE),
+                uniform_entry(2, compute_fragment),
+                storage_entry(3, wgpu::ShaderStages::COMPUTE, true),
+                storage_entry(4, compute_fragment, false),
+            ],
+            label: Some("splat_bind_group_layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Splat Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let splat_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Splat Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_splat",
+        });
+
+        let resolve_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Splat Resolve Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_resolve", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_resolve",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Splat Params Buffer"),
+            size: std::mem::size_of::<SplatParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            enabled: false,
+            splat_pipeline,
+            resolve_pipeline,
+            bind_group_layout,
+            bind_group: None,
+            params_buffer,
+            frame_buffer: create_frame_buffer(device, config.width, config.height),
+            width: config.width,
+            height: config.height,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.frame_buffer = create_frame_buffer(device, width, height);
+        self.width = width;
+        self.height = height;
+    }
+}
+
+fn create_frame_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Splat Frame Buffer"),
+        size: (width.max(1) * height.max(1) * 4) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+impl State {
+    pub fn toggle_splatting(&mut self) {
+        let enabled = !self.marker.splatter.enabled;
+        self.marker.splatter.enabled = enabled;
+        self.notify(format!("renderer: {}", if enabled { "compute splats" } else { "instanced quads" }));
+    }
+
+    /// Uploads the splat parameters and binds the current instance buffer; call after the marks are uploaded.
+    pub(crate) fn prepare_splats(&mut self) {
+        let marker = &mut self.marker;
+        let splatter = &mut marker.splatter;
+        if !splatter.enabled {
+            return;
+        }
+
+        let params =
+            SplatParams { width: splatter.width, height: splatter.height, n_marks: marker.n_visible, _padding: 0 };
+        self.queue.write_buffer(&splatter.params_buffer, 0, bytemuck::cast_slice(&[params]));
+
+        splatter.bind_group = Some(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &splatter.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: marker.camera_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: marker.globals_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: splatter.params_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: marker.instance_ring.current().as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 4, resource: splatter.frame_buffer.as_entire_binding() },
+            ],
+            label: Some("splat_bind_group"),
+        }));
+    }
+
+    /// Clears the splat buffer and runs the splat compute pass; must be encoded before the scene pass.
+    pub fn dispatch_splats(&self, encoder: &mut wgpu::CommandEncoder) {
+        let marker = &self.marker;
+        let splatter = &marker.splatter;
+        let Some(bind_group) = splatter.bind_group.as_ref().filter(|_| splatter.enabled) else {
+            return;
+        };
+
+        encoder.clear_buffer(&splatter.frame_buffer, 0, None);
+
+        let n_groups = marker.n_visible.div_ceil(WORKGROUP_SIZE);
+        if n_groups == 0 {
+            return;
+        }
+        let groups_x = n_groups.min(MAX_WORKGROUPS_X);
+        let groups_y = n_groups.div_ceil(groups_x);
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Splat Pass") });
+        compute_pass.set_pipeline(&splatter.splat_pipeline);
+        compute_pass.set_bind_group(0, bind_group, &[]);
+        compute_pass.dispatch_workgroups(groups_x, groups_y, 1);
+    }
+}
+
+impl Marker {
+    /// Draws the splatted marks into a pass with a [`DEPTH_FORMAT`] attachment, returning false if splatting is off.
+    pub(crate) fn resolve_splats<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) -> bool {
+        let splatter = &self.splatter;
+        let Some(bind_group) = splatter.bind_group.as_ref().filter(|_| splatter.enabled) else {
+            return false;
+        };
+
+        render_pass.set_pipeline(&splatter.resolve_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        true
+    }
+}
@@ -0,0 +1,421 @@
+use super::camera::{MAX_FOV, MAX_SENSITIVITY, MIN_FOV, MIN_SENSITIVITY};
+use super::crash;
+use super::hud::{MAX_UI_SCALE, MIN_UI_SCALE};
+use super::locale::Locale;
+use super::marker::Palette;
+use super::resample::{MAX_RENDER_SCALE, MIN_RENDER_SCALE};
+use super::settings::Settings;
+use super::State;
+use tracing::warn;
+use winit::event::VirtualKeyCode;
+
+/// Frame rate caps the menu steps through, from none.
+const FPS_CAPS: [Option<u32>; 7] = [None, Some(30), Some(60), Some(90), Some(120), Some(144), Some(240)];
+/// Beam flash limits the menu steps through, from none; three per second is the usual photosensitivity threshold.
+const FLASH_LIMITS: [Option<u32>; 4] = [None, Some(10), Some(3), Some(1)];
+/// Auto-stop densities the menu steps through, from none, in marks per square unit.
+const AUTO_STOP_DENSITIES: [Option<u32>; 5] = [None, Some(100), Some(50), Some(20), Some(10)];
+const SENSITIVITY_STEP: f32 = 0.1;
+const FOV_STEP: f32 = 5.0;
+const SCALE_STEP: f32 = 0.25;
+/// Bounds of the scanner's ray budget, which the menu doubles or halves.
+const MIN_RAY_BUDGET: usize = 10;
+const MAX_RAY_BUDGET: usize = 100_000;
+
+/// A line of the settings menu: a setting it changes, or an action.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Entry {
+    Sensitivity,
+    Fov,
+    Vsync,
+    FpsCap,
+    LowPower,
+    RenderScale,
+    UiScale,
+    Palette,
+    AdaptiveQuality,
+    MissMarks,
+    MissIndicator,
+    RayBudget,
+    AutoStop,
+    Watertight,
+    ToggleScan,
+    ReducedMotion,
+    FlashLimit,
+    RestoreDefaults,
+}
+
+pub const ENTRIES: [Entry; 18] = [
+    Entry::Sensitivity,
+    Entry::Fov,
+    Entry::Vsync,
+    Entry::FpsCap,
+    Entry::LowPower,
+    Entry::RenderScale,
+    Entry::UiScale,
+    Entry::Palette,
+    Entry::AdaptiveQuality,
+    Entry::MissMarks,
+    Entry::MissIndicator,
+    Entry::RayBudget,
+    Entry::AutoStop,
+    Entry::Watertight,
+    Entry::ToggleScan,
+    Entry::ReducedMotion,
+    Entry::FlashLimit,
+    Entry::RestoreDefaults,
+];
+
+impl Entry {
+    /// Key of the setting in `settings.toml`, which also names the entry's label in the locale table.
+    fn key(self) -> &'static str {
+        match self {
+            Entry::Sensitivity => "controls.sensitivity",
+            Entry::Fov => "display.fov",
+            Entry::Vsync => "display.vsync",
+            Entry::FpsCap => "display.fps_cap",
+            Entry::LowPower => "display.low_power",
+            Entry::RenderScale => "display.render_scale",
+            Entry::UiScale => "display.ui_scale",
+            Entry::Palette => "display.palette",
+            Entry::AdaptiveQuality => "display.adaptive_quality",
+            Entry::MissMarks => "scanner.miss_marks",
+            Entry::MissIndicator => "scanner.miss_indicator",
+            Entry::RayBudget => "scanner.ray_budget",
+            Entry::AutoStop => "scanner.auto_stop",
+            Entry::Watertight => "scanner.watertight",
+            Entry::ToggleScan => "accessibility.toggle_scan",
+            Entry::ReducedMotion => "accessibility.reduced_motion",
+            Entry::FlashLimit => "accessibility.flash_limit",
+            Entry::RestoreDefaults => "restore_defaults",
+        }
+    }
+
+    pub fn label(self, locale: &Locale) -> String {
+        locale.get(&format!("menu.{}", self.key())).to_string()
+    }
+
+    /// The entry's current value as shown in the menu; empty for actions.
+    pub fn value(self, settings: &Settings, locale: &Locale) -> String {
+        let switch = |on: bool| locale.get(if on { "menu.on" } else { "menu.off" }).to_string();
+        let display = &settings.display;
+        match self {
+            Entry::Sensitivity => format!("{:.1}", settings.controls.sensitivity),
+            Entry::Fov => format!("{:.0}", display.fov),
+            Entry::Vsync => switch(display.vsync),
+            Entry::FpsCap => display.fps_cap.map_or_else(|| switch(false), |fps| fps.to_string()),
+            Entry::LowPower => switch(display.low_power),
+            Entry::RenderScale => format!("{:.2}", display.render_scale),
+            Entry::UiScale => format!("{:.2}", display.ui_scale),
+            Entry::Palette => display.palette.name().to_string(),
+            Entry::AdaptiveQuality => switch(display.adaptive_quality),
+            Entry::MissMarks => switch(settings.scanner.miss_marks),
+            Entry::MissIndicator => switch(settings.scanner.miss_indicator),
+            Entry::RayBudget => settings.scanner.ray_budget.to_string(),
+            Entry::AutoStop => settings.scanner.auto_stop.map_or_else(|| switch(false), |n| n.to_string()),
+            Entry::Watertight => switch(settings.scanner.watertight),
+            Entry::ToggleScan => switch(settings.accessibility.toggle_scan),
+            Entry::ReducedMotion => switch(settings.accessibility.reduced_motion),
+            Entry::FlashLimit => settings.accessibility.flash_limit.map_or_else(|| switch(false), |n| n.to_string()),
+            Entry::RestoreDefaults => String::new(),
+        }
+    }
+
+    /// Moves the setting `steps` increments up or down, keeping it in its valid range; switches flip on any step,
+    /// and zero steps only brings an out-of-range value back into range.
+    fn adjust(self, settings: &mut Settings, steps: i32) {
+        let step = |value: f32, size: f32, min: f32, max: f32| match steps {
+            0 => value.clamp(min, max),
+            _ => (((value + size * steps as f32) / size).round() * size).clamp(min, max),
+        };
+        let flip = |value: &mut bool| *value ^= steps != 0;
+        let display = &mut settings.display;
+        match self {
+            Entry::Sensitivity => {
+                let sensitivity = &mut settings.controls.sensitivity;
+                *sensitivity = step(*sensitivity, SENSITIVITY_STEP, MIN_SENSITIVITY, MAX_SENSITIVITY);
+            }
+            Entry::Fov => display.fov = step(display.fov, FOV_STEP, MIN_FOV, MAX_FOV),
+            Entry::Vsync => flip(&mut display.vsync),
+            Entry::FpsCap => display.fps_cap = step_choice(&FPS_CAPS, display.fps_cap, steps),
+            Entry::LowPower => flip(&mut display.low_power),
+            Entry::RenderScale => {
+                display.render_scale = step(display.render_scale, SCALE_STEP, MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+            }
+            Entry::UiScale => display.ui_scale = step(display.ui_scale, SCALE_STEP, MIN_UI_SCALE, MAX_UI_SCALE),
+            Entry::Palette => {
+                let index = Palette::ALL.iter().position(|&palette| palette == display.palette).unwrap_or(0);
+                let count = Palette::ALL.len() as i32;
+                display.palette = Palette::ALL[(index as i32 + steps).rem_euclid(count) as usize];
+            }
+            Entry::AdaptiveQuality => flip(&mut display.adaptive_quality),
+            Entry::MissMarks => flip(&mut settings.scanner.miss_marks),
+            Entry::MissIndicator => flip(&mut settings.scanner.miss_indicator),
+            Entry::RayBudget => {
+                let budget = &mut settings.scanner.ray_budget;
+                *budget = match steps.signum() {
+                    1 => budget.saturating_mul(2),
+                    -1 => *budget / 2,
+                    _ => *budget,
+                }
+                .clamp(MIN_RAY_BUDGET, MAX_RAY_BUDGET);
+            }
+            Entry::AutoStop => {
+                let density = &mut settings.scanner.auto_stop;
+                *density = step_choice(&AUTO_STOP_DENSITIES, *density, steps);
+            }
+            Entry::Watertight => flip(&mut settings.scanner.watertight),
+            Entry::ToggleScan => flip(&mut settings.accessibility.toggle_scan),
+            Entry::ReducedMotion => flip(&mut settings.accessibility.reduced_motion),
+            Entry::FlashLimit => {
+                let limit = &mut settings.accessibility.flash_limit;
+                *limit = step_choice(&FLASH_LIMITS, *limit, steps);
+            }
+            Entry::RestoreDefaults => {}
+        }
+    }
+
+    /// Sets the setting back to its default value.
+    fn restore(self, settings: &mut Settings) {
+        let defaults = Settings::default();
+        let (display, default) = (&mut settings.display, defaults.display);
+        match self {
+            Entry::Sensitivity => settings.controls.sensitivity = defaults.controls.sensitivity,
+            Entry::Fov => display.fov = default.fov,
+            Entry::Vsync => display.vsync = default.vsync,
+            Entry::FpsCap => display.fps_cap = default.fps_cap,
+            Entry::LowPower => display.low_power = default.low_power,
+            Entry::RenderScale => display.render_scale = default.render_scale,
+            Entry::UiScale => display.ui_scale = default.ui_scale,
+            Entry::Palette => display.palette = default.palette,
+            Entry::AdaptiveQuality => display.adaptive_quality = default.adaptive_quality,
+            Entry::MissMarks => settings.scanner.miss_marks = defaults.scanner.miss_marks,
+            Entry::MissIndicator => settings.scanner.miss_indicator = defaults.scanner.miss_indicator,
+            Entry::RayBudget => settings.scanner.ray_budget = defaults.scanner.ray_budget,
+            Entry::AutoStop => settings.scanner.auto_stop = defaults.scanner.auto_stop,
+            Entry::Watertight => settings.scanner.watertight = defaults.scanner.watertight,
+            Entry::ToggleScan => settings.accessibility.toggle_scan = defaults.accessibility.toggle_scan,
+            Entry::ReducedMotion => settings.accessibility.reduced_motion = defaults.accessibility.reduced_motion,
+            Entry::FlashLimit => settings.accessibility.flash_limit = defaults.accessibility.flash_limit,
+            Entry::RestoreDefaults => {}
+        }
+    }
+}
+
+/// Steps `value` through `choices` of an optional limit, in the order the menu shows them, none first and the limits
+/// ordered after it. A value that is not one of them sits between its neighbours, so the first step lands on the
+/// nearest choice in the step direction. Zero steps only turns a zero limit into none.
+fn step_choice(choices: &[Option<u32>], value: Option<u32>, steps: i32) -> Option<u32> {
+    let value = value.filter(|&limit| limit > 0);
+    if steps == 0 {
+        return value;
+    }
+    let last = choices.len() - 1;
+    let index = match choices.iter().position(|&choice| choice == value) {
+        Some(index) => index.saturating_add_signed(steps as isize),
+        None => {
+            let value = value.unwrap_or(0);
+            let mut limits = choices.iter().flatten();
+            let ascending = matches!((limits.next(), limits.last()), (Some(first), Some(last)) if first < last);
+            // Index of the last choice the value is past in menu order, none included.
+            let before = choices
+                .iter()
+                .rposition(|&choice| match choice {
+                    None => true,
+                    Some(choice) => (choice < value) == ascending,
+                })
+                .unwrap_or(0);
+            if steps > 0 {
+                before.saturating_add_signed(steps as isize)
+            } else {
+                (before + 1).saturating_add_signed(steps as isize)
+            }
+        }
+    };
+    choices[index.min(last)]
+}
+
+/// Brings every setting the menu covers into its valid range, for settings files edited by hand.
+pub fn validate(settings: &mut Settings) {
+    let locale = Locale::english();
+    for entry in ENTRIES {
+        let value = entry.value(settings, &locale);
+        entry.adjust(settings, 0);
+        let valid = entry.value(settings, &locale);
+        if valid != value {
+            warn!("{} {} is out of range, using {}", entry.key(), value, valid);
+        }
+    }
+}
+
+/// Settings menu drawn over the paused scene. Changes apply as they are made and are written back to the settings
+/// file when the menu closes.
+pub struct Menu {
+    pub open: bool,
+    pub selected: usize,
+    /// Whether a setting changed since the menu opened.
+    changed: bool,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Self { open: false, selected: 0, changed: false }
+    }
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    pub fn toggle_menu(&mut self) {
+        if self.menu.open {
+            self.menu.open = false;
+            if self.menu.changed {
+                self.save_settings();
+            }
+            return;
+        }
+        if self.photo.active {
+            self.notify("settings: leave photo mode first".to_string());
+            return;
+        }
+        // The menu is drawn by the HUD, so it can't be used without it.
+        if !cfg!(feature = "ui") {
+            self.notify("the settings menu is not available in this build".to_string());
+            return;
+        }
+
+        self.menu = Menu { open: true, ..Menu::new() };
+        self.marker.should_cast = false;
+        self.editor.building = false;
+        self.camera.mov.clear();
+    }
+
+    /// Handles a key press while the menu is open: up and down pick an entry, left and right change it, and return
+    /// flips switches or runs the selected action.
+    pub fn menu_key(&mut self, keycode: VirtualKeyCode) {
+        let entry = ENTRIES[self.menu.selected];
+        match keycode {
+            VirtualKeyCode::Up | VirtualKeyCode::W => {
+                self.menu.selected = (self.menu.selected + ENTRIES.len() - 1) % ENTRIES.len()
+            }
+            VirtualKeyCode::Down | VirtualKeyCode::S => self.menu.selected = (self.menu.selected + 1) % ENTRIES.len(),
+            VirtualKeyCode::Left | VirtualKeyCode::A => self.change_setting(entry, -1),
+            VirtualKeyCode::Right | VirtualKeyCode::D => self.change_setting(entry, 1),
+            VirtualKeyCode::Return if entry == Entry::RestoreDefaults => {
+                for entry in ENTRIES {
+                    entry.restore(&mut self.settings);
+                    self.apply_setting(entry);
+                }
+                self.menu.changed = true;
+                self.notify("settings: defaults restored".to_string());
+            }
+            VirtualKeyCode::Return => self.change_setting(entry, 1),
+            _ => {}
+        }
+    }
+
+    /// Switches marks to the next palette.
+    pub fn cycle_palette(&mut self) {
+        self.change_setting(Entry::Palette, 1);
+        self.notify(format!("palette: {}", self.settings.display.palette.name()));
+    }
+
+    fn change_setting(&mut self, entry: Entry, steps: i32) {
+        entry.adjust(&mut self.settings, steps);
+        self.apply_setting(entry);
+        self.menu.changed = true;
+    }
+
+    /// Brings the parts of the program that copy `entry`'s setting in line with it; the rest read it as they go.
+    fn apply_setting(&mut self, entry: Entry) {
+        let settings = &self.settings;
+        match entry {
+            Entry::Sensitivity => self.camera.set_sensitivity(settings.controls.sensitivity),
+            Entry::Fov => self.camera.set_fov(settings.display.fov),
+            Entry::Vsync => self.set_vsync(settings.display.vsync),
+            Entry::RenderScale => self.set_render_scale(settings.display.render_scale),
+            Entry::Palette => self.marker.set_palette(settings.display.palette),
+            Entry::AdaptiveQuality if self.governor.enabled != settings.display.adaptive_quality => {
+                self.toggle_governor()
+            }
+            Entry::RayBudget => self.marker.queue.budget = settings.scanner.ray_budget,
+            _ => {}
+        }
+    }
+
+    fn save_settings(&mut self) {
+        crash::record_settings(&self.settings);
+        match self.settings.save() {
+            Ok(()) => self.notify("settings saved".to_string()),
+            Err(e) => {
+                warn!("failed to save settings: {}", e);
+                self.notify("settings: save failed".to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, Entry, ENTRIES, MIN_RAY_BUDGET};
+    use crate::camera::MAX_FOV;
+    use crate::hud::MIN_UI_SCALE;
+    use crate::marker::Palette;
+    use crate::settings::Settings;
+
+    #[test]
+    fn adjusting_stays_in_range_and_snaps_to_steps() {
+        let mut settings = Settings::default();
+        for _ in 0..100 {
+            Entry::Fov.adjust(&mut settings, 1);
+            Entry::RayBudget.adjust(&mut settings, -1);
+        }
+        assert_eq!(settings.display.fov, MAX_FOV);
+        assert_eq!(settings.scanner.ray_budget, MIN_RAY_BUDGET);
+
+        settings.controls.sensitivity = 1.03;
+        Entry::Sensitivity.adjust(&mut settings, 2);
+        assert_eq!(settings.controls.sensitivity, 1.2);
+
+        Entry::FpsCap.adjust(&mut settings, 2);
+        assert_eq!(settings.display.fps_cap, Some(60));
+        settings.display.fps_cap = Some(100);
+        Entry::FpsCap.adjust(&mut settings, -1);
+        assert_eq!(settings.display.fps_cap, Some(90));
+        settings.display.fps_cap = Some(100);
+        Entry::FpsCap.adjust(&mut settings, 1);
+        assert_eq!(settings.display.fps_cap, Some(120));
+
+        Entry::FlashLimit.adjust(&mut settings, 2);
+        assert_eq!(settings.accessibility.flash_limit, Some(3));
+        Entry::AutoStop.adjust(&mut settings, 10);
+        assert_eq!(settings.scanner.auto_stop, Some(10));
+
+        Entry::Palette.adjust(&mut settings, -1);
+        assert_eq!(settings.display.palette, Palette::Cividis);
+    }
+
+    #[test]
+    fn validation_clamps_hand_edited_values_and_keeps_valid_ones() {
+        let mut settings = Settings::default();
+        settings.display.fov = 500.0;
+        settings.display.ui_scale = 0.0;
+        settings.display.fps_cap = Some(0);
+        settings.display.vsync = true;
+        validate(&mut settings);
+        assert_eq!(settings.display.fov, MAX_FOV);
+        assert_eq!(settings.display.ui_scale, MIN_UI_SCALE);
+        assert_eq!(settings.display.fps_cap, None);
+        assert!(settings.display.vsync);
+
+        for entry in ENTRIES {
+            entry.restore(&mut settings);
+        }
+        assert!(!settings.display.vsync);
+        assert_eq!(settings.display.fov, Settings::default().display.fov);
+    }
+}
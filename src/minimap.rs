@@ -0,0 +1,294 @@
+use super::marker::octree::Octree;
+use super::marker::MarkTag;
+use super::State;
+use glam::{vec2, Vec2, Vec3};
+
+/// Cells along each side of the map, centered on the player.
+const MAP_CELLS: usize = 128;
+/// Edge length of a cell in world units.
+const CELL_SIZE: f32 = 4.0;
+/// Marks farther above or below the player than this are left off the map, so stacked cavern levels don't overlap.
+const HEIGHT_BAND: f32 = 40.0;
+/// How far the player may stray from the map center, in cells horizontally and in units vertically, before the
+/// map is rebuilt around them.
+const RECENTER_CELLS: i32 = MAP_CELLS as i32 / 4;
+const RECENTER_HEIGHT: f32 = HEIGHT_BAND / 4.0;
+/// Seconds between uploads of newly recorded marks.
+const REFRESH_TIME: f64 = 0.25;
+/// Marks in a cell at which it shows at full brightness.
+const FULL_CELL: f32 = 64.0;
+
+/// Marks counted into cells around a center, seen from above.
+struct Grid {
+    /// Marks per cell, rows from north (-Z) to south.
+    cells: Vec<u16>,
+    /// Cell the grid is centered on, and the height its band is centered on.
+    center: (i32, i32),
+    center_height: f32,
+}
+
+fn cell(pos: Vec3) -> (i32, i32) {
+    ((pos.x / CELL_SIZE).floor() as i32, (pos.z / CELL_SIZE).floor() as i32)
+}
+
+impl Grid {
+    fn new() -> Self {
+        Self { cells: vec![0; MAP_CELLS * MAP_CELLS], center: (0, 0), center_height: 0.0 }
+    }
+
+    /// Index of the cell `pos` falls into, if it is on the grid and within the height band.
+    fn index(&self, pos: Vec3) -> Option<usize> {
+        let (x, z) = cell(pos);
+        let half = MAP_CELLS as i32 / 2;
+        let (col, row) = (x - self.center.0 + half, z - self.center.1 + half);
+        let on_map = (0..MAP_CELLS as i32).contains(&col) && (0..MAP_CELLS as i32).contains(&row);
+        (on_map && (pos.y - self.center_height).abs() <= HEIGHT_BAND).then(|| row as usize * MAP_CELLS + col as usize)
+    }
+
+    /// Counts `pos` into its cell, returning whether it was on the grid.
+    fn record(&mut self, pos: Vec3) -> bool {
+        let Some(i) = self.index(pos) else {
+            return false;
+        };
+        self.cells[i] = self.cells[i].saturating_add(1);
+        true
+    }
+
+    /// Recounts the grid around `pos` from the marks in the octree leaves that overlap it.
+    fn rebuild(&mut self, octree: &Octree, pos: Vec3) {
+        self.center = cell(pos);
+        self.center_height = pos.y;
+        self.cells.fill(0);
+        let half = MAP_CELLS as f32 * 0.5 * CELL_SIZE + CELL_SIZE;
+        for (center, extension, marks) in octree.leaves() {
+            let outside = (center.x - pos.x).abs() > half + extension
+                || (center.z - pos.z).abs() > half + extension
+                || (center.y - pos.y).abs() > HEIGHT_BAND + extension;
+            if outside {
+                continue;
+            }
+            for mark in marks.iter().filter(|mark| mark.tag() != MarkTag::Miss) {
+                self.record(mark.pos.into());
+            }
+        }
+    }
+
+    /// Where `pos` lies on the grid, from `(0, 0)` at the north-west corner to `(1, 1)` at the south-east one.
+    fn locate(&self, pos: Vec3) -> Vec2 {
+        let offset = vec2(pos.x, pos.z) / CELL_SIZE - vec2(self.center.0 as f32, self.center.1 as f32);
+        (offset + MAP_CELLS as f32 * 0.5) / MAP_CELLS as f32
+    }
+}
+
+/// A top-down occupancy map of the marks around the player, shown in a HUD corner. Marks are counted into a grid of
+/// cells that is rebuilt from the octree leaves whenever the player wanders off its center, and updated mark by mark
+/// in between.
+pub struct Minimap {
+    pub enabled: bool,
+    grid: Grid,
+    /// Set when the grid must be rebuilt from the octree, e.g. after loading a scan.
+    stale: bool,
+    /// Set when the grid changed since the last upload.
+    dirty: bool,
+    timer: f64,
+    texture: wgpu::Texture,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Minimap {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let (texture, pipeline, bind_group) = create_resources(device, format);
+        Self { enabled: true, grid: Grid::new(), stale: true, dirty: true, timer: 0.0, texture, pipeline, bind_group }
+    }
+
+    /// Rebuilds the GPU resources on a new device, keeping the map.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        (self.texture, self.pipeline, self.bind_group) = create_resources(device, format);
+        self.dirty = true;
+    }
+
+    pub fn record(&mut self, pos: Vec3) {
+        self.dirty |= self.grid.record(pos);
+    }
+
+    /// Marks the map for a rebuild from the octree on the next update, e.g. after a scan file was loaded or the
+    /// local origin moved.
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// Where `pos` lies on the map, from `(0, 0)` at the north-west corner to `(1, 1)` at the south-east one.
+    pub fn locate(&self, pos: Vec3) -> Vec2 {
+        self.grid.locate(pos)
+    }
+}
+
+fn create_resources(
+    device: &wgpu::Device,
+    format: wgpu::TextureFormat,
+) -> (wgpu::Texture, wgpu::RenderPipeline, wgpu::BindGroup) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Minimap Texture"),
+        size: wgpu::Extent3d { width: MAP_CELLS as u32, height: MAP_CELLS as u32, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Unorm,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        }],
+        label: Some("minimap_bind_group_layout"),
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: &bind_group_layout,
+        entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) }],
+        label: Some("minimap_bind_group"),
+    });
+
+    let shader = device.create_shader_module(wgpu::include_wgsl!("minimap.wgsl"));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Minimap Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &[],
+    });
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Minimap Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+        multiview: None,
+    });
+    (texture, pipeline, bind_group)
+}
+
+impl State {
+    pub fn toggle_minimap(&mut self) {
+        self.minimap.enabled = !self.minimap.enabled;
+        self.notify(format!("minimap: {}", if self.minimap.enabled { "on" } else { "off" }));
+    }
+
+    /// Rebuilds the map around the player once they stray from its center, and uploads the cells every
+    /// `REFRESH_TIME` seconds if they changed.
+    pub fn update_minimap(&mut self, dt: f64) {
+        let minimap = &mut self.minimap;
+        if !minimap.enabled {
+            return;
+        }
+
+        let pos = self.camera.pos;
+        let (x, z) = cell(pos);
+        let grid = &mut minimap.grid;
+        let strayed = (x - grid.center.0).abs() > RECENTER_CELLS
+            || (z - grid.center.1).abs() > RECENTER_CELLS
+            || (pos.y - grid.center_height).abs() > RECENTER_HEIGHT;
+        if minimap.stale || strayed {
+            let _span = tracing::info_span!("rebuild_minimap").entered();
+            grid.rebuild(&self.marker.octree, pos);
+            minimap.stale = false;
+            minimap.dirty = true;
+        }
+
+        minimap.timer -= dt;
+        if !minimap.dirty || minimap.timer > 0.0 {
+            return;
+        }
+        minimap.timer = REFRESH_TIME;
+        minimap.dirty = false;
+        let texels: Vec<u8> = minimap
+            .grid
+            .cells
+            .iter()
+            .map(|&count| ((count as f32 / FULL_CELL).sqrt().min(1.0) * 255.0) as u8)
+            .collect();
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &minimap.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(MAP_CELLS as u32),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d { width: MAP_CELLS as u32, height: MAP_CELLS as u32, depth_or_array_layers: 1 },
+        );
+    }
+
+    /// Draws the map into the corner the HUD lays it out in, with the HUD drawing the frame and player on top.
+    pub fn render_minimap<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let Some((pos, size)) = self.minimap_rect() else {
+            return;
+        };
+        let scale = self.ui_scale();
+        let (pos, size) = (pos * scale, size * scale);
+        let max = vec2(self.config.width as f32, self.config.height as f32);
+        if pos.x < 0.0 || pos.y < 0.0 || pos.x + size > max.x || pos.y + size > max.y {
+            return;
+        }
+        render_pass.set_viewport(pos.x, pos.y, size, size, 0.0, 1.0);
+        render_pass.set_scissor_rect(pos.x as u32, pos.y as u32, size.ceil() as u32, size.ceil() as u32);
+        render_pass.set_pipeline(&self.minimap.pipeline);
+        render_pass.set_bind_group(0, &self.minimap.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Grid, CELL_SIZE, HEIGHT_BAND, MAP_CELLS};
+    use crate::marker::octree::Octree;
+    use crate::marker::Mark;
+    use glam::{Vec2, Vec3};
+
+    #[test]
+    fn rebuilt_grid_counts_marks_on_the_map_and_in_the_height_band() {
+        let player = Vec3::new(13.0, 5.0, -7.0);
+        let near = [player + Vec3::new(-40.5, 3.0, 90.25), player + Vec3::new(60.0, -20.0, -100.0)];
+        let mut marks: Vec<Mark> = near.iter().map(|pos| Mark::new(*pos, Vec3::ONE)).collect();
+        marks.push(Mark::new(near[0], Vec3::ONE));
+        marks.push(Mark::new(player + Vec3::new(0.0, HEIGHT_BAND * 2.0, 0.0), Vec3::ONE));
+        marks.push(Mark::new(player + Vec3::new(CELL_SIZE * MAP_CELLS as f32, 0.0, 0.0), Vec3::ONE));
+        marks.push(Mark::miss(player + Vec3::X));
+        let mut octree = Octree::new();
+        for mark in marks {
+            octree.insert(mark);
+        }
+
+        let mut grid = Grid::new();
+        grid.rebuild(&octree, player);
+        assert_eq!(grid.cells.iter().map(|&count| count as usize).sum::<usize>(), 3);
+        for (pos, count) in [(near[0], 2), (near[1], 1)] {
+            let at = grid.locate(pos) * MAP_CELLS as f32;
+            assert_eq!(grid.index(pos), Some(at.y as usize * MAP_CELLS + at.x as usize));
+            assert_eq!(grid.cells[grid.index(pos).unwrap()], count);
+        }
+        assert!(grid.locate(player).abs_diff_eq(Vec2::splat(0.5), 1.0 / MAP_CELLS as f32));
+    }
+}
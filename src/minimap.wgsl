@@ -0,0 +1,28 @@
+@group(0) @binding(0)
+var occupancy: texture_2d<f32>;
+
+let BACKGROUND = vec4<f32>(0.0, 0.0, 0.0, 0.5);
+let OCCUPIED = vec4<f32>(0.4, 1.0, 0.9, 0.9);
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle covering the viewport, which is set to the minimap's corner of the window.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(occupancy));
+    let texel = vec2<i32>(min(in.uv * size, size - 1.0));
+    let density = textureLoad(occupancy, texel, 0).r;
+    return mix(BACKGROUND, OCCUPIED, density);
+}
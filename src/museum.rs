@@ -0,0 +1,104 @@
+use super::camera::Viewpoint;
+use super::State;
+use glam::{vec3, Vec3};
+
+const ROTATE_SENSITIVITY: f32 = 0.005;
+const ZOOM_SPEED: f32 = 0.1;
+const MIN_RADIUS: f32 = 5.0;
+/// Initial distance from the center, in multiples of the bounding sphere radius.
+const FRAMING: f32 = 2.5;
+const MAX_PITCH: f32 = 1.5;
+
+/// Orbit camera for reviewing a finished scan from outside. While active, scanning and free-fly movement are
+/// frozen and the cursor is released: dragging rotates around the point cloud's bounding box and the wheel zooms.
+pub struct Museum {
+    pub active: bool,
+    pub dragging: bool,
+    center: Vec3,
+    radius: f32,
+    yaw: f32,
+    pitch: f32,
+    saved: Option<Viewpoint>,
+}
+
+impl Default for Museum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Museum {
+    pub fn new() -> Self {
+        Self { active: false, dragging: false, center: Vec3::ZERO, radius: 0.0, yaw: 0.0, pitch: 0.0, saved: None }
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        self.center -= shift;
+        if let Some(saved) = &mut self.saved {
+            saved.pos -= shift;
+        }
+    }
+
+    pub fn rotate(&mut self, dx: f32, dy: f32) {
+        self.yaw += dx * ROTATE_SENSITIVITY;
+        self.pitch = f32::clamp(self.pitch + dy * ROTATE_SENSITIVITY, -MAX_PITCH, MAX_PITCH);
+    }
+
+    pub fn zoom(&mut self, y: f32) {
+        self.radius = f32::max(self.radius * (1.0 - y * ZOOM_SPEED), MIN_RADIUS);
+    }
+
+    fn eye(&self) -> Vec3 {
+        let offset = vec3(
+            f32::cos(self.yaw) * f32::cos(self.pitch),
+            f32::sin(self.pitch),
+            f32::sin(self.yaw) * f32::cos(self.pitch),
+        );
+        self.center + offset * self.radius
+    }
+}
+
+impl State {
+    pub fn toggle_museum(&mut self) {
+        if self.museum.active {
+            self.museum.active = false;
+            self.museum.dragging = false;
+            if let Some(viewpoint) = self.museum.saved.take() {
+                self.camera.set_viewpoint(viewpoint);
+            }
+            self.grab_cursor(self.input.focused);
+            self.notify("museum: off".to_string());
+            return;
+        }
+
+        if self.photo.active {
+            self.notify("museum: leave photo mode first".to_string());
+            return;
+        }
+        let Some((min, max)) = self.marker.octree.bounds() else {
+            self.notify("museum: nothing scanned yet".to_string());
+            return;
+        };
+
+        let player = self.camera.viewpoint();
+        let museum = &mut self.museum;
+        museum.center = (min + max) * 0.5;
+        museum.radius = f32::max((max - min).length() * 0.5 * FRAMING, MIN_RADIUS);
+        // Start from the player's side of the cloud, looking the same way they were.
+        museum.yaw = player.yaw + std::f32::consts::PI;
+        museum.pitch = 0.3;
+        museum.saved = Some(player);
+        museum.active = true;
+
+        self.marker.should_cast = false;
+        self.camera.mov.clear();
+        self.grab_cursor(false);
+        self.notify("museum: on".to_string());
+    }
+
+    pub fn update_museum(&mut self) {
+        let eye = self.museum.eye();
+        self.camera.look_at(eye, self.museum.center);
+    }
+}
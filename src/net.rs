@@ -0,0 +1,601 @@
+use super::marker::{Mark, MarkRaw};
+use super::State;
+use glam::{vec3, DVec3, Vec3};
+use std::collections::{HashMap, VecDeque};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use tracing::warn;
+
+/// Changed along with the mark layout, so peers running an older build ignore each other's packets.
+const MAGIC: &[u8; 4] = b"SCN4";
+/// Magic, sender id, sequence number, player id and handshake token and echo in four bytes each, the origin in three
+/// `f64`s and the mark count in four bytes.
+const HEADER_SIZE: usize = 52;
+/// Kept under common path MTUs so packets are not fragmented.
+pub(crate) const MAX_PACKET_SIZE: usize = 1200;
+pub(crate) const MARKS_PER_PACKET: usize = (MAX_PACKET_SIZE - HEADER_SIZE) / MarkRaw::STORED_SIZE;
+
+/// Player id of marks that come from the server's shared scan rather than a live player.
+pub(crate) const SHARED_PLAYER: u32 = 0;
+
+/// Outgoing packets allowed per second, shared between all peers.
+pub(crate) const MAX_PACKETS_PER_SECOND: f64 = 240.0;
+/// Packets that may be sent at once after a quiet period.
+pub(crate) const MAX_PACKET_BURST: f64 = 32.0;
+/// Marks waiting to be sent; the oldest are dropped first when scanning outpaces the rate limit.
+const MAX_PENDING_MARKS: usize = 200000;
+/// Packets read per frame, so a flood of incoming marks cannot stall rendering.
+const MAX_RECEIVED_PER_FRAME: usize = 256;
+
+/// Empty packets are sent at this interval so peers that only listen learn our address.
+pub(crate) const HEARTBEAT_TIME: f64 = 1.0;
+/// Peers and players are forgotten after this long without a packet.
+pub(crate) const PEER_TIMEOUT: f64 = 10.0;
+/// Peers learned from incoming packets beyond this many are ignored, so spoofed sources cannot grow the list.
+pub(crate) const MAX_PEERS: usize = 32;
+
+/// Fields in front of the marks of every packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Header {
+    /// Id of whoever sent the packet; picked anew on every start, so a change means the sender restarted.
+    pub sender: u32,
+    /// Counts the sender's packets to one address, starting anywhere.
+    pub sequence: u32,
+    /// Player who scanned the marks. Differs from the sender when the server relays them.
+    pub player: u32,
+    /// Random token the sender picked for the receiver, to be echoed back.
+    pub token: u32,
+    /// Last token the sender got from the receiver, or 0 before it heard from it. Proves the sender really is at the
+    /// address the packet came from, since only that address was sent the token.
+    pub echo: u32,
+    /// World position the positions of the marks are relative to. Marks are stored as `f32`, which only holds world
+    /// positions near the world origin precisely, so they travel as offsets from a nearby origin instead.
+    pub origin: DVec3,
+}
+
+pub(crate) fn encode_packet(header: Header, marks: &[MarkRaw]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(HEADER_SIZE + marks.len() * MarkRaw::STORED_SIZE);
+    packet.extend_from_slice(MAGIC);
+    packet.extend_from_slice(&header.sender.to_le_bytes());
+    packet.extend_from_slice(&header.sequence.to_le_bytes());
+    packet.extend_from_slice(&header.player.to_le_bytes());
+    packet.extend_from_slice(&header.token.to_le_bytes());
+    packet.extend_from_slice(&header.echo.to_le_bytes());
+    for coord in header.origin.to_array() {
+        packet.extend_from_slice(&coord.to_le_bytes());
+    }
+    packet.extend_from_slice(&(marks.len() as u32).to_le_bytes());
+    for mark in marks {
+        packet.extend_from_slice(mark.stored_bytes());
+    }
+    packet
+}
+
+/// Splits a packet into its header and marks, or returns `None` if it is not one of ours, is truncated or has an
+/// origin that is not finite.
+pub(crate) fn decode_packet(packet: &[u8]) -> Option<(Header, impl Iterator<Item = MarkRaw> + '_)> {
+    if packet.len() < HEADER_SIZE || &packet[0..4] != MAGIC {
+        return None;
+    }
+    let read_u32 = |offset: usize| u32::from_le_bytes(packet[offset..offset + 4].try_into().unwrap());
+    let read_f64 = |offset: usize| f64::from_le_bytes(packet[offset..offset + 8].try_into().unwrap());
+    let header = Header {
+        sender: read_u32(4),
+        sequence: read_u32(8),
+        player: read_u32(12),
+        token: read_u32(16),
+        echo: read_u32(20),
+        origin: DVec3::new(read_f64(24), read_f64(32), read_f64(40)),
+    };
+    let count = read_u32(48) as usize;
+
+    if count > MARKS_PER_PACKET || packet.len() != HEADER_SIZE + count * MarkRaw::STORED_SIZE {
+        return None;
+    }
+    if !header.origin.is_finite() {
+        return None;
+    }
+    Some((header, packet[HEADER_SIZE..].chunks_exact(MarkRaw::STORED_SIZE).map(MarkRaw::from_stored)))
+}
+
+/// Random id for this run, never [`SHARED_PLAYER`].
+pub(crate) fn random_id() -> u32 {
+    loop {
+        let id = rand::random();
+        if id != SHARED_PLAYER {
+            return id;
+        }
+    }
+}
+
+/// Tracks the latest sequence number received from a peer and which of the 32 before it arrived, so reordered
+/// packets are still accepted while duplicates are dropped.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SequenceWindow {
+    sender: Option<u32>,
+    latest: u32,
+    received: u32,
+}
+
+impl SequenceWindow {
+    /// Records a packet, returning `false` if it was already seen or is too old to tell.
+    pub fn accept(&mut self, header: Header) -> bool {
+        let seq = header.sequence;
+        if self.sender != Some(header.sender) {
+            // A restarted sender picks a new id and starts counting again.
+            *self = Self { sender: Some(header.sender), latest: seq, received: 0 };
+            return true;
+        }
+
+        let ahead = seq.wrapping_sub(self.latest) as i32;
+        if ahead > 0 {
+            self.received = if ahead >= 32 { 0 } else { (self.received << ahead) | (1 << (ahead - 1)) };
+            self.latest = seq;
+            true
+        } else if ahead == 0 || ahead < -32 {
+            false
+        } else {
+            let bit = 1 << (-ahead - 1);
+            let new = self.received & bit == 0;
+            self.received |= bit;
+            new
+        }
+    }
+}
+
+/// Token bucket allowing `rate` packets per second on average and up to [`MAX_PACKET_BURST`] at once.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct RateLimit {
+    rate: f64,
+    tokens: f64,
+}
+
+impl RateLimit {
+    /// Starts with a full burst.
+    pub fn new(rate: f64) -> Self {
+        Self { rate, tokens: MAX_PACKET_BURST }
+    }
+
+    /// Adds the allowance of `dt` seconds.
+    pub fn refill(&mut self, dt: f64) {
+        self.tokens = f64::min(self.tokens + dt * self.rate, MAX_PACKET_BURST);
+    }
+
+    /// Spends the allowance for one packet, returning `false` if there is none left.
+    pub fn take(&mut self) -> bool {
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+/// Outcome of reading one packet.
+enum Received {
+    /// Marks scanned by `player`, relative to the world position `origin`. Duplicates arrive with no marks.
+    Marks { player: u32, origin: DVec3 },
+    /// A malformed packet, one of our own, or an error caused by an earlier send.
+    Ignored,
+}
+
+struct Peer {
+    addr: SocketAddr,
+    /// Given on the command line; kept even while silent and sent marks before the handshake.
+    configured: bool,
+    /// Token picked for the peer, see [`Header::token`].
+    token: u32,
+    /// Last token the peer picked for us.
+    echo: u32,
+    /// Echoed our token, so it is really at `addr` and may be sent marks.
+    verified: bool,
+    sequence: SequenceWindow,
+    /// Outgoing sequence number for this peer.
+    next_sequence: u32,
+    last_heard: f64,
+}
+
+impl Peer {
+    fn new(addr: SocketAddr, configured: bool, time: f64) -> Self {
+        Self {
+            addr,
+            configured,
+            token: random_id(),
+            echo: 0,
+            verified: false,
+            sequence: SequenceWindow::default(),
+            next_sequence: 0,
+            last_heard: time,
+        }
+    }
+
+    fn header(&mut self, sender: u32, player: u32, origin: DVec3) -> Header {
+        let header =
+            Header { sender, sequence: self.next_sequence, player, token: self.token, echo: self.echo, origin };
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        header
+    }
+}
+
+/// Shares scanned marks with other players over UDP. Each packet carries the sender's id, a sequence number and the
+/// id of the player who scanned the marks; received marks are tinted with a color derived from that player's id.
+///
+/// Marks are sent directly to peers given with `--peer` and to peers that completed a handshake by echoing a token
+/// sent to their address, so packets from spoofed sources are never answered with marks. With more than two players
+/// either each one lists all the others with `--peer`, or everyone connects to a `scanner server`, which relays marks
+/// between them.
+pub struct Net {
+    socket: Option<UdpSocket>,
+    pub player: u32,
+    peers: Vec<Peer>,
+    /// When each other player was last heard from, directly or relayed.
+    players: HashMap<u32, f64>,
+    /// Marks waiting to be sent, with their world positions.
+    pending: VecDeque<(DVec3, MarkRaw)>,
+    outgoing: RateLimit,
+    heartbeat_timer: f64,
+    /// Until the next handshake packet to peers that have not echoed their token yet.
+    challenge_timer: f64,
+    time: f64,
+}
+
+impl Default for Net {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl Net {
+    /// A network layer that sends and receives nothing.
+    pub fn disabled() -> Self {
+        Self {
+            socket: None,
+            player: random_id(),
+            peers: Vec::new(),
+            players: HashMap::new(),
+            pending: VecDeque::new(),
+            outgoing: RateLimit::new(MAX_PACKETS_PER_SECOND),
+            heartbeat_timer: 0.0,
+            challenge_timer: 0.0,
+            time: 0.0,
+        }
+    }
+
+    /// Binds a non-blocking socket on `port` (any free port if 0) and adds `peers` as `host:port` addresses.
+    pub fn bind(port: u16, peers: &[String]) -> Result<Self, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(|e| format!("failed to bind port {}: {}", port, e))?;
+        socket.set_nonblocking(true).map_err(|e| e.to_string())?;
+
+        let mut net = Self { socket: Some(socket), ..Self::disabled() };
+        for peer in peers {
+            let addr = peer
+                .to_socket_addrs()
+                .map_err(|e| format!("failed to resolve peer '{}': {}", peer, e))?
+                .next()
+                .ok_or_else(|| format!("peer '{}' has no address", peer))?;
+            net.peers.push(Peer::new(addr, true, 0.0));
+        }
+        Ok(net)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    /// Number of other players heard from recently.
+    pub fn players(&self) -> usize {
+        self.players.len()
+    }
+
+    /// Queues a locally scanned mark at world position `pos` to be sent to every peer. Marks travel in world
+    /// coordinates, since every player rebases their local origin independently.
+    pub fn share(&mut self, pos: DVec3, mark: Mark) {
+        if !self.enabled() {
+            return;
+        }
+        if self.pending.len() == MAX_PENDING_MARKS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((pos, mark.to_raw()));
+    }
+
+    /// Sends `marks`, relative to `origin`, to every peer that may be sent marks, or only handshake packets without
+    /// marks to the others.
+    fn send(&mut self, origin: DVec3, marks: &[MarkRaw], trusted: bool) {
+        let Some(socket) = &self.socket else {
+            return;
+        };
+
+        for peer in self.peers.iter_mut().filter(|peer| (peer.configured || peer.verified) == trusted) {
+            let header = peer.header(self.player, self.player, origin);
+            let packet = encode_packet(header, if trusted { marks } else { &[] });
+            if let Err(e) = socket.send_to(&packet, peer.addr) {
+                warn!("failed to send to {}: {}", peer.addr, e);
+            }
+        }
+    }
+
+    /// Reads one packet into `marks`, returning `Ok(None)` if nothing is waiting or `Err` if the socket failed.
+    fn receive(&mut self, buf: &mut [u8], marks: &mut Vec<MarkRaw>) -> Result<Option<Received>, String> {
+        let Some(socket) = &self.socket else {
+            return Ok(None);
+        };
+        let (len, addr) = match socket.recv_from(buf) {
+            Ok(received) => received,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(None),
+            // Windows reports an earlier packet that bounced off a closed port this way.
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => return Ok(Some(Received::Ignored)),
+            Err(e) => return Err(e.to_string()),
+        };
+
+        marks.clear();
+        let Some((header, packet_marks)) = decode_packet(&buf[..len]) else {
+            return Ok(Some(Received::Ignored));
+        };
+        if header.sender == self.player || header.player == self.player {
+            return Ok(Some(Received::Ignored));
+        }
+
+        let index = match self.peers.iter().position(|peer| peer.addr == addr) {
+            Some(index) => index,
+            None if self.peers.iter().filter(|peer| !peer.configured).count() >= MAX_PEERS => {
+                return Ok(Some(Received::Ignored))
+            }
+            None => {
+                self.peers.push(Peer::new(addr, false, self.time));
+                self.peers.len() - 1
+            }
+        };
+        let peer = &mut self.peers[index];
+        peer.last_heard = self.time;
+        peer.echo = header.token;
+        peer.verified |= header.echo == peer.token;
+        if peer.sequence.accept(header) {
+            marks.extend(packet_marks);
+        }
+        Ok(Some(Received::Marks { player: header.player, origin: header.origin }))
+    }
+}
+
+/// Distinct, fully saturated color for a player id.
+pub fn player_color(player: u32) -> Vec3 {
+    let hue = (player.wrapping_mul(2654435761) >> 8) as f32 / (1 << 24) as f32 * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as u32 {
+        0 => vec3(1.0, x, 0.0),
+        1 => vec3(x, 1.0, 0.0),
+        2 => vec3(0.0, 1.0, x),
+        3 => vec3(0.0, x, 1.0),
+        4 => vec3(x, 0.0, 1.0),
+        _ => vec3(1.0, 0.0, x),
+    }
+}
+
+impl State {
+    /// Sends queued marks within the rate limit and merges marks received from peers into the octree.
+    pub fn update_net(&mut self, dt: f64) {
+        if !self.net.enabled() {
+            return;
+        }
+        let net = &mut self.net;
+        net.time += dt;
+        net.outgoing.refill(dt);
+
+        net.heartbeat_timer -= dt;
+        let mut batch = Vec::with_capacity(MARKS_PER_PACKET);
+        while (!net.pending.is_empty() || net.heartbeat_timer <= 0.0) && net.outgoing.take() {
+            let n = usize::min(net.pending.len(), MARKS_PER_PACKET);
+            // The marks of a batch were scanned close together, so offsets from the first one stay small.
+            let origin = net.pending.front().map_or(DVec3::ZERO, |(pos, _)| *pos);
+            batch.clear();
+            batch.extend(
+                net.pending.drain(..n).map(|(pos, raw)| MarkRaw { pos: (pos - origin).as_vec3().into(), ..raw }),
+            );
+            net.send(origin, &batch, true);
+            net.heartbeat_timer = HEARTBEAT_TIME;
+        }
+        net.challenge_timer -= dt;
+        if net.challenge_timer <= 0.0 {
+            net.send(DVec3::ZERO, &[], false);
+            net.challenge_timer = HEARTBEAT_TIME;
+        }
+
+        let mut buf = [0; MAX_PACKET_SIZE];
+        let mut marks = Vec::with_capacity(MARKS_PER_PACKET);
+        for _ in 0..MAX_RECEIVED_PER_FRAME {
+            let (player, origin) = match self.net.receive(&mut buf, &mut marks) {
+                Ok(Some(Received::Marks { player, origin })) => (player, origin),
+                Ok(Some(Received::Ignored)) => continue,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("failed to receive: {}", e);
+                    break;
+                }
+            };
+
+            if player != SHARED_PLAYER && self.net.players.insert(player, self.net.time).is_none() {
+                self.notify(format!("player {:08x} joined", player));
+            }
+            // Peers are not trusted to send usable positions, which would otherwise end up in the octree's bounds.
+            for raw in marks.iter().filter(|raw| Vec3::from(raw.pos).is_finite()) {
+                // The shared scan already carries the colors of whoever scanned it.
+                let mut mark = match player {
+                    SHARED_PLAYER => Mark::from(*raw),
+                    _ => Mark::new(raw.pos.into(), player_color(player)),
+                };
+                mark.pos = self.to_local(origin + mark.pos.as_dvec3());
+                let mark = self.stamp(mark);
+                self.marker.octree.insert(mark);
+                self.coverage.record(mark.pos);
+                self.minimap.record(mark.pos);
+            }
+        }
+
+        let time = self.net.time;
+        self.net.peers.retain(|peer| peer.configured || time - peer.last_heard < PEER_TIMEOUT);
+        let before = self.net.players.len();
+        self.net.players.retain(|_, last_heard| time - *last_heard < PEER_TIMEOUT);
+        let left = before - self.net.players.len();
+        if left > 0 {
+            self.notify(format!("{} player(s) left", left));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_packet, encode_packet, Header, Net, RateLimit, Received, SequenceWindow, HEADER_SIZE, MARKS_PER_PACKET,
+        MAX_PACKET_BURST, MAX_PACKET_SIZE, MAX_PEERS,
+    };
+    use crate::marker::MarkRaw;
+    use glam::DVec3;
+
+    fn local(port: u16) -> String {
+        format!("127.0.0.1:{}", port)
+    }
+
+    fn port(net: &Net) -> u16 {
+        net.socket.as_ref().unwrap().local_addr().unwrap().port()
+    }
+
+    /// Waits briefly for a packet, returning the marks it carried or `None` if nothing arrived.
+    fn wait(net: &mut Net) -> Option<Vec<MarkRaw>> {
+        let (mut buf, mut marks) = ([0; MAX_PACKET_SIZE], Vec::new());
+        for _ in 0..200 {
+            match net.receive(&mut buf, &mut marks).unwrap() {
+                Some(Received::Marks { .. }) => return Some(marks),
+                Some(Received::Ignored) => return None,
+                None => std::thread::sleep(std::time::Duration::from_millis(1)),
+            }
+        }
+        None
+    }
+
+    fn header(sender: u32, sequence: u32) -> Header {
+        // Far enough out that `f32` positions would be off by whole units.
+        Header { sender, sequence, player: sender, token: 7, echo: 9, origin: DVec3::new(1e9 + 0.25, -3.5, 7e8) }
+    }
+
+    #[test]
+    fn packets_decode_to_what_was_encoded() {
+        let marks: Vec<MarkRaw> = (0..MARKS_PER_PACKET)
+            .map(|i| MarkRaw { pos: [i as f32, -1.0, 2.5], color: [1, 2, 3, 4], time: 0.5, layer: 0 })
+            .collect();
+        let packet = encode_packet(header(3, 4), &marks);
+        assert!(packet.len() <= MAX_PACKET_SIZE);
+
+        let (decoded, decoded_marks) = decode_packet(&packet).unwrap();
+        assert_eq!(decoded, header(3, 4));
+        assert!(decoded_marks.zip(&marks).all(|(a, b)| a.stored_bytes() == b.stored_bytes()));
+        assert_eq!(decode_packet(&encode_packet(header(3, 4), &[])).unwrap().1.count(), 0);
+    }
+
+    #[test]
+    fn truncated_oversized_and_foreign_packets_are_rejected() {
+        let packet = encode_packet(header(3, 4), &[MarkRaw::default(); 2]);
+        for len in [0, 3, HEADER_SIZE - 1, HEADER_SIZE, packet.len() - 1] {
+            assert!(decode_packet(&packet[..len]).is_none(), "{} bytes", len);
+        }
+        let mut longer = packet.clone();
+        longer.push(0);
+        assert!(decode_packet(&longer).is_none());
+
+        let mut foreign = packet.clone();
+        foreign[3] = b'2';
+        assert!(decode_packet(&foreign).is_none());
+
+        let nowhere = Header { origin: DVec3::new(0.0, f64::NAN, 0.0), ..header(3, 4) };
+        assert!(decode_packet(&encode_packet(nowhere, &[])).is_none());
+
+        // More marks than fit in a packet, even if all of them are there.
+        let oversized = encode_packet(header(3, 4), &vec![MarkRaw::default(); MARKS_PER_PACKET + 1]);
+        assert!(decode_packet(&oversized).is_none());
+    }
+
+    #[test]
+    fn sequence_windows_accept_reordered_packets_once() {
+        let mut window = SequenceWindow::default();
+        let accepted: Vec<bool> = [10, 12, 11, 12, 11, 10, 9, 42, 11, 10, 43]
+            .into_iter()
+            .map(|sequence| window.accept(header(1, sequence)))
+            .collect();
+        // 9 was sent before the first packet seen but never arrived itself; 10 and 11 are more than 32 behind 42.
+        assert_eq!(accepted, [true, true, true, false, false, false, true, true, false, false, true]);
+
+        // 32 behind the latest is the oldest packet still told apart.
+        let mut window = SequenceWindow::default();
+        assert!(window.accept(header(1, 100)));
+        assert!(window.accept(header(1, 68)));
+        assert!(!window.accept(header(1, 68)));
+        assert!(!window.accept(header(1, 67)));
+    }
+
+    #[test]
+    fn sequence_windows_follow_wraparound_and_restarted_senders() {
+        let mut window = SequenceWindow::default();
+        for sequence in [u32::MAX - 1, 1, u32::MAX, 0] {
+            assert!(window.accept(header(1, sequence)), "{}", sequence);
+        }
+        assert!(!window.accept(header(1, u32::MAX)));
+        assert!(!window.accept(header(1, 1)));
+        assert!(window.accept(header(1, 2)));
+
+        // A restarted sender counts from wherever it likes under its new id.
+        assert!(window.accept(header(2, 5)));
+        assert!(!window.accept(header(2, 5)));
+        assert!(window.accept(header(2, 6)));
+        // Its old id is stale.
+        assert!(window.accept(header(1, 3)));
+    }
+
+    #[test]
+    fn rate_limits_allow_a_burst_then_their_rate() {
+        let mut limit = RateLimit::new(8.0);
+        let burst = (0..100).take_while(|_| limit.take()).count();
+        assert_eq!(burst, MAX_PACKET_BURST as usize);
+
+        limit.refill(0.25);
+        assert_eq!((0..100).take_while(|_| limit.take()).count(), 2);
+        limit.refill(0.0625);
+        assert!(!limit.take());
+        limit.refill(0.0625);
+        assert!(limit.take());
+
+        // Quiet periods only ever save up one burst.
+        limit.refill(3600.0);
+        assert_eq!((0..100).take_while(|_| limit.take()).count(), MAX_PACKET_BURST as usize);
+    }
+
+    #[test]
+    fn peers_that_only_sent_packets_get_marks_after_echoing_a_token() {
+        let mark = MarkRaw { pos: [1.0, 2.0, 3.0], ..Default::default() };
+        let mut host = Net::bind(0, &[]).unwrap();
+        let mut guest = Net::bind(0, &[local(port(&host))]).unwrap();
+
+        // A configured peer is sent marks right away, and whoever sent them becomes a peer that is not trusted yet.
+        guest.send(DVec3::ZERO, &[mark], true);
+        assert_eq!(wait(&mut host).map(|marks| marks.len()), Some(1));
+        host.send(DVec3::ZERO, &[mark], true);
+        assert!(wait(&mut guest).is_none());
+
+        // The handshake packet carries no marks; answering it proves the guest is at its address.
+        host.send(DVec3::ZERO, &[mark], false);
+        assert_eq!(wait(&mut guest).map(|marks| marks.len()), Some(0));
+        guest.send(DVec3::ZERO, &[], true);
+        assert_eq!(wait(&mut host).map(|marks| marks.len()), Some(0));
+        assert!(host.peers[0].verified);
+        host.send(DVec3::ZERO, &[mark], true);
+        assert_eq!(wait(&mut guest).map(|marks| marks.len()), Some(1));
+    }
+
+    #[test]
+    fn peers_learned_from_incoming_packets_are_capped() {
+        let mut host = Net::bind(0, &[]).unwrap();
+        for _ in 0..MAX_PEERS + 2 {
+            let mut guest = Net::bind(0, &[local(port(&host))]).unwrap();
+            guest.send(DVec3::ZERO, &[], true);
+            wait(&mut host);
+        }
+        assert_eq!(host.peers.len(), MAX_PEERS);
+    }
+}
@@ -0,0 +1,178 @@
+use super::marker::Marker;
+use super::State;
+use glam::{vec3, Vec3};
+use std::collections::HashMap;
+use wgpu::util::DeviceExt;
+
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// Edge length of the terrain chunks meshed for the depth pre-pass.
+const CHUNK_SIZE: f32 = 40.0;
+/// Chunks whose center is within this distance of the camera are meshed and drawn.
+const OCCLUSION_RANGE: f32 = 160.0;
+/// Loaded chunks beyond this distance are released.
+const EVICT_RANGE: f32 = OCCLUSION_RANGE * 1.5;
+/// Chunks meshed per frame; terrain generation is too slow to fill the whole range at once.
+const CHUNKS_PER_FRAME: usize = 6;
+
+type ChunkId = (i32, i32, i32);
+
+struct Chunk {
+    /// `None` for chunks without any surface.
+    vertex_buffer: Option<wgpu::Buffer>,
+    n_vertices: u32,
+}
+
+/// Hides marks behind terrain by rendering the terrain around the camera into the depth buffer before the marks,
+/// which are then depth tested against it. The terrain is meshed in chunks that are cached on the GPU.
+pub struct Occlusion {
+    pub enabled: bool,
+    pipeline: wgpu::RenderPipeline,
+    depth_view: wgpu::TextureView,
+    chunks: HashMap<ChunkId, Chunk>,
+}
+
+impl Occlusion {
+    pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, marker: &Marker) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("occlusion.wgsl"));
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Occlusion Pipeline Layout"),
+            bind_group_layouts: &[&marker.camera_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Occlusion Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                })],
+            }),
+            primitive: wgpu::PrimitiveState { cull_mode: None, ..Default::default() },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        Self {
+            enabled: true,
+            pipeline,
+            depth_view: create_depth_view(device, config.width, config.height),
+            chunks: HashMap::new(),
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.depth_view = create_depth_view(device, width, height);
+    }
+
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+
+    /// Drops every cached chunk, e.g. after the local origin moved.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// Drops every cached chunk that may overlap the sphere at `center`, so it is re-meshed from the edited terrain.
+    pub fn invalidate(&mut self, center: Vec3, radius: f32) {
+        let reach = radius + CHUNK_SIZE * 3f32.sqrt() * 0.5;
+        self.chunks.retain(|id, _| chunk_center(*id).distance(center) > reach);
+    }
+}
+
+fn create_depth_view(device: &wgpu::Device, width: u32, height: u32) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Depth Texture"),
+        size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+fn chunk_center(id: ChunkId) -> Vec3 {
+    (vec3(id.0 as f32, id.1 as f32, id.2 as f32) + 0.5) * CHUNK_SIZE
+}
+
+impl State {
+    pub fn toggle_occlusion(&mut self) {
+        self.occlusion.enabled = !self.occlusion.enabled;
+        self.notify(format!("occlusion: {}", if self.occlusion.enabled { "on" } else { "off" }));
+    }
+
+    /// Meshes the nearest missing chunks in range and releases the ones left far behind.
+    pub fn update_occlusion(&mut self) {
+        if !self.occlusion.enabled {
+            return;
+        }
+
+        let pos = self.camera.pos;
+        self.occlusion.chunks.retain(|id, _| chunk_center(*id).distance(pos) <= EVICT_RANGE);
+
+        let base = (pos / CHUNK_SIZE).floor();
+        let reach = (OCCLUSION_RANGE / CHUNK_SIZE).ceil() as i32;
+        let mut missing: Vec<(f32, ChunkId)> = itertools::iproduct!(-reach..=reach, -reach..=reach, -reach..=reach)
+            .map(|(x, y, z)| (base.x as i32 + x, base.y as i32 + y, base.z as i32 + z))
+            .filter(|id| !self.occlusion.chunks.contains_key(id))
+            .map(|id| (chunk_center(id).distance(pos), id))
+            .filter(|(dist, _)| *dist <= OCCLUSION_RANGE)
+            .collect();
+        missing.sort_unstable_by(|a, b| f32::total_cmp(&a.0, &b.0));
+
+        for (_, id) in missing.into_iter().take(CHUNKS_PER_FRAME) {
+            let triangles = self.world.retrieve_triangles(chunk_center(id), CHUNK_SIZE * 0.5);
+            let vertices: Vec<[f32; 3]> =
+                triangles.iter().flat_map(|triangle| [triangle.a, triangle.b, triangle.c]).map(Vec3::into).collect();
+            let vertex_buffer = (!vertices.is_empty()).then(|| {
+                self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Occlusion Chunk Buffer"),
+                    contents: bytemuck::cast_slice(&vertices),
+                    usage: wgpu::BufferUsages::VERTEX,
+                })
+            });
+            self.occlusion.chunks.insert(id, Chunk { vertex_buffer, n_vertices: vertices.len() as u32 });
+        }
+    }
+
+    /// Draws the cached terrain chunks into the depth buffer; must come before the marks in the same pass.
+    pub fn render_occlusion<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.occlusion.enabled {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.occlusion.pipeline);
+        render_pass.set_bind_group(0, &self.marker.camera_bind_group, &[]);
+        for chunk in self.occlusion.chunks.values() {
+            if let Some(vertex_buffer) = &chunk.vertex_buffer {
+                render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+                render_pass.draw(0..chunk.n_vertices, 0..1);
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+struct CameraUniform {
+    pos: vec4<f32>,
+    to_view: mat4x4<f32>,
+    to_proj: mat4x4<f32>,
+};
+
+struct GlobalsUniform {
+    ruler_spacing: f32,
+    ruler_enabled: u32,
+    point_size: f32,
+    time_limit: f32,
+    palette: array<vec4<f32>, 8>,
+    // Terrain on the negative side of this plane is cut away along with the marks there.
+    clip_plane: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(0) @binding(1)
+var<uniform> globals: GlobalsUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) pos: vec3<f32>,
+}
+
+// Terrain depth is pushed away from the camera so marks lying on the surface are not hidden by it.
+let DEPTH_PUSH_REL = 0.01;
+let DEPTH_PUSH_ABS = 1.0;
+
+@vertex
+fn vs_main(@location(0) pos: vec3<f32>) -> VertexOutput {
+    let view = camera.to_view * vec4<f32>(pos, 1.0);
+    let pushed = view.xyz * (1.0 + DEPTH_PUSH_REL) + normalize(view.xyz) * DEPTH_PUSH_ABS;
+    var out: VertexOutput;
+    out.clip_position = camera.to_proj * vec4<f32>(pushed, 1.0);
+    out.pos = pos;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (dot(globals.clip_plane, vec4<f32>(in.pos, 1.0)) < 0.0) {
+        discard;
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+}
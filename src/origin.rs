@@ -0,0 +1,60 @@
+use super::State;
+use glam::{DVec3, Vec3};
+use tracing::info;
+
+/// Once the camera is this far from the local origin, everything is moved back around it. Far enough that it rarely
+/// happens, near enough that `f32` positions keep millimeter precision.
+const REBASE_DISTANCE: f32 = 4096.0;
+/// Rebasing shifts by multiples of this, which every terrain grid, voxel, chunk and cell size as well as the ruler
+/// spacings divide, so cell keys stay whole and grids line up as before.
+pub const REBASE_GRID: f32 = 960.0;
+
+impl State {
+    /// World position of the local origin every `f32` position is relative to.
+    pub fn origin(&self) -> DVec3 {
+        self.origin
+    }
+
+    /// World position of the local position `pos`.
+    pub fn to_world(&self, pos: Vec3) -> DVec3 {
+        self.origin + pos.as_dvec3()
+    }
+
+    /// Local position of the world position `pos`.
+    pub fn to_local(&self, pos: DVec3) -> Vec3 {
+        (pos - self.origin).as_vec3()
+    }
+
+    /// Rebases once the camera strayed `REBASE_DISTANCE` from the local origin.
+    pub fn update_origin(&mut self) {
+        if self.camera.pos.abs().max_element() >= REBASE_DISTANCE {
+            let shift = (self.camera.pos / REBASE_GRID).round() * REBASE_GRID;
+            self.rebase(shift);
+        }
+    }
+
+    /// Moves the local origin to `shift`: the camera, marks, terrain and everything else positioned in the scene
+    /// move by `-shift`, which leaves them where they were in the world.
+    pub fn rebase(&mut self, shift: Vec3) {
+        self.origin += shift.as_dvec3();
+        self.camera.rebase(shift);
+        self.marker.rebase(shift);
+        self.world.rebase(shift);
+        self.entities.rebase(shift);
+        self.waypoints.rebase(shift);
+        self.breadcrumbs.rebase(shift);
+        self.trail.rebase(shift);
+        self.museum.rebase(shift);
+        self.photo.rebase(shift);
+        self.beams.rebase(shift);
+        self.clip.rebase(shift);
+        self.selection.rebase(shift);
+        self.picker.rebase(shift);
+        self.minimap.invalidate();
+        self.gameplay.rebase(shift);
+        self.coverage.rebase(shift);
+        self.history.rebase(shift);
+        self.occlusion.clear();
+        info!("rebased local origin to {:.0}", self.origin);
+    }
+}
@@ -0,0 +1,517 @@
+use super::marker::{octree::Octree, Mark, MarkRaw, MarkTag};
+use super::settings::AutosaveSettings;
+use super::waypoints::Waypoint;
+use super::State;
+use glam::{vec3, DVec3, Vec3};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use tracing::warn;
+
+pub(crate) const SAVE_DIR: &str = "saves";
+const QUICKSAVE_NAME: &str = "quicksave.scan";
+const AUTOSAVE_PREFIX: &str = "autosave-";
+const SAVE_EXTENSION: &str = "scan";
+/// Extension of scan files still being written.
+const TMP_EXTENSION: &str = "tmp";
+
+const MAGIC: &[u8; 4] = b"SCAN";
+const VERSION: u32 = 4;
+/// Oldest version still readable; version 1 files have no waypoint section, version 2 files no origin and files
+/// before version 4 no scan time per mark.
+const MIN_VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 16;
+/// Longest waypoint name read from a file; waypoints are named by index, so a longer one means the file is corrupt.
+const MAX_WAYPOINT_NAME: usize = 1024;
+/// Seconds an autosave waits after a failed or skipped attempt before trying again, doubling with every further one
+/// up to the maximum.
+const MIN_RETRY_DELAY: f64 = 10.0;
+const MAX_RETRY_DELAY: f64 = 600.0;
+
+/// Contents of a scan file.
+pub struct Scan {
+    pub octree: Octree,
+    pub waypoints: Vec<Waypoint>,
+    /// World position the marks and waypoints are relative to.
+    pub origin: DVec3,
+}
+
+impl Scan {
+    /// Moves the marks and waypoints to be relative to `origin` instead. Each position is moved in double precision
+    /// and rounded once, so a shift between far apart origins costs no more precision than the new origin does.
+    pub fn relocate(&mut self, origin: DVec3) {
+        if origin == self.origin {
+            return;
+        }
+        let shift = self.origin - origin;
+        let moved = |pos: Vec3| (pos.as_dvec3() + shift).as_vec3();
+        let marks = self.octree.marks().map(|mark| Mark { pos: moved(mark.pos.into()), ..Mark::from(*mark) });
+        self.octree = Octree::from_marks(marks.collect());
+        for waypoint in &mut self.waypoints {
+            waypoint.pos = moved(waypoint.pos);
+        }
+        self.origin = origin;
+    }
+}
+
+/// Writes every mark in `octree` except miss marks to `path`: a small header (magic, version, mark count) followed by
+/// the raw marks, then the waypoint count and each waypoint as its position and length-prefixed UTF-8 name, and
+/// finally the world position of the local origin the positions are relative to, as three `f64`s.
+pub fn save_scan(octree: &Octree, waypoints: &[Waypoint], origin: DVec3, path: &Path) -> Result<(), String> {
+    let _span = tracing::info_span!("save_scan", marks = octree.count()).entered();
+    let count = saved_marks(octree).count();
+    write_atomically(path, |file| write_scan(file, count, saved_marks(octree), waypoints, origin))
+}
+
+fn write_scan<'a>(
+    file: &mut impl Write,
+    count: usize,
+    marks: impl Iterator<Item = &'a MarkRaw>,
+    waypoints: &[Waypoint],
+    origin: DVec3,
+) -> Result<(), String> {
+    file.write_all(MAGIC).map_err(|e| e.to_string())?;
+    file.write_all(&VERSION.to_le_bytes()).map_err(|e| e.to_string())?;
+    file.write_all(&(count as u64).to_le_bytes()).map_err(|e| e.to_string())?;
+    for mark in marks {
+        file.write_all(mark.stored_bytes()).map_err(|e| e.to_string())?;
+    }
+
+    file.write_all(&(waypoints.len() as u32).to_le_bytes()).map_err(|e| e.to_string())?;
+    for waypoint in waypoints {
+        file.write_all(bytemuck::cast_slice(&waypoint.pos.to_array())).map_err(|e| e.to_string())?;
+        file.write_all(&(waypoint.name.len() as u32).to_le_bytes()).map_err(|e| e.to_string())?;
+        file.write_all(waypoint.name.as_bytes()).map_err(|e| e.to_string())?;
+    }
+    file.write_all(bytemuck::cast_slice(&origin.to_array())).map_err(|e| e.to_string())
+}
+
+/// Writes a file through `write` into a temporary file next to `path`, which only replaces `path` once it is
+/// complete and on disk, so a crash or power loss mid-write leaves the previous file intact.
+fn write_atomically(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<(), String>) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+
+    let tmp = path.with_extension(TMP_EXTENSION);
+    let result = File::create(&tmp).map_err(|e| e.to_string()).and_then(|file| {
+        let mut file = BufWriter::new(file);
+        write(&mut file)?;
+        let file = file.into_inner().map_err(|e| e.to_string())?;
+        file.sync_all().map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, path).map_err(|e| e.to_string())
+    });
+    if result.is_err() {
+        _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Reads a file written by [`save_scan`] into a new octree.
+pub fn load_scan(path: &Path) -> Result<Scan, String> {
+    let _span = tracing::info_span!("load_scan").entered();
+    let mut file = std::io::BufReader::new(File::open(path).map_err(|e| e.to_string())?);
+
+    let mut header = [0; HEADER_SIZE as usize];
+    file.read_exact(&mut header).map_err(|e| e.to_string())?;
+    if &header[0..4] != MAGIC {
+        return Err(format!("{} is not a scan file", path.display()));
+    }
+    let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(format!("unsupported scan file version {}", version));
+    }
+    let count = u64::from_le_bytes(header[8..16].try_into().unwrap());
+
+    let mut stored = [0; MarkRaw::STORED_SIZE];
+    let stored = if version >= 4 { &mut stored[..] } else { &mut stored[..MarkRaw::UNTIMED_SIZE] };
+    // A corrupt count is only believed as far as the file could hold that many marks.
+    let len = file.get_ref().metadata().map_err(|e| e.to_string())?.len();
+    let mut marks = Vec::with_capacity(count.min(len.saturating_sub(HEADER_SIZE) / stored.len() as u64) as usize);
+    for _ in 0..count {
+        file.read_exact(stored).map_err(|e| e.to_string())?;
+        marks.push(Mark::from(MarkRaw::from_stored(stored)));
+    }
+    let octree = Octree::from_marks(marks);
+
+    let mut waypoints = Vec::new();
+    if version >= 2 {
+        let n_waypoints = read_u32(&mut file)?;
+        for _ in 0..n_waypoints {
+            let mut pos = [0.0_f32; 3];
+            file.read_exact(bytemuck::cast_slice_mut(&mut pos)).map_err(|e| e.to_string())?;
+            let name_len = read_u32(&mut file)? as usize;
+            if name_len > MAX_WAYPOINT_NAME {
+                return Err(format!("waypoint name of {} bytes in {}", name_len, path.display()));
+            }
+            let mut name = vec![0; name_len];
+            file.read_exact(&mut name).map_err(|e| e.to_string())?;
+            let name = String::from_utf8(name).map_err(|e| e.to_string())?;
+            waypoints.push(Waypoint { name, pos: vec3(pos[0], pos[1], pos[2]) });
+        }
+    }
+
+    let mut origin = [0.0_f64; 3];
+    if version >= 3 {
+        file.read_exact(bytemuck::cast_slice_mut(&mut origin)).map_err(|e| e.to_string())?;
+    }
+
+    Ok(Scan { octree, waypoints, origin: DVec3::from(origin) })
+}
+
+/// Marks that belong in a scan file; miss marks only mean something in the session that cast them.
+fn saved_marks(octree: &Octree) -> impl Iterator<Item = &MarkRaw> {
+    octree.marks().filter(|mark| mark.tag() != MarkTag::Miss)
+}
+
+fn read_u32(file: &mut impl Read) -> Result<u32, String> {
+    let mut bytes = [0; 4];
+    file.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Size in bytes of the file [`save_scan`] would write for `octree` and `waypoints`.
+pub fn scan_size(octree: &Octree, waypoints: &[Waypoint]) -> u64 {
+    let waypoints_size: usize = waypoints.iter().map(|waypoint| 16 + waypoint.name.len()).sum();
+    let origin_size = std::mem::size_of::<DVec3>();
+    HEADER_SIZE + (saved_marks(octree).count() * MarkRaw::STORED_SIZE + 4 + waypoints_size + origin_size) as u64
+}
+
+pub struct Autosave {
+    /// Seconds since the last attempt to save.
+    timer: f64,
+    saved_count: usize,
+    saved_waypoints: usize,
+    /// Marks in the scan at the last attempt to save it, whether it succeeded or not.
+    attempted_count: usize,
+    /// Seconds to wait after the last attempt before the next one, after failed or skipped attempts.
+    retry_delay: f64,
+    pending: Option<PendingSave>,
+}
+
+/// An autosave being written on a background thread.
+struct PendingSave {
+    writer: JoinHandle<Result<(), String>>,
+    count: usize,
+    waypoints: usize,
+}
+
+/// Copy of what goes into a scan file, for writing it off the main thread while scanning goes on.
+struct Snapshot {
+    marks: Vec<MarkRaw>,
+    waypoints: Vec<Waypoint>,
+    origin: DVec3,
+}
+
+impl Snapshot {
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let _span = tracing::info_span!("save_snapshot", marks = self.marks.len()).entered();
+        write_atomically(path, |file| {
+            write_scan(file, self.marks.len(), self.marks.iter(), &self.waypoints, self.origin)
+        })
+    }
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Autosave {
+    pub fn new() -> Self {
+        Self { timer: 0.0, saved_count: 0, saved_waypoints: 0, attempted_count: 0, retry_delay: 0.0, pending: None }
+    }
+
+    /// Advances the timer by `dt` and returns whether to attempt a save of a scan of `count` marks now: once
+    /// `interval_secs` passed or `mark_interval` marks were added or removed since the last attempt, and any retry
+    /// delay is over. A due attempt is recorded right away, so a failing one isn't repeated every frame.
+    fn attempt_due(&mut self, dt: f64, count: usize, settings: &AutosaveSettings) -> bool {
+        self.timer += dt;
+        let marks_due = settings.mark_interval > 0 && count.abs_diff(self.attempted_count) >= settings.mark_interval;
+        let due = self.timer >= settings.interval_secs || marks_due;
+        if !due || self.timer < self.retry_delay || self.pending.is_some() {
+            return false;
+        }
+        self.timer = 0.0;
+        self.attempted_count = count;
+        true
+    }
+
+    /// Waits longer before the next attempt after one failed or was skipped.
+    fn back_off(&mut self) {
+        self.retry_delay = (self.retry_delay * 2.0).clamp(MIN_RETRY_DELAY, MAX_RETRY_DELAY);
+    }
+
+    /// Records the scan as saved with `count` marks and `waypoints` waypoints.
+    fn saved(&mut self, count: usize, waypoints: usize) {
+        self.saved_count = count;
+        self.saved_waypoints = waypoints;
+        self.attempted_count = count;
+        self.retry_delay = 0.0;
+    }
+}
+
+impl State {
+    pub fn quicksave(&mut self) {
+        let path = Path::new(SAVE_DIR).join(QUICKSAVE_NAME);
+        match save_scan(&self.marker.octree, &self.waypoints.list, self.origin(), &path) {
+            Ok(()) => {
+                self.notify(format!("quicksaved {} marks", self.marker.octree.count()));
+                self.new_layer();
+            }
+            Err(e) => self.notify(format!("quicksave failed: {}", e)),
+        }
+    }
+
+    pub fn quickload(&mut self) {
+        let path = Path::new(SAVE_DIR).join(QUICKSAVE_NAME);
+        match load_scan(&path) {
+            Ok(mut scan) => {
+                scan.relocate(self.origin());
+                self.marker.octree = scan.octree;
+                self.reset_layers("quicksave");
+                self.playback.catch_up(self.marker.octree.marks().fold(0.0, |time, mark| mark.time.max(time)));
+                self.waypoints.set(scan.waypoints);
+                self.coverage.rebuild(&self.marker.octree);
+                self.minimap.invalidate();
+                self.autosave.saved(self.marker.octree.count(), self.waypoints.list.len());
+                self.notify(format!("quickloaded {} marks", self.marker.octree.count()));
+            }
+            Err(e) => self.notify(format!("quickload failed: {}", e)),
+        }
+    }
+
+    /// Saves the scan to a new timestamped autosave file every `interval_secs`, or sooner once `mark_interval` marks
+    /// were added or removed, if the scan changed since the last save, then prunes the oldest autosaves beyond the
+    /// retention count. The file is written on a background thread from a copy of the marks. After a failed or
+    /// skipped attempt the next one waits increasingly long.
+    pub fn update_autosave(&mut self, dt: f64) {
+        self.finish_autosave(false);
+        let settings = &self.settings.autosave;
+        let count = self.marker.octree.count();
+        if !settings.enabled || !self.autosave.attempt_due(dt, count, settings) {
+            return;
+        }
+
+        let n_waypoints = self.waypoints.list.len();
+        if count == self.autosave.saved_count && n_waypoints == self.autosave.saved_waypoints {
+            return;
+        }
+
+        let max_size = settings.max_size_mb.saturating_mul(1024 * 1024);
+        if scan_size(&self.marker.octree, &self.waypoints.list) > max_size {
+            self.autosave.back_off();
+            self.notify(format!("autosave skipped: scan exceeds {} MB", settings.max_size_mb));
+            return;
+        }
+
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let name = format!("{}{}.{}", AUTOSAVE_PREFIX, timestamp.as_millis(), SAVE_EXTENSION);
+        let path = Path::new(SAVE_DIR).join(name);
+
+        let snapshot = Snapshot {
+            marks: saved_marks(&self.marker.octree).copied().collect(),
+            waypoints: self.waypoints.list.clone(),
+            origin: self.origin(),
+        };
+        let writer = std::thread::spawn(move || snapshot.save(&path));
+        self.autosave.pending = Some(PendingSave { writer, count, waypoints: n_waypoints });
+    }
+
+    /// Reports the autosave being written in the background once it is done, or right away after waiting for it
+    /// with `wait`.
+    pub fn finish_autosave(&mut self, wait: bool) {
+        let Some(pending) = self.autosave.pending.take_if(|pending| wait || pending.writer.is_finished()) else {
+            return;
+        };
+        let result = pending.writer.join().unwrap_or_else(|_| Err("writer thread panicked".to_string()));
+        match result {
+            Ok(()) => {
+                self.autosave.saved(pending.count, pending.waypoints);
+                prune_autosaves(self.settings.autosave.retained);
+                self.notify(format!("autosaved {} marks", pending.count));
+            }
+            Err(e) => {
+                self.autosave.back_off();
+                self.notify(format!("autosave failed: {}", e));
+            }
+        }
+    }
+}
+
+/// Autosave files, oldest first.
+fn autosaves() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(SAVE_DIR) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            name.starts_with(AUTOSAVE_PREFIX) && name.ends_with(SAVE_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn prune_autosaves(retained: usize) {
+    let paths = autosaves();
+    for path in &paths[..paths.len().saturating_sub(retained)] {
+        if let Err(e) = std::fs::remove_file(path) {
+            warn!("failed to remove {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        load_scan, save_scan, write_scan, Autosave, Scan, Snapshot, HEADER_SIZE, MAGIC, MIN_RETRY_DELAY, VERSION,
+    };
+    use crate::marker::{octree::Octree, Mark, MarkRaw, MarkTag};
+    use crate::settings::AutosaveSettings;
+    use crate::waypoints::Waypoint;
+    use glam::{vec3, DVec3, Vec3};
+
+    #[test]
+    fn relocated_scans_round_each_position_once() {
+        let mark = Mark { time: 7.0, layer: 2, ..Mark::scanned(vec3(0.1, 2.0, -3.0), 1.0) };
+        let waypoint = Waypoint { name: "camp".to_string(), pos: vec3(0.1, 0.0, 0.0) };
+        let origin = DVec3::new(1e6 + 0.3, -20.0, 0.0);
+        let mut scan = Scan { octree: Octree::from_marks(vec![mark]), waypoints: vec![waypoint], origin };
+        scan.relocate(DVec3::ZERO);
+        // Shifting by the origin difference rounded to f32 first would land one f32 step further, 0.0625 more.
+        let marks: Vec<MarkRaw> = scan.octree.marks().copied().collect();
+        assert_eq!(marks.len(), 1);
+        assert_eq!(Vec3::from(marks[0].pos), vec3(1e6 + 0.375, -18.0, -3.0));
+        assert_eq!((marks[0].time, marks[0].layer, marks[0].color), (7.0, 2, mark.to_raw().color));
+        assert_eq!(scan.waypoints[0].pos, vec3(1e6 + 0.375, -20.0, 0.0));
+        assert_eq!(scan.origin, DVec3::ZERO);
+    }
+
+    #[test]
+    fn snapshot_replaces_the_previous_save_and_leaves_no_temporary_file() {
+        let dir = std::env::temp_dir().join(format!("scanner-autosave-test-{}", std::process::id()));
+        let path = dir.join("autosave.scan");
+        let waypoint = Waypoint { name: "camp".to_string(), pos: vec3(1.0, 2.0, 3.0) };
+        save_scan(&Octree::new(), &[], DVec3::ZERO, &path).unwrap();
+
+        let marks = vec![Mark::scanned(vec3(4.0, 5.0, 6.0), 1.0).to_raw(); 3];
+        let snapshot = Snapshot { marks, waypoints: vec![waypoint.clone()], origin: DVec3::new(1e9, 0.0, -2.5) };
+        snapshot.save(&path).unwrap();
+        let scan = load_scan(&path).unwrap();
+        let leftovers = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(scan.octree.count(), 3);
+        assert_eq!(scan.waypoints, vec![waypoint]);
+        assert_eq!(scan.origin, DVec3::new(1e9, 0.0, -2.5));
+        assert_eq!(leftovers, 1);
+    }
+
+    /// A mark as files before version 4 stored it: position, then color with the tag in the last byte.
+    fn untimed_mark(pos: [f32; 3], color: [u8; 4]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = bytemuck::cast_slice(&pos).to_vec();
+        bytes.extend_from_slice(&color);
+        assert_eq!(bytes.len(), MarkRaw::UNTIMED_SIZE);
+        bytes
+    }
+
+    #[test]
+    fn files_from_before_scan_times_load_their_untimed_marks() {
+        let path = std::env::temp_dir().join(format!("scanner-untimed-test-{}.scan", std::process::id()));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        bytes.extend(untimed_mark([1.0, 2.0, 3.0], [255, 128, 0, 255]));
+        bytes.extend(untimed_mark([-40.0, 0.5, 60.0], [10, 20, 30, 20]));
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(bytemuck::cast_slice(&[4.0f32, 5.0, 6.0]));
+        bytes.extend_from_slice(&4u32.to_le_bytes());
+        bytes.extend_from_slice(b"camp");
+        bytes.extend_from_slice(bytemuck::cast_slice(&[1e9f64, 0.0, -2.5]));
+        std::fs::write(&path, &bytes).unwrap();
+        let scan = load_scan(&path);
+        // Version 1 files end after the marks.
+        bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+        bytes.truncate(HEADER_SIZE as usize + 2 * MarkRaw::UNTIMED_SIZE);
+        std::fs::write(&path, &bytes).unwrap();
+        let first = load_scan(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let scan = scan.unwrap();
+        let mut marks: Vec<MarkRaw> = scan.octree.marks().copied().collect();
+        marks.sort_by(|a, b| f32::total_cmp(&a.pos[0], &b.pos[0]));
+        assert_eq!(marks.len(), 2);
+        assert_eq!(
+            (marks[0].pos, marks[0].color, marks[0].tag()),
+            ([-40.0, 0.5, 60.0], [10, 20, 30, 20], MarkTag::Surfel(20))
+        );
+        assert_eq!(
+            (marks[1].pos, marks[1].color, marks[1].tag()),
+            ([1.0, 2.0, 3.0], [255, 128, 0, 255], MarkTag::Surface)
+        );
+        assert!(marks.iter().all(|mark| mark.time == 0.0 && mark.layer == 0));
+        assert_eq!(scan.waypoints, vec![Waypoint { name: "camp".to_string(), pos: vec3(4.0, 5.0, 6.0) }]);
+        assert_eq!(scan.origin, DVec3::new(1e9, 0.0, -2.5));
+
+        let first = first.unwrap();
+        assert_eq!(first.octree.count(), 2);
+        assert!(first.waypoints.is_empty());
+        assert_eq!(first.origin, DVec3::ZERO);
+    }
+
+    #[test]
+    fn corrupt_mark_counts_fail_to_load_instead_of_allocating() {
+        let path = std::env::temp_dir().join(format!("scanner-count-test-{}.scan", std::process::id()));
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(1u64 << 62).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let result = load_scan(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn corrupt_waypoint_names_fail_to_load_instead_of_allocating() {
+        let path = std::env::temp_dir().join(format!("scanner-waypoint-test-{}.scan", std::process::id()));
+        let mut bytes = Vec::new();
+        write_scan(&mut bytes, 0, std::iter::empty(), &[], DVec3::ZERO).unwrap();
+        // Claim one waypoint with a name of almost 4 GiB in place of the empty waypoint list.
+        bytes.truncate(HEADER_SIZE as usize);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+        let result = load_scan(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err_and(|e| e.contains("waypoint name")));
+    }
+
+    #[test]
+    fn failed_autosaves_are_not_retried_every_frame() {
+        let settings = AutosaveSettings { mark_interval: 100, interval_secs: 300.0, ..Default::default() };
+        let mut autosave = Autosave::new();
+        assert!(!autosave.attempt_due(1.0, 50, &settings));
+        assert!(autosave.attempt_due(1.0, 150, &settings));
+
+        // The attempt failed: the marks since don't make the next one due, and neither does the interval until the
+        // retry delay is over too.
+        autosave.back_off();
+        assert!(!autosave.attempt_due(1.0, 150, &settings));
+        assert!(!autosave.attempt_due(MIN_RETRY_DELAY / 2.0, 260, &settings));
+        assert!(autosave.attempt_due(MIN_RETRY_DELAY / 2.0, 260, &settings));
+        autosave.back_off();
+        assert!(!autosave.attempt_due(MIN_RETRY_DELAY * 1.5, 400, &settings));
+        assert!(autosave.attempt_due(MIN_RETRY_DELAY / 2.0, 400, &settings));
+
+        autosave.saved(400, 0);
+        assert!(autosave.attempt_due(0.0, 500, &settings));
+        assert!(!autosave.attempt_due(0.0, 500, &settings));
+    }
+}
@@ -0,0 +1,236 @@
+use super::camera::Viewpoint;
+use super::export::EXPORT_DIR;
+use super::State;
+use glam::Vec3;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// High-resolution screenshots are rendered at between these multiples of the window size.
+const MIN_SCREENSHOT_SCALE: u32 = 2;
+const MAX_SCREENSHOT_SCALE: u32 = 4;
+
+/// Photo mode for framing shots of the scan: the simulation pauses, the camera detaches from the scanner and flies
+/// freely through the terrain, and the HUD and every other overlay are hidden. An optional vignette darkens the
+/// edges of the frame. Leaving photo mode puts the camera back where the scanner was.
+pub struct Photo {
+    pub active: bool,
+    pub vignette: bool,
+    saved: Option<Viewpoint>,
+    pipeline: wgpu::RenderPipeline,
+}
+
+impl Photo {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self { active: false, vignette: true, saved: None, pipeline: create_pipeline(device, format) }
+    }
+
+    /// Rebuilds the GPU resources on a new device, keeping the mode and the saved viewpoint.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        self.pipeline = create_pipeline(device, format);
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        if let Some(saved) = &mut self.saved {
+            saved.pos -= shift;
+        }
+    }
+}
+
+fn create_pipeline(device: &wgpu::Device, format: wgpu::TextureFormat) -> wgpu::RenderPipeline {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("photo.wgsl"));
+
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Vignette Pipeline Layout"),
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Vignette Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+        multiview: None,
+    })
+}
+
+/// Writes tightly packed 8-bit RGBA pixels to a PNG file.
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    writer.write_image_data(pixels).map_err(|e| e.to_string())
+}
+
+impl State {
+    pub fn toggle_photo_mode(&mut self) {
+        if self.photo.active {
+            self.photo.active = false;
+            if let Some(viewpoint) = self.photo.saved.take() {
+                self.camera.set_viewpoint(viewpoint);
+            }
+            self.notify("photo mode: off".to_string());
+            return;
+        }
+        if self.museum.active {
+            self.notify("photo mode: leave the museum first".to_string());
+            return;
+        }
+
+        self.photo.active = true;
+        self.photo.saved = Some(self.camera.viewpoint());
+        self.marker.should_cast = false;
+        self.editor.building = false;
+        self.notify("photo mode: on".to_string());
+    }
+
+    pub fn toggle_vignette(&mut self) {
+        self.photo.vignette = !self.photo.vignette;
+        self.notify(format!("vignette: {}", if self.photo.vignette { "on" } else { "off" }));
+    }
+
+    pub fn render_photo<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        if !self.photo.active || !self.photo.vignette {
+            return;
+        }
+        render_pass.set_pipeline(&self.photo.pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Renders the scene at a multiple of the window size to an offscreen target and writes it to a new timestamped
+    /// PNG file in the save directory. Overlays are left out, except for the vignette in photo mode.
+    pub fn take_screenshot(&mut self) {
+        let scale = self.screenshot_scale();
+        let (width, height) = (self.config.width * scale, self.config.height * scale);
+
+        self.occlusion.resize(&self.device, width, height);
+        self.marker.splatter.resize(&self.device, width, height);
+        self.prepare_splats();
+        let pixels = self.render_offscreen(width, height);
+        self.resize_scene();
+        self.prepare_splats();
+
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        let path = Path::new(EXPORT_DIR).join(format!("screenshot-{}.png", timestamp.as_millis()));
+        match pixels.and_then(|pixels| write_png(&path, width, height, &pixels)) {
+            Ok(()) => self.notify(format!("saved {}x{} screenshot to {}", width, height, path.display())),
+            Err(e) => self.notify(format!("screenshot failed: {}", e)),
+        }
+    }
+
+    /// The configured screenshot scale, lowered until the target and the splat frame buffer fit the device limits.
+    fn screenshot_scale(&self) -> u32 {
+        let limits = self.device.limits();
+        let mut scale = self.settings.display.screenshot_scale.clamp(MIN_SCREENSHOT_SCALE, MAX_SCREENSHOT_SCALE);
+        while scale > 1 {
+            let (width, height) = (self.config.width * scale, self.config.height * scale);
+            let fits_texture = width.max(height) <= limits.max_texture_dimension_2d;
+            let fits_splats = width as u64 * height as u64 * 4 <= limits.max_storage_buffer_binding_size as u64;
+            if fits_texture && fits_splats {
+                break;
+            }
+            scale -= 1;
+        }
+        scale
+    }
+
+    /// Renders one frame to a new `width` by `height` texture and reads it back as tightly packed RGBA pixels.
+    fn render_offscreen(&self, width: u32, height: u32) -> Result<Vec<u8>, String> {
+        let swap_red_blue = match self.config.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => return Err(format!("unsupported surface format {:?}", format)),
+        };
+
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Screenshot Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let row_bytes = width * 4;
+        let padded_row_bytes =
+            row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Screenshot Buffer"),
+            size: padded_row_bytes as u64 * height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder =
+            self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Screenshot Encoder") });
+        self.encode_scene(&mut encoder, &view, false);
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Screenshot Overlay Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: true },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.render_photo(&mut render_pass);
+        }
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: std::num::NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| _ = sender.send(result));
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+
+        let mut pixels = Vec::with_capacity((row_bytes * height) as usize);
+        for row in slice.get_mapped_range().chunks_exact(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        buffer.unmap();
+        if swap_red_blue {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Ok(pixels)
+    }
+}
@@ -0,0 +1,24 @@
+// Distance from the center, in half-screens, where the vignette starts and where it is darkest.
+let INNER_RADIUS: f32 = 0.55;
+let OUTER_RADIUS: f32 = 1.3;
+let STRENGTH: f32 = 0.85;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) offset: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.offset = uv * 2.0 - 1.0;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = smoothstep(INNER_RADIUS, OUTER_RADIUS, length(in.offset));
+    return vec4<f32>(0.0, 0.0, 0.0, falloff * STRENGTH);
+}
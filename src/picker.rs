@@ -0,0 +1,125 @@
+use super::lines::LineRenderer;
+use super::marker::Marker;
+use super::State;
+use glam::{vec4, Vec2, Vec3, Vec4};
+
+/// Pixels from the crosshair a mark may be to be picked.
+const PICK_TOLERANCE: f32 = 12.0;
+/// Farthest from the camera a mark is picked.
+const PICK_RANGE: f32 = 1000.0;
+/// Size of the hover highlight per unit of distance, so it stays the same size on screen.
+const HIGHLIGHT_SIZE: f32 = 0.015;
+/// How far in front of a picked mark waypoints are placed, so teleporting there does not land inside the surface.
+const WAYPOINT_STANDOFF: f32 = 5.0;
+
+const HIGHLIGHT_COLOR: Vec4 = vec4(1.0, 1.0, 0.4, 0.9);
+const MEASURE_COLOR: Vec4 = vec4(1.0, 1.0, 0.4, 0.6);
+
+/// Finds the mark nearest the crosshair, for tools that snap to existing marks, and measures between picked marks.
+pub struct Picker {
+    /// Mark under the crosshair this frame.
+    pub hovered: Option<Vec3>,
+    /// Marks the measurement runs between; the second follows the hovered mark until it is placed.
+    measure: Option<(Vec3, Option<Vec3>)>,
+    lines: LineRenderer,
+}
+
+impl Picker {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) -> Self {
+        Self {
+            hovered: None,
+            measure: None,
+            lines: LineRenderer::new(device, format, &marker.camera_bind_group_layout),
+        }
+    }
+
+    /// Rebuilds the highlight renderer on a new device, keeping the measurement.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) {
+        self.lines = LineRenderer::new(device, format, &marker.camera_bind_group_layout);
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        self.hovered = self.hovered.map(|pos| pos - shift);
+        self.measure = self.measure.map(|(start, end)| (start - shift, end.map(|end| end - shift)));
+    }
+
+    /// Queues a small octahedron around `pos`, scaled with its distance from `eye` so it keeps its size on screen.
+    fn push_highlight(&mut self, pos: Vec3, eye: Vec3, color: Vec4) {
+        let r = pos.distance(eye) * HIGHLIGHT_SIZE;
+        let points = [Vec3::X, Vec3::Y, Vec3::NEG_X, Vec3::NEG_Y, Vec3::Z, Vec3::NEG_Z].map(|axis| pos + axis * r);
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (3, 0), (0, 4), (1, 4), (2, 4), (3, 4), (0, 5), (1, 5), (2, 5), (3, 5)] {
+            self.lines.push(points[a], points[b], color);
+        }
+    }
+}
+
+impl State {
+    /// The drawn mark nearest the view ray within the pick tolerance of the crosshair.
+    pub fn pick_mark(&self) -> Option<Vec3> {
+        let ray = self.camera.cast_ray_at(Vec2::ZERO);
+        let tolerance = PICK_TOLERANCE * self.camera.pixel_spread(self.config.height);
+        let visibility = self.marker.visibility();
+        self.marker.octree.pick(ray, tolerance, PICK_RANGE, visibility).map(|mark| mark.pos.into())
+    }
+
+    /// Pins the start of a measurement to the hovered mark, then its end, then starts over.
+    pub fn measure(&mut self) {
+        let Some(hovered) = self.picker.hovered else {
+            self.notify("no mark under the crosshair".to_string());
+            return;
+        };
+        match self.picker.measure {
+            Some((start, None)) => {
+                self.picker.measure = Some((start, Some(hovered)));
+                self.notify(format!("distance: {:.2}", start.distance(hovered)));
+            }
+            _ => {
+                self.picker.measure = Some((hovered, None));
+                self.notify("measuring: pick the end mark".to_string());
+            }
+        }
+    }
+
+    pub fn clear_measurement(&mut self) {
+        if self.picker.measure.take().is_some() {
+            self.notify("measurement cleared".to_string());
+        }
+    }
+
+    /// Drops a waypoint just in front of the hovered mark.
+    pub fn drop_waypoint_at_mark(&mut self) {
+        let Some(hovered) = self.picker.hovered else {
+            self.notify("no mark under the crosshair".to_string());
+            return;
+        };
+        let back = (self.camera.pos - hovered).normalize_or_zero();
+        let standoff = f32::min(WAYPOINT_STANDOFF, self.camera.pos.distance(hovered));
+        self.add_waypoint(hovered + back * standoff);
+    }
+
+    /// Picks the mark under the crosshair and queues its highlight and the measurement. Nothing is highlighted while
+    /// scanning, when the crosshair is on fresh marks all the time.
+    pub fn update_picker(&mut self) {
+        self.picker.hovered = self.pick_mark();
+
+        let eye = self.camera.pos;
+        let picker = &mut self.picker;
+        let hovered = picker.hovered.filter(|_| !self.marker.should_cast);
+        if let Some(pos) = hovered {
+            picker.push_highlight(pos, eye, HIGHLIGHT_COLOR);
+        }
+        if let Some((start, end)) = picker.measure {
+            picker.push_highlight(start, eye, MEASURE_COLOR);
+            if let Some(end) = end.or(hovered) {
+                picker.lines.push(start, end, MEASURE_COLOR);
+                picker.push_highlight(end, eye, MEASURE_COLOR);
+            }
+        }
+        picker.lines.upload(&self.queue);
+    }
+
+    pub fn render_picker<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.picker.lines.draw(render_pass, &self.marker.camera_bind_group);
+    }
+}
@@ -0,0 +1,138 @@
+use super::State;
+
+/// A full replay takes about this many seconds, however long the scan took.
+const REPLAY_TIME: f32 = 20.0;
+/// Share of the scan each wheel step moves the scrubber by.
+const SCRUB_STEP: f32 = 0.01;
+
+/// Clock that stamps every mark with when it was scanned, and the scrubber that replays the scan in that order by
+/// only drawing the marks scanned before the scrub time.
+#[derive(Default)]
+pub struct Playback {
+    /// Seconds of scan time, which carries on from the latest mark of a loaded scan.
+    clock: f64,
+    /// Scan time up to which marks are drawn, while scrubbing.
+    pub scrub: Option<f32>,
+    pub playing: bool,
+}
+
+impl Playback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan time to stamp new marks with.
+    pub fn now(&self) -> f32 {
+        self.clock as f32
+    }
+
+    /// Moves the clock past `time`, so marks scanned from now on come after marks that were loaded.
+    pub fn catch_up(&mut self, time: f32) {
+        self.clock = self.clock.max(time as f64);
+    }
+
+    /// Scrub time as a share of the whole scan.
+    pub fn progress(&self) -> Option<f32> {
+        self.scrub.map(|scrub| scrub / self.now().max(f32::EPSILON))
+    }
+
+    /// Moves the scrubber by `steps` wheel steps and pauses the replay, returning whether it was scrubbing.
+    fn scrub_by(&mut self, steps: f32) -> bool {
+        let Some(scrub) = self.scrub else {
+            return false;
+        };
+        let end = self.now();
+        self.scrub = Some((scrub + steps * SCRUB_STEP * end).clamp(0.0, end));
+        self.playing = false;
+        true
+    }
+
+    /// Advances the scan clock by `dt` and, while playing, the replay, which stops at the end.
+    fn advance(&mut self, dt: f64) {
+        self.clock += dt;
+        if let (Some(scrub), true) = (self.scrub, self.playing) {
+            let end = self.now();
+            let speed = f32::max(end / REPLAY_TIME, 1.0);
+            let scrub = f32::min(scrub + dt as f32 * speed, end);
+            self.scrub = Some(scrub);
+            self.playing = scrub < end;
+        }
+    }
+}
+
+impl State {
+    /// Starts replaying the scan from its beginning, or shows every mark again.
+    pub fn toggle_playback(&mut self) {
+        let playback = &mut self.playback;
+        playback.scrub = match playback.scrub {
+            Some(_) => None,
+            None => Some(0.0),
+        };
+        playback.playing = playback.scrub.is_some();
+        let on = playback.playing;
+        self.marker.accumulator.clear();
+        self.notify(format!("scan playback: {}", if on { "on" } else { "off" }));
+    }
+
+    /// Pauses or resumes the replay, starting over once it reached the end.
+    pub fn toggle_playback_pause(&mut self) {
+        let playback = &mut self.playback;
+        let Some(scrub) = playback.scrub else {
+            return;
+        };
+        if scrub >= playback.now() {
+            playback.scrub = Some(0.0);
+        }
+        playback.playing = !playback.playing;
+    }
+
+    /// Moves the scrubber by `steps` wheel steps, pausing the replay.
+    pub fn scrub(&mut self, steps: f32) {
+        if self.playback.scrub_by(steps) {
+            self.marker.accumulator.clear();
+        }
+    }
+
+    /// Advances the scan clock and the replay, and hides the marks past the scrub time.
+    pub fn update_playback(&mut self, dt: f64) {
+        self.playback.advance(dt);
+        self.marker.set_time_limit(self.playback.scrub);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Playback, REPLAY_TIME};
+
+    #[test]
+    fn replays_run_to_the_end_and_scrubbing_pauses_within_the_scan() {
+        let mut playback = Playback::new();
+        playback.catch_up(100.0);
+        assert!(!playback.scrub_by(1.0));
+        assert_eq!(playback.progress(), None);
+
+        (playback.scrub, playback.playing) = (Some(0.0), true);
+        // A 100 second scan replays five times as fast, and the clock runs on behind the scrubber.
+        playback.advance(1.0);
+        assert_eq!(playback.scrub, Some(101.0 / REPLAY_TIME));
+        assert_eq!(playback.now(), 101.0);
+
+        assert!(playback.scrub_by(10.0));
+        assert!(!playback.playing);
+        let scrubbed = playback.scrub.unwrap();
+        assert!((scrubbed - (101.0 / REPLAY_TIME + 101.0 * 0.1)).abs() < 1e-4, "{}", scrubbed);
+        playback.advance(1.0);
+        assert_eq!(playback.scrub, Some(scrubbed));
+
+        assert!(playback.scrub_by(-1000.0));
+        assert_eq!(playback.progress(), Some(0.0));
+        assert!(playback.scrub_by(1000.0));
+        assert_eq!(playback.progress(), Some(1.0));
+
+        playback.scrub_by(-50.0);
+        playback.playing = true;
+        playback.advance(REPLAY_TIME as f64);
+        assert_eq!(playback.progress(), Some(1.0));
+        assert!(!playback.playing);
+    }
+}
@@ -0,0 +1,253 @@
+use super::State;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Frames whose timestamps can be in flight at once; a frame is left unprofiled if all of them are still being read.
+const READBACK_FRAMES: usize = 3;
+/// Weight of each new frame in the displayed timings, which would flicker too fast to read otherwise.
+const SMOOTHING: f32 = 0.1;
+
+/// Points in the frame a GPU timestamp is written at, in order.
+#[derive(Copy, Clone)]
+pub enum Stamp {
+    FrameStart,
+    SplatStart,
+    SplatEnd,
+    SceneEnd,
+    FrameEnd,
+}
+
+const STAMPS: usize = Stamp::FrameEnd as usize + 1;
+
+/// Phases of the frame shown in the HUD, each timed between two stamps: the splatting compute pass, the scene pass
+/// with the terrain occluders and marks, everything after it, and the whole frame.
+pub const PHASES: [(&str, Stamp, Stamp); 4] = [
+    ("hud.gpu_splat", Stamp::SplatStart, Stamp::SplatEnd),
+    ("hud.gpu_scene", Stamp::SplatEnd, Stamp::SceneEnd),
+    ("hud.gpu_post", Stamp::SceneEnd, Stamp::FrameEnd),
+    ("hud.gpu_total", Stamp::FrameStart, Stamp::FrameEnd),
+];
+
+struct Readback {
+    buffer: wgpu::Buffer,
+    /// Set from the map callback once the buffer can be read.
+    mapped: Arc<AtomicBool>,
+    pending: bool,
+    /// Stamps written in the frame being read back, one bit each.
+    written: u8,
+}
+
+/// GPU timestamp queries around the main render passes, read back a few frames late, next to the CPU time spent on
+/// each frame, to tell whether the GPU or the CPU holds the frame rate back. Only available on adapters that support
+/// timestamp queries.
+pub struct Profiler {
+    pub enabled: bool,
+    query_set: Option<wgpu::QuerySet>,
+    readbacks: Vec<Readback>,
+    /// Readback the current frame's stamps go to, if it is profiled.
+    current: Option<usize>,
+    written: Cell<u8>,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    cpu_start: Option<Instant>,
+    cpu_time: Duration,
+    /// Smoothed GPU time of each phase and CPU time per frame, in milliseconds.
+    pub gpu_ms: [Option<f32>; PHASES.len()],
+    pub cpu_ms: Option<f32>,
+    /// Unsmoothed CPU time of the last frame and GPU time of the last frame read back, in seconds, for the quality
+    /// governor. Neither counts the time spent waiting for the display.
+    pub cpu_frame: Option<f64>,
+    pub gpu_frame: Option<f64>,
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = (STAMPS * std::mem::size_of::<u64>()) as u64;
+        let query_set = device.features().contains(wgpu::Features::TIMESTAMP_QUERY).then(|| {
+            device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Profiler Query Set"),
+                ty: wgpu::QueryType::Timestamp,
+                count: STAMPS as u32,
+            })
+        });
+        let readbacks = (0..READBACK_FRAMES)
+            .map(|_| Readback {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Profiler Readback Buffer"),
+                    size,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                mapped: Arc::new(AtomicBool::new(false)),
+                pending: false,
+                written: 0,
+            })
+            .collect();
+
+        Self {
+            enabled: false,
+            query_set,
+            readbacks,
+            current: None,
+            written: Cell::new(0),
+            period: queue.get_timestamp_period(),
+            cpu_start: None,
+            cpu_time: Duration::ZERO,
+            gpu_ms: [None; PHASES.len()],
+            cpu_ms: None,
+            cpu_frame: None,
+            gpu_frame: None,
+        }
+    }
+
+    pub fn supported(&self) -> bool {
+        self.query_set.is_some()
+    }
+
+    /// Starts timing the CPU side of a frame.
+    pub fn begin_cpu(&mut self) {
+        self.cpu_time = Duration::ZERO;
+        self.cpu_start = Some(Instant::now());
+    }
+
+    /// Stops the CPU timer while the frame waits on something other than its own work, like the next surface texture.
+    pub fn pause_cpu(&mut self) {
+        if let Some(start) = self.cpu_start.take() {
+            self.cpu_time += start.elapsed();
+        }
+    }
+
+    pub fn resume_cpu(&mut self) {
+        self.cpu_start = Some(Instant::now());
+    }
+
+    pub fn end_cpu(&mut self) {
+        self.pause_cpu();
+        let seconds = self.cpu_time.as_secs_f64();
+        self.cpu_frame = Some(seconds);
+        if self.enabled {
+            self.cpu_ms = Some(smooth(self.cpu_ms, seconds as f32 * 1000.0));
+        }
+    }
+
+    /// Picks a readback for this frame's timestamps, leaving the frame unprofiled if none is free. Frames are timed
+    /// while the profiler is shown or `timed` asks for them anyway.
+    fn begin_frame(&mut self, timed: bool) {
+        self.written.set(0);
+        self.current = match self.query_set {
+            Some(_) if self.enabled || timed => self.readbacks.iter().position(|readback| !readback.pending),
+            _ => None,
+        };
+    }
+
+    /// Writes `stamp` into the current frame's timestamps, if it is profiled.
+    pub fn stamp(&self, encoder: &mut wgpu::CommandEncoder, stamp: Stamp) {
+        if let (Some(query_set), Some(_)) = (&self.query_set, self.current) {
+            encoder.write_timestamp(query_set, stamp as u32);
+            self.written.set(self.written.get() | 1 << stamp as u8);
+        }
+    }
+
+    /// Resolves the frame's timestamps into its readback buffer. Stamps the frame skipped are written here so no query
+    /// is resolved unwritten, and their phases are left out when read.
+    fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let (Some(query_set), Some(current)) = (&self.query_set, self.current) else {
+            return;
+        };
+        let written = self.written.get();
+        for stamp in 0..STAMPS as u32 {
+            if written & 1 << stamp == 0 {
+                encoder.write_timestamp(query_set, stamp);
+            }
+        }
+        let readback = &mut self.readbacks[current];
+        encoder.resolve_query_set(query_set, 0..STAMPS as u32, &readback.buffer, 0);
+        readback.written = written;
+    }
+
+    /// Starts mapping the frame's readback buffer; called once the frame is submitted.
+    fn map(&mut self) {
+        let Some(current) = self.current.take() else {
+            return;
+        };
+        let readback = &mut self.readbacks[current];
+        readback.pending = true;
+        let mapped = readback.mapped.clone();
+        // A failed mapping leaves the readback pending for good, which only happens with the device lost, and that
+        // recreates the profiler.
+        readback.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            mapped.store(result.is_ok(), Ordering::Release);
+        });
+    }
+
+    /// Reads the timestamps of frames whose readback buffers finished mapping into the displayed timings.
+    fn collect(&mut self) {
+        for readback in &mut self.readbacks {
+            if !readback.pending || !readback.mapped.swap(false, Ordering::Acquire) {
+                continue;
+            }
+            let ticks: Vec<u64> = bytemuck::cast_slice(&readback.buffer.slice(..).get_mapped_range()).to_vec();
+            readback.buffer.unmap();
+            readback.pending = false;
+
+            for ((_, from, to), ms) in PHASES.iter().zip(&mut self.gpu_ms) {
+                let (from, to) = (*from as usize, *to as usize);
+                if readback.written & (1 << from | 1 << to) != 1 << from | 1 << to {
+                    *ms = None;
+                    continue;
+                }
+                let elapsed = ticks[to].saturating_sub(ticks[from]) as f32 * self.period / 1e6;
+                *ms = Some(smooth(*ms, elapsed));
+            }
+            let (start, end) = (Stamp::FrameStart as usize, Stamp::FrameEnd as usize);
+            if readback.written & (1 << start | 1 << end) == 1 << start | 1 << end {
+                self.gpu_frame = Some(ticks[end].saturating_sub(ticks[start]) as f64 * self.period as f64 / 1e9);
+            }
+        }
+    }
+}
+
+fn smooth(average: Option<f32>, value: f32) -> f32 {
+    average.map_or(value, |average| average + (value - average) * SMOOTHING)
+}
+
+impl State {
+    pub fn toggle_profiler(&mut self) {
+        if !self.profiler.supported() {
+            self.notify("profiler: timestamp queries are not supported by this adapter".to_string());
+            return;
+        }
+        self.profiler.enabled = !self.profiler.enabled;
+        self.profiler.gpu_ms = [None; PHASES.len()];
+        self.profiler.cpu_ms = None;
+        self.notify(format!("profiler: {}", if self.profiler.enabled { "on" } else { "off" }));
+    }
+
+    /// Picks up finished timestamp readbacks.
+    pub fn update_profiler(&mut self) {
+        if self.profiler.readbacks.iter().any(|readback| readback.pending) {
+            self.device.poll(wgpu::Maintain::Poll);
+            self.profiler.collect();
+        }
+    }
+
+    /// Starts profiling the frame recorded into `encoder`.
+    pub fn begin_profile(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.profiler.begin_frame(self.governor.enabled);
+        self.profiler.stamp(encoder, Stamp::FrameStart);
+    }
+
+    /// Finishes the frame's timestamps before `encoder` is submitted.
+    pub fn resolve_profile(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.profiler.stamp(encoder, Stamp::FrameEnd);
+        self.profiler.resolve(encoder);
+    }
+
+    /// Starts reading the frame's timestamps back after it was submitted.
+    pub fn map_profile(&mut self) {
+        self.profiler.map();
+        self.profiler.end_cpu();
+    }
+}
@@ -0,0 +1,164 @@
+use super::State;
+
+/// Render scales allowed by the display settings.
+pub const MIN_RENDER_SCALE: f32 = 0.5;
+pub const MAX_RENDER_SCALE: f32 = 2.0;
+
+/// Renders the scene at a multiple of the window size: marks and terrain are drawn to an offscreen texture, which is
+/// then filtered onto the surface before the overlays, so the HUD stays sharp at any scale. Below 1 this trades
+/// sharpness for framerate on weak GPUs, above 1 it supersamples against aliasing. At exactly 1 the scene is drawn to
+/// the surface directly.
+pub struct Resampler {
+    scale: f32,
+    target: Option<Target>,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+struct Target {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Resampler {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, scale: f32) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("resample.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("resample_bind_group_layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Resample Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Resample Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: 1, mask: !0, alpha_to_coverage_enabled: false },
+            multiview: None,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Resample Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            scale: scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE),
+            target: None,
+            pipeline,
+            bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Recreates the offscreen target at `size`, or drops it if that is the surface size anyway.
+    fn resize(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, size: (u32, u32), surface: (u32, u32)) {
+        if size == surface {
+            self.target = None;
+            return;
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Scene Texture"),
+            size: wgpu::Extent3d { width: size.0, height: size.1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+            ],
+            label: Some("resample_bind_group"),
+        });
+        self.target = Some(Target { view, bind_group });
+    }
+
+    /// The offscreen texture the scene is drawn to, if it is not drawn to the surface directly.
+    pub fn target_view(&self) -> Option<&wgpu::TextureView> {
+        self.target.as_ref().map(|target| &target.view)
+    }
+}
+
+impl State {
+    /// Size the scene is rendered at: the window size times the render scale, kept within the device limits.
+    pub fn render_size(&self) -> (u32, u32) {
+        let (width, height) = (self.config.width as f32, self.config.height as f32);
+        let limits = self.device.limits();
+        let scale = self
+            .resampler
+            .scale
+            .min(limits.max_texture_dimension_2d as f32 / width.max(height))
+            .min((limits.max_storage_buffer_binding_size as f32 / (width * height * 4.0)).sqrt());
+        ((width * scale).round().max(1.0) as u32, (height * scale).round().max(1.0) as u32)
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.resampler.scale
+    }
+
+    /// Changes the render scale and refits the scene targets to it.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.resampler.scale = scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+        self.resize_scene();
+    }
+
+    /// Fits the depth buffer, splat frame, accumulation history, eye views and offscreen target to the render size.
+    pub fn resize_scene(&mut self) {
+        let (width, height) = self.render_size();
+        self.occlusion.resize(&self.device, width, height);
+        self.marker.splatter.resize(&self.device, width, height);
+        self.marker.accumulator.resize(&self.device, width, height);
+        self.anaglyph.resize(&self.device, self.config.format, width, height);
+        let surface = (self.config.width, self.config.height);
+        self.resampler.resize(&self.device, self.config.format, (width, height), surface);
+    }
+
+    /// Filters the offscreen scene onto the whole surface; a no-op when the scene was drawn there directly.
+    pub fn render_resampled<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let Some(target) = &self.resampler.target else {
+            return;
+        };
+        render_pass.set_pipeline(&self.resampler.pipeline);
+        render_pass.set_bind_group(0, &target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,599 @@
+use super::State;
+#[cfg(feature = "scripting")]
+use super::{
+    camera::Viewpoint,
+    marker::Mark,
+    util::Ray,
+    world::{Plane, Terrain},
+};
+#[cfg(feature = "scripting")]
+use glam::{Vec2, Vec3};
+#[cfg(feature = "scripting")]
+use rhai::{module_resolvers::DummyModuleResolver, Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Scope};
+#[cfg(feature = "scripting")]
+use rhai::{AST, FLOAT};
+#[cfg(feature = "scripting")]
+use std::{
+    cell::RefCell,
+    path::Path,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+#[cfg(feature = "scripting")]
+use tracing::{info, warn};
+
+/// Folder the `.rhai` scripts are loaded from.
+#[cfg(feature = "scripting")]
+const SCRIPT_DIR: &str = "scripts";
+#[cfg(feature = "scripting")]
+const SCRIPT_EXTENSION: &str = "rhai";
+/// Operations a script may run per callback before it is stopped, so an endless loop can't hang the app.
+#[cfg(feature = "scripting")]
+const MAX_OPERATIONS: u64 = 10_000_000;
+/// Time a script may run per callback before it is stopped, however cheap its operations.
+#[cfg(feature = "scripting")]
+const MAX_CALLBACK_TIME: Duration = Duration::from_millis(10);
+/// Operations between checks of the callback's time limit.
+#[cfg(feature = "scripting")]
+const TIME_CHECK_OPERATIONS: u64 = 1024;
+/// Deepest function calls may nest, so runaway recursion fails before it overflows the stack.
+#[cfg(feature = "scripting")]
+const MAX_CALL_LEVELS: usize = 32;
+/// Largest strings, arrays and object maps a script may build, so it can't exhaust memory.
+#[cfg(feature = "scripting")]
+const MAX_STRING_SIZE: usize = 1 << 20;
+#[cfg(feature = "scripting")]
+const MAX_ARRAY_SIZE: usize = 1 << 16;
+#[cfg(feature = "scripting")]
+const MAX_MAP_SIZE: usize = 1 << 16;
+/// Marks all scripts together may insert per frame.
+#[cfg(feature = "scripting")]
+const MAX_MARKS_PER_FRAME: usize = 10_000;
+
+/// What a script's callbacks may do. Scripts only read the scene until the user approves them, since the scripts
+/// folder may hold files shared by others.
+#[cfg(feature = "scripting")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Capability {
+    /// Read the camera and cast rays, move the camera and show notifications.
+    #[default]
+    ReadOnly,
+    /// Also insert and clear marks and take screenshots.
+    Modify,
+}
+
+/// What scripts see of the app during a callback, and what they asked of it.
+#[cfg(feature = "scripting")]
+#[derive(Default)]
+struct Frame {
+    viewpoint: Option<Viewpoint>,
+    view_dir: Vec3,
+    /// The terrain, lent to the scripts for the length of the callbacks so they can cast rays.
+    world: Option<Box<dyn Terrain>>,
+    commands: Vec<Command>,
+    /// What the running script may do.
+    capability: Capability,
+    /// Set when the running script called a function its capability doesn't allow.
+    denied: bool,
+    /// When the running callback is stopped, if it has a time limit.
+    deadline: Option<Instant>,
+    /// Marks inserted this frame, up to `MAX_MARKS_PER_FRAME`.
+    marks: usize,
+}
+
+#[cfg(feature = "scripting")]
+impl Frame {
+    fn insert_mark(&mut self, mark: Mark) -> Result<(), Box<EvalAltResult>> {
+        self.require_modify("insert_mark")?;
+        if self.marks == MAX_MARKS_PER_FRAME {
+            return Err(format!("more than {} marks inserted in one frame", MAX_MARKS_PER_FRAME).into());
+        }
+        self.marks += 1;
+        self.commands.push(Command::InsertMark(mark));
+        Ok(())
+    }
+
+    /// Fails a call to `function` unless the running script may change the scene.
+    fn require_modify(&mut self, function: &str) -> Result<(), Box<EvalAltResult>> {
+        if self.capability == Capability::Modify {
+            return Ok(());
+        }
+        self.denied = true;
+        Err(format!("{} needs the script to be approved", function).into())
+    }
+}
+
+/// Changes scripts asked for, applied once their callbacks returned.
+#[cfg(feature = "scripting")]
+enum Command {
+    InsertMark(Mark),
+    ClearRegion(Vec3, Vec3),
+    SetViewpoint(Viewpoint),
+    Screenshot,
+    Notify(String),
+}
+
+#[cfg(feature = "scripting")]
+struct Script {
+    name: String,
+    ast: AST,
+    scope: Scope<'static>,
+    /// The script's own state, reachable as `this` in its callbacks.
+    this: Dynamic,
+    /// Set after an error, so a broken script doesn't fail again every frame.
+    failed: bool,
+    /// Name and hash of the source, which approvals are stored under so an edited script needs approving again.
+    id: String,
+    capability: Capability,
+    /// Whether the script was stopped for calling a function it needs approval for.
+    wants_approval: bool,
+}
+
+/// Rhai scripts from the scripts folder that run every frame, for prototyping scan patterns and automated captures
+/// without recompiling. A script may define `on_load()`, called once, and `on_frame(dt)`; both can use `this` to keep
+/// state between calls. Positions are `[x, y, z]` arrays. Scripts can read `camera_pos()`, `camera_dir()`,
+/// `camera_yaw()` and `camera_pitch()`, query the terrain with `cast_ray(origin, dir, dist)`, and ask for
+/// `insert_mark(pos)` or `insert_mark(pos, color)`, `clear_region(a, b)`, `set_viewpoint(pos, yaw, pitch)`,
+/// `screenshot()` and `notify(text)`, which take effect once the callback returns. Without the `scripting` feature
+/// nothing is loaded.
+///
+/// Scripts run sandboxed: every callback is stopped after `MAX_OPERATIONS` operations or `MAX_CALLBACK_TIME`, strings,
+/// arrays, maps and call depth are bounded, `import` loads nothing, and at most `MAX_MARKS_PER_FRAME` marks are
+/// inserted per frame. `insert_mark`, `clear_region` and `screenshot` only work in scripts the user approved; a
+/// script calling them before is stopped until it is approved with Ctrl+Shift+F5.
+pub struct Scripting {
+    #[cfg(feature = "scripting")]
+    engine: Engine,
+    #[cfg(feature = "scripting")]
+    frame: Rc<RefCell<Frame>>,
+    #[cfg(feature = "scripting")]
+    scripts: Vec<Script>,
+}
+
+#[cfg(feature = "scripting")]
+fn to_vec3(array: Array) -> Result<Vec3, Box<EvalAltResult>> {
+    let number = |value: &Dynamic| value.as_float().or_else(|_| value.as_int().map(|int| int as FLOAT));
+    match array.as_slice() {
+        [x, y, z] => match (number(x), number(y), number(z)) {
+            (Ok(x), Ok(y), Ok(z)) => Ok(Vec3::new(x as f32, y as f32, z as f32)),
+            _ => Err("expected [x, y, z] numbers".into()),
+        },
+        _ => Err(format!("expected [x, y, z], got {} values", array.len()).into()),
+    }
+}
+
+/// FNV-1a hash of `bytes`, which unlike the standard hasher is stable across builds.
+#[cfg(feature = "scripting")]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+#[cfg(feature = "scripting")]
+fn from_vec3(v: Vec3) -> Array {
+    vec![Dynamic::from_float(v.x as FLOAT), Dynamic::from_float(v.y as FLOAT), Dynamic::from_float(v.z as FLOAT)]
+}
+
+/// An engine exposing the scripting API, working on `frame`.
+#[cfg(feature = "scripting")]
+fn create_engine(frame: &Rc<RefCell<Frame>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_ARRAY_SIZE);
+    engine.set_max_map_size(MAX_MAP_SIZE);
+    let f = frame.clone();
+    engine.on_progress(move |operations| {
+        let deadline = f.borrow().deadline?;
+        (operations % TIME_CHECK_OPERATIONS == 0 && Instant::now() > deadline).then_some(Dynamic::UNIT)
+    });
+    engine.on_print(|text| info!("script: {}", text));
+
+    let f = frame.clone();
+    engine.register_fn("camera_pos", move || from_vec3(f.borrow().viewpoint.map_or(Vec3::ZERO, |view| view.pos)));
+    let f = frame.clone();
+    engine.register_fn("camera_dir", move || from_vec3(f.borrow().view_dir));
+    let f = frame.clone();
+    engine.register_fn("camera_yaw", move || f.borrow().viewpoint.map_or(0.0, |view| view.yaw) as FLOAT);
+    let f = frame.clone();
+    engine.register_fn("camera_pitch", move || f.borrow().viewpoint.map_or(0.0, |view| view.pitch) as FLOAT);
+
+    // The first hit as `[x, y, z]`, or `()` if the ray hits nothing within `dist`.
+    let f = frame.clone();
+    engine.register_fn(
+        "cast_ray",
+        move |origin: Array, dir: Array, dist: FLOAT| -> Result<Dynamic, Box<EvalAltResult>> {
+            let ray = Ray { pos: to_vec3(origin)?, dir: to_vec3(dir)?.normalize_or_zero() };
+            if ray.dir == Vec3::ZERO {
+                return Err("cast_ray needs a nonzero direction".into());
+            }
+            let mut frame = f.borrow_mut();
+            let world = frame.world.as_mut().ok_or("cast_ray is only available in callbacks")?;
+            Ok(world.raycast(ray, dist as f32).map_or(Dynamic::UNIT, |hit| from_vec3(hit).into()))
+        },
+    );
+
+    // Marks without a color are colored by their distance from the camera, like scanned ones.
+    let f = frame.clone();
+    engine.register_fn("insert_mark", move |pos: Array| -> Result<(), Box<EvalAltResult>> {
+        let pos = to_vec3(pos)?;
+        let mut frame = f.borrow_mut();
+        let eye = frame.viewpoint.map_or(Vec3::ZERO, |view| view.pos);
+        frame.insert_mark(Mark::scanned(pos, pos.distance(eye)))
+    });
+    let f = frame.clone();
+    engine.register_fn("insert_mark", move |pos: Array, color: Array| -> Result<(), Box<EvalAltResult>> {
+        let mark = Mark::new(to_vec3(pos)?, to_vec3(color)?);
+        f.borrow_mut().insert_mark(mark)
+    });
+    let f = frame.clone();
+    engine.register_fn("clear_region", move |a: Array, b: Array| -> Result<(), Box<EvalAltResult>> {
+        let (a, b) = (to_vec3(a)?, to_vec3(b)?);
+        let mut frame = f.borrow_mut();
+        frame.require_modify("clear_region")?;
+        frame.commands.push(Command::ClearRegion(a.min(b), a.max(b)));
+        Ok(())
+    });
+
+    let f = frame.clone();
+    engine.register_fn(
+        "set_viewpoint",
+        move |pos: Array, yaw: FLOAT, pitch: FLOAT| -> Result<(), Box<EvalAltResult>> {
+            let viewpoint = Viewpoint { pos: to_vec3(pos)?, yaw: yaw as f32, pitch: pitch as f32 };
+            f.borrow_mut().commands.push(Command::SetViewpoint(viewpoint));
+            Ok(())
+        },
+    );
+    let f = frame.clone();
+    engine.register_fn("screenshot", move || -> Result<(), Box<EvalAltResult>> {
+        let mut frame = f.borrow_mut();
+        frame.require_modify("screenshot")?;
+        frame.commands.push(Command::Screenshot);
+        Ok(())
+    });
+    let f = frame.clone();
+    engine.register_fn("notify", move |text: &str| f.borrow_mut().commands.push(Command::Notify(text.to_string())));
+    engine
+}
+
+#[cfg(feature = "scripting")]
+impl Script {
+    /// Runs the script's callback `name` if it defines one, within its time limit and with its capability on
+    /// `frame`, marking the script failed on an error.
+    fn call(
+        &mut self,
+        engine: &Engine,
+        frame: &RefCell<Frame>,
+        name: &str,
+        args: impl rhai::FuncArgs,
+    ) -> Result<(), String> {
+        if self.failed || !self.ast.iter_functions().any(|function| function.name == name) {
+            return Ok(());
+        }
+        self.sandboxed(frame, name, |script| {
+            let options = CallFnOptions::new().eval_ast(false).rewind_scope(false).bind_this_ptr(&mut script.this);
+            engine.call_fn_with_options::<Dynamic>(options, &mut script.scope, &script.ast, name, args).map(|_| ())
+        })
+    }
+
+    /// Runs `run` on the script within its time limit and with its capability on `frame`, marking the script failed
+    /// on an error from `stage`.
+    fn sandboxed(
+        &mut self,
+        frame: &RefCell<Frame>,
+        stage: &str,
+        run: impl FnOnce(&mut Self) -> Result<(), Box<EvalAltResult>>,
+    ) -> Result<(), String> {
+        {
+            let mut frame = frame.borrow_mut();
+            frame.capability = self.capability;
+            frame.denied = false;
+            frame.deadline = Some(Instant::now() + MAX_CALLBACK_TIME);
+        }
+        let result = run(self);
+        let mut frame = frame.borrow_mut();
+        frame.deadline = None;
+        result.map_err(|e| {
+            self.failed = true;
+            self.wants_approval = frame.denied;
+            match *e {
+                EvalAltResult::ErrorTerminated(..) => {
+                    format!("script {} ran over {} ms in {}", self.name, MAX_CALLBACK_TIME.as_millis(), stage)
+                }
+                e => format!("script {} failed in {}: {}", self.name, stage, e),
+            }
+        })
+    }
+}
+
+impl Default for Scripting {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scripting {
+    pub fn new() -> Self {
+        #[cfg(feature = "scripting")]
+        {
+            let frame = Rc::new(RefCell::new(Frame::default()));
+            Self { engine: create_engine(&frame), frame, scripts: Vec::new() }
+        }
+        #[cfg(not(feature = "scripting"))]
+        Self {}
+    }
+}
+
+/// Compiles the scripts in `dir` and runs their top level on `frame`; scripts that fail to compile are skipped. Scripts
+/// whose id is in `approved` may change the scene.
+#[cfg(feature = "scripting")]
+fn load_scripts(engine: &Engine, frame: &RefCell<Frame>, dir: &Path, approved: &[String]) -> Vec<Script> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == SCRIPT_EXTENSION))
+        .collect();
+    paths.sort();
+
+    let mut scripts = Vec::new();
+    for path in paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                warn!("skipping script {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let ast = match engine.compile(&source) {
+            Ok(ast) => ast,
+            Err(e) => {
+                warn!("skipping script {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let id = format!("{}:{:016x}", name, fnv1a(source.as_bytes()));
+        let capability = if approved.contains(&id) { Capability::Modify } else { Capability::ReadOnly };
+        let mut script = Script {
+            name,
+            ast,
+            scope: Scope::new(),
+            this: Map::new().into(),
+            failed: false,
+            id,
+            capability,
+            wants_approval: false,
+        };
+        match script
+            .sandboxed(frame, "its top level", |script| engine.run_ast_with_scope(&mut script.scope, &script.ast))
+        {
+            Ok(()) => info!("loaded script {}", script.name),
+            Err(e) => warn!("{}", e),
+        }
+        scripts.push(script);
+    }
+    scripts
+}
+
+impl State {
+    /// Compiles every script in the scripts folder, in file name order, and calls their `on_load()`.
+    pub fn load_scripts(&mut self) {
+        #[cfg(feature = "scripting")]
+        {
+            let scripting = &mut self.scripting;
+            let approved = &self.settings.scripting.approved;
+            scripting.scripts = load_scripts(&scripting.engine, &scripting.frame, Path::new(SCRIPT_DIR), approved);
+            self.run_scripts("on_load", ());
+        }
+    }
+
+    /// Loads the scripts folder again, e.g. after editing a script.
+    pub fn reload_scripts(&mut self) {
+        #[cfg(feature = "scripting")]
+        {
+            self.load_scripts();
+            self.notify(format!("loaded {} scripts", self.scripting.scripts.len()));
+        }
+        #[cfg(not(feature = "scripting"))]
+        self.notify("scripting is not available in this build".to_string());
+    }
+
+    /// Lets the scripts stopped for changing the scene do so from now on, remembering the approval in the settings
+    /// until the script is edited.
+    pub fn approve_scripts(&mut self) {
+        #[cfg(feature = "scripting")]
+        {
+            let mut approved = Vec::new();
+            for script in self.scripting.scripts.iter_mut().filter(|script| script.wants_approval) {
+                script.capability = Capability::Modify;
+                script.wants_approval = false;
+                script.failed = false;
+                approved.push(script.name.clone());
+                if !self.settings.scripting.approved.contains(&script.id) {
+                    self.settings.scripting.approved.push(script.id.clone());
+                }
+            }
+            if approved.is_empty() {
+                self.notify("no scripts are waiting for approval".to_string());
+                return;
+            }
+            match self.settings.save() {
+                Ok(()) => self.notify(format!("approved {}", approved.join(", "))),
+                Err(e) => self.notify(format!("approved {} for this run only: {}", approved.join(", "), e)),
+            }
+        }
+        #[cfg(not(feature = "scripting"))]
+        self.notify("scripting is not available in this build".to_string());
+    }
+
+    /// Calls every script's `on_frame(dt)` and applies what the scripts asked for.
+    pub fn update_scripts(&mut self, dt: f64) {
+        #[cfg(feature = "scripting")]
+        self.run_scripts("on_frame", (dt as FLOAT,));
+        #[cfg(not(feature = "scripting"))]
+        let _ = dt;
+    }
+
+    /// Calls the callback `name` of every script with the terrain lent to them, then applies their commands.
+    #[cfg(feature = "scripting")]
+    fn run_scripts(&mut self, name: &str, args: impl rhai::FuncArgs + Clone) {
+        if self.scripting.scripts.is_empty() {
+            return;
+        }
+        {
+            let mut frame = self.scripting.frame.borrow_mut();
+            frame.viewpoint = Some(self.camera.viewpoint());
+            frame.view_dir = self.camera.cast_ray_at(Vec2::ZERO).dir;
+            frame.world = Some(std::mem::replace(&mut self.world, Box::new(Plane::new())));
+            frame.marks = 0;
+        }
+        let scripting = &mut self.scripting;
+        let mut waiting = Vec::new();
+        let mut errors = Vec::new();
+        for script in &mut scripting.scripts {
+            if let Err(e) = script.call(&scripting.engine, &scripting.frame, name, args.clone()) {
+                errors.push(e);
+                if script.wants_approval {
+                    waiting.push(script.name.clone());
+                }
+            }
+        }
+        let commands = {
+            let mut frame = scripting.frame.borrow_mut();
+            self.world = frame.world.take().unwrap();
+            std::mem::take(&mut frame.commands)
+        };
+
+        for error in errors {
+            warn!("{}", error);
+            self.notify(error);
+        }
+        if !waiting.is_empty() {
+            self.notify(format!("{} wants to change the scene: Ctrl+Shift+F5 approves", waiting.join(", ")));
+        }
+        let mut cleared = false;
+        for command in commands {
+            match command {
+                Command::InsertMark(mark) => {
+                    let mark = self.stamp(mark);
+                    self.marker.octree.insert(mark);
+                    self.coverage.record(mark.pos);
+                    self.minimap.record(mark.pos);
+                }
+                Command::ClearRegion(min, max) => {
+                    self.erase_marks(min, max);
+                    cleared = true;
+                }
+                Command::SetViewpoint(viewpoint) => self.camera.set_viewpoint(viewpoint),
+                Command::Screenshot => self.take_screenshot(),
+                Command::Notify(text) => self.notify(text),
+            }
+        }
+        if cleared {
+            self.coverage.rebuild(&self.marker.octree);
+            self.minimap.invalidate();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "scripting"))]
+mod tests {
+    use super::{create_engine, Capability, Command, Frame, Script, MAX_MARKS_PER_FRAME};
+    use crate::camera::Viewpoint;
+    use crate::util::Ray;
+    use crate::world::{Plane, Terrain};
+    use glam::Vec3;
+    use rhai::{Dynamic, Engine, Map, Scope, FLOAT};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn script(engine: &Engine, source: &str, capability: Capability) -> Script {
+        Script {
+            name: "test".to_string(),
+            ast: engine.compile(source).unwrap(),
+            scope: Scope::new(),
+            this: Map::new().into(),
+            failed: false,
+            id: String::new(),
+            capability,
+            wants_approval: false,
+        }
+    }
+
+    #[test]
+    fn scripts_queue_commands_and_cast_rays_against_the_lent_terrain() {
+        let frame = Rc::new(RefCell::new(Frame::default()));
+        let engine = create_engine(&frame);
+        let source = r#"
+            fn on_frame(dt) {
+                this.frames += 1;
+                let hit = cast_ray(camera_pos(), [0, -1, 0], 1000.0);
+                insert_mark(hit, [1, 0, 0]);
+                if cast_ray(camera_pos(), [0, 1, 0], 1000.0) == () {
+                    clear_region([1, 2, 3], [-1, -2, -3]);
+                }
+                notify(`frame ${this.frames}`);
+            }
+        "#;
+        let mut script = script(&engine, source, Capability::Modify);
+        script.this = Map::from_iter([("frames".into(), Dynamic::from_int(0))]).into();
+
+        frame.borrow_mut().viewpoint = Some(Viewpoint { pos: Vec3::new(5.0, 0.0, 7.0), yaw: 0.0, pitch: 0.0 });
+        frame.borrow_mut().world = Some(Box::new(Plane::new()));
+        for _ in 0..2 {
+            script.call(&engine, &frame, "on_frame", (0.1 as FLOAT,)).unwrap();
+        }
+        let hit = Plane::new().raycast(Ray { pos: Vec3::new(5.0, 0.0, 7.0), dir: Vec3::NEG_Y }, 1000.0).unwrap();
+        let commands = std::mem::take(&mut frame.borrow_mut().commands);
+        assert_eq!(commands.len(), 6);
+        assert!(matches!(commands[0], Command::InsertMark(mark) if mark.pos == hit && mark.color == Vec3::X));
+        assert!(
+            matches!(commands[1], Command::ClearRegion(min, max) if min == -max && max == Vec3::new(1.0, 2.0, 3.0))
+        );
+        assert!(matches!(&commands[5], Command::Notify(text) if text == "frame 2"));
+
+        frame.borrow_mut().world = None;
+        assert!(script.call(&engine, &frame, "on_frame", (0.1 as FLOAT,)).is_err());
+        assert!(script.call(&engine, &frame, "on_frame", (0.1 as FLOAT,)).is_ok(), "a failed script is not run again");
+    }
+
+    #[test]
+    fn sandboxed_scripts_are_stopped_at_their_limits() {
+        let frame = Rc::new(RefCell::new(Frame::default()));
+        let engine = create_engine(&frame);
+        let run = |source: &str, capability| {
+            let mut script = script(&engine, source, capability);
+            let result = script.call(&engine, &frame, "on_frame", (0.1 as FLOAT,));
+            (result, script.wants_approval)
+        };
+
+        let (result, _) = run("fn on_frame(dt) { loop { let x = dt * 2.0; } }", Capability::Modify);
+        assert!(result.unwrap_err().contains("ran over"));
+        let (result, _) = run("fn on_frame(dt) { on_frame(dt) }", Capability::Modify);
+        assert!(result.is_err());
+        let (result, _) = run("fn on_frame(dt) { let a = []; a.pad(100000, 0); }", Capability::Modify);
+        assert!(result.is_err());
+        let (result, _) = run("fn on_frame(dt) { let s = \"x\"; for i in 0..30 { s += s; } }", Capability::Modify);
+        assert!(result.is_err());
+        let module = std::env::temp_dir().join(format!("scanner-script-test-{}.rhai", std::process::id()));
+        std::fs::write(&module, "export const SECRET = 1;").unwrap();
+        let imported = engine.run(&format!("import {:?} as m; print(m::SECRET);", module.with_extension("")));
+        std::fs::remove_file(&module).unwrap();
+        assert!(imported.is_err(), "imports load nothing");
+
+        // Read-only scripts can look, but are stopped and flagged for approval when they change the scene.
+        let (result, wants_approval) = run("fn on_frame(dt) { notify(`${camera_pos()}`); }", Capability::ReadOnly);
+        assert!(result.is_ok() && !wants_approval);
+        let (result, wants_approval) = run("fn on_frame(dt) { insert_mark([0, 0, 0]); }", Capability::ReadOnly);
+        assert!(result.is_err() && wants_approval);
+        assert_eq!(frame.borrow().marks, 0);
+
+        frame.borrow_mut().marks = MAX_MARKS_PER_FRAME - 5;
+        let (result, _) = run("fn on_frame(dt) { for i in 0..10 { insert_mark([0, 0, 0]); } }", Capability::Modify);
+        assert!(result.unwrap_err().contains("marks inserted in one frame"));
+        assert_eq!(frame.borrow().marks, MAX_MARKS_PER_FRAME);
+    }
+}
@@ -0,0 +1,152 @@
+use super::lines::LineRenderer;
+use super::marker::Marker;
+use super::State;
+use glam::{vec3, vec4, BVec3, Vec2, Vec3, Vec4};
+
+/// Farthest the aim ray looks for terrain to place a corner on.
+const SELECT_RANGE: f32 = 500.0;
+/// Where a corner goes along the aim ray when it hits no terrain, e.g. over imported points.
+const SELECT_DISTANCE: f32 = 30.0;
+/// Added around the corners on every side, so a box spanned by two points on the same floor still has some height.
+const SELECT_PADDING: f32 = 1.0;
+
+/// Colors marks are painted with, one per press in turn.
+const PAINT_COLORS: [Vec3; 4] =
+    [vec3(1.0, 0.35, 0.35), vec3(1.0, 0.85, 0.2), vec3(0.35, 1.0, 0.45), vec3(0.75, 0.45, 1.0)];
+
+const PLACING_COLOR: Vec4 = vec4(1.0, 1.0, 1.0, 0.4);
+const SELECTED_COLOR: Vec4 = vec4(1.0, 1.0, 1.0, 0.9);
+
+/// A box spanned by two corners placed with the aim ray, which marks can be exported from, deleted from or painted
+/// in. Only the LAS export of the marks is limited to it; the trail and the terrain are exported whole.
+pub struct Selection {
+    first: Option<Vec3>,
+    second: Option<Vec3>,
+    /// Where the second corner would go, while the first one is placed.
+    aim: Option<Vec3>,
+    next_color: usize,
+    lines: LineRenderer,
+}
+
+impl Selection {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) -> Self {
+        Self {
+            first: None,
+            second: None,
+            aim: None,
+            next_color: 0,
+            lines: LineRenderer::new(device, format, &marker.camera_bind_group_layout),
+        }
+    }
+
+    /// Rebuilds the box renderer on a new device, keeping the selection.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) {
+        self.lines = LineRenderer::new(device, format, &marker.camera_bind_group_layout);
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        for corner in [&mut self.first, &mut self.second, &mut self.aim].into_iter().flatten() {
+            *corner -= shift;
+        }
+    }
+
+    /// The `(min, max)` corners of the selected box, padded on every side, once both corners are placed.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        Some(padded_box(self.first?, self.second?))
+    }
+
+    fn push_box(&mut self, (min, max): (Vec3, Vec3), color: Vec4) {
+        let corner = |i: usize| Vec3::select(BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0), max, min);
+        for i in 0..8 {
+            for axis in [1, 2, 4] {
+                if i & axis == 0 {
+                    self.lines.push(corner(i), corner(i | axis), color);
+                }
+            }
+        }
+    }
+}
+
+fn padded_box(a: Vec3, b: Vec3) -> (Vec3, Vec3) {
+    (a.min(b) - SELECT_PADDING, a.max(b) + SELECT_PADDING)
+}
+
+impl State {
+    /// Places a corner of the selection on the mark under the crosshair or where the aim ray meets the terrain. The
+    /// first press starts a new box and the second completes it.
+    pub fn place_selection_corner(&mut self) {
+        let corner = self.selection_aim();
+        let selection = &mut self.selection;
+        if selection.first.is_none() || selection.second.is_some() {
+            selection.first = Some(corner);
+            selection.second = None;
+            self.notify("selection: place the opposite corner".to_string());
+        } else {
+            selection.second = Some(corner);
+            let (min, max) = selection.bounds().unwrap();
+            let count = self.marker.octree.marks_in(min, max).len();
+            self.notify(format!("selected {} marks", count));
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        let selection = &mut self.selection;
+        if selection.first.take().is_some() {
+            selection.second = None;
+            self.notify("selection cleared".to_string());
+        }
+    }
+
+    pub fn delete_selection(&mut self) {
+        let Some((min, max)) = self.selection.bounds() else {
+            self.notify("nothing selected".to_string());
+            return;
+        };
+        let removed = self.erase_marks(min, max);
+        self.coverage.rebuild(&self.marker.octree);
+        self.minimap.invalidate();
+        self.notify(format!("deleted {} marks", removed));
+    }
+
+    /// Paints the selected marks in the next of the paint colors.
+    pub fn paint_selection(&mut self) {
+        let Some((min, max)) = self.selection.bounds() else {
+            self.notify("nothing selected".to_string());
+            return;
+        };
+        let selection = &mut self.selection;
+        let color = PAINT_COLORS[selection.next_color];
+        selection.next_color = (selection.next_color + 1) % PAINT_COLORS.len();
+        let painted = self.paint_marks(min, max, color);
+        self.minimap.invalidate();
+        self.notify(format!("painted {} marks", painted));
+    }
+
+    /// The mark under the crosshair, or else where the aim ray meets the terrain.
+    fn selection_aim(&mut self) -> Vec3 {
+        if let Some(pos) = self.picker.hovered {
+            return pos;
+        }
+        let ray = self.camera.cast_ray_at(Vec2::ZERO);
+        self.world.raycast(ray, SELECT_RANGE).unwrap_or(ray.pos + ray.dir * SELECT_DISTANCE)
+    }
+
+    /// Follows the aim with the unfinished box and queues the box outline.
+    pub fn update_selection(&mut self) {
+        let placing = self.selection.first.is_some() && self.selection.second.is_none();
+        self.selection.aim = placing.then(|| self.selection_aim());
+
+        let selection = &mut self.selection;
+        if let Some(bounds) = selection.bounds() {
+            selection.push_box(bounds, SELECTED_COLOR);
+        } else if let (Some(first), Some(aim)) = (selection.first, selection.aim) {
+            selection.push_box(padded_box(first, aim), PLACING_COLOR);
+        }
+        selection.lines.upload(&self.queue);
+    }
+
+    pub fn render_selection<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.selection.lines.draw(render_pass, &self.marker.camera_bind_group);
+    }
+}
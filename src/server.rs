@@ -0,0 +1,288 @@
+use super::marker::{octree::Octree, Mark, MarkRaw};
+use super::net::{
+    decode_packet, encode_packet, player_color, random_id, Header, RateLimit, SequenceWindow, HEARTBEAT_TIME,
+    MARKS_PER_PACKET, MAX_PACKETS_PER_SECOND, MAX_PACKET_SIZE, PEER_TIMEOUT, SHARED_PLAYER,
+};
+use super::persistence::{load_scan, save_scan};
+use super::world::{Terrain, TerrainKind, SURFACE_THRESHOLD};
+use glam::{DVec3, Vec3};
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+pub const DEFAULT_PORT: u16 = 7777;
+pub const DEFAULT_SAVE_PATH: &str = "saves/server.scan";
+
+/// How long the server waits for a packet before doing its periodic work.
+const TICK: Duration = Duration::from_millis(5);
+const SAVE_INTERVAL: f64 = 60.0;
+/// Packets accepted from each client per second; the rest are dropped unread.
+const MAX_CLIENT_PACKETS_PER_SECOND: f64 = 2.0 * MAX_PACKETS_PER_SECOND;
+/// Distance from a mark within which the terrain density has to cross the surface for the mark to be kept. Half a
+/// voxel, since the marching-cubes surface only approximates the density field.
+const SURFACE_TOLERANCE: f32 = 2.5;
+/// Clients beyond this many are ignored until one times out.
+const MAX_CLIENTS: usize = 64;
+
+/// Options of `scanner server`.
+pub struct ServerOptions {
+    pub port: u16,
+    /// Where the shared scan is loaded from on start and saved to while running.
+    pub save: PathBuf,
+    pub terrain: TerrainKind,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self { port: DEFAULT_PORT, save: DEFAULT_SAVE_PATH.into(), terrain: TerrainKind::Caves }
+    }
+}
+
+struct Client {
+    addr: SocketAddr,
+    /// Id the client sends its own marks under.
+    player: u32,
+    /// Token picked for the client, see [`Header::token`].
+    token: u32,
+    /// Last token the client picked for us.
+    echo: u32,
+    /// Echoed our token, so it is really at `addr` and is sent the scan and other players' marks.
+    verified: bool,
+    sequence: SequenceWindow,
+    next_sequence: u32,
+    last_heard: f64,
+    /// Incoming packet allowance.
+    incoming: RateLimit,
+    /// Outgoing packet allowance, spent on the backlog.
+    outgoing: RateLimit,
+    /// Marks of the shared scan the client has not been sent yet.
+    backlog: VecDeque<MarkRaw>,
+}
+
+impl Client {
+    fn send(&mut self, socket: &UdpSocket, sender: u32, player: u32, origin: DVec3, marks: &[MarkRaw]) {
+        let header =
+            Header { sender, sequence: self.next_sequence, player, token: self.token, echo: self.echo, origin };
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        if let Err(e) = socket.send_to(&encode_packet(header, marks), self.addr) {
+            warn!("failed to send to {}: {}", self.addr, e);
+        }
+    }
+}
+
+/// Authoritative host of a shared scan. Clients connect with `--peer`; the server checks each mark they send against
+/// its own copy of the terrain, stores it, relays it to the other clients and sends newcomers the whole scan so far.
+/// Newcomers only get handshake packets until they echo one back, so the scan is never streamed to a spoofed address.
+/// Needs no window or GPU.
+struct Server {
+    socket: UdpSocket,
+    id: u32,
+    world: Box<dyn Terrain>,
+    octree: Octree,
+    clients: Vec<Client>,
+    save: PathBuf,
+    saved_count: usize,
+    rejected: u64,
+    start: Instant,
+}
+
+impl Server {
+    fn new(options: &ServerOptions) -> Result<Self, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", options.port))
+            .map_err(|e| format!("failed to bind port {}: {}", options.port, e))?;
+        socket.set_read_timeout(Some(TICK)).map_err(|e| e.to_string())?;
+
+        let octree = if options.save.exists() {
+            // Marks are shared in world coordinates, so the server keeps its origin at the world origin.
+            let mut scan = load_scan(&options.save)?;
+            scan.relocate(DVec3::ZERO);
+            let octree = scan.octree;
+            info!("loaded {} marks from {}", octree.count(), options.save.display());
+            octree
+        } else {
+            Octree::new()
+        };
+
+        Ok(Self {
+            socket,
+            id: random_id(),
+            world: options.terrain.create()?,
+            saved_count: octree.count(),
+            octree,
+            clients: Vec::new(),
+            save: options.save.clone(),
+            rejected: 0,
+            start: Instant::now(),
+        })
+    }
+
+    fn time(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    /// Whether the terrain surface passes within [`SURFACE_TOLERANCE`] of `pos`. Terrains need not read every
+    /// coordinate, so positions that are not finite are rejected up front.
+    fn on_surface(&self, pos: Vec3) -> bool {
+        if !pos.is_finite() {
+            return false;
+        }
+        let offsets = [Vec3::ZERO, Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+        let inside =
+            offsets.map(|offset| self.world.surface_level(pos + offset * SURFACE_TOLERANCE) >= SURFACE_THRESHOLD);
+        inside.contains(&true) && inside.contains(&false)
+    }
+
+    /// Handles one incoming packet, returning `false` if the socket had nothing to read.
+    fn receive(&mut self, buf: &mut [u8]) -> Result<bool, String> {
+        let (len, addr) = match self.socket.recv_from(buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                return Ok(false)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => return Ok(true),
+            Err(e) => return Err(e.to_string()),
+        };
+        let Some((header, marks)) = decode_packet(&buf[..len]) else {
+            return Ok(true);
+        };
+        // Clients only send their own marks; anything else would let them pose as another player.
+        if header.player != header.sender || header.player == SHARED_PLAYER {
+            return Ok(true);
+        }
+
+        let time = self.time();
+        let index = match self.clients.iter().position(|client| client.addr == addr) {
+            Some(index) => index,
+            None if self.clients.len() >= MAX_CLIENTS => return Ok(true),
+            None => {
+                self.clients.push(Client {
+                    addr,
+                    player: header.player,
+                    token: random_id(),
+                    echo: 0,
+                    verified: false,
+                    sequence: SequenceWindow::default(),
+                    next_sequence: 0,
+                    last_heard: time,
+                    incoming: RateLimit::new(MAX_CLIENT_PACKETS_PER_SECOND),
+                    outgoing: RateLimit::new(MAX_PACKETS_PER_SECOND),
+                    backlog: VecDeque::new(),
+                });
+                self.clients.len() - 1
+            }
+        };
+
+        let client = &mut self.clients[index];
+        client.player = header.player;
+        client.last_heard = time;
+        client.echo = header.token;
+        if !client.verified && header.echo == client.token {
+            info!("player {:08x} joined from {}", header.player, addr);
+            client.verified = true;
+            client.backlog = self.octree.marks().copied().collect();
+        }
+        if !client.incoming.take() || !client.sequence.accept(header) {
+            return Ok(true);
+        }
+
+        let color = player_color(header.player);
+        let mut accepted = Vec::with_capacity(MARKS_PER_PACKET);
+        for raw in marks {
+            let pos = (header.origin + Vec3::from(raw.pos).as_dvec3()).as_vec3();
+            if self.on_surface(pos) {
+                self.octree.insert(Mark::new(pos, color));
+                accepted.push(raw);
+            } else {
+                self.rejected += 1;
+            }
+        }
+
+        // Empty packets are relayed too, so the other clients know the player is still around.
+        for (i, other) in self.clients.iter_mut().enumerate() {
+            if i != index && other.verified {
+                other.send(&self.socket, self.id, header.player, header.origin, &accepted);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Refills rate limits, streams backlogs, sends heartbeats and drops silent clients.
+    fn tick(&mut self, dt: f64, heartbeat: bool) {
+        let time = self.time();
+        for client in &mut self.clients {
+            client.incoming.refill(dt);
+            client.outgoing.refill(dt);
+
+            let mut sent = false;
+            while !client.backlog.is_empty() && client.outgoing.take() {
+                let n = usize::min(client.backlog.len(), MARKS_PER_PACKET);
+                let batch: Vec<MarkRaw> = client.backlog.drain(..n).collect();
+                client.send(&self.socket, self.id, SHARED_PLAYER, DVec3::ZERO, &batch);
+                sent = true;
+            }
+            if heartbeat && !sent {
+                client.send(&self.socket, self.id, SHARED_PLAYER, DVec3::ZERO, &[]);
+            }
+        }
+
+        self.clients.retain(|client| {
+            let alive = time - client.last_heard < PEER_TIMEOUT;
+            if !alive && client.verified {
+                info!("player {:08x} timed out", client.player);
+            }
+            alive
+        });
+    }
+
+    /// Writes the shared scan if it changed.
+    fn save(&mut self) {
+        let count = self.octree.count();
+        if count == self.saved_count {
+            return;
+        }
+
+        match save_scan(&self.octree, &[], DVec3::ZERO, &self.save) {
+            Ok(()) => {
+                info!("saved {} marks ({} rejected so far)", count, self.rejected);
+                self.saved_count = count;
+            }
+            Err(e) => warn!("failed to save {}: {}", self.save.display(), e),
+        }
+    }
+}
+
+/// Runs the server until the process is killed. The scan is saved every [`SAVE_INTERVAL`] seconds.
+pub fn run(options: &ServerOptions) -> Result<(), String> {
+    let mut server = Server::new(options)?;
+    info!("server {:08x} listening on port {}", server.id, options.port);
+
+    let mut buf = [0; MAX_PACKET_SIZE];
+    let mut last_tick = server.time();
+    let mut heartbeat_timer = 0.0;
+    let mut save_timer = 0.0;
+    loop {
+        while server.receive(&mut buf)? {
+            if server.time() - last_tick >= TICK.as_secs_f64() {
+                break;
+            }
+        }
+
+        let time = server.time();
+        let dt = time - last_tick;
+        last_tick = time;
+
+        heartbeat_timer -= dt;
+        server.tick(dt, heartbeat_timer <= 0.0);
+        if heartbeat_timer <= 0.0 {
+            heartbeat_timer = HEARTBEAT_TIME;
+        }
+
+        save_timer += dt;
+        if save_timer >= SAVE_INTERVAL {
+            save_timer = 0.0;
+            server.save();
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use super::camera::Viewpoint;
+use super::State;
+use glam::DVec3;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+const SESSION_PATH: &str = "session.toml";
+pub const N_VIEWPOINTS: usize = 10;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct SavedViewpoint {
+    pub slot: usize,
+    pub viewpoint: Viewpoint,
+    /// World position of the local origin the viewpoint is relative to.
+    #[serde(default)]
+    pub origin: [f64; 3],
+}
+
+/// User data kept between runs, stored as TOML next to the executable's working directory.
+#[derive(Default, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(default)]
+    pub viewpoints: Vec<SavedViewpoint>,
+}
+
+impl Session {
+    /// Loads the session file, falling back to an empty session if it is missing or unreadable.
+    pub fn load() -> Self {
+        let Ok(text) = std::fs::read_to_string(SESSION_PATH) else {
+            return Self::default();
+        };
+        toml::from_str(&text).unwrap_or_else(|e| {
+            warn!("failed to parse {}: {}", SESSION_PATH, e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(SESSION_PATH, text).map_err(|e| e.to_string())
+    }
+
+    /// The viewpoint in `slot`, relative to the local `origin`.
+    pub fn viewpoint(&self, slot: usize, origin: DVec3) -> Option<Viewpoint> {
+        let saved = self.viewpoints.iter().find(|saved| saved.slot == slot)?;
+        let shift = (origin - DVec3::from(saved.origin)).as_vec3();
+        Some(Viewpoint { pos: saved.viewpoint.pos - shift, ..saved.viewpoint })
+    }
+
+    pub fn set_viewpoint(&mut self, slot: usize, viewpoint: Viewpoint, origin: DVec3) {
+        self.viewpoints.retain(|saved| saved.slot != slot);
+        self.viewpoints.push(SavedViewpoint { slot, viewpoint, origin: origin.to_array() });
+        self.viewpoints.sort_by_key(|saved| saved.slot);
+    }
+}
+
+impl State {
+    pub fn store_viewpoint(&mut self, slot: usize) {
+        self.session.set_viewpoint(slot, self.camera.viewpoint(), self.origin());
+        match self.session.save() {
+            Ok(()) => self.notify(format!("viewpoint {} saved", slot)),
+            Err(e) => self.notify(format!("failed to save viewpoint {}: {}", slot, e)),
+        }
+    }
+
+    pub fn recall_viewpoint(&mut self, slot: usize) {
+        match self.session.viewpoint(slot, self.origin()) {
+            Some(viewpoint) if self.settings.accessibility.reduced_motion => {
+                self.camera.set_viewpoint(viewpoint);
+                self.notify(format!("viewpoint {}", slot));
+            }
+            Some(viewpoint) => {
+                self.camera.tween_to(viewpoint);
+                self.notify(format!("viewpoint {}", slot));
+            }
+            None => self.notify(format!("viewpoint {} is empty", slot)),
+        }
+    }
+}
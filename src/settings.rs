@@ -0,0 +1,218 @@
+use crate::marker::Palette;
+use crate::util::geom::TriangleTest;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+const SETTINGS_PATH: &str = "settings.toml";
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub enabled: bool,
+    pub volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self { enabled: true, volume: 0.5 }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutosaveSettings {
+    pub enabled: bool,
+    pub interval_secs: f64,
+    /// Marks added or removed since the last autosave that trigger one before the interval is up; `0` to disable.
+    pub mark_interval: usize,
+    pub retained: usize,
+    pub max_size_mb: u64,
+}
+
+impl Default for AutosaveSettings {
+    fn default() -> Self {
+        Self { enabled: true, interval_secs: 300.0, mark_interval: 100_000, retained: 3, max_size_mb: 512 }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlsSettings {
+    /// Multiple of the default mouse look speed, from 0.1 to 5.
+    pub sensitivity: f32,
+}
+
+impl Default for ControlsSettings {
+    fn default() -> Self {
+        Self { sensitivity: 1.0 }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplaySettings {
+    pub fullscreen: bool,
+    /// Index into the available monitors used for fullscreen; the current monitor if unset or out of range.
+    pub monitor: Option<usize>,
+    /// Multiple of the window size high-resolution screenshots are rendered at, from 2 to 4.
+    pub screenshot_scale: u32,
+    /// Multiple of the window size the scene is rendered at before it is filtered to the window, from 0.5 to 2.
+    pub render_scale: f32,
+    /// Size of the HUD relative to its default on top of the monitor's scale factor, from 0.5 to 3.
+    pub ui_scale: f32,
+    /// Language of the HUD, window title and session summary, read from `locales/<language>.json` unless `en`.
+    pub language: String,
+    /// Highest frame rate to render at; unlimited if unset.
+    pub fps_cap: Option<u32>,
+    /// Drop to 30 frames per second and pause scanning while the window is unfocused.
+    pub low_power: bool,
+    /// Lower the render scale, visible marks and point size while frames take longer than the target frame rate
+    /// allows, and restore them once there is headroom.
+    pub adaptive_quality: bool,
+    /// Frame rate adaptive quality aims for.
+    pub target_fps: u32,
+    /// Vertical field of view in degrees, from 30 to 120.
+    pub fov: f32,
+    /// Wait for the display's refresh before presenting frames, which avoids tearing at the cost of latency.
+    pub vsync: bool,
+    /// Colors marks are shaded with by their distance from the camera.
+    pub palette: Palette,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        Self {
+            fullscreen: false,
+            monitor: None,
+            screenshot_scale: 2,
+            render_scale: 1.0,
+            ui_scale: 1.0,
+            language: "en".to_string(),
+            fps_cap: None,
+            low_power: false,
+            adaptive_quality: false,
+            target_fps: 60,
+            fov: crate::camera::DEFAULT_FOV,
+            vsync: false,
+            palette: Palette::Spectrum,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScannerSettings {
+    /// Leave a faint mark at maximum range for every ray that hits nothing.
+    pub miss_marks: bool,
+    /// Show a HUD warning while the scanner's rays hit nothing.
+    pub miss_indicator: bool,
+    /// Seed of the scanner's ray jitter; `--seed` takes precedence, and a random seed is picked if neither is set.
+    pub seed: Option<u64>,
+    /// Most rays cast per frame; shots beyond it wait for the next frames.
+    pub ray_budget: usize,
+    /// Stop marking surfaces scanned this densely, in marks per square unit, and aim those shots elsewhere in the
+    /// cone instead; off if unset.
+    pub auto_stop: Option<u32>,
+    /// Test shots against the terrain's triangles watertight, so they cannot slip through the seams between them.
+    pub watertight: bool,
+}
+
+impl ScannerSettings {
+    /// How shots are tested against the terrain's triangles.
+    pub fn triangle_test(&self) -> TriangleTest {
+        TriangleTest { watertight: self.watertight, ..Default::default() }
+    }
+}
+
+impl Default for ScannerSettings {
+    fn default() -> Self {
+        Self {
+            miss_marks: false,
+            miss_indicator: true,
+            seed: None,
+            ray_budget: crate::marker::DEFAULT_RAY_BUDGET,
+            auto_stop: None,
+            watertight: true,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// Clicking starts and stops scanning instead of scanning while the button is held.
+    pub toggle_scan: bool,
+    /// Cut straight to recalled viewpoints instead of flying the camera there.
+    pub reduced_motion: bool,
+    /// Most scanner beams drawn per second, so rapid shots don't strobe; unlimited if unset.
+    pub flash_limit: Option<u32>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameplaySettings {
+    /// Start with limited scanner range and energy.
+    pub enabled: bool,
+    /// Sustained scanning heats the scanner up, widening the cone and eventually forcing a pause.
+    pub heat: bool,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptingSettings {
+    /// Scripts the user allowed to change the scene, by name and a hash of their source.
+    pub approved: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TerrainSettings {
+    /// Edge length of the voxels voxel terrains are meshed with.
+    pub voxel_size: f32,
+    /// Distance from the camera within which voxel terrains are meshed at twice the resolution; `0` to disable.
+    pub detail_band: f32,
+    /// Coarser levels of detail voxel terrains are meshed with far from the camera; `0`, the default, disables them. A
+    /// server checks marks against the full-resolution surface, so distant marks may be rejected with levels on.
+    pub lod_levels: u32,
+    /// Distance from the camera where the first level of detail takes over; each further level starts twice as far.
+    pub lod_distance: f32,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self { voxel_size: crate::world::DEFAULT_VOXEL_SIZE, detail_band: 0.0, lod_levels: 0, lod_distance: 250.0 }
+    }
+}
+
+/// User preferences read from `settings.toml`; missing keys take their default value.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub accessibility: AccessibilitySettings,
+    pub audio: AudioSettings,
+    pub autosave: AutosaveSettings,
+    pub controls: ControlsSettings,
+    pub display: DisplaySettings,
+    pub scanner: ScannerSettings,
+    pub scripting: ScriptingSettings,
+    pub gameplay: GameplaySettings,
+    pub terrain: TerrainSettings,
+}
+
+impl Settings {
+    /// Loads the settings file, falling back to defaults if it is missing or unreadable.
+    pub fn load() -> Self {
+        let Ok(text) = std::fs::read_to_string(SETTINGS_PATH) else {
+            return Self::default();
+        };
+        toml::from_str(&text).unwrap_or_else(|e| {
+            warn!("failed to parse {}: {}", SETTINGS_PATH, e);
+            Self::default()
+        })
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|e| e.to_string())?;
+        std::fs::write(SETTINGS_PATH, text).map_err(|e| e.to_string())
+    }
+}
@@ -4,15 +4,43 @@ struct CameraUniform {
     to_proj: mat4x4<f32>,
 };
 
+struct GlobalsUniform {
+    ruler_spacing: f32,
+    ruler_enabled: u32,
+    point_size: f32,
+    // Marks scanned later than this, in seconds of scan time, are hidden.
+    time_limit: f32,
+    // Palette lookup table from the near to the far distance.
+    palette: array<vec4<f32>, 8>,
+    // Marks on the negative side of this plane are hidden.
+    clip_plane: vec4<f32>,
+    // Per layer, the color its marks are drawn in instead of their own where alpha is set.
+    layer_colors: array<vec4<f32>, 32>,
+    // Per kind of mark, the color it is shaded with instead of by distance while `by_tag` is set.
+    tag_colors: array<vec4<f32>, 4>,
+    // Non-zero to draw every mark in its own color, which holds the density heatmap.
+    heatmap: u32,
+    // Non-zero to shade marks by their tag rather than their distance.
+    by_tag: u32,
+};
+
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
+@group(0) @binding(1)
+var<uniform> globals: GlobalsUniform;
+
 struct VertexInput {
     @location(0) position: vec2<f32>,
 }
 
 struct InstanceInput {
     @location(1) pos: vec3<f32>,
+    // RGB and the mark tag.
+    @location(2) color: vec4<u32>,
+    // Seconds of scan time the mark was recorded at.
+    @location(3) time: f32,
+    @location(4) layer: u32,
 }
 
 struct VertexOutput {
@@ -20,18 +48,51 @@ struct VertexOutput {
     @location(0) quad_position: vec2<f32>,
     @location(1) color: vec3<f32>,
     @location(2) dist: f32,
+    @location(3) opacity: f32,
 }
 
+// Outside the clip volume, for marks hidden by the clip plane or the time limit.
+let CULLED = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+
 let PI = 3.1415926535;
 let EULER = 2.7182818;
 
 let DISTANCE_NEA = 100.0;
-let DISTANCE_MID = 200.0;
 let DISTANCE_FAR = 300.0;
+let PALETTE_SIZE = 8u;
+
+let RULER_COLOR = vec3<f32>(1.0, 1.0, 1.0);
+let RULER_WIDTH = 0.4;
+
+let PAINTED_TAG = 0u;
+let MISS_TAG = 1u;
+let DYNAMIC_TAG = 2u;
+let MISS_OPACITY = 0.15;
+
+// Tags in this range are surfels, with the tag holding their diameter in sixteenths of a world unit. Painted surfels
+// have `PAINTED_SURFEL_BIT` set on top of their diameter.
+let SURFEL_MIN_TAG = 3u;
+let SURFEL_MAX_TAG = 126u;
+let SURFEL_TAGS_PER_UNIT = 16.0;
+let PAINTED_SURFEL_BIT = 128u;
+
+// Color of a mark at `dist` from the camera, interpolated from the palette lookup table.
+fn palette_color(dist: f32) -> vec3<f32> {
+    let x = clamp((dist - DISTANCE_NEA) / (DISTANCE_FAR - DISTANCE_NEA), 0.0, 1.0) * f32(PALETTE_SIZE - 1u);
+    let i = min(u32(x), PALETTE_SIZE - 2u);
+    return mix(globals.palette[i].rgb, globals.palette[i + 1u].rgb, x - f32(i));
+}
 
-let COLOR_NEA = vec3<f32>(1.0, 0.0, 0.0);
-let COLOR_MID = vec3<f32>(0.0, 1.0, 0.0);
-let COLOR_FAR = vec3<f32>(0.0, 0.2, 1.0);
+// Index into the tag colors of a mark: surface hits, painted marks, misses and marks on entities.
+fn tag_kind(tag: u32, painted: bool) -> u32 {
+    if (painted) {
+        return 1u;
+    }
+    if (tag == MISS_TAG) {
+        return 2u;
+    }
+    return select(0u, 3u, tag == DYNAMIC_TAG);
+}
 
 @vertex
 fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
@@ -57,13 +118,32 @@ fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
 
     var out: VertexOutput;
 
-    out.clip_position = camera.to_proj * model_to_view * vec4<f32>(model.position, 0.0, 1.0);
+    let tag = instance.color.w;
+    let size_tag = tag & (PAINTED_SURFEL_BIT - 1u);
+    let surfel = size_tag >= SURFEL_MIN_TAG && size_tag <= SURFEL_MAX_TAG;
+    var point_size = globals.point_size;
+    if (surfel) {
+        point_size = max(point_size, f32(size_tag) / SURFEL_TAGS_PER_UNIT);
+    }
+    let painted = tag == PAINTED_TAG || (surfel && tag != size_tag);
+
+    out.clip_position = camera.to_proj * model_to_view * vec4<f32>(model.position * point_size, 0.0, 1.0);
     out.quad_position = model.position;
     out.dist = dist;
-
-    out.color = COLOR_NEA;
-    out.color = mix(out.color, COLOR_MID, smoothstep(DISTANCE_NEA, DISTANCE_MID, dist));
-    out.color = mix(out.color, COLOR_FAR, smoothstep(DISTANCE_MID, DISTANCE_FAR, dist));
+    out.opacity = select(1.0, MISS_OPACITY, tag == MISS_TAG);
+
+    let own_color = painted || globals.heatmap != 0u;
+    out.color = select(palette_color(dist), vec3<f32>(instance.color.rgb) / 255.0, own_color);
+    if (globals.by_tag != 0u && globals.heatmap == 0u) {
+        out.color = globals.tag_colors[tag_kind(tag, painted)].rgb;
+    }
+    let layer_color = globals.layer_colors[instance.layer];
+    if (layer_color.a > 0.0 && globals.heatmap == 0u) {
+        out.color = layer_color.rgb;
+    }
+    if (dot(globals.clip_plane, vec4<f32>(instance.pos, 1.0)) < 0.0 || instance.time > globals.time_limit) {
+        out.clip_position = CULLED;
+    }
 
     return out;
 }
@@ -76,5 +156,19 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     let sigm: f32 = 1.0 / (1.0 + pow(EULER, -(in.dist * 0.02 - 10.0)));
     let alpha_scalar: f32 = 1.0 - sigm * 0.9;
 
-    return vec4<f32>(in.color, clamp(alpha, 0.0, 1.0) * alpha_scalar);
+    var color: vec3<f32> = in.color;
+    if (globals.ruler_enabled != 0u) {
+        let ring_offset: f32 = abs(fract(in.dist / globals.ruler_spacing + 0.5) - 0.5) * globals.ruler_spacing;
+        color = mix(color, RULER_COLOR, 1.0 - smoothstep(0.0, RULER_WIDTH, ring_offset));
+    }
+
+    return vec4<f32>(color, clamp(alpha, 0.0, 1.0) * alpha_scalar * in.opacity);
+}
+
+// Writes only the depth of each mark's disc, for eye-dome lighting.
+@fragment
+fn fs_depth(in: VertexOutput) {
+    if (length(in.quad_position) > 0.5) {
+        discard;
+    }
 }
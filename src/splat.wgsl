@@ -0,0 +1,211 @@
+struct CameraUniform {
+    pos: vec4<f32>,
+    to_view: mat4x4<f32>,
+    to_proj: mat4x4<f32>,
+};
+
+struct GlobalsUniform {
+    ruler_spacing: f32,
+    ruler_enabled: u32,
+    point_size: f32,
+    // Marks scanned later than this, in seconds of scan time, are hidden.
+    time_limit: f32,
+    // Palette lookup table from the near to the far distance.
+    palette: array<vec4<f32>, 8>,
+    // Marks on the negative side of this plane are hidden.
+    clip_plane: vec4<f32>,
+    // Per layer, the color its marks are drawn in instead of their own where alpha is set.
+    layer_colors: array<vec4<f32>, 32>,
+    // Per kind of mark, the color it is shaded with instead of by distance while `by_tag` is set.
+    tag_colors: array<vec4<f32>, 4>,
+    // Non-zero to draw every mark in its own color, which holds the density heatmap.
+    heatmap: u32,
+    // Non-zero to shade marks by their tag rather than their distance.
+    by_tag: u32,
+};
+
+struct SplatParams {
+    width: u32,
+    height: u32,
+    n_marks: u32,
+};
+
+// Laid out like the instance attributes, so the position is three scalars rather than a 16 byte aligned vector.
+struct Mark {
+    pos: array<f32, 3>,
+    color: u32,
+    time: f32,
+    layer: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+@group(0) @binding(1)
+var<uniform> globals: GlobalsUniform;
+
+@group(0) @binding(2)
+var<uniform> params: SplatParams;
+
+@group(0) @binding(3)
+var<storage, read> marks: array<Mark>;
+
+// One word per pixel: inverted 16 bit depth in the high half so atomicMax keeps the nearest mark, RGB565 color in
+// the low half. Zero means no mark was splatted.
+@group(0) @binding(4)
+var<storage, read_write> frame: array<atomic<u32>>;
+
+let WORKGROUP_SIZE = 256u;
+let NEAR_DEPTH = 0.1;
+let MAX_DEPTH = 1024.0;
+let MAX_RADIUS = 3;
+
+let DISTANCE_NEA = 100.0;
+let DISTANCE_FAR = 300.0;
+let PALETTE_SIZE = 8u;
+
+let RULER_COLOR = vec3<f32>(1.0, 1.0, 1.0);
+let RULER_WIDTH = 0.4;
+
+let PAINTED_TAG = 0u;
+let MISS_TAG = 1u;
+let DYNAMIC_TAG = 2u;
+let MISS_BRIGHTNESS = 0.15;
+
+// Tags in this range are surfels, with the tag holding their diameter in sixteenths of a world unit. Painted surfels
+// have `PAINTED_SURFEL_BIT` set on top of their diameter.
+let SURFEL_MIN_TAG = 3u;
+let SURFEL_MAX_TAG = 126u;
+let SURFEL_TAGS_PER_UNIT = 16.0;
+let PAINTED_SURFEL_BIT = 128u;
+
+// Color of a mark at `dist` from the camera, interpolated from the palette lookup table.
+fn palette_color(dist: f32) -> vec3<f32> {
+    let x = clamp((dist - DISTANCE_NEA) / (DISTANCE_FAR - DISTANCE_NEA), 0.0, 1.0) * f32(PALETTE_SIZE - 1u);
+    let i = min(u32(x), PALETTE_SIZE - 2u);
+    return mix(globals.palette[i].rgb, globals.palette[i + 1u].rgb, x - f32(i));
+}
+
+// Index into the tag colors of a mark: surface hits, painted marks, misses and marks on entities.
+fn tag_kind(tag: u32, painted: bool) -> u32 {
+    if (painted) {
+        return 1u;
+    }
+    if (tag == MISS_TAG) {
+        return 2u;
+    }
+    return select(0u, 3u, tag == DYNAMIC_TAG);
+}
+
+fn mark_color(dist: f32) -> vec3<f32> {
+    var color = palette_color(dist);
+    if (globals.ruler_enabled != 0u) {
+        let ring_offset = abs(fract(dist / globals.ruler_spacing + 0.5) - 0.5) * globals.ruler_spacing;
+        color = mix(color, RULER_COLOR, 1.0 - smoothstep(0.0, RULER_WIDTH, ring_offset));
+    }
+    return color;
+}
+
+fn pack_color(color: vec3<f32>) -> u32 {
+    let c = vec3<u32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)) * vec3<f32>(31.0, 63.0, 31.0) + 0.5);
+    return (c.r << 11u) | (c.g << 5u) | c.b;
+}
+
+fn unpack_color(packed: u32) -> vec3<f32> {
+    let c = vec3<u32>((packed >> 11u) & 31u, (packed >> 5u) & 63u, packed & 31u);
+    return vec3<f32>(c) / vec3<f32>(31.0, 63.0, 31.0);
+}
+
+@compute @workgroup_size(256)
+fn cs_splat(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+    let index = id.x + id.y * groups.x * WORKGROUP_SIZE;
+    if (index >= params.n_marks) {
+        return;
+    }
+
+    let mark = marks[index];
+    let pos = vec3<f32>(mark.pos[0], mark.pos[1], mark.pos[2]);
+    if (dot(globals.clip_plane, vec4<f32>(pos, 1.0)) < 0.0 || mark.time > globals.time_limit) {
+        return;
+    }
+    let view = camera.to_view * vec4<f32>(pos, 1.0);
+    let depth = -view.z;
+    if (depth <= NEAR_DEPTH || depth >= MAX_DEPTH) {
+        return;
+    }
+
+    let clip = camera.to_proj * view;
+    let ndc = clip.xy / clip.w;
+    let size = vec2<f32>(f32(params.width), f32(params.height));
+    let center = vec2<i32>(floor((vec2<f32>(ndc.x, -ndc.y) * 0.5 + 0.5) * size));
+
+    let tag = mark.color >> 24u;
+    let size_tag = tag & (PAINTED_SURFEL_BIT - 1u);
+    let surfel = size_tag >= SURFEL_MIN_TAG && size_tag <= SURFEL_MAX_TAG;
+    var point_size = globals.point_size;
+    if (surfel) {
+        point_size = max(point_size, f32(size_tag) / SURFEL_TAGS_PER_UNIT);
+    }
+    let painted = tag == PAINTED_TAG || (surfel && tag != size_tag);
+    let radius_px = point_size * 0.5 * camera.to_proj[1][1] * size.y * 0.5 / depth;
+    let radius = min(i32(radius_px), MAX_RADIUS);
+
+    let depth_bits = min(u32(depth / MAX_DEPTH * 65535.0), 65534u);
+    var color = mark_color(distance(pos, camera.pos.xyz));
+    if (painted || globals.heatmap != 0u) {
+        let rgb = mark.color;
+        color = vec3<f32>(f32(rgb & 0xffu), f32((rgb >> 8u) & 0xffu), f32((rgb >> 16u) & 0xffu)) / 255.0;
+    }
+    if (globals.by_tag != 0u && globals.heatmap == 0u) {
+        color = globals.tag_colors[tag_kind(tag, painted)].rgb;
+    }
+    let layer_color = globals.layer_colors[mark.layer];
+    if (layer_color.a > 0.0 && globals.heatmap == 0u) {
+        color = layer_color.rgb;
+    }
+    if (tag == MISS_TAG) {
+        color *= MISS_BRIGHTNESS;
+    }
+    let packed = ((65535u - depth_bits) << 16u) | pack_color(color);
+
+    for (var dy = -radius; dy <= radius; dy += 1) {
+        for (var dx = -radius; dx <= radius; dx += 1) {
+            let pixel = center + vec2<i32>(dx, dy);
+            if (dx * dx + dy * dy > radius * radius || pixel.x < 0 || pixel.y < 0) {
+                continue;
+            }
+            if (pixel.x >= i32(params.width) || pixel.y >= i32(params.height)) {
+                continue;
+            }
+            atomicMax(&frame[u32(pixel.y) * params.width + u32(pixel.x)], packed);
+        }
+    }
+}
+
+struct ResolveOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+@vertex
+fn vs_resolve(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_resolve(@builtin(position) position: vec4<f32>) -> ResolveOutput {
+    let pixel = vec2<u32>(position.xy);
+    let packed = atomicLoad(&frame[pixel.y * params.width + pixel.x]);
+    if (packed == 0u) {
+        discard;
+    }
+
+    let depth = f32(65535u - (packed >> 16u)) / 65535.0 * MAX_DEPTH;
+    let clip = camera.to_proj * vec4<f32>(0.0, 0.0, -depth, 1.0);
+
+    var out: ResolveOutput;
+    out.color = vec4<f32>(unpack_color(packed & 0xffffu), 1.0);
+    out.depth = clamp(clip.z / clip.w, 0.0, 1.0);
+    return out;
+}
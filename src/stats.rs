@@ -0,0 +1,100 @@
+use super::locale::Locale;
+use super::State;
+use glam::Vec3;
+use serde::Serialize;
+use std::io::Write;
+use tracing::warn;
+
+const STATS_PATH: &str = "stats.jsonl";
+
+/// Running totals for the current session, reported on exit and appended to `stats.jsonl` as one JSON object per
+/// line.
+#[derive(Default, Serialize)]
+pub struct Stats {
+    /// Unix time the session started, in seconds.
+    pub started: u64,
+    pub seconds: f64,
+    /// Rays cast, counting every sample of a precision shot.
+    pub rays: u64,
+    pub shots: u64,
+    /// Shots that hit the terrain or an entity.
+    pub hits: u64,
+    pub marks: u64,
+    /// Distance the camera moved under its own power, not counting teleports.
+    pub distance: f64,
+    /// Lowest camera height reached.
+    pub deepest: Option<f32>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        let started = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        Self { started: started.as_secs(), ..Default::default() }
+    }
+
+    pub fn record_shot(&mut self, rays: usize, hit: bool) {
+        self.rays += rays as u64;
+        self.shots += 1;
+        self.hits += hit as u64;
+    }
+
+    pub fn record_mark(&mut self) {
+        self.marks += 1;
+    }
+
+    pub fn record_movement(&mut self, from: Vec3, to: Vec3) {
+        self.distance += Vec3::distance(from, to) as f64;
+        self.deepest = Some(self.deepest.map_or(to.y, |deepest| deepest.min(to.y)));
+    }
+
+    pub fn hit_ratio(&self) -> f64 {
+        if self.shots == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.shots as f64
+        }
+    }
+
+    /// Human-readable summary in the language of `locale`, one statistic per line.
+    pub fn report(&self, locale: &Locale) -> String {
+        let minutes = (self.seconds / 60.0) as u64;
+        let deepest = self.deepest.map_or("-".to_string(), |deepest| format!("{:.1}", deepest));
+        let rows = [
+            ("summary.time_played", format!("{}m {:02}s", minutes, self.seconds as u64 % 60)),
+            ("summary.rays", self.rays.to_string()),
+            ("summary.hit_ratio", format!("{:.1}%", self.hit_ratio() * 100.0)),
+            ("summary.marks", self.marks.to_string()),
+            ("summary.distance", format!("{:.1}", self.distance)),
+            ("summary.deepest", deepest),
+        ];
+        let mut report = locale.get("summary.title").to_string();
+        for (key, value) in rows {
+            report += &format!("\n  {:<16}{}", format!("{}:", locale.get(key)), value);
+        }
+        report
+    }
+
+    /// Appends the session as a JSON line to `stats.jsonl`.
+    pub fn append(&self) -> Result<(), String> {
+        let line = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        let mut file =
+            std::fs::OpenOptions::new().create(true).append(true).open(STATS_PATH).map_err(|e| e.to_string())?;
+        writeln!(file, "{}", line).map_err(|e| e.to_string())
+    }
+}
+
+impl State {
+    pub fn update_stats(&mut self, dt: f64) {
+        self.stats.seconds += dt;
+    }
+
+    /// Prints the session summary and appends it to the stats file, after letting an autosave in progress finish;
+    /// called once when the window closes.
+    pub fn finish_session(&mut self) {
+        self.finish_autosave(true);
+        println!("{}", self.stats.report(&self.locale));
+        if let Err(e) = self.stats.append() {
+            warn!("failed to write {}: {}", STATS_PATH, e);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+use super::State;
+
+pub(crate) const EXPORT_DIR: &str = "saves";
+
+/// Stand-ins for the point cloud and terrain exporters in builds without the `export` feature.
+impl State {
+    pub fn export_terrain(&mut self) {
+        self.notify("exporting is not available in this build".to_string());
+    }
+
+    pub fn export_las(&mut self) {
+        self.notify("exporting is not available in this build".to_string());
+    }
+}
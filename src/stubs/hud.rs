@@ -0,0 +1,48 @@
+use super::State;
+use glam::Vec2;
+
+/// UI scales allowed by the display settings.
+pub const MIN_UI_SCALE: f32 = 0.5;
+pub const MAX_UI_SCALE: f32 = 3.0;
+
+/// Stand-in for the HUD in builds without the `ui` feature: nothing is drawn over the scene.
+pub struct Hud {
+    pub visible: bool,
+    scale_factor: f64,
+}
+
+impl Hud {
+    pub fn new(_device: &wgpu::Device, _format: wgpu::TextureFormat, scale_factor: f64) -> Self {
+        Self { visible: false, scale_factor }
+    }
+
+    pub fn record_scroll(&mut self) {}
+
+    pub fn record_shot(&mut self, _hit: bool) {}
+
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+}
+
+impl State {
+    pub fn toggle_hud(&mut self) {
+        self.notify("the HUD is not available in this build".to_string());
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.hud.scale_factor = scale_factor;
+    }
+
+    pub fn ui_scale(&self) -> f32 {
+        self.hud.scale_factor as f32 * self.settings.display.ui_scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE)
+    }
+
+    pub fn minimap_rect(&self) -> Option<(Vec2, f32)> {
+        None
+    }
+
+    pub fn update_hud(&mut self, _dt: f64) {}
+
+    pub fn render_hud<'a>(&'a self, _render_pass: &mut wgpu::RenderPass<'a>) {}
+}
@@ -0,0 +1,31 @@
+use super::marker::Mark;
+use super::State;
+use glam::DVec3;
+
+/// Stand-in for the network layer in builds without the `net` feature: marks are never shared.
+#[derive(Default)]
+pub struct Net {}
+
+impl Net {
+    pub fn disabled() -> Self {
+        Self {}
+    }
+
+    pub fn bind(_port: u16, _peers: &[String]) -> Result<Self, String> {
+        Err("networking is not available in this build".to_string())
+    }
+
+    pub fn enabled(&self) -> bool {
+        false
+    }
+
+    pub fn players(&self) -> usize {
+        0
+    }
+
+    pub fn share(&mut self, _pos: DVec3, _mark: Mark) {}
+}
+
+impl State {
+    pub fn update_net(&mut self, _dt: f64) {}
+}
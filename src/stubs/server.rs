@@ -0,0 +1,22 @@
+use super::world::TerrainKind;
+use std::path::PathBuf;
+
+pub const DEFAULT_PORT: u16 = 7777;
+pub const DEFAULT_SAVE_PATH: &str = "saves/server.scan";
+
+pub struct ServerOptions {
+    pub port: u16,
+    pub save: PathBuf,
+    pub terrain: TerrainKind,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self { port: DEFAULT_PORT, save: DEFAULT_SAVE_PATH.into(), terrain: TerrainKind::Caves }
+    }
+}
+
+/// Stand-in for `scanner server` in builds without the `net` feature.
+pub fn run(_options: &ServerOptions) -> Result<(), String> {
+    Err("the server is not available in this build".to_string())
+}
@@ -0,0 +1,192 @@
+use super::State;
+
+/// Render step of a system, drawing into the overlay pass after the scene.
+type RenderFn = for<'a, 'b> fn(&'a State, &'b mut wgpu::RenderPass<'a>);
+
+/// A part of the app that is updated once per frame and may draw an overlay. Systems update in the order they were
+/// registered, so a system sees what the ones before it did this frame, and draw by their render order.
+pub trait System {
+    fn name(&self) -> &str;
+
+    /// Whether the system stands still while the app is paused, in photo mode or the settings menu.
+    fn pausable(&self) -> bool {
+        false
+    }
+
+    fn update(&mut self, state: &mut State, dt: f64);
+
+    /// Draws the system's overlay; skipped in photo mode.
+    fn render<'a>(&'a self, _state: &'a State, _render_pass: &mut wgpu::RenderPass<'a>) {}
+
+    /// Where the system's overlay is drawn among the others: lower orders first, and equal ones in the order the
+    /// systems were registered.
+    fn render_order(&self) -> i32 {
+        0
+    }
+}
+
+/// A system made of `State` methods, which is how the built-in systems are written.
+pub struct Builtin {
+    name: &'static str,
+    pausable: bool,
+    update: fn(&mut State, f64),
+    render: Option<RenderFn>,
+    render_order: i32,
+}
+
+impl Builtin {
+    pub fn new(name: &'static str, update: fn(&mut State, f64)) -> Self {
+        Self { name, pausable: false, update, render: None, render_order: 0 }
+    }
+
+    pub fn pausable(self) -> Self {
+        Self { pausable: true, ..self }
+    }
+
+    /// Draws the overlay with `render` at `render_order`, see [`System::render_order`].
+    pub fn with_render(self, render_order: i32, render: RenderFn) -> Self {
+        Self { render: Some(render), render_order, ..self }
+    }
+}
+
+impl System for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn pausable(&self) -> bool {
+        self.pausable
+    }
+
+    fn update(&mut self, state: &mut State, dt: f64) {
+        (self.update)(state, dt);
+    }
+
+    fn render<'a>(&'a self, state: &'a State, render_pass: &mut wgpu::RenderPass<'a>) {
+        if let Some(render) = self.render {
+            render(state, render_pass);
+        }
+    }
+
+    fn render_order(&self) -> i32 {
+        self.render_order
+    }
+}
+
+/// The built-in systems in update order. Optional ones are only registered when they are in use. Their overlays are
+/// drawn with the beams first and the minimap and HUD last, on top of the rest.
+pub fn builtin_systems(net: bool) -> Vec<Box<dyn System>> {
+    let mut systems: Vec<Box<dyn System>> = vec![
+        Box::new(Builtin::new("profiler", |state, _| state.update_profiler())),
+        Box::new(Builtin::new("governor", State::update_governor)),
+        Box::new(Builtin::new("touch", State::update_touch)),
+        Box::new(Builtin::new("entities", State::update_entities).pausable()),
+        Box::new(Builtin::new("playback", State::update_playback).pausable()),
+        Box::new(Builtin::new("layers", |state, _| state.update_layers())),
+    ];
+    if net {
+        systems.push(Box::new(Builtin::new("net", State::update_net)));
+    }
+    systems.extend([
+        Box::new(Builtin::new("archive", |state, _| state.update_archive())) as Box<dyn System>,
+        Box::new(Builtin::new("camera", State::update_camera)),
+        Box::new(Builtin::new("origin", |state, _| state.update_origin())),
+        Box::new(Builtin::new("world streaming", |state, _| {
+            state.world.prefetch(state.camera.pos, state.camera.heading())
+        })),
+        Box::new(Builtin::new("clip", |state, _| state.update_clip()).with_render(1, State::render_clip)),
+        Box::new(Builtin::new("picker", |state, _| state.update_picker()).with_render(3, State::render_picker)),
+        Box::new(
+            Builtin::new("selection", |state, _| state.update_selection()).with_render(2, State::render_selection),
+        ),
+        Box::new(Builtin::new("breadcrumbs", State::update_breadcrumbs)),
+        Box::new(Builtin::new("trail", |state, _| state.update_trail()).with_render(4, State::render_trail)),
+        Box::new(Builtin::new("gameplay", State::update_gameplay).pausable().with_render(5, State::render_gameplay)),
+        Box::new(Builtin::new("editor", State::update_editor).pausable()),
+    ]);
+    if cfg!(feature = "scripting") {
+        systems.push(Box::new(Builtin::new("scripting", State::update_scripts).pausable()));
+    }
+    systems.extend([
+        Box::new(
+            Builtin::new("scanner", |state, dt| {
+                if !state.low_power() {
+                    state.update_marker(dt);
+                }
+            })
+            .pausable(),
+        ) as Box<dyn System>,
+        Box::new(Builtin::new("beams", State::update_beams).pausable().with_render(0, State::render_beams)),
+        Box::new(Builtin::new("occlusion", |state, _| state.update_occlusion())),
+        Box::new(Builtin::new("accumulation", State::update_accumulation)),
+        Box::new(Builtin::new("inspector", State::update_inspector).with_render(6, State::render_inspector)),
+        Box::new(Builtin::new("coverage", State::update_coverage)),
+        Box::new(Builtin::new("minimap", State::update_minimap).with_render(7, State::render_minimap)),
+        Box::new(Builtin::new("hud", State::update_hud).with_render(8, State::render_hud)),
+        Box::new(Builtin::new("audio", State::update_audio)),
+        Box::new(Builtin::new("autosave", State::update_autosave)),
+        Box::new(Builtin::new("stats", State::update_stats)),
+        Box::new(Builtin::new("crash context", |state, _| state.update_crash_context())),
+    ]);
+    systems
+}
+
+impl State {
+    /// Adds `system` after the ones already registered.
+    pub fn register_system(&mut self, system: Box<dyn System>) {
+        self.systems.push(system);
+    }
+
+    /// Updates every system in order, skipping the pausable ones while `paused`. Systems registered during the
+    /// update run from the next frame on.
+    pub(crate) fn update_systems(&mut self, dt: f64, paused: bool) {
+        let mut systems = std::mem::take(&mut self.systems);
+        for system in systems.iter_mut().filter(|system| !paused || !system.pausable()) {
+            system.update(self, dt);
+        }
+        systems.append(&mut self.systems);
+        self.systems = systems;
+    }
+
+    pub(crate) fn render_systems<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let mut systems: Vec<&dyn System> = self.systems.iter().map(|system| system.as_ref()).collect();
+        systems.sort_by_key(|system| system.render_order());
+        for system in systems {
+            system.render(self, render_pass);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::builtin_systems;
+
+    #[test]
+    fn builtin_systems_are_unique_and_ordered_by_their_dependencies() {
+        for net in [false, true] {
+            let systems = builtin_systems(net);
+            let names: Vec<&str> = systems.iter().map(|system| system.name()).collect();
+            let position = |name: &str| names.iter().position(|&other| other == name);
+            assert!(names.iter().enumerate().all(|(i, name)| position(name) == Some(i)), "duplicate in {:?}", names);
+            assert_eq!(position("net").is_some(), net);
+            // The selection aims with the picked mark, the scanner culls with the layer mask, beams follow the rays
+            // the scanner cast this frame and the HUD draws on top of the minimap.
+            for (before, after) in [
+                ("camera", "origin"),
+                ("picker", "selection"),
+                ("layers", "scanner"),
+                ("scanner", "beams"),
+                ("minimap", "hud"),
+            ] {
+                assert!(position(before) < position(after), "{} runs after {}", before, after);
+            }
+            // Beams are drawn under the tool overlays, the selection box under the picked mark's highlight.
+            let order = |name: &str| systems[position(name).unwrap()].render_order();
+            for (below, above) in
+                [("beams", "clip"), ("clip", "selection"), ("selection", "picker"), ("minimap", "hud")]
+            {
+                assert!(order(below) < order(above), "{} is drawn over {}", below, above);
+            }
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use super::crash::RecentLog;
+use std::path::Path;
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Installs the global tracing subscriber. Events and `log` records at info level and above are logged to stderr and
+/// kept for crash reports; with `trace` set, every span is also recorded to that file as a Chrome trace for frame
+/// profiling in Perfetto or `chrome://tracing`. The trace file is only complete once the returned guard is dropped.
+pub fn init(trace: Option<&Path>) -> Option<FlushGuard> {
+    let log =
+        tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_target(false).with_filter(LevelFilter::INFO);
+    let recent = tracing_subscriber::fmt::layer()
+        .with_writer(|| RecentLog)
+        .with_ansi(false)
+        .with_target(false)
+        .with_filter(LevelFilter::INFO);
+    let (chrome, guard) = match trace {
+        Some(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new().file(path).include_args(true).build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    // `try_init` also forwards `log` records, which is how wgpu and winit report, into the same layers.
+    let subscriber = tracing_subscriber::registry().with(log).with(recent).with(chrome);
+    if let Err(e) = subscriber.try_init() {
+        eprintln!("failed to install the tracing subscriber: {}", e);
+    }
+    guard
+}
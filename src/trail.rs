@@ -0,0 +1,154 @@
+use super::lines::LineRenderer;
+use super::marker::Marker;
+use super::State;
+use glam::{vec4, Vec3, Vec4};
+
+/// Moves shorter than this are not recorded, so standing still adds nothing.
+const MIN_STEP: f32 = 0.05;
+/// A recorded position is dropped when the straight line that replaces it passes within this distance of it.
+const TOLERANCE: f32 = 0.25;
+/// Longest straight stretch kept as a single segment, and most positions a straight stretch may replace.
+const MAX_SEGMENT: f32 = 50.0;
+const MAX_SKIPPED: usize = 256;
+/// Most points kept; later positions are no longer recorded.
+const MAX_POINTS: usize = 1 << 20;
+/// Newest segments drawn, as many as the line buffer holds.
+const DRAWN_SEGMENTS: usize = 8192;
+
+/// The trail fades from this color at the start to the next one at the camera.
+const TAIL_COLOR: Vec4 = vec4(0.5, 0.7, 1.0, 0.05);
+const HEAD_COLOR: Vec4 = vec4(0.7, 0.9, 1.0, 0.35);
+
+/// Distance from `pos` to the segment from `a` to `b`.
+fn segment_distance(pos: Vec3, a: Vec3, b: Vec3) -> f32 {
+    let ab = b - a;
+    let t = if ab == Vec3::ZERO { 0.0 } else { ((pos - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0) };
+    pos.distance(a + ab * t)
+}
+
+/// A polyline recorded one position at a time and decimated as it goes, so it stays within [`TOLERANCE`] of every
+/// position it replaced.
+#[derive(Default)]
+struct Track {
+    /// Corners of the polyline, not including `tip`.
+    points: Vec<Vec3>,
+    /// Latest position, which ends the polyline.
+    tip: Option<Vec3>,
+    /// Positions since the last corner, which the segment from it to `tip` must stay close to.
+    skipped: Vec<Vec3>,
+}
+
+impl Track {
+    fn polyline(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.points.iter().copied().chain(self.tip)
+    }
+
+    /// Extends the track to `pos`, turning the previous tip into a corner once the straight segment from the last
+    /// corner would stray too far from a position it replaces.
+    fn record(&mut self, pos: Vec3) {
+        let Some(tip) = self.tip else {
+            self.points.push(pos);
+            self.tip = Some(pos);
+            return;
+        };
+        if tip.distance(pos) < MIN_STEP || self.points.len() >= MAX_POINTS {
+            return;
+        }
+
+        let corner = *self.points.last().unwrap();
+        let straight = corner.distance(pos) <= MAX_SEGMENT
+            && self.skipped.len() < MAX_SKIPPED
+            && self.skipped.iter().all(|&skipped| segment_distance(skipped, corner, pos) <= TOLERANCE);
+        if !straight {
+            self.points.push(tip);
+            self.skipped.clear();
+        }
+        self.skipped.push(pos);
+        self.tip = Some(pos);
+    }
+}
+
+/// The path the camera took this session, drawn as a faint line in the world and exported next to the point cloud.
+pub struct Trail {
+    track: Track,
+    pub visible: bool,
+    lines: LineRenderer,
+}
+
+impl Trail {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) -> Self {
+        Self {
+            track: Track::default(),
+            visible: false,
+            lines: LineRenderer::new(device, format, &marker.camera_bind_group_layout),
+        }
+    }
+
+    /// Rebuilds the line renderer on a new device, keeping the trail.
+    pub fn recreate(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, marker: &Marker) {
+        self.lines = LineRenderer::new(device, format, &marker.camera_bind_group_layout);
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        let track = &mut self.track;
+        for pos in track.points.iter_mut().chain(&mut track.tip).chain(&mut track.skipped) {
+            *pos -= shift;
+        }
+    }
+
+    /// The polyline from the first recorded camera position to the latest one.
+    pub fn polyline(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.track.polyline()
+    }
+}
+
+impl State {
+    pub fn toggle_trail(&mut self) {
+        self.trail.visible = !self.trail.visible;
+        self.notify(format!("camera trail: {}", if self.trail.visible { "on" } else { "off" }));
+    }
+
+    /// Records the camera position and queues the newest part of the trail.
+    pub fn update_trail(&mut self) {
+        if !self.museum.active {
+            self.trail.track.record(self.camera.pos);
+        }
+
+        let trail = &mut self.trail;
+        if trail.visible {
+            let n = trail.track.points.len() + trail.track.tip.is_some() as usize;
+            let first = n.saturating_sub(DRAWN_SEGMENTS + 1);
+            let points: Vec<Vec3> = trail.polyline().skip(first).collect();
+            let color = |i: usize| TAIL_COLOR.lerp(HEAD_COLOR, (first + i) as f32 / n.saturating_sub(1).max(1) as f32);
+            for (i, pair) in points.windows(2).enumerate() {
+                trail.lines.push_gradient(pair[0], color(i), pair[1], color(i + 1));
+            }
+        }
+        trail.lines.upload(&self.queue);
+    }
+
+    pub fn render_trail<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        self.trail.lines.draw(render_pass, &self.marker.camera_bind_group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Track;
+    use glam::vec3;
+    use glam::Vec3;
+
+    #[test]
+    fn straight_runs_collapse_and_turns_become_corners() {
+        let mut track = Track::default();
+        for i in 0..=20 {
+            track.record(vec3(i as f32, 0.0, 0.0));
+        }
+        for i in 1..=10 {
+            track.record(vec3(20.0, 0.0, i as f32));
+        }
+        let expected = vec![Vec3::ZERO, vec3(20.0, 0.0, 0.0), vec3(20.0, 0.0, 10.0)];
+        assert_eq!(track.polyline().collect::<Vec<_>>(), expected);
+    }
+}
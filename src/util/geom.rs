@@ -0,0 +1,376 @@
+use super::{Ray, Triangle};
+use glam::Vec3;
+
+/// Smallest determinant and distance a ray-triangle hit may have, so grazing and self hits are ignored.
+pub const TRIANGLE_EPSILON: f32 = 0.0001;
+
+/// Unit normal of `triangle`, facing the side its corners wind counter-clockwise around, or `None` if it is
+/// degenerate.
+pub fn triangle_normal(triangle: &Triangle) -> Option<Vec3> {
+    Vec3::cross(triangle.b - triangle.a, triangle.c - triangle.a).try_normalize()
+}
+
+/// Point of `triangle` nearest to `p`, found by which corner, edge or face region of the triangle `p` lies in.
+pub fn closest_point_on_triangle(p: Vec3, triangle: &Triangle) -> Vec3 {
+    let Triangle { a, b, c } = *triangle;
+    let (ab, ac) = (b - a, c - a);
+
+    let ap = p - a;
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+/// Whether `p`, projected onto the plane of `triangle`, lies within it, edges included. Degenerate triangles hold no
+/// points.
+pub fn point_in_triangle(p: Vec3, triangle: &Triangle) -> bool {
+    let (e1, e2, ep) = (triangle.b - triangle.a, triangle.c - triangle.a, p - triangle.a);
+    let (d11, d12, d22) = (e1.dot(e1), e1.dot(e2), e2.dot(e2));
+    let denom = d11 * d22 - d12 * d12;
+    if denom <= 0.0 {
+        return false;
+    }
+
+    let (dp1, dp2) = (ep.dot(e1), ep.dot(e2));
+    let u = (d22 * dp1 - d12 * dp2) / denom;
+    let v = (d11 * dp2 - d12 * dp1) / denom;
+    u >= 0.0 && v >= 0.0 && u + v <= 1.0
+}
+
+/// Which ray-triangle hits [`intersect_ray_triangle`] reports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TriangleTest {
+    /// Skips triangles seen from behind, where the ray travels along their normal (see [`triangle_normal`]).
+    pub cull_backfaces: bool,
+    /// Tests with the watertight algorithm of Woop et al. instead of Möller–Trumbore, so a ray through an edge or
+    /// corner shared by several triangles hits at least one of them rather than slipping between them.
+    pub watertight: bool,
+}
+
+/// Distance along `ray` to where it crosses `triangle`, or `None` if it misses, grazes the triangle edge-on, crosses
+/// it behind the ray origin or sees its back while `test` culls backfaces. Raycast bundles run a deliberate copy of
+/// this test four rays at a time, which must be kept in step with it; the bundle tests compare the two.
+#[inline]
+pub fn intersect_ray_triangle(ray: Ray, triangle: &Triangle, test: TriangleTest) -> Option<f32> {
+    if test.watertight {
+        return intersect_watertight(ray, triangle, test.cull_backfaces);
+    }
+
+    let e1 = triangle.b - triangle.a;
+    let e2 = triangle.c - triangle.a;
+
+    let p = Vec3::cross(ray.dir, e2);
+    let det = Vec3::dot(e1, p);
+    // The determinant is negative where the ray sees the back of the triangle.
+    if det.abs() < TRIANGLE_EPSILON || (test.cull_backfaces && det < 0.0) {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+
+    let tv = ray.pos - triangle.a;
+    let u = Vec3::dot(tv, p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = Vec3::cross(tv, e1);
+    let v = Vec3::dot(ray.dir, q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = Vec3::dot(e2, q) * inv_det;
+    (t >= TRIANGLE_EPSILON).then_some(t)
+}
+
+/// The watertight test: the triangle is sheared so the ray runs along an axis from the origin, and the signs of its
+/// 2D edge functions around the origin decide the hit. Each edge function only depends on the edge, so triangles
+/// sharing one agree on which side of it the ray passes.
+fn intersect_watertight(ray: Ray, triangle: &Triangle, cull_backfaces: bool) -> Option<f32> {
+    let rows = watertight_shear(ray.dir)?;
+    let [a, b, c] = [triangle.a, triangle.b, triangle.c].map(|corner| rows.map(|row| (corner - ray.pos).dot(row)));
+    let [u, v, w] = edge_functions([a[0], a[1]], [b[0], b[1]], [c[0], c[1]]);
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    // The edge functions sum to a negative determinant where the ray sees the back of the triangle.
+    let det = u + v + w;
+    if det == 0.0 || (cull_backfaces && det < 0.0) {
+        return None;
+    }
+    let t = (u * a[2] + v * b[2] + w * c[2]) / det;
+    (t >= TRIANGLE_EPSILON).then_some(t)
+}
+
+/// Rows of the shear the watertight test applies to points relative to a ray origin, so the ray along `dir` runs
+/// along the z axis: the first two give the sheared x and y, the last the distance along the ray per unit of `dir`.
+/// Each row only picks and scales coordinates, so applying it with a dot product rounds like the scalar shear. `None`
+/// for a ray without direction.
+pub fn watertight_shear(dir: Vec3) -> Option<[Vec3; 3]> {
+    let abs = dir.abs();
+    let kz = if abs.x >= abs.y && abs.x >= abs.z {
+        0
+    } else if abs.y >= abs.z {
+        1
+    } else {
+        2
+    };
+    let dir = dir.to_array();
+    if dir[kz] == 0.0 {
+        return None;
+    }
+    // Swapping the other two axes for rays along the negative axis keeps the winding, and so the facing, intact.
+    let (kx, ky) = if dir[kz] < 0.0 { ((kz + 2) % 3, (kz + 1) % 3) } else { ((kz + 1) % 3, (kz + 2) % 3) };
+    let axis = |k: usize, scale: f32| Vec3::from_array([0, 1, 2].map(|i| if i == k { scale } else { 0.0 }));
+    Some([
+        axis(kx, 1.0) + axis(kz, -dir[kx] / dir[kz]),
+        axis(ky, 1.0) + axis(kz, -dir[ky] / dir[kz]),
+        axis(kz, 1.0 / dir[kz]),
+    ])
+}
+
+/// Edge functions of the sheared triangle `a`, `b`, `c` around the origin, opposite each corner in turn. An edge
+/// through the origin is decided in double precision, which is exact for these products.
+pub fn edge_functions(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> [f32; 3] {
+    let ([ax, ay], [bx, by], [cx, cy]) = (a, b, c);
+    let edges = [cx * by - cy * bx, ax * cy - ay * cx, bx * ay - by * ax];
+    if !edges.contains(&0.0) {
+        return edges;
+    }
+    let [ax, ay, bx, by, cx, cy] = [ax, ay, bx, by, cx, cy].map(f64::from);
+    [(cx * by - cy * bx) as f32, (ax * cy - ay * cx) as f32, (bx * ay - by * ax) as f32]
+}
+
+/// Whether the box from `min` to `max` touches the plane of points `x` with `normal · x = offset`.
+pub fn aabb_intersects_plane(min: Vec3, max: Vec3, normal: Vec3, offset: f32) -> bool {
+    let (center, extent) = ((min + max) * 0.5, (max - min) * 0.5);
+    (normal.dot(center) - offset).abs() <= extent.dot(normal.abs())
+}
+
+/// Whether the sphere of `radius` around `center` touches `triangle`.
+pub fn sphere_intersects_triangle(center: Vec3, radius: f32, triangle: &Triangle) -> bool {
+    closest_point_on_triangle(center, triangle).distance_squared(center) <= radius * radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        aabb_intersects_plane, closest_point_on_triangle, intersect_ray_triangle, point_in_triangle,
+        sphere_intersects_triangle, triangle_normal, TriangleTest, TRIANGLE_EPSILON,
+    };
+    use crate::util::{Ray, Triangle};
+    use glam::vec3;
+    use glam::Vec3;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    fn unit() -> Triangle {
+        Triangle { a: Vec3::ZERO, b: Vec3::X, c: Vec3::Y }
+    }
+
+    fn random_point(rng: &mut StdRng) -> Vec3 {
+        vec3(rng.gen_range(-2.0..2.0), rng.gen_range(-2.0..2.0), rng.gen_range(-2.0..2.0))
+    }
+
+    #[test]
+    fn closest_points_fall_in_the_right_region_and_beat_a_dense_sampling() {
+        // Each corner, edge and the face of the unit triangle.
+        for (p, expected) in [
+            (vec3(-1.0, -1.0, 1.0), Vec3::ZERO),
+            (vec3(2.0, -1.0, 0.0), Vec3::X),
+            (vec3(-1.0, 2.0, -1.0), Vec3::Y),
+            (vec3(0.5, -1.0, 3.0), vec3(0.5, 0.0, 0.0)),
+            (vec3(-1.0, 0.5, 0.0), vec3(0.0, 0.5, 0.0)),
+            (vec3(1.0, 1.0, 2.0), vec3(0.5, 0.5, 0.0)),
+            (vec3(0.25, 0.25, -5.0), vec3(0.25, 0.25, 0.0)),
+        ] {
+            assert!(closest_point_on_triangle(p, &unit()).abs_diff_eq(expected, 1e-6), "{}", p);
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let triangle = Triangle { a: random_point(&mut rng), b: random_point(&mut rng), c: random_point(&mut rng) };
+            let p = random_point(&mut rng);
+            let closest = closest_point_on_triangle(p, &triangle);
+            let sampled = itertools::iproduct!(0..=64, 0..=64)
+                .filter(|(i, j)| i + j <= 64)
+                .map(|(i, j)| {
+                    let (u, v) = (i as f32 / 64.0, j as f32 / 64.0);
+                    triangle.a + (triangle.b - triangle.a) * u + (triangle.c - triangle.a) * v
+                })
+                .map(|sample| sample.distance(p))
+                .fold(f32::INFINITY, f32::min);
+            assert!(closest.distance(p) <= sampled + 1e-4, "{} vs {}", closest.distance(p), sampled);
+            assert!(closest.distance(p) >= sampled - 0.1);
+        }
+    }
+
+    #[test]
+    fn points_in_triangles_are_found_in_any_plane() {
+        let floor = Triangle { a: vec3(0.0, -3.0, 0.0), b: vec3(0.0, -3.0, 2.0), c: vec3(2.0, -3.0, 0.0) };
+        let wall = Triangle { a: vec3(5.0, 0.0, 0.0), b: vec3(5.0, 2.0, 0.0), c: vec3(5.0, 0.0, 2.0) };
+        let tilted = Triangle { a: vec3(0.0, 0.0, 1.0), b: vec3(1.0, 0.0, 0.0), c: vec3(0.0, 1.0, 0.0) };
+        for (triangle, inside, outside) in [
+            (&floor, vec3(0.5, 4.0, 0.5), vec3(1.5, -3.0, 1.5)),
+            (&wall, vec3(-1.0, 0.5, 0.5), vec3(5.0, -0.1, 1.0)),
+            (&tilted, Vec3::splat(1.0 / 3.0), vec3(1.0, 1.0, 0.0)),
+            (&unit(), vec3(0.5, 0.5, 1.0), vec3(0.5, 0.51, 0.0)),
+        ] {
+            assert!(point_in_triangle(inside, triangle), "{}", inside);
+            assert!(!point_in_triangle(outside, triangle), "{}", outside);
+            for corner in [triangle.a, triangle.b, triangle.c] {
+                assert!(point_in_triangle(corner, triangle));
+            }
+        }
+        let sliver = Triangle { a: Vec3::ZERO, b: Vec3::X, c: Vec3::X * 2.0 };
+        assert!(!point_in_triangle(Vec3::X, &sliver));
+        assert_eq!(triangle_normal(&sliver), None);
+        assert_eq!(triangle_normal(&unit()), Some(Vec3::Z));
+    }
+
+    /// Every combination of the options of [`TriangleTest`].
+    const TESTS: [TriangleTest; 4] = [
+        TriangleTest { cull_backfaces: false, watertight: false },
+        TriangleTest { cull_backfaces: true, watertight: false },
+        TriangleTest { cull_backfaces: false, watertight: true },
+        TriangleTest { cull_backfaces: true, watertight: true },
+    ];
+
+    /// Plane crossing followed by a barycentric inside test, for comparison.
+    fn naive_intersection(ray: Ray, triangle: &Triangle, test: TriangleTest) -> Option<f32> {
+        let n = Vec3::cross(triangle.b - triangle.a, triangle.c - triangle.a);
+        let facing = ray.dir.dot(n);
+        if facing == 0.0 || (test.cull_backfaces && facing > 0.0) {
+            return None;
+        }
+        let t = (triangle.a - ray.pos).dot(n) / facing;
+        (t >= TRIANGLE_EPSILON && point_in_triangle(ray.pos + ray.dir * t, triangle)).then_some(t)
+    }
+
+    #[test]
+    fn rays_hit_triangles_ahead_of_their_origin_from_the_sides_tested() {
+        let down = |x: f32, y: f32| Ray { pos: vec3(x, y, 2.0), dir: -Vec3::Z };
+        let up = Ray { pos: vec3(0.25, 0.25, -3.0), dir: Vec3::Z };
+        for test in TESTS {
+            let hit = |ray: Ray| intersect_ray_triangle(ray, &unit(), test);
+            assert_eq!(hit(down(0.25, 0.25)), Some(2.0), "{:?}", test);
+            assert_eq!(hit(up), (!test.cull_backfaces).then_some(3.0), "{:?}", test);
+            assert_eq!(hit(Ray { dir: -Vec3::Z, ..up }), None);
+            assert_eq!(hit(down(0.75, 0.75)), None);
+            assert_eq!(hit(down(-0.01, 0.5)), None);
+            assert_eq!(hit(down(0.0, 0.5)), Some(2.0), "{:?}", test);
+            assert_eq!(hit(Ray { pos: vec3(-1.0, 0.25, 0.0), dir: Vec3::X }), None);
+            assert_eq!(hit(Ray { pos: vec3(0.25, 0.25, 0.0), dir: Vec3::Z }), None);
+            assert_eq!(hit(Ray { pos: vec3(0.25, 0.25, 1.0), dir: Vec3::ZERO }), None);
+        }
+    }
+
+    #[test]
+    fn intersections_agree_with_the_naive_test_away_from_edges() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (mut hits, mut compared) = (0, 0);
+        for _ in 0..2000 {
+            let triangle = Triangle { a: random_point(&mut rng), b: random_point(&mut rng), c: random_point(&mut rng) };
+            let (u, v) = (rng.gen_range(-0.3..1.0), rng.gen_range(-0.3..1.0));
+            let target = triangle.a + (triangle.b - triangle.a) * u + (triangle.c - triangle.a) * v;
+            let pos = random_point(&mut rng) * 2.0;
+            let ray = Ray { pos, dir: (target - pos).normalize() };
+            // Near edges and edge-on, rounding may go either way.
+            let normal = triangle_normal(&triangle).unwrap();
+            if [u, v, 1.0 - u - v].iter().any(|w| w.abs() < 1e-3) || ray.dir.dot(normal).abs() < 0.05 {
+                continue;
+            }
+            compared += 1;
+            for test in TESTS {
+                let (actual, expected) =
+                    (intersect_ray_triangle(ray, &triangle, test), naive_intersection(ray, &triangle, test));
+                assert_eq!(actual.is_some(), expected.is_some(), "{:?} {:?} at {}, {}", test, actual, u, v);
+                if let (Some(actual), Some(expected)) = (actual, expected) {
+                    assert!((actual - expected).abs() < 1e-3 * expected.max(1.0), "{} vs {}", actual, expected);
+                    assert!((ray.pos + ray.dir * actual).distance(target) < 1e-3);
+                    hits += 1;
+                }
+            }
+        }
+        assert!(compared > 1500 && hits > 1000, "{} of {}", hits, compared);
+    }
+
+    #[test]
+    fn watertight_rays_cannot_slip_between_triangles_sharing_edges_and_corners() {
+        // A fan of triangles around an off-grid hub, and rays towards the hub and along each shared spoke.
+        let hub = vec3(0.3141, -0.2718, 0.1618);
+        let rim: Vec<Vec3> = (0..7)
+            .map(|i| {
+                let angle = i as f32 / 7.0 * std::f32::consts::TAU;
+                hub + vec3(angle.cos() * 1.7, 0.13 * angle.sin(), angle.sin() * 1.3)
+            })
+            .collect();
+        let fan: Vec<Triangle> =
+            (0..rim.len()).map(|i| Triangle { a: hub, b: rim[(i + 1) % rim.len()], c: rim[i] }).collect();
+
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..2000 {
+            let pos = hub + vec3(rng.gen_range(-3.0..3.0), rng.gen_range(0.5..3.0), rng.gen_range(-3.0..3.0));
+            let spoke = rim[rng.gen_range(0..rim.len())];
+            let target = hub.lerp(spoke, [0.0, rng.gen_range(0.01..0.99)][rng.gen_range(0..2)]);
+            let ray = Ray { pos, dir: (target - pos).normalize() };
+            let test = TriangleTest { watertight: true, ..Default::default() };
+            assert!(
+                fan.iter().any(|triangle| intersect_ray_triangle(ray, triangle, test).is_some()),
+                "towards {}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn boxes_and_spheres_touch_what_they_reach() {
+        let (min, max) = (vec3(-1.0, 0.0, -1.0), vec3(1.0, 2.0, 1.0));
+        assert!(aabb_intersects_plane(min, max, Vec3::Y, 1.0));
+        assert!(aabb_intersects_plane(min, max, Vec3::Y, 2.0));
+        assert!(!aabb_intersects_plane(min, max, Vec3::Y, 2.1));
+        assert!(!aabb_intersects_plane(min, max, -Vec3::Y, 0.5));
+        let diagonal = Vec3::ONE.normalize();
+        assert!(aabb_intersects_plane(min, max, diagonal, 4.0 / 3f32.sqrt() - 1e-4));
+        assert!(!aabb_intersects_plane(min, max, diagonal, 4.0 / 3f32.sqrt() + 1e-4));
+
+        assert!(sphere_intersects_triangle(vec3(0.25, 0.25, 0.9), 1.0, &unit()));
+        assert!(!sphere_intersects_triangle(vec3(0.25, 0.25, 1.1), 1.0, &unit()));
+        assert!(sphere_intersects_triangle(vec3(0.5, -0.9, 0.0), 1.0, &unit()));
+        assert!(sphere_intersects_triangle(vec3(1.2, 1.2, 0.0), 1.0, &unit()));
+        assert!(!sphere_intersects_triangle(vec3(-0.8, -0.8, 0.0), 1.0, &unit()));
+        assert!(sphere_intersects_triangle(vec3(-0.7, -0.7, 0.0), 1.0, &unit()));
+    }
+}
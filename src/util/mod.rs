@@ -0,0 +1,246 @@
+pub mod geom;
+
+use std::mem::MaybeUninit;
+use std::ops::{Deref, DerefMut, Index};
+
+#[derive(Clone, Copy)]
+pub struct Ray {
+    pub pos: glam::Vec3,
+    pub dir: glam::Vec3,
+}
+
+#[derive(Clone)]
+pub struct Triangle {
+    pub a: glam::Vec3,
+    pub b: glam::Vec3,
+    pub c: glam::Vec3,
+}
+
+pub type Frustum = [glam::Vec4; 6];
+
+/// Source of the scanner's random jitter. Every `rand` generator implements it; tests can inject a scripted
+/// sequence instead.
+pub trait ScanRng {
+    /// Uniform sample in `[0, 1)`.
+    fn next_unit(&mut self) -> f32;
+}
+
+impl<R: rand::RngCore + ?Sized> ScanRng for R {
+    fn next_unit(&mut self) -> f32 {
+        rand::Rng::gen(self)
+    }
+}
+
+/// Fixed-capacity vector stored inline, used for octree leaf buckets.
+pub struct SVec<T, const N: usize> {
+    len: usize,
+    buf: [MaybeUninit<T>; N],
+}
+
+impl<T, const N: usize> SVec<T, N> {
+    pub fn new() -> Self {
+        // SAFETY: an array of `MaybeUninit` does not require initialization.
+        Self { len: 0, buf: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() } }
+    }
+
+    /// Collects `iter` into a new vector, or returns `None` if it yields more than `N` items.
+    pub fn try_from_iter<I: IntoIterator<Item = T>>(iter: I) -> Option<Self> {
+        let mut vec = Self::new();
+        for value in iter {
+            if !vec.push(value) {
+                return None;
+            }
+        }
+        Some(vec)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Appends `value`, returning `false` (and dropping `value`) if the vector is full.
+    pub fn push(&mut self, value: T) -> bool {
+        if self.len >= N {
+            return false;
+        }
+        self.buf[self.len].write(value);
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // SAFETY: the slot was initialized and is no longer tracked by `len`, so it is read exactly once.
+        Some(unsafe { self.buf[self.len].assume_init_read() })
+    }
+
+    /// Removes the element at `index` and moves the last one into its slot, so the freed slot is at the end and
+    /// reused by the next push.
+    pub fn swap_remove(&mut self, index: usize) -> T {
+        assert!(index < self.len, "swap_remove index {} out of bounds (len {})", index, self.len);
+        let last = self.len - 1;
+        self.as_mut_slice().swap(index, last);
+        self.pop().unwrap()
+    }
+
+    pub fn clear(&mut self) {
+        let len = self.len;
+        // Reset first so a panicking destructor can at worst leak the remaining elements.
+        self.len = 0;
+        // SAFETY: the first `len` slots were initialized and are no longer reachable through `self`.
+        unsafe {
+            std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(self.buf.as_mut_ptr() as *mut T, len));
+        }
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[T] {
+        // SAFETY: the first `len` slots are initialized.
+        unsafe { std::slice::from_raw_parts(self.buf.as_ptr() as *const T, self.len) }
+    }
+
+    #[inline]
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        // SAFETY: the first `len` slots are initialized.
+        unsafe { std::slice::from_raw_parts_mut(self.buf.as_mut_ptr() as *mut T, self.len) }
+    }
+}
+
+impl<T, const N: usize> Drop for SVec<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, const N: usize> Default for SVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for SVec<T, N> {
+    fn clone(&self) -> Self {
+        let mut vec = Self::new();
+        for value in self.iter() {
+            vec.push(value.clone());
+        }
+        vec
+    }
+}
+
+impl<T: std::fmt::Debug, const N: usize> std::fmt::Debug for SVec<T, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, const N: usize> Index<usize> for SVec<T, N> {
+    type Output = T;
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+impl<T, const N: usize> Deref for SVec<T, N> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T, const N: usize> DerefMut for SVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a SVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SVec;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::rc::Rc;
+
+    #[test]
+    fn svec_matches_vec_under_random_operations() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..200 {
+            let mut svec = SVec::<u32, 16>::new();
+            let mut model = Vec::new();
+            for _ in 0..100 {
+                match rng.gen_range(0..10) {
+                    0..=5 => {
+                        let value = rng.gen();
+                        let pushed = svec.push(value);
+                        assert_eq!(pushed, model.len() < 16);
+                        if pushed {
+                            model.push(value);
+                        }
+                    }
+                    6..=7 => assert_eq!(svec.pop(), model.pop()),
+                    8 if !model.is_empty() => {
+                        let index = rng.gen_range(0..model.len());
+                        assert_eq!(svec.swap_remove(index), model.swap_remove(index));
+                    }
+                    _ => {
+                        svec.clear();
+                        model.clear();
+                    }
+                }
+                assert_eq!(svec.as_slice(), model.as_slice());
+                assert_eq!(svec.len(), model.len());
+            }
+        }
+    }
+
+    #[test]
+    fn svec_drops_every_element_exactly_once() {
+        let token = Rc::new(());
+        {
+            let mut svec = SVec::<Rc<()>, 8>::new();
+            for _ in 0..8 {
+                assert!(svec.push(token.clone()));
+            }
+            assert!(!svec.push(token.clone()));
+            assert_eq!(Rc::strong_count(&token), 9);
+
+            drop(svec.pop());
+            assert_eq!(Rc::strong_count(&token), 8);
+
+            let cloned = svec.clone();
+            assert_eq!(Rc::strong_count(&token), 15);
+            drop(cloned);
+        }
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+
+    #[test]
+    fn svec_try_from_iter_respects_capacity() {
+        let svec = SVec::<usize, 4>::try_from_iter(0..4).unwrap();
+        assert_eq!(svec.as_slice(), &[0, 1, 2, 3]);
+        assert!(SVec::<usize, 4>::try_from_iter(0..5).is_none());
+    }
+}
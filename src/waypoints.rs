@@ -0,0 +1,98 @@
+use super::camera::SPAWN_POS;
+use super::State;
+use glam::Vec3;
+
+/// A named position the player can teleport back to. Waypoints are stored in scan files alongside the marks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Waypoint {
+    pub name: String,
+    pub pos: Vec3,
+}
+
+#[derive(Default)]
+pub struct Waypoints {
+    pub list: Vec<Waypoint>,
+    selected: Option<usize>,
+}
+
+impl Waypoints {
+    pub fn new() -> Self {
+        Self { list: Vec::new(), selected: None }
+    }
+
+    /// Replaces every waypoint, e.g. with the ones read from a save file.
+    pub fn set(&mut self, list: Vec<Waypoint>) {
+        self.list = list;
+        self.selected = None;
+    }
+
+    /// Follows the local origin to `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        for waypoint in &mut self.list {
+            waypoint.pos -= shift;
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Waypoint> {
+        self.list.get(index)
+    }
+
+    /// Moves the selection `step` entries forward (or backward), wrapping around, and returns the new index.
+    pub fn cycle(&mut self, step: isize) -> Option<usize> {
+        if self.list.is_empty() {
+            return None;
+        }
+        let n = self.list.len() as isize;
+        let index = match self.selected {
+            Some(index) => (index as isize + step).rem_euclid(n),
+            None if step < 0 => n - 1,
+            None => 0,
+        } as usize;
+        self.selected = Some(index);
+        self.selected
+    }
+}
+
+impl State {
+    /// Drops a waypoint at the camera position, named after its index.
+    pub fn drop_waypoint(&mut self) {
+        self.add_waypoint(self.camera.pos);
+    }
+
+    pub fn add_waypoint(&mut self, pos: Vec3) {
+        let name = format!("waypoint {}", self.waypoints.list.len() + 1);
+        self.waypoints.list.push(Waypoint { name: name.clone(), pos });
+        self.notify(format!("dropped {}", name));
+    }
+
+    pub fn teleport_to_waypoint(&mut self, index: usize) {
+        match self.waypoints.get(index) {
+            Some(waypoint) => {
+                let (name, pos) = (waypoint.name.clone(), waypoint.pos);
+                self.teleport(pos);
+                self.notify(format!("teleported to {}", name));
+            }
+            None => self.notify(format!("no waypoint {}", index + 1)),
+        }
+    }
+
+    /// Teleports to the next (`step > 0`) or previous waypoint in drop order.
+    pub fn cycle_waypoint(&mut self, step: isize) {
+        match self.waypoints.cycle(step) {
+            Some(index) => self.teleport_to_waypoint(index),
+            None => self.notify("no waypoints dropped".to_string()),
+        }
+    }
+
+    pub fn return_to_origin(&mut self) {
+        self.teleport(SPAWN_POS);
+        self.notify("returned to origin".to_string());
+    }
+
+    /// Moves the camera instantly, keeping its orientation.
+    fn teleport(&mut self, pos: Vec3) {
+        let mut viewpoint = self.camera.viewpoint();
+        viewpoint.pos = pos;
+        self.camera.set_viewpoint(viewpoint);
+    }
+}
@@ -0,0 +1,68 @@
+use super::{SCALE, SEED};
+use glam::DVec3;
+use noise::NoiseFn;
+use rayon::prelude::*;
+
+/// Grids with at least this many points are sampled on the rayon thread pool.
+const PARALLEL_MIN_SAMPLES: usize = 1024;
+
+/// Density field the marching-cubes [`super::World`] meshes. Shared with the threads meshing it in the background.
+pub trait TerrainGenerator: Send + Sync {
+    /// Density at the world position `pos` in `[0, 1]`; the surface lies at `SURFACE_THRESHOLD`. Positions are in
+    /// double precision so the field stays smooth however far the local origin was rebased.
+    fn surface_level(&self, pos: DVec3) -> f64;
+
+    /// Densities at `min + step * [x, y, z]` for every index below `dims`, written to `out` with `x` varying fastest.
+    /// Meshing a region samples its lattice through this in one pass, so generators can batch the work.
+    fn sample_grid(&self, min: DVec3, step: f64, dims: [usize; 3], out: &mut [f64]) {
+        for (i, level) in out.iter_mut().enumerate() {
+            *level = self.surface_level(grid_point(min, step, dims, i));
+        }
+    }
+}
+
+/// Position of the `i`th point of a grid laid out as in [`TerrainGenerator::sample_grid`].
+#[inline]
+fn grid_point(min: DVec3, step: f64, dims: [usize; 3], i: usize) -> DVec3 {
+    let index = [i % dims[0], i / dims[0] % dims[1], i / dims[0] / dims[1]];
+    min + step * DVec3::from_array(index.map(|i| i as f64))
+}
+
+/// The procedural caves: 3D simplex noise.
+pub struct NoiseGenerator {
+    noise: noise::SuperSimplex,
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        Self::new(SEED)
+    }
+}
+
+impl NoiseGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self { noise: noise::SuperSimplex::new(seed) }
+    }
+}
+
+impl TerrainGenerator for NoiseGenerator {
+    #[inline]
+    fn surface_level(&self, pos: DVec3) -> f64 {
+        let noise_pos = SCALE as f64 * pos;
+        (self.noise.get(noise_pos.to_array()) + 1.0) * 0.5
+    }
+
+    /// Samples rows of the grid in parallel once it is large enough to be worth the hand-off.
+    fn sample_grid(&self, min: DVec3, step: f64, dims: [usize; 3], out: &mut [f64]) {
+        let sample_row = |(row, levels): (usize, &mut [f64])| {
+            for (x, level) in levels.iter_mut().enumerate() {
+                *level = self.surface_level(grid_point(min, step, dims, row * dims[0] + x));
+            }
+        };
+        if out.len() >= PARALLEL_MIN_SAMPLES && rayon::current_num_threads() > 1 {
+            out.par_chunks_mut(dims[0]).enumerate().for_each(sample_row);
+        } else {
+            out.chunks_mut(dims[0]).enumerate().for_each(sample_row);
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use super::{Terrain, MAX_RAY_LENGTH, SEED, SURFACE_THRESHOLD};
+use crate::util::{Ray, Triangle};
+use glam::{vec3, DVec3, Vec3};
+use noise::NoiseFn;
+
+const CELL_SIZE: f32 = 5.0;
+const SCALE: f32 = 0.005;
+const BASE_HEIGHT: f32 = -60.0;
+const AMPLITUDE: f32 = 40.0;
+const DENSITY_FALLOFF: f32 = 0.05;
+const N_REFINEMENTS: i32 = 8;
+
+/// Open terrain defined by a 2D noise height map, meshed as two triangles per grid cell.
+pub struct Heightfield {
+    noise: noise::SuperSimplex,
+    /// World position of the local origin.
+    origin: DVec3,
+}
+
+impl Default for Heightfield {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heightfield {
+    pub fn new() -> Self {
+        Self { noise: noise::SuperSimplex::new(SEED), origin: DVec3::ZERO }
+    }
+
+    #[inline]
+    fn height(&self, x: f32, z: f32) -> f32 {
+        let noise_pos = [(self.origin.x + x as f64) * SCALE as f64, (self.origin.z + z as f64) * SCALE as f64];
+        (BASE_HEIGHT as f64 + self.noise.get(noise_pos) * AMPLITUDE as f64 - self.origin.y) as f32
+    }
+
+    #[inline]
+    fn vertex(&self, x: f32, z: f32) -> Vec3 {
+        vec3(x, self.height(x, z), z)
+    }
+}
+
+impl Terrain for Heightfield {
+    fn raycast(&mut self, ray: Ray, dist: f32) -> Option<Vec3> {
+        let max_t = if dist <= 0.0 { MAX_RAY_LENGTH } else { dist };
+        let above = |t: f32| {
+            let p = ray.pos + t * ray.dir;
+            p.y > self.height(p.x, p.z)
+        };
+
+        if !above(0.0) {
+            return Some(ray.pos);
+        }
+
+        let step = CELL_SIZE * 0.5;
+        let mut t = 0.0;
+        while t < max_t {
+            let next_t = f32::min(t + step, max_t);
+            if !above(next_t) {
+                let (mut lo, mut hi) = (t, next_t);
+                for _ in 0..N_REFINEMENTS {
+                    let mid = (lo + hi) * 0.5;
+                    if above(mid) {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                return Some(ray.pos + hi * ray.dir);
+            }
+            t = next_t;
+        }
+
+        None
+    }
+
+    fn retrieve_triangles(&mut self, center: Vec3, dist: f32) -> Vec<Triangle> {
+        let mut tri_list = Vec::new();
+        let base_cell = (center / CELL_SIZE).floor();
+
+        let off_dist = (dist / CELL_SIZE).ceil() as i32;
+        for (i, j) in itertools::iproduct!(-off_dist..=off_dist, -off_dist..=off_dist) {
+            let x = (base_cell.x + i as f32) * CELL_SIZE;
+            let z = (base_cell.z + j as f32) * CELL_SIZE;
+
+            let v00 = self.vertex(x, z);
+            let v01 = self.vertex(x, z + CELL_SIZE);
+            let v10 = self.vertex(x + CELL_SIZE, z);
+            let v11 = self.vertex(x + CELL_SIZE, z + CELL_SIZE);
+
+            tri_list.push(Triangle { a: v00, b: v01, c: v11 });
+            tri_list.push(Triangle { a: v00, b: v11, c: v10 });
+        }
+
+        tri_list
+    }
+
+    fn surface_level(&self, pos: Vec3) -> f64 {
+        let depth = self.height(pos.x, pos.z) - pos.y;
+        (SURFACE_THRESHOLD + (depth * DENSITY_FALLOFF) as f64).clamp(0.0, 1.0)
+    }
+
+    fn rebase(&mut self, shift: Vec3) {
+        self.origin += shift.as_dvec3();
+    }
+
+    fn detached(&self) -> Box<dyn Terrain + Send> {
+        Box::new(Heightfield { noise: self.noise, origin: self.origin })
+    }
+}
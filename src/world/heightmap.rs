@@ -0,0 +1,76 @@
+use super::generator::TerrainGenerator;
+use super::SURFACE_THRESHOLD;
+use glam::DVec3;
+use std::path::Path;
+
+/// World units between neighbouring pixels.
+const PIXEL_SIZE: f32 = 2.0;
+/// Height of black pixels.
+const BASE_HEIGHT: f32 = -100.0;
+/// Height difference between black and white pixels.
+const AMPLITUDE: f32 = 150.0;
+const DENSITY_FALLOFF: f32 = 0.05;
+
+/// Open terrain read from a grayscale PNG, 8 or 16 bits per pixel, centered on the origin. Heights are interpolated
+/// between pixels and the edge pixels extend outwards.
+pub struct HeightmapGenerator {
+    width: usize,
+    height: usize,
+    /// Row-major heights in world units.
+    heights: Vec<f32>,
+}
+
+impl HeightmapGenerator {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+        // Expand palettes and low bit depths to whole bytes, but keep 16-bit samples.
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info().map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let channels = info.color_type.samples();
+        let levels: Vec<f32> = match info.bit_depth {
+            png::BitDepth::Sixteen => buf[..info.buffer_size()]
+                .chunks_exact(2 * channels)
+                .map(|pixel| u16::from_be_bytes([pixel[0], pixel[1]]) as f32 / u16::MAX as f32)
+                .collect(),
+            _ => buf[..info.buffer_size()].chunks_exact(channels).map(|pixel| pixel[0] as f32 / 255.0).collect(),
+        };
+        Ok(Self::from_levels(info.width as usize, info.height as usize, &levels))
+    }
+
+    /// Builds a heightmap from `width * height` row-major levels in `[0, 1]`.
+    pub fn from_levels(width: usize, height: usize, levels: &[f32]) -> Self {
+        let heights = levels.iter().map(|level| BASE_HEIGHT + level * AMPLITUDE).collect();
+        Self { width, height, heights }
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> f32 {
+        let x = x.clamp(0, self.width as i64 - 1) as usize;
+        let y = y.clamp(0, self.height as i64 - 1) as usize;
+        self.heights[y * self.width + x]
+    }
+
+    /// Bilinearly interpolated height at the world position `(x, z)`.
+    fn height_at(&self, x: f32, z: f32) -> f32 {
+        let px = x / PIXEL_SIZE + self.width as f32 * 0.5;
+        let py = z / PIXEL_SIZE + self.height as f32 * 0.5;
+        let (x0, y0) = (px.floor(), py.floor());
+        let (tx, ty) = (px - x0, py - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.pixel(x0, y0) * (1.0 - tx) + self.pixel(x0 + 1, y0) * tx;
+        let bottom = self.pixel(x0, y0 + 1) * (1.0 - tx) + self.pixel(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+impl TerrainGenerator for HeightmapGenerator {
+    fn surface_level(&self, pos: DVec3) -> f64 {
+        let pos = pos.as_vec3();
+        let depth = self.height_at(pos.x, pos.z) - pos.y;
+        (SURFACE_THRESHOLD + (depth * DENSITY_FALLOFF) as f64).clamp(0.0, 1.0)
+    }
+}
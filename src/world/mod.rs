@@ -1,183 +1,1103 @@
-use super::util::{Ray, Triangle};
-use glam::{vec3, Vec3};
-use noise::NoiseFn;
+use super::origin::REBASE_GRID;
+use super::util::geom::{self, TriangleTest, TRIANGLE_EPSILON};
+use super::util::{Ray, SVec, Triangle};
+use glam::{DVec3, Vec3, Vec4};
+use prefetch::{Prefetcher, Snapshot};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 
+pub use generator::{NoiseGenerator, TerrainGenerator};
+pub use heightfield::Heightfield;
+pub use heightmap::HeightmapGenerator;
+pub use plane::Plane;
+pub use sdf::SdfGenerator;
+pub use voxel::{Voxel, CHUNK_VOXELS};
+
+mod generator;
+mod heightfield;
+mod heightmap;
+mod plane;
+mod prefetch;
+mod sdf;
 mod tables;
+mod voxel;
 
 const SEED: u32 = 115;
 const SCALE: f32 = 0.01;
-const SURFACE_THRESHOLD: f64 = 0.5;
+/// Density of the surface; denser points are inside the terrain.
+pub const SURFACE_THRESHOLD: f64 = 0.5;
+
+/// Edge length of the voxels a [`World`] is meshed with unless configured otherwise.
+pub const DEFAULT_VOXEL_SIZE: f32 = 5.0;
+pub const MIN_VOXEL_SIZE: f32 = 1.0;
+pub const MAX_VOXEL_SIZE: f32 = 20.0;
+/// How many times finer the lattice near the ray origin is.
+const DETAIL_FACTOR: f32 = 2.0;
+/// Most coarser levels of detail a [`World`] meshes distant terrain with, each with twice the voxel size of the last.
+pub const MAX_LOD_LEVELS: u32 = 4;
+/// Range of a raycast without an explicit distance.
+pub const MAX_RAY_LENGTH: f32 = 1500.0;
+/// Relative distance within which a ray crossing several voxel boundaries is taken to cross them at one point.
+const GRAZE_TOLERANCE: f32 = 1e-5;
+
+/// Geometry the scanner can cast rays against and the camera can collide with.
+pub trait Terrain {
+    /// Returns the first hit of `ray` within `dist` world units, or within the maximum scan range if `dist <= 0`.
+    fn raycast(&mut self, ray: Ray, dist: f32) -> Option<Vec3>;
+
+    /// Like [`Terrain::raycast`], testing the ray against triangles with `test`, e.g. watertight so it cannot slip
+    /// through the seams between them. Terrains that do not trace rays against triangles ignore `test`.
+    fn raycast_with(&mut self, ray: Ray, dist: f32, _test: TriangleTest) -> Option<Vec3> {
+        self.raycast(ray, dist)
+    }
+
+    /// Returns the first hit of each of `rays` as [`Terrain::raycast_with`] would, in order. Terrains that can trace
+    /// a bundle of nearly parallel rays, such as the scanner cone's, faster together than one by one override this.
+    fn raycast_bundle(&mut self, rays: &[Ray], dist: f32, test: TriangleTest) -> Vec<Option<Vec3>> {
+        rays.iter().map(|ray| self.raycast_with(*ray, dist, test)).collect()
+    }
+
+    /// Returns every triangle of the surface within (roughly) `dist` world units of `center`.
+    fn retrieve_triangles(&mut self, center: Vec3, dist: f32) -> Vec<Triangle>;
+
+    /// Calls `f` with every triangle of the surface near the sphere of `radius` around `center`, without collecting
+    /// them first. Terrains that cache their triangles visit them in place, and only those of voxels the sphere
+    /// touches.
+    fn for_each_triangle_in_radius(&mut self, center: Vec3, radius: f32, f: &mut dyn FnMut(&Triangle)) {
+        self.retrieve_triangles(center, radius).iter().for_each(f);
+    }
+
+    /// Density at the world position `pos` in `[0, 1]`; the surface lies at `SURFACE_THRESHOLD`.
+    fn surface_level(&self, pos: Vec3) -> f64;
+
+    /// Adds `strength` to the density within `radius` of `center`, fading out smoothly towards the edge, so positive
+    /// strengths build terrain up and negative ones dig it away. Returns the change, or `None` if the terrain cannot
+    /// be edited.
+    fn edit_density(&mut self, _center: Vec3, _radius: f32, _strength: f64) -> Option<DensityEdit> {
+        None
+    }
+
+    /// Sets the edited density at the given voxel corners, e.g. to take back a [`DensityEdit`].
+    fn restore_density(&mut self, _points: &mut dyn Iterator<Item = (Voxel, f64)>) {}
+
+    /// Moves the local origin to `shift`, so the terrain that was at `shift` is at the origin afterwards. `shift` is
+    /// a multiple of [`REBASE_GRID`], which every terrain grid divides.
+    fn rebase(&mut self, shift: Vec3);
+
+    /// A copy of the surface to raycast on another thread. It shares what the surface is generated from, edits
+    /// included, but none of the caches, so it meshes whatever its rays reach anew.
+    fn detached(&self) -> Box<dyn Terrain + Send>;
+
+    /// Voxel size and detail band, for terrains meshed on a voxel lattice.
+    fn resolution(&self) -> Option<(f32, f32)> {
+        None
+    }
+
+    /// Re-meshes the terrain with voxels of edge `voxel_size`, and with voxels half that size within `detail_band`
+    /// of each ray's origin if it is positive. Edits are kept; the generator is unchanged.
+    fn set_resolution(&mut self, _voxel_size: f32, _detail_band: f32) -> Result<(), String> {
+        Err("this terrain has no voxel resolution".to_string())
+    }
 
-const VOXEL_SIZE: f32 = 5.0;
-const MAX_RAY_DIST: i32 = (1500.0 / VOXEL_SIZE) as i32;
+    /// Meshes terrain beyond `distance` from each ray's origin with `levels` coarser lattices, doubling the voxel size
+    /// and the distance at each level. No levels means full resolution at any distance.
+    fn set_lod(&mut self, _levels: u32, _distance: f32) -> Result<(), String> {
+        Err("this terrain has no levels of detail".to_string())
+    }
+
+    /// Meshes the terrain around `center` in the background ahead of rays, favoring what lies along `heading`, and
+    /// takes in what finished since the last call. Meant to be called every frame.
+    fn prefetch(&mut self, _center: Vec3, _heading: Vec3) {}
+
+    /// How well cached density samples are being reused, for terrains that sample a density field.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+}
+
+/// Density samples a terrain keeps and how often it reused one instead of sampling again.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Lattice points currently cached.
+    pub cached: usize,
+    pub reused: u64,
+    pub sampled: u64,
+}
+
+impl CacheStats {
+    /// Fraction of lookups served from the cache, `None` before the first one.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.reused + self.sampled;
+        (lookups > 0).then(|| self.reused as f64 / lookups as f64)
+    }
+}
 
-type Voxel = (i32, i32, i32);
+/// The density a terrain edit added at each voxel corner it touched, before and after, so the edit can be taken back
+/// and applied again.
+#[derive(Clone, Debug)]
+pub struct DensityEdit {
+    pub points: HashMap<Voxel, (f64, f64)>,
+    /// Edge of the lattice the points are on.
+    pub voxel_size: f32,
+}
+
+impl DensityEdit {
+    /// Folds a later edit into this one, keeping the oldest value of each point.
+    pub fn merge(&mut self, later: DensityEdit) {
+        for (point, (before, after)) in later.points {
+            self.points.entry(point).or_insert((before, after)).1 = after;
+        }
+    }
+
+    /// Approximate memory held by the edit.
+    pub fn size(&self) -> usize {
+        self.points.capacity() * std::mem::size_of::<(Voxel, (f64, f64))>()
+    }
+
+    /// Moves the edit along with a rebase of the terrain by `shift`.
+    pub fn rebase(&mut self, shift: Vec3) {
+        let offset = Voxel::of_shift(shift, self.voxel_size).index().map(|i| -i);
+        self.points = self.points.drain().map(|(point, values)| (point.offset(offset), values)).collect();
+    }
+
+    /// Center and radius of a sphere holding every touched point.
+    pub fn bounds(&self) -> (Vec3, f32) {
+        let points = self.points.keys().map(|point| point.corner(self.voxel_size).as_vec3());
+        let (min, max) = points.fold((Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)), |(min, max), p| {
+            (min.min(p), max.max(p))
+        });
+        ((min + max) * 0.5, (max - min).length() * 0.5)
+    }
+}
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TerrainKind {
+    Caves,
+    Heightfield,
+    Plane,
+    /// Marching cubes over a grayscale PNG heightmap.
+    Heightmap(PathBuf),
+    /// Marching cubes over a voxel signed distance field, see [`SdfGenerator`].
+    Sdf(PathBuf),
+}
+
+impl TerrainKind {
+    pub fn create(&self) -> Result<Box<dyn Terrain>, String> {
+        Ok(match self {
+            TerrainKind::Caves => Box::new(World::new()),
+            TerrainKind::Heightfield => Box::new(Heightfield::new()),
+            TerrainKind::Plane => Box::new(Plane::new()),
+            TerrainKind::Heightmap(path) => Box::new(World::with_generator(HeightmapGenerator::load(path)?)),
+            TerrainKind::Sdf(path) => Box::new(World::with_generator(SdfGenerator::load(path)?)),
+        })
+    }
+}
+
+impl FromStr for TerrainKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("heightmap", path)) => return Ok(TerrainKind::Heightmap(path.into())),
+            Some(("sdf", path)) => return Ok(TerrainKind::Sdf(path.into())),
+            _ => {}
+        }
+        match s {
+            "caves" => Ok(TerrainKind::Caves),
+            "heightfield" => Ok(TerrainKind::Heightfield),
+            "plane" => Ok(TerrainKind::Plane),
+            _ => Err(format!(
+                "unknown terrain '{}', expected one of: caves, heightfield, plane, heightmap:FILE.png, sdf:FILE.vsdf",
+                s
+            )),
+        }
+    }
+}
+
+/// Terrain meshed with marching cubes from the density field of a [`TerrainGenerator`], voxel by voxel as it is
+/// queried. Optionally, rays are marched on a finer lattice within a band around their origin, which is where the
+/// scanner is, so nearby surfaces show more detail than distant ones; and on coarser lattices far from it, so long
+/// rays mesh and cache fewer triangles.
 pub struct World {
-    noise: noise::SuperSimplex,
-    triangle_cache: HashMap<Voxel, Vec<Triangle>>,
+    generator: Arc<dyn TerrainGenerator>,
+    lattice: Lattice,
+    detail: Option<Detail>,
+    /// Coarser levels of detail, each twice the voxel size of the one before.
+    lods: Vec<Lattice>,
+    /// Distance from the ray origin where the first level of detail takes over.
+    lod_distance: f32,
+    /// Density added by edits at the corners of `lattice`, on top of the generator's.
+    edits: Arc<HashMap<Voxel, f64>>,
+    /// World position of the local origin.
+    origin: DVec3,
+    prefetcher: Prefetcher,
+}
+
+/// The finer lattice rays are marched on near their origin.
+struct Detail {
+    lattice: Lattice,
+    /// Distance from the ray origin covered by the fine lattice.
+    band: f32,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl World {
+    /// Creates an empty world; voxel triangles are generated lazily from the noise field as they are queried.
     pub fn new() -> Self {
-        Self { noise: noise::SuperSimplex::new(SEED), triangle_cache: HashMap::new() }
+        Self::with_seed(SEED)
     }
 
-    pub fn retrieve_triangles(&mut self, center: Vec3, dist: f32) -> Vec<Triangle> {
-        let mut tri_list = Vec::new();
-        let base_voxel = (center / VOXEL_SIZE).floor();
+    /// Creates an empty world from a different noise seed.
+    pub fn with_seed(seed: u32) -> Self {
+        Self::with_generator(NoiseGenerator::new(seed))
+    }
 
-        let off_dist = (dist / VOXEL_SIZE).ceil() as i32;
-        for off in itertools::iproduct!(-off_dist..=off_dist, -off_dist..=off_dist, -off_dist..=off_dist) {
-            let voxel = base_voxel + vec3(off.0 as f32, off.1 as f32, off.2 as f32);
-            let mut triangles = self.voxel_triangles(voxel);
-            tri_list.append(&mut triangles);
+    /// Creates an empty world meshing the density field of `generator`.
+    pub fn with_generator(generator: impl TerrainGenerator + 'static) -> Self {
+        Self {
+            generator: Arc::new(generator),
+            lattice: Lattice::new(DEFAULT_VOXEL_SIZE),
+            detail: None,
+            lods: Vec::new(),
+            lod_distance: f32::INFINITY,
+            edits: Arc::new(HashMap::new()),
+            origin: DVec3::ZERO,
+            prefetcher: Prefetcher::new(),
         }
+    }
 
-        tri_list
+    /// Triangles of `voxel` on the coarse lattice.
+    fn voxel_triangles(&mut self, voxel: Voxel) -> &[Triangle] {
+        let (lattice, field) = self.lattice_and_field();
+        lattice.voxel_triangles(&field, voxel)
     }
 
-    pub fn raycast(&mut self, ray: Ray, dist: f32) -> Option<Vec3> {
-        let mut cur_voxel = (ray.pos / VOXEL_SIZE).floor();
+    /// The coarse lattice and the density field it is meshed from, borrowed apart so the lattice can cache the
+    /// field's triangles while it is read.
+    fn lattice_and_field(&mut self) -> (&mut Lattice, Field<'_>) {
+        let (lattice, _, _, field) = self.lattices_and_field();
+        (lattice, field)
+    }
 
-        if let Some(t_hit) = self.voxel_collision(cur_voxel, ray) {
-            return handle_hit(ray, t_hit, dist);
+    /// Every lattice, coarse, fine and levels of detail, with the density field they are meshed from.
+    fn lattices_and_field(&mut self) -> (&mut Lattice, Option<&mut Detail>, &mut [Lattice], Field<'_>) {
+        let field = Field {
+            generator: &*self.generator,
+            edits: &self.edits,
+            edit_size: self.lattice.voxel_size,
+            origin: self.origin,
+        };
+        (&mut self.lattice, self.detail.as_mut(), &mut self.lods, field)
+    }
+
+    fn field(&self) -> Field<'_> {
+        Field {
+            generator: &*self.generator,
+            edits: &self.edits,
+            edit_size: self.lattice.voxel_size,
+            origin: self.origin,
         }
+    }
 
-        let step = {
-            let _step = |x: f32| (x < 0.0).then_some(-1.0).unwrap_or(1.0);
-            vec3(_step(ray.dir.x), _step(ray.dir.y), _step(ray.dir.z))
+    /// The density field, and the lattices rays of `length` are marched on with the distance from the ray origin
+    /// where each one's reach ends. Each lattice takes over where the previous one's reach ends, finest first.
+    fn segments(&mut self, length: f32) -> (Field<'_>, Vec<(&mut Lattice, f32)>) {
+        let lod_distance = self.lod_distance;
+        let (lattice, detail, lods, field) = self.lattices_and_field();
+        let mut segments: Vec<(&mut Lattice, f32)> = Vec::with_capacity(lods.len() + 2);
+        if let Some(detail) = detail.filter(|detail| detail.band > 0.0) {
+            segments.push((&mut detail.lattice, detail.band));
+        }
+        segments.push((lattice, lod_distance));
+        let mut reach = lod_distance;
+        for lod in lods {
+            reach *= 2.0;
+            segments.push((lod, reach));
+        }
+        if let Some(last) = segments.last_mut() {
+            last.1 = length;
+        }
+        (field, segments)
+    }
+}
+
+impl Terrain for World {
+    fn retrieve_triangles(&mut self, center: Vec3, dist: f32) -> Vec<Triangle> {
+        let mut tri_list = Vec::new();
+        let size = self.lattice.voxel_size;
+        let Some(base_voxel) = Voxel::containing(center, size) else {
+            return tri_list;
         };
 
-        let inv_dir = 1.0 / ray.dir;
-        let mut t = {
-            let min = (ray.pos / VOXEL_SIZE).floor() * VOXEL_SIZE;
-            let max = min + VOXEL_SIZE;
+        let off_dist = (dist / size).ceil() as i64;
+        let (lattice, field) = self.lattice_and_field();
+        lattice.mesh_region(&field, base_voxel.offset([-off_dist; 3]), base_voxel.offset([off_dist; 3]));
+        for (x, y, z) in itertools::iproduct!(-off_dist..=off_dist, -off_dist..=off_dist, -off_dist..=off_dist) {
+            tri_list.extend_from_slice(self.voxel_triangles(base_voxel.offset([x, y, z])));
+        }
 
-            let t1 = (min - ray.pos) * inv_dir;
-            let t2 = (max - ray.pos) * inv_dir;
+        tri_list
+    }
 
-            Vec3::max(t1, t2)
+    fn for_each_triangle_in_radius(&mut self, center: Vec3, radius: f32, f: &mut dyn FnMut(&Triangle)) {
+        let size = self.lattice.voxel_size;
+        let (Some(min), Some(max)) =
+            (Voxel::containing(center - radius, size), Voxel::containing(center + radius, size))
+        else {
+            return;
         };
+        let (lattice, field) = self.lattice_and_field();
+        lattice.mesh_region(&field, min, max);
 
-        let delta_t = VOXEL_SIZE * inv_dir * step;
-        let mut voxel_incr = Vec3::ZERO;
-
-        let voxel_dist =
-            if dist <= 0.0 { MAX_RAY_DIST } else { i32::max((dist / VOXEL_SIZE as f32).ceil() as i32, MAX_RAY_DIST) };
+        let span = max.delta(min);
+        for (x, y, z) in itertools::iproduct!(0..=span[0], 0..=span[1], 0..=span[2]) {
+            let voxel = min.offset([x, y, z]);
+            // The corners of the box around the sphere lie outside it.
+            let corner = voxel.corner(size).as_vec3();
+            if center.clamp(corner, corner + size).distance_squared(center) > radius * radius {
+                continue;
+            }
+            lattice.voxel_triangles(&field, voxel).iter().for_each(&mut *f);
+        }
+    }
 
-        for _ in 0..voxel_dist {
-            voxel_incr.x = ((t.x <= t.y) && (t.x <= t.z)) as u32 as f32;
-            voxel_incr.y = ((t.y <= t.x) && (t.y <= t.z)) as u32 as f32;
-            voxel_incr.z = ((t.z <= t.x) && (t.z <= t.y)) as u32 as f32;
+    fn raycast(&mut self, ray: Ray, dist: f32) -> Option<Vec3> {
+        self.raycast_with(ray, dist, TriangleTest::default())
+    }
 
-            t += voxel_incr * delta_t;
-            cur_voxel += voxel_incr * step;
+    fn raycast_with(&mut self, ray: Ray, dist: f32, test: TriangleTest) -> Option<Vec3> {
+        let length = ray_length(dist);
+        let (field, segments) = self.segments(length);
 
-            if let Some(t_hit) = self.voxel_collision(cur_voxel, ray) {
-                return handle_hit(ray, t_hit, dist);
+        let (mut start, mut open) = (0.0, None);
+        for (lattice, end) in segments {
+            let end = f32::min(end, length);
+            if end <= start {
+                continue;
             }
+            let segment = Ray { pos: ray.pos + ray.dir * start, dir: ray.dir };
+            // Where the ray leaves one lattice in the open but the next one's surface encloses the switch point, the
+            // gap between the two meshes is closed by a wall at the switch, like the skirts between transvoxel cells,
+            // so rays cannot slip through it.
+            if open == Some(true) && lattice.solid_at(&field, segment.pos) {
+                return handle_hit(ray, start, dist);
+            }
+            if let Some(t) = lattice.march(&field, segment, end - start, test) {
+                return handle_hit(ray, start + t, dist);
+            }
+            open = Some(!lattice.solid_at(&field, ray.pos + ray.dir * end));
+            start = end;
         }
-
         None
     }
 
-    #[inline]
-    fn voxel_collision(&mut self, voxel: Vec3, ray: Ray) -> Option<f32> {
-        for triangle in self.voxel_triangles(voxel) {
-            const EPSILON: f32 = 0.0001;
-
-            let e1 = triangle.b - triangle.a;
-            let e2 = triangle.c - triangle.a;
+    /// Traces the rays through each lattice segment together, see [`Lattice::march_bundle`], with the same switches
+    /// between lattices as [`World::raycast`].
+    fn raycast_bundle(&mut self, rays: &[Ray], dist: f32, test: TriangleTest) -> Vec<Option<Vec3>> {
+        let length = ray_length(dist);
+        let (field, segments) = self.segments(length);
 
-            let p = Vec3::cross(ray.dir, e2);
-            let det = Vec3::dot(e1, p);
-            if det.abs() < EPSILON {
+        let mut hits = vec![None; rays.len()];
+        // Rays still travelling, and whether each left the previous lattice in the open.
+        let mut pending: Vec<(usize, Option<bool>)> = (0..rays.len()).map(|i| (i, None)).collect();
+        let mut start = 0.0;
+        for (lattice, end) in segments {
+            let end = f32::min(end, length);
+            if end <= start || pending.is_empty() {
                 continue;
             }
+            pending.retain(|&(i, open)| {
+                let walled = open == Some(true) && lattice.solid_at(&field, rays[i].pos + rays[i].dir * start);
+                if walled {
+                    hits[i] = handle_hit(rays[i], start, dist);
+                }
+                !walled
+            });
+
+            let segments: Vec<Ray> = pending
+                .iter()
+                .map(|&(i, _)| Ray { pos: rays[i].pos + rays[i].dir * start, dir: rays[i].dir })
+                .collect();
+            let ts = lattice.march_bundle(&field, &segments, end - start, test);
+            pending = pending
+                .into_iter()
+                .zip(ts)
+                .filter_map(|((i, _), t)| match t {
+                    Some(t) => {
+                        hits[i] = handle_hit(rays[i], start + t, dist);
+                        None
+                    }
+                    None => Some((i, Some(!lattice.solid_at(&field, rays[i].pos + rays[i].dir * end)))),
+                })
+                .collect();
+            start = end;
+        }
+        hits
+    }
 
-            let inv_det = 1.0 / det;
+    #[inline]
+    fn surface_level(&self, pos: Vec3) -> f64 {
+        self.field().density(pos.as_dvec3())
+    }
 
-            let tv = ray.pos - triangle.a;
-            let u = Vec3::dot(tv, p) * inv_det;
-            if u < 0.0 || u > 1.0 {
+    fn edit_density(&mut self, center: Vec3, radius: f32, strength: f64) -> Option<DensityEdit> {
+        let size = self.lattice.voxel_size;
+        let mut edit = DensityEdit { points: HashMap::new(), voxel_size: size };
+        let (Some(min), Some(max)) =
+            (Voxel::containing(center - radius, size), Voxel::containing(center + radius, size))
+        else {
+            return Some(edit);
+        };
+        let span = max.delta(min);
+        for (x, y, z) in itertools::iproduct!(0..=span[0], 0..=span[1], 0..=span[2]) {
+            let point = min.offset([x, y, z]);
+            let dist = point.corner(size).distance(center.as_dvec3()) as f32;
+            if dist >= radius {
                 continue;
             }
 
-            let q = Vec3::cross(tv, e1);
-            let v = Vec3::dot(ray.dir, q) * inv_det;
-            if v < 0.0 || u + v > 1.0 {
-                continue;
+            let falloff = 1.0 - (dist / radius).powi(2);
+            let before = self.edits.get(&point).copied().unwrap_or(0.0);
+            // Kept within what can still change the clamped density, so undoing an edit takes as long as making it.
+            let after = (before + strength * (falloff * falloff) as f64).clamp(-1.0, 1.0);
+            edit.points.insert(point, (before, after));
+        }
+        self.restore_density(&mut edit.points.iter().map(|(&point, &(_, after))| (point, after)));
+        Some(edit)
+    }
+
+    fn restore_density(&mut self, points: &mut dyn Iterator<Item = (Voxel, f64)>) {
+        let size = self.lattice.voxel_size;
+        for (point, delta) in points {
+            if delta == 0.0 {
+                Arc::make_mut(&mut self.edits).remove(&point);
+            } else {
+                Arc::make_mut(&mut self.edits).insert(point, delta);
+            }
+            // The edit is interpolated over the voxels sharing the point, on any lattice.
+            self.lattice.corners.remove(&point);
+            for (dx, dy, dz) in itertools::iproduct!(0..2, 0..2, 0..2) {
+                self.lattice.triangle_cache.remove(&point.offset([-dx, -dy, -dz]));
             }
+            let corner = point.corner(size).as_vec3();
+            let others = self.detail.iter_mut().map(|detail| &mut detail.lattice).chain(&mut self.lods);
+            for lattice in others {
+                lattice.invalidate(corner, size);
+            }
+        }
+        self.prefetcher.invalidate();
+    }
 
-            let t = Vec3::dot(e2, q) * inv_det;
-            if t < EPSILON {
-                continue;
+    fn rebase(&mut self, shift: Vec3) {
+        self.origin += shift.as_dvec3();
+        let offset = Voxel::of_shift(shift, self.lattice.voxel_size).index().map(|i| -i);
+        self.edits = Arc::new(self.edits.iter().map(|(point, delta)| (point.offset(offset), *delta)).collect());
+        self.prefetcher.invalidate();
+        let lattices = self.detail.iter_mut().map(|detail| &mut detail.lattice).chain(&mut self.lods);
+        for lattice in std::iter::once(&mut self.lattice).chain(lattices) {
+            lattice.clear();
+        }
+    }
+
+    fn detached(&self) -> Box<dyn Terrain + Send> {
+        Box::new(World {
+            generator: self.generator.clone(),
+            lattice: Lattice::new(self.lattice.voxel_size),
+            detail: self
+                .detail
+                .as_ref()
+                .map(|detail| Detail { lattice: Lattice::new(detail.lattice.voxel_size), band: detail.band }),
+            lods: self.lods.iter().map(|lod| Lattice::new(lod.voxel_size)).collect(),
+            lod_distance: self.lod_distance,
+            edits: self.edits.clone(),
+            origin: self.origin,
+            prefetcher: Prefetcher::new(),
+        })
+    }
+
+    fn resolution(&self) -> Option<(f32, f32)> {
+        Some((self.lattice.voxel_size, self.detail.as_ref().map_or(0.0, |detail| detail.band)))
+    }
+
+    fn set_resolution(&mut self, voxel_size: f32, detail_band: f32) -> Result<(), String> {
+        let levels = self.lods.len() as u32;
+        check_lattices(voxel_size, levels)?;
+
+        if voxel_size != self.lattice.voxel_size {
+            // Edits are resampled onto the new lattice, which keeps their shape up to the coarser of the two.
+            let old = self.field();
+            let mut edits = HashMap::new();
+            if let Some((min, max)) = edit_bounds(&self.edits, self.lattice.voxel_size) {
+                let size = self.lattice.voxel_size;
+                let (Some(min), Some(max)) =
+                    (Voxel::containing(min - size, voxel_size), Voxel::containing(max + size, voxel_size))
+                else {
+                    return Err("edits out of range".to_string());
+                };
+                let span = max.delta(min);
+                for (x, y, z) in itertools::iproduct!(0..=span[0], 0..=span[1], 0..=span[2]) {
+                    let point = min.offset([x, y, z]);
+                    let delta = old.edit(point.corner(voxel_size));
+                    if delta != 0.0 {
+                        edits.insert(point, delta);
+                    }
+                }
             }
+            self.edits = Arc::new(edits);
+            self.lattice = Lattice::new(voxel_size);
+            self.prefetcher.invalidate();
+        }
+        self.detail = (detail_band > 0.0)
+            .then(|| Detail { lattice: Lattice::new(voxel_size / DETAIL_FACTOR), band: detail_band });
+        self.lods = lod_lattices(voxel_size, levels);
+        Ok(())
+    }
 
-            return Some(t);
+    fn set_lod(&mut self, levels: u32, distance: f32) -> Result<(), String> {
+        check_lattices(self.lattice.voxel_size, levels)?;
+        if levels > 0 && (distance.is_nan() || distance <= 0.0) {
+            return Err(format!("level of detail distance {} must be positive", distance));
         }
+        self.lods = lod_lattices(self.lattice.voxel_size, levels);
+        self.lod_distance = if levels > 0 { distance } else { f32::INFINITY };
+        Ok(())
+    }
 
-        None
+    fn prefetch(&mut self, center: Vec3, heading: Vec3) {
+        for batch in self.prefetcher.receive() {
+            self.lattice.merge(batch.lattice);
+        }
+        let snapshot = Snapshot {
+            generator: self.generator.clone(),
+            edits: self.edits.clone(),
+            voxel_size: self.lattice.voxel_size,
+            origin: self.origin,
+        };
+        self.prefetcher.request(&snapshot, center, heading);
     }
 
+    fn cache_stats(&self) -> Option<CacheStats> {
+        let lattices = self.detail.iter().map(|detail| &detail.lattice).chain(&self.lods);
+        Some(std::iter::once(&self.lattice).chain(lattices).fold(CacheStats::default(), |total, lattice| CacheStats {
+            cached: total.cached + lattice.corners.len(),
+            reused: total.reused + lattice.stats.reused,
+            sampled: total.sampled + lattice.stats.sampled,
+        }))
+    }
+}
+
+/// Checks that a [`World`] can be meshed with voxels of edge `voxel_size` and `lod_levels` levels of detail: every
+/// lattice has to divide [`REBASE_GRID`], so rebasing keeps voxel boundaries in place.
+fn check_lattices(voxel_size: f32, lod_levels: u32) -> Result<(), String> {
+    let aligned = |size: f32| (REBASE_GRID / size).fract() == 0.0;
+    if !(MIN_VOXEL_SIZE..=MAX_VOXEL_SIZE).contains(&voxel_size) || !aligned(voxel_size / DETAIL_FACTOR) {
+        return Err(format!(
+            "voxel size {} must be between {} and {} and half of it must divide {} evenly",
+            voxel_size, MIN_VOXEL_SIZE, MAX_VOXEL_SIZE, REBASE_GRID
+        ));
+    }
+    if lod_levels > MAX_LOD_LEVELS || !aligned(voxel_size * 2f32.powi(lod_levels as i32)) {
+        return Err(format!(
+            "{} levels of detail over voxel size {} do not fit; at most {} whose voxels divide {} evenly",
+            lod_levels, voxel_size, MAX_LOD_LEVELS, REBASE_GRID
+        ));
+    }
+    Ok(())
+}
+
+fn lod_lattices(voxel_size: f32, levels: u32) -> Vec<Lattice> {
+    (1..=levels).map(|level| Lattice::new(voxel_size * 2f32.powi(level as i32))).collect()
+}
+
+/// `(min, max)` corners of the lattice points holding edits.
+fn edit_bounds(edits: &HashMap<Voxel, f64>, size: f32) -> Option<(Vec3, Vec3)> {
+    let mut corners = edits.keys().map(|point| point.corner(size).as_vec3());
+    let first = corners.next()?;
+    Some(corners.fold((first, first), |(min, max), corner| (min.min(corner), max.max(corner))))
+}
+
+/// The density field a [`World`] meshes: the generator's, plus edits interpolated between their lattice points.
+struct Field<'a> {
+    generator: &'a dyn TerrainGenerator,
+    edits: &'a HashMap<Voxel, f64>,
+    /// Edge of the lattice the edits are stored on.
+    edit_size: f32,
+    origin: DVec3,
+}
+
+impl Field<'_> {
+    /// Density at the local position `pos`.
     #[inline]
-    fn voxel_triangles(&mut self, voxel: Vec3) -> Vec<Triangle> {
-        let vx = (voxel.x as i32, voxel.y as i32, voxel.z as i32);
-        if let Some(triangles) = self.triangle_cache.get(&vx) {
-            return triangles.to_vec();
+    fn density(&self, pos: DVec3) -> f64 {
+        let level = self.generator.surface_level(self.origin + pos);
+        (level + self.edit(pos)).clamp(0.0, 1.0)
+    }
+
+    /// Densities at the lattice points of edge `size` from `min` on, laid out as in
+    /// [`TerrainGenerator::sample_grid`].
+    fn sample_grid(&self, min: Voxel, size: f32, dims: [usize; 3], out: &mut [f64]) {
+        let corner = min.corner(size);
+        self.generator.sample_grid(self.origin + corner, size as f64, dims, out);
+        for (i, level) in out.iter_mut().enumerate() {
+            let index = [i % dims[0], i / dims[0] % dims[1], i / dims[0] / dims[1]];
+            let pos = min.offset(index.map(|i| i as i64)).corner(size);
+            *level = (*level + self.edit(pos)).clamp(0.0, 1.0);
+        }
+    }
+
+    /// Density added by edits at the local position `pos`, interpolated between the corners of the edit voxel
+    /// holding it like the mesh interpolates density.
+    fn edit(&self, pos: DVec3) -> f64 {
+        if self.edits.is_empty() {
+            return 0.0;
+        }
+        let Some(base) = Voxel::containing(pos.as_vec3(), self.edit_size) else {
+            return 0.0;
+        };
+        let t = ((pos - base.corner(self.edit_size)) / self.edit_size as f64).clamp(DVec3::ZERO, DVec3::ONE);
+        let mut edit = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let delta = self.edits.get(&base.offset(offset)).copied().unwrap_or(0.0);
+            edit += delta * trilinear_weight(t, offset);
         }
+        edit
+    }
+}
 
-        let mut cube_indeces = [
-            (voxel + vec3(0.0, 0.0, 0.0), 0.0),
-            (voxel + vec3(0.0, 0.0, 1.0), 0.0),
-            (voxel + vec3(1.0, 0.0, 1.0), 0.0),
-            (voxel + vec3(1.0, 0.0, 0.0), 0.0),
-            (voxel + vec3(0.0, 1.0, 0.0), 0.0),
-            (voxel + vec3(0.0, 1.0, 1.0), 0.0),
-            (voxel + vec3(1.0, 1.0, 1.0), 0.0),
-            (voxel + vec3(1.0, 1.0, 0.0), 0.0),
-        ];
+/// A marching-cubes lattice and the triangles meshed on it so far.
+struct Lattice {
+    voxel_size: f32,
+    triangle_cache: HashMap<Voxel, Vec<Triangle>>,
+    /// Density sampled at each lattice point so far, shared by the up to eight voxels meeting there.
+    corners: HashMap<Voxel, f64>,
+    stats: CacheStats,
+}
+
+impl Lattice {
+    fn new(voxel_size: f32) -> Self {
+        Self { voxel_size, triangle_cache: HashMap::new(), corners: HashMap::new(), stats: CacheStats::default() }
+    }
+
+    /// Drops every cached triangle and sample; the stats keep counting.
+    fn clear(&mut self) {
+        self.triangle_cache.clear();
+        self.corners.clear();
+    }
 
-        let mut cube_layout: usize = 0;
-        for (i, vertex) in cube_indeces.iter_mut().enumerate() {
-            vertex.1 = self.surface_level(vertex.0);
-            if vertex.1 < SURFACE_THRESHOLD {
-                cube_layout |= 1 << i;
+    /// Meshes the voxels from `min` to `max` that are not cached yet, sampling all their corners in one batch.
+    fn mesh_region(&mut self, field: &Field, min: Voxel, max: Voxel) {
+        let span = max.delta(min);
+        let voxels = || itertools::iproduct!(0..=span[0], 0..=span[1], 0..=span[2]);
+        if voxels().all(|(x, y, z)| self.triangle_cache.contains_key(&min.offset([x, y, z]))) {
+            return;
+        }
+
+        let dims = span.map(|d| d as usize + 2);
+        let points: Vec<Voxel> = itertools::iproduct!(0..dims[2] as i64, 0..dims[1] as i64, 0..dims[0] as i64)
+            .map(|(z, y, x)| min.offset([x, y, z]))
+            .collect();
+        let mut levels: Vec<Option<f64>> = points.iter().map(|point| self.corners.get(point).copied()).collect();
+        let missing = levels.iter().filter(|level| level.is_none()).count();
+        // A batch samples the whole grid, which only pays off while most of it is missing.
+        if missing * 4 >= levels.len() {
+            let mut sampled = vec![0.0; levels.len()];
+            field.sample_grid(min, self.voxel_size, dims, &mut sampled);
+            for (level, sample) in levels.iter_mut().zip(sampled) {
+                level.get_or_insert(sample);
+            }
+        } else {
+            for (level, point) in levels.iter_mut().zip(&points) {
+                level.get_or_insert_with(|| field.density(point.corner(self.voxel_size)));
             }
         }
+        self.stats.reused += (levels.len() - missing) as u64;
+        self.stats.sampled += missing as u64;
+        let levels: Vec<f64> = levels.into_iter().map(Option::unwrap).collect();
+        self.corners.extend(points.into_iter().zip(levels.iter().copied()));
 
-        let edges = tables::TRIANGULATION_TABLE[cube_layout];
-        let mut triangles = Vec::with_capacity(5);
+        let origin = min.corner(self.voxel_size);
+        for (x, y, z) in voxels() {
+            let voxel = min.offset([x, y, z]);
+            if self.triangle_cache.contains_key(&voxel) {
+                continue;
+            }
+            let cube = CORNERS.map(|[dx, dy, dz]| {
+                let index = [x + dx, y + dy, z + dz].map(|i| i as usize);
+                let pos = origin + DVec3::from_array(index.map(|i| i as f64)) * self.voxel_size as f64;
+                (pos.as_vec3(), levels[index[0] + dims[0] * (index[1] + dims[1] * index[2])])
+            });
+            self.triangle_cache.insert(voxel, cube_triangles(cube));
+        }
+    }
 
-        let mut i = 0;
-        while edges[i] != -1 {
-            let a = edge_vertex(cube_indeces, edges[i + 0]);
-            let b = edge_vertex(cube_indeces, edges[i + 1]);
-            let c = edge_vertex(cube_indeces, edges[i + 2]);
-            triangles.push(Triangle { a, b, c });
-            i += 3;
+    /// Drops the voxels whose triangles may change when the edit at the lattice point at `corner`, on an edit lattice
+    /// of edge `edit_size`, does.
+    fn invalidate(&mut self, corner: Vec3, edit_size: f32) {
+        let size = self.voxel_size;
+        let (Some(min), Some(max)) =
+            (Voxel::containing(corner - edit_size - size, size), Voxel::containing(corner + edit_size, size))
+        else {
+            return;
+        };
+        let span = max.delta(min);
+        for (x, y, z) in itertools::iproduct!(0..=span[0] + 1, 0..=span[1] + 1, 0..=span[2] + 1) {
+            self.triangle_cache.remove(&min.offset([x, y, z]));
+            self.corners.remove(&min.offset([x, y, z]));
         }
+    }
 
-        self.triangle_cache.insert(vx, triangles.to_vec());
-        triangles
+    /// Whether `pos` lies inside the surface meshed on this lattice, judged by the density interpolated between the
+    /// corners of its voxel as the mesh is.
+    fn solid_at(&self, field: &Field, pos: Vec3) -> bool {
+        let Some(voxel) = Voxel::containing(pos, self.voxel_size) else {
+            return false;
+        };
+        let base = voxel.corner(self.voxel_size);
+        let t = ((pos.as_dvec3() - base) / self.voxel_size as f64).clamp(DVec3::ZERO, DVec3::ONE);
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            density += field.density(voxel.offset(offset).corner(self.voxel_size)) * trilinear_weight(t, offset);
+        }
+        density >= SURFACE_THRESHOLD
     }
 
+    /// Distance along `ray` to the first surface in the voxels it crosses within `length`.
+    fn march(&mut self, field: &Field, ray: Ray, length: f32, test: TriangleTest) -> Option<f32> {
+        VoxelWalk::new(ray, self.voxel_size, length)?.find_map(|voxel| self.voxel_collision(field, voxel, ray, test))
+    }
+
+    /// Like [`Lattice::march`] for each of `rays` at once. The rays step through their voxels in lockstep, so each
+    /// voxel any of them enters is looked up once per step however many rays share it, and its triangles are tested
+    /// against four rays at a time. Nearly parallel rays from one origin share most of their voxels.
+    fn march_bundle(&mut self, field: &Field, rays: &[Ray], length: f32, test: TriangleTest) -> Vec<Option<f32>> {
+        // Rays still travelling, by index.
+        let mut walks: Vec<(usize, VoxelWalk)> = rays
+            .iter()
+            .enumerate()
+            .filter_map(|(i, ray)| Some((i, VoxelWalk::new(*ray, self.voxel_size, length)?)))
+            .collect();
+        let mut hits = vec![None; rays.len()];
+        // The voxel each travelling ray entered this step, sorted by key so the rays sharing a voxel sit together.
+        let mut step: Vec<(u64, Voxel, usize)> = Vec::with_capacity(rays.len());
+        while !walks.is_empty() {
+            step.clear();
+            walks.retain_mut(|(i, walk)| match walk.next() {
+                Some(voxel) => {
+                    step.push((voxel.key(), voxel, *i));
+                    true
+                }
+                None => false,
+            });
+
+            step.sort_unstable_by_key(|&(key, _, _)| key);
+            for group in step.chunk_by(|a, b| a.1 == b.1) {
+                let voxel = group[0].1;
+                let triangles = self.voxel_triangles(field, voxel);
+                if triangles.is_empty() {
+                    continue;
+                }
+                for packet in group.chunks(RayPacket::LANES) {
+                    let packet_rays = RayPacket::new(packet.iter().map(|(_, _, i)| rays[*i]), test);
+                    let nearest = triangles.iter().fold(Vec4::splat(f32::INFINITY), |nearest, triangle| {
+                        nearest.min(packet_rays.intersect(triangle))
+                    });
+                    for (lane, (_, _, i)) in packet.iter().enumerate() {
+                        if nearest[lane].is_finite() {
+                            hits[*i] = Some(nearest[lane]);
+                        }
+                    }
+                }
+            }
+            walks.retain(|(i, _)| hits[*i].is_none());
+        }
+        hits
+    }
+
+    /// Nearest hit of `ray` on the triangles of `voxel`, as the distance along the ray.
     #[inline]
-    fn surface_level(&self, pos: Vec3) -> f64 {
-        let noise_pos = SCALE * VOXEL_SIZE * pos;
-        (self.noise.get([noise_pos.x as f64, noise_pos.y as f64, noise_pos.z as f64]) + 1.0) * 0.5
+    fn voxel_collision(&mut self, field: &Field, voxel: Voxel, ray: Ray, test: TriangleTest) -> Option<f32> {
+        nearest_hit(self.voxel_triangles(field, voxel), ray, test)
+    }
+
+    /// Triangles of `voxel`, meshed into the cache first if they are not in it yet.
+    #[inline]
+    fn voxel_triangles(&mut self, field: &Field, voxel: Voxel) -> &[Triangle] {
+        let entry = match self.triangle_cache.entry(voxel) {
+            Entry::Occupied(entry) => return entry.into_mut(),
+            Entry::Vacant(entry) => entry,
+        };
+
+        let cube = CORNERS.map(|offset| {
+            let point = voxel.offset(offset);
+            let corner = point.corner(self.voxel_size);
+            let density = match self.corners.entry(point) {
+                Entry::Occupied(entry) => {
+                    self.stats.reused += 1;
+                    *entry.get()
+                }
+                Entry::Vacant(entry) => {
+                    self.stats.sampled += 1;
+                    *entry.insert(field.density(corner))
+                }
+            };
+            (corner.as_vec3(), density)
+        });
+        entry.insert(cube_triangles(cube))
+    }
+}
+
+/// The voxels a ray crosses within a length, in order, found by stepping to whichever voxel boundary it reaches next.
+struct VoxelWalk {
+    voxel: Voxel,
+    /// Distance along the ray to the next boundary on each axis.
+    t: Vec3,
+    /// Distance along the ray between boundaries on each axis.
+    delta_t: Vec3,
+    step: [i64; 3],
+    length: f32,
+    /// Whether the voxel holding the ray origin was returned yet.
+    started: bool,
+    /// Voxels still to return from the last step, popped from the back.
+    pending: SVec<Voxel, 7>,
+}
+
+impl VoxelWalk {
+    /// Walks the voxels of edge `size` that `ray` crosses within `length`, or `None` if its origin is not finite.
+    fn new(ray: Ray, size: f32, length: f32) -> Option<Self> {
+        let voxel = Voxel::containing(ray.pos, size)?;
+        let step = ray.dir.to_array().map(|x| if x < 0.0 { -1 } else { 1 });
+
+        // Axes the ray does not move along are never crossed. Their infinite `inv_dir` would otherwise turn into
+        // `0 * inf = NaN`, which fails every comparison below and stalls the walk.
+        let still = ray.dir.cmpeq(Vec3::ZERO);
+        let inv_dir = 1.0 / ray.dir;
+        let t = {
+            let min = voxel.corner(size).as_vec3();
+            let max = min + size;
+
+            let t1 = (min - ray.pos) * inv_dir;
+            let t2 = (max - ray.pos) * inv_dir;
+
+            Vec3::select(still, Vec3::splat(f32::INFINITY), Vec3::max(t1, t2))
+        };
+        let delta_t = Vec3::select(still, Vec3::ZERO, (size * inv_dir).abs());
+        Some(Self { voxel, t, delta_t, step, length, started: false, pending: SVec::new() })
+    }
+}
+
+impl Iterator for VoxelWalk {
+    type Item = Voxel;
+
+    fn next(&mut self) -> Option<Voxel> {
+        if !self.started {
+            self.started = true;
+            return Some(self.voxel);
+        }
+        if let Some(voxel) = self.pending.pop() {
+            return Some(voxel);
+        }
+        // Each voxel is entered where the ray leaves the last one, at the nearest of `t`.
+        let t = self.t;
+        let t_min = t.min_element();
+        if t_min > self.length {
+            return None;
+        }
+        // Boundaries crossed at the same point up to rounding mean the ray passes through an edge or corner there.
+        // Stepping across all of them at once would skip the voxels beside it, whose triangles meet at that point.
+        let tie = t_min + GRAZE_TOLERANCE * t_min.abs().max(1.0);
+        let voxel_incr = t.cmple(Vec3::splat(tie));
+        self.t = Vec3::select(voxel_incr, t + self.delta_t, t);
+
+        let offset = |mask: usize| [0, 1, 2].map(|i| ((mask >> i) & 1) as i64 * self.step[i]);
+        let crossed = voxel_incr.bitmask() as usize;
+        // Push the voxel beyond the crossing first so the grazed ones beside it come out before it.
+        self.pending.push(self.voxel.offset(offset(crossed)));
+        for mask in (1..crossed).rev().filter(|mask| mask & !crossed == 0) {
+            self.pending.push(self.voxel.offset(offset(mask)));
+        }
+        self.voxel = self.voxel.offset(offset(crossed));
+        self.pending.pop()
     }
 }
 
+/// Up to four rays with each coordinate in the lanes of a vector, so a triangle is tested against all of them with
+/// the same operations [`geom::intersect_ray_triangle`] applies to one under the packet's [`TriangleTest`].
+struct RayPacket {
+    pos: [Vec4; 3],
+    dir: [Vec4; 3],
+    test: TriangleTest,
+    /// Rows of each ray's [`geom::watertight_shear`] for the watertight test, zero in lanes without a ray.
+    shear: [[Vec4; 3]; 3],
+}
+
+impl RayPacket {
+    const LANES: usize = 4;
+
+    /// Packs up to [`RayPacket::LANES`] rays to test with `test`; unused lanes hold a ray without direction, which
+    /// hits nothing.
+    fn new(rays: impl Iterator<Item = Ray>, test: TriangleTest) -> Self {
+        let mut packet = Self { pos: [Vec4::ZERO; 3], dir: [Vec4::ZERO; 3], test, shear: [[Vec4::ZERO; 3]; 3] };
+        for (lane, ray) in rays.take(Self::LANES).enumerate() {
+            for axis in 0..3 {
+                packet.pos[axis][lane] = ray.pos[axis];
+                packet.dir[axis][lane] = ray.dir[axis];
+            }
+            let shear = test.watertight.then(|| geom::watertight_shear(ray.dir)).flatten();
+            for (row, shear) in packet.shear.iter_mut().zip(shear.unwrap_or([Vec3::ZERO; 3])) {
+                for axis in 0..3 {
+                    row[axis][lane] = shear[axis];
+                }
+            }
+        }
+        packet
+    }
+
+    /// Distance along each ray to `triangle`, or infinity where it misses, matching [`geom::intersect_ray_triangle`]
+    /// under the packet's [`TriangleTest`]. The Möller–Trumbore test is repeated here on purpose rather than shared:
+    /// calling the scalar one per lane would give up the packet.
+    #[inline]
+    fn intersect(&self, triangle: &Triangle) -> Vec4 {
+        if self.test.watertight {
+            return self.intersect_watertight(triangle);
+        }
+        let splat = |v: Vec3| v.to_array().map(Vec4::splat);
+        let cross = |a: [Vec4; 3], b: [Vec4; 3]| {
+            [a[1] * b[2] - b[1] * a[2], a[2] * b[0] - b[2] * a[0], a[0] * b[1] - b[0] * a[1]]
+        };
+
+        let e1 = splat(triangle.b - triangle.a);
+        let e2 = splat(triangle.c - triangle.a);
+
+        let p = cross(self.dir, e2);
+        let det = dot4(e1, p);
+        let inv_det = 1.0 / det;
+
+        let a = splat(triangle.a);
+        let tv = [0, 1, 2].map(|axis| self.pos[axis] - a[axis]);
+        let u = dot4(tv, p) * inv_det;
+
+        let q = cross(tv, e1);
+        let v = dot4(self.dir, q) * inv_det;
+        let t = dot4(e2, q) * inv_det;
+
+        // Culling backfaces also rejects the negative determinants of rays seeing the back of the triangle.
+        let facing = if self.test.cull_backfaces { det } else { det.abs() };
+        let hit = facing.cmpge(Vec4::splat(TRIANGLE_EPSILON))
+            & u.cmpge(Vec4::ZERO)
+            & u.cmple(Vec4::ONE)
+            & v.cmpge(Vec4::ZERO)
+            & (u + v).cmple(Vec4::ONE)
+            & t.cmpge(Vec4::splat(TRIANGLE_EPSILON));
+        Vec4::select(hit, t, Vec4::splat(f32::INFINITY))
+    }
+
+    /// The watertight test of [`geom::intersect_ray_triangle`] on every lane, with lanes whose edge functions come out
+    /// zero decided again in double precision by [`geom::edge_functions`].
+    #[inline]
+    fn intersect_watertight(&self, triangle: &Triangle) -> Vec4 {
+        let sheared = |corner: Vec3| {
+            let relative = [0, 1, 2].map(|axis| Vec4::splat(corner[axis]) - self.pos[axis]);
+            self.shear.map(|row| dot4(relative, row))
+        };
+        let [a, b, c] = [triangle.a, triangle.b, triangle.c].map(sheared);
+
+        let mut u = c[0] * b[1] - c[1] * b[0];
+        let mut v = a[0] * c[1] - a[1] * c[0];
+        let mut w = b[0] * a[1] - b[1] * a[0];
+        let zero = (u.cmpeq(Vec4::ZERO) | v.cmpeq(Vec4::ZERO) | w.cmpeq(Vec4::ZERO)).bitmask();
+        for lane in (0..Self::LANES).filter(|lane| zero & 1 << lane != 0) {
+            let corner = |p: [Vec4; 3]| [p[0][lane], p[1][lane]];
+            [u[lane], v[lane], w[lane]] = geom::edge_functions(corner(a), corner(b), corner(c));
+        }
+
+        let negative = u.cmplt(Vec4::ZERO) | v.cmplt(Vec4::ZERO) | w.cmplt(Vec4::ZERO);
+        let positive = u.cmpgt(Vec4::ZERO) | v.cmpgt(Vec4::ZERO) | w.cmpgt(Vec4::ZERO);
+        let det = u + v + w;
+        let facing = if self.test.cull_backfaces { det.cmpgt(Vec4::ZERO) } else { det.cmpne(Vec4::ZERO) };
+        let t = (u * a[2] + v * b[2] + w * c[2]) / det;
+        let hit = !(negative & positive) & facing & t.cmpge(Vec4::splat(TRIANGLE_EPSILON));
+        Vec4::select(hit, t, Vec4::splat(f32::INFINITY))
+    }
+}
+
+/// Dot product of two vectors with each coordinate in the lanes of a vector, summed in the order [`Vec3::dot`] does.
+#[inline]
+fn dot4(a: [Vec4; 3], b: [Vec4; 3]) -> Vec4 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Offsets of the corners of a voxel, in the order of [`tables::EDGE_TABLE`].
+const CORNERS: [[i64; 3]; 8] = [[0, 0, 0], [0, 0, 1], [1, 0, 1], [1, 0, 0], [0, 1, 0], [0, 1, 1], [1, 1, 1], [1, 1, 0]];
+
+/// Marching-cubes triangles of a voxel from the position and density of each of its corners.
+fn cube_triangles(cube: [(Vec3, f64); 8]) -> Vec<Triangle> {
+    let mut cube_layout: usize = 0;
+    for (i, (_, density)) in cube.iter().enumerate() {
+        if *density < SURFACE_THRESHOLD {
+            cube_layout |= 1 << i;
+        }
+    }
+
+    let edges = tables::TRIANGULATION_TABLE[cube_layout];
+    let mut triangles = Vec::with_capacity(5);
+
+    let mut i = 0;
+    while edges[i] != -1 {
+        let a = edge_vertex(cube, edges[i]);
+        let b = edge_vertex(cube, edges[i + 1]);
+        let c = edge_vertex(cube, edges[i + 2]);
+        triangles.push(Triangle { a, b, c });
+        i += 3;
+    }
+    triangles
+}
+
+/// Weight of the voxel corner at `offset` when interpolating at the fraction `t` across the voxel.
+#[inline]
+fn trilinear_weight(t: DVec3, offset: [i64; 3]) -> f64 {
+    let weight = DVec3::select(DVec3::from_array(offset.map(|i| i as f64)).cmpeq(DVec3::ONE), t, 1.0 - t);
+    weight.x * weight.y * weight.z
+}
+
 #[inline]
 fn edge_vertex(cube_vertices: [(Vec3, f64); 8], edge: i32) -> Vec3 {
     let (i1, i2) = tables::EDGE_TABLE[edge as usize];
-    let (a_voxel, a_val) = cube_vertices[i1];
-    let (b_voxel, b_val) = cube_vertices[i2];
+    let (mut a, mut b) = (cube_vertices[i1], cube_vertices[i2]);
+    // Every voxel sharing an edge interpolates it from its lower corner, so they place the vertex bit for bit alike
+    // and leave no cracks between their triangles.
+    if a.0.to_array() > b.0.to_array() {
+        std::mem::swap(&mut a, &mut b);
+    }
+    let ((a_voxel, a_val), (b_voxel, b_val)) = (a, b);
     let t = (SURFACE_THRESHOLD - a_val) / (b_val - a_val);
-    Vec3::lerp(a_voxel, b_voxel, t as f32) * VOXEL_SIZE
+    Vec3::lerp(a_voxel, b_voxel, t as f32)
+}
+
+/// How far along a ray to march for a raycast within `dist`, so a short range stops the walk early instead of only
+/// discarding hits beyond it.
+fn ray_length(dist: f32) -> f32 {
+    if dist <= 0.0 {
+        MAX_RAY_LENGTH
+    } else {
+        dist
+    }
+}
+
+/// Nearest hit of `ray` on `triangles` under `test`, as the distance along the ray.
+#[inline]
+fn nearest_hit(triangles: &[Triangle], ray: Ray, test: TriangleTest) -> Option<f32> {
+    // A voxel holds up to five triangles and a ray can cross several of them.
+    triangles.iter().filter_map(|triangle| geom::intersect_ray_triangle(ray, triangle, test)).reduce(f32::min)
 }
 
 #[inline]
@@ -188,3 +1108,511 @@ fn handle_hit(ray: Ray, t: f32, dist: f32) -> Option<Vec3> {
         false => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Heightfield, HeightmapGenerator, NoiseGenerator, Plane, SdfGenerator, Terrain, TerrainGenerator, Voxel, World,
+        DEFAULT_VOXEL_SIZE as VOXEL_SIZE, MAX_LOD_LEVELS,
+    };
+    use crate::util::geom::TriangleTest;
+    use crate::util::{Ray, Triangle};
+    use glam::{vec3, DVec3, Vec3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const RAY_LENGTH: f32 = 50.0;
+    /// Straight down onto the flat world from well above it.
+    const DOWN_RAY: Ray = Ray { pos: Vec3::new(3.0, 200.0, -7.0), dir: Vec3::NEG_Y };
+
+    /// A world whose surface is level everywhere.
+    fn flat_world() -> World {
+        World::with_generator(HeightmapGenerator::from_levels(4, 4, &[0.5; 16]))
+    }
+
+    /// Nearest hit within `RAY_LENGTH` over every triangle of every voxel the ray could possibly reach.
+    fn brute_force(world: &mut World, ray: Ray) -> Option<Vec3> {
+        let end = ray.pos + ray.dir * RAY_LENGTH;
+        let min = (Vec3::min(ray.pos, end) / VOXEL_SIZE).floor() - 1.0;
+        let max = (Vec3::max(ray.pos, end) / VOXEL_SIZE).floor() + 1.0;
+        let voxels =
+            itertools::iproduct!(min.x as i32..=max.x as i32, min.y as i32..=max.y as i32, min.z as i32..=max.z as i32);
+        nearest_hit(world, ray, voxels.map(|(x, y, z)| Voxel::from_index([x as i64, y as i64, z as i64])))
+    }
+
+    fn nearest_hit(world: &mut World, ray: Ray, voxels: impl Iterator<Item = Voxel>) -> Option<Vec3> {
+        let mut nearest: Option<f32> = None;
+        for voxel in voxels {
+            for triangle in world.voxel_triangles(voxel) {
+                let Some(t) = intersect(ray, triangle.a, triangle.b, triangle.c) else {
+                    continue;
+                };
+                nearest = Some(nearest.map_or(t, |nearest| nearest.min(t)));
+            }
+        }
+        nearest.filter(|t| *t <= RAY_LENGTH).map(|t| ray.pos + ray.dir * t)
+    }
+
+    /// Textbook Möller–Trumbore with the raycaster's tolerances.
+    fn intersect(ray: Ray, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let (e1, e2) = (b - a, c - a);
+        let p = ray.dir.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < 0.0001 {
+            return None;
+        }
+        let tv = ray.pos - a;
+        let u = tv.dot(p) / det;
+        let q = tv.cross(e1);
+        let v = ray.dir.dot(q) / det;
+        let t = e2.dot(q) / det;
+        ((0.0..=1.0).contains(&u) && v >= 0.0 && u + v <= 1.0 && t >= 0.0001).then_some(t)
+    }
+
+    fn assert_matches(world: &mut World, ray: Ray) -> bool {
+        let expected = brute_force(world, ray);
+        assert_hit(world, ray, expected)
+    }
+
+    fn assert_hit(world: &mut World, ray: Ray, expected: Option<Vec3>) -> bool {
+        let actual = world.raycast(ray, RAY_LENGTH);
+        match (actual, expected) {
+            (None, None) => false,
+            (Some(actual), Some(expected)) => {
+                assert!(
+                    actual.distance(expected) < 1e-3,
+                    "ray {:?} -> {:?} hit {} instead of {}",
+                    ray.pos,
+                    ray.dir,
+                    actual,
+                    expected
+                );
+                true
+            }
+            _ => panic!("ray {:?} -> {:?} hit {:?} but expected {:?}", ray.pos, ray.dir, actual, expected),
+        }
+    }
+
+    #[test]
+    fn raycast_matches_brute_force_on_random_rays() {
+        for seed in 0..4 {
+            let mut world = World::with_seed(seed);
+            let mut rng = StdRng::seed_from_u64(seed as u64);
+            let mut hits = 0;
+            for _ in 0..100 {
+                let pos = vec3(rng.gen_range(-40.0..40.0), rng.gen_range(-40.0..40.0), rng.gen_range(-40.0..40.0));
+                let dir = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                if dir.length() < 0.01 {
+                    continue;
+                }
+                hits += assert_matches(&mut world, Ray { pos, dir: dir.normalize() }) as u32;
+            }
+            assert!(hits > 10, "seed {} produced only {} hits", seed, hits);
+        }
+    }
+
+    #[test]
+    fn raycast_from_inside_a_surface_voxel() {
+        let mut world = World::new();
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut voxels = Vec::new();
+        for x in -10..10 {
+            for y in -10..10 {
+                let voxel = vec3(x as f32, y as f32, 0.0);
+                if world.voxel_triangles(Voxel::from_index([x, y, 0])).len() >= 2 {
+                    voxels.push(voxel);
+                }
+            }
+        }
+        assert!(!voxels.is_empty());
+
+        for voxel in voxels {
+            for _ in 0..8 {
+                let pos = (voxel + vec3(rng.gen(), rng.gen(), rng.gen())) * VOXEL_SIZE;
+                let dir = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                assert_matches(&mut world, Ray { pos, dir: dir.normalize_or_zero() });
+            }
+        }
+    }
+
+    #[test]
+    fn raycast_along_axes_from_voxel_boundaries() {
+        let mut world = World::new();
+        let axes = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+        let mut hits = 0;
+        for x in -4..4 {
+            for z in -4..4 {
+                // Corners of the voxel lattice, so every coordinate lies on a boundary.
+                let pos = vec3(x as f32, 0.0, z as f32) * VOXEL_SIZE * 2.0;
+                for dir in axes {
+                    let ray = Ray { pos, dir };
+                    // A ray along a boundary grazes the triangles on both sides of it; it belongs to the voxels
+                    // `floor` puts its points in, so only those are searched.
+                    let start = Voxel::containing(pos, VOXEL_SIZE).unwrap();
+                    let step = dir.to_array().map(|x| x as i64);
+                    let column = (-1..=(RAY_LENGTH / VOXEL_SIZE) as i64 + 1).map(|i| start.offset(step.map(|x| x * i)));
+                    let expected = nearest_hit(&mut world, ray, column);
+                    hits += assert_hit(&mut world, ray, expected) as u32;
+                }
+            }
+        }
+        assert!(hits > 0);
+    }
+
+    #[test]
+    fn limited_range_raycasts_stop_at_their_range() {
+        let mut world = flat_world();
+        let surface = world.raycast(DOWN_RAY, -1.0).unwrap();
+        let gap = DOWN_RAY.pos.distance(surface);
+        assert_eq!(world.raycast(DOWN_RAY, gap + 1.0), Some(surface));
+        assert_eq!(world.raycast(DOWN_RAY, gap - 1.0), None);
+        assert_eq!(world.raycast_bundle(&[DOWN_RAY, DOWN_RAY], gap - 1.0, TriangleTest::default()), [None, None]);
+
+        // A ray skimming the surface never hits, and only marches the voxels within its range.
+        let mut world = flat_world();
+        let skim = Ray { pos: surface + Vec3::Y * VOXEL_SIZE * 3.5, dir: Vec3::X };
+        assert_eq!(world.raycast(skim, 20.0), None);
+        assert!(world.lattice.triangle_cache.len() <= (20.0 / VOXEL_SIZE) as usize + 2);
+        assert_eq!(world.raycast_bundle(&[skim], 20.0, TriangleTest::default()), [None]);
+        assert!(world.lattice.triangle_cache.len() <= (20.0 / VOXEL_SIZE) as usize + 2);
+    }
+
+    #[test]
+    fn watertight_rays_cannot_slip_through_seams_of_generated_surfaces() {
+        let watertight = TriangleTest { watertight: true, ..Default::default() };
+        let mut rng = StdRng::seed_from_u64(9);
+        let (mut cases, mut leaks) = (0, 0);
+        for seed in 0..3 {
+            let mut world = World::with_seed(seed);
+            for _ in 0..20 {
+                let pos = vec3(rng.gen_range(-40.0..40.0), rng.gen_range(-40.0..40.0), rng.gen_range(-40.0..40.0));
+                let dir = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                let Some(surface) = world.raycast(Ray { pos, dir: dir.normalize() }, RAY_LENGTH) else {
+                    continue;
+                };
+                let voxel = Voxel::containing(surface, VOXEL_SIZE).unwrap();
+                let nearby: Vec<Triangle> = itertools::iproduct!(-2..=2, -2..=2, -2..=2)
+                    .flat_map(|(x, y, z)| world.voxel_triangles(voxel.offset([x, y, z])).to_vec())
+                    .collect();
+                // Corners of marching-cubes triangles lie on voxel edges and their edges on voxel faces, so rays
+                // aimed at them cross the seams between voxels as well as between triangles.
+                for triangle in world.retrieve_triangles(surface, 1.0) {
+                    let targets = [
+                        (triangle.a, vec![triangle.a]),
+                        (triangle.b, vec![triangle.b]),
+                        (triangle.c, vec![triangle.c]),
+                        (triangle.a.lerp(triangle.b, 0.5), vec![triangle.a, triangle.b]),
+                    ];
+                    for (target, corners) in targets {
+                        let offset = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                        let pos = target + offset.normalize() * rng.gen_range(1.0..8.0);
+                        let ray = Ray { pos, dir: (target - pos).normalize() };
+
+                        // Only a ray facing every triangle around its target from one side is sure to cross the
+                        // surface there; one grazing a ridge may rightly pass it by a rounding error.
+                        let facing: Vec<f32> = nearby
+                            .iter()
+                            .filter(|t| [t.a, t.b, t.c].iter().any(|v| corners.iter().any(|c| v.distance(*c) < 1e-4)))
+                            .filter_map(|t| (t.b - t.a).cross(t.c - t.a).try_normalize())
+                            .map(|normal| normal.dot(ray.dir))
+                            .collect();
+                        if !(facing.iter().all(|&d| d > 0.2) || facing.iter().all(|&d| d < -0.2)) {
+                            continue;
+                        }
+                        cases += 1;
+                        let reach = pos.distance(target) * 1.0001 + 1e-3;
+                        leaks += world.raycast_with(ray, reach, watertight).is_none() as u32;
+                    }
+                }
+            }
+        }
+        assert!(cases > 1000, "{} cases", cases);
+        assert_eq!(leaks, 0, "{} of {} rays slipped through", leaks, cases);
+    }
+
+    #[test]
+    fn generated_worlds_put_the_surface_where_their_fields_do() {
+        // A flat heightmap at mid gray lies halfway between black and white.
+        let mut world = World::with_generator(HeightmapGenerator::from_levels(4, 4, &[0.5; 16]));
+        let flat = world.surface_level(Vec3::ZERO);
+        let hit = world.raycast(Ray { pos: vec3(3.0, 200.0, -7.0), dir: -Vec3::Y }, -1.0).unwrap();
+        assert!((world.surface_level(hit) - super::SURFACE_THRESHOLD).abs() < 0.01, "{} at {}", flat, hit);
+
+        // A sphere of radius 20 around the origin, sampled every 2 units.
+        let n = 31u32;
+        let mut bytes = b"VSDF".to_vec();
+        for word in [n, n, n, 2.0f32.to_bits(), (-30.0f32).to_bits(), (-30.0f32).to_bits(), (-30.0f32).to_bits()] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        for i in 0..n * n * n {
+            let pos = vec3((i % n) as f32, (i / n % n) as f32, (i / n / n) as f32) * 2.0 - 30.0;
+            bytes.extend_from_slice(&(pos.length() - 20.0).to_le_bytes());
+        }
+        let mut world = World::with_generator(SdfGenerator::parse(&bytes).unwrap());
+        for dir in [Vec3::X, -Vec3::Y, vec3(1.0, 1.0, -1.0).normalize()] {
+            let hit = world.raycast(Ray { pos: dir * -50.0, dir }, -1.0).unwrap();
+            assert!((hit.length() - 20.0).abs() < 1.0, "hit {} along {}", hit, dir);
+        }
+        assert!(SdfGenerator::parse(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn density_edits_move_the_meshed_surface() {
+        let mut world = World::with_generator(HeightmapGenerator::from_levels(4, 4, &[0.5; 16]));
+        let down = Ray { pos: vec3(3.0, 200.0, -7.0), dir: -Vec3::Y };
+        let flat = world.raycast(down, -1.0).unwrap();
+
+        assert!(world.edit_density(flat, 20.0, 0.5).is_some());
+        let raised = world.raycast(down, -1.0).unwrap();
+        assert!(raised.y > flat.y + 1.0, "{} over {}", raised, flat);
+        assert!(world.surface_level(flat) > super::SURFACE_THRESHOLD);
+
+        // Far more than was built up, which leaves a pit rather than overflowing the edit.
+        world.edit_density(flat, 20.0, -3.0);
+        let dug = world.raycast(down, -1.0).unwrap();
+        assert!(dug.y < flat.y - 1.0, "{} under {}", dug, flat);
+    }
+
+    #[test]
+    fn resolution_changes_keep_edits_and_the_surface_in_place() {
+        let mut world = World::with_generator(HeightmapGenerator::from_levels(4, 4, &[0.5; 16]));
+        let down = Ray { pos: vec3(3.0, 200.0, -7.0), dir: -Vec3::Y };
+        let flat = world.raycast(down, -1.0).unwrap();
+        world.edit_density(flat, 20.0, 0.5);
+        let raised = world.raycast(down, -1.0).unwrap();
+
+        for (size, band) in [(2.5, 0.0), (10.0, 0.0), (5.0, 300.0)] {
+            world.set_resolution(size, band).unwrap();
+            assert_eq!(world.resolution(), Some((size, band)));
+            let hit = world.raycast(down, -1.0).unwrap();
+            assert!((hit.y - raised.y).abs() < 1.5, "{} vs {} at voxel size {}", hit, raised, size);
+        }
+
+        // Starting within the detail band hits the same surface as marching only the coarse lattice.
+        let near = Ray { pos: raised + Vec3::Y * 20.0, ..down };
+        let fine = world.raycast(near, -1.0).unwrap();
+        world.set_resolution(5.0, 0.0).unwrap();
+        assert!(fine.distance(world.raycast(near, -1.0).unwrap()) < 1.0);
+
+        assert!(world.set_resolution(7.0, 0.0).is_err());
+        assert!(world.set_resolution(0.5, 0.0).is_err());
+        assert!(Plane::new().set_resolution(5.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn distant_levels_of_detail_hit_the_same_surface_with_fewer_voxels() {
+        let flat = || World::with_generator(HeightmapGenerator::from_levels(4, 4, &[0.5; 16]));
+        let (mut full, mut lod) = (flat(), flat());
+        lod.set_lod(3, 50.0).unwrap();
+        let surface = full.raycast(Ray { pos: vec3(3.0, 200.0, -7.0), dir: -Vec3::Y }, -1.0).unwrap();
+        let rays: Vec<Ray> = (0..32)
+            .map(|i| {
+                let angle = i as f32 * 0.2;
+                let dir = vec3(angle.cos(), -0.05 - 0.01 * (i % 8) as f32, angle.sin()).normalize();
+                Ray { pos: surface + Vec3::Y * 20.0, dir }
+            })
+            .collect();
+
+        let mut hits = 0;
+        for ray in &rays {
+            let (Some(expected), Some(hit)) = (full.raycast(*ray, -1.0), lod.raycast(*ray, -1.0)) else {
+                continue;
+            };
+            hits += 1;
+            // Coarse lattices place the surface less exactly, which grazing rays stretch out along their length, so
+            // the error is measured across the surface: within a quarter of the coarsest voxel.
+            assert!((hit.y - expected.y).abs() < 10.0, "{} vs {} along {}", hit, expected, ray.dir);
+        }
+        assert!(hits > rays.len() / 2);
+
+        let cached = |world: &World| {
+            let lattices = std::iter::once(&world.lattice).chain(&world.lods);
+            lattices.map(|lattice| lattice.triangle_cache.len()).sum::<usize>()
+        };
+        assert!(cached(&lod) * 2 < cached(&full), "{} vs {}", cached(&lod), cached(&full));
+
+        assert!(lod.set_lod(MAX_LOD_LEVELS + 1, 100.0).is_err());
+        assert!(lod.set_lod(2, 0.0).is_err());
+        lod.set_lod(0, 0.0).unwrap();
+        assert!(lod.lods.is_empty());
+    }
+
+    #[test]
+    fn bundles_hit_exactly_where_single_rays_do() {
+        let mut rng = StdRng::seed_from_u64(6);
+        let mut single = World::new();
+        let mut bundled = World::new();
+        for world in [&mut single, &mut bundled] {
+            world.set_resolution(VOXEL_SIZE, 15.0).unwrap();
+            world.set_lod(2, 40.0).unwrap();
+        }
+
+        let mut hits = 0;
+        for _ in 0..8 {
+            // A scanner cone: nearly parallel rays from one origin, plus one ray without a direction.
+            let pos = vec3(rng.gen_range(-40.0..40.0), rng.gen_range(-40.0..40.0), rng.gen_range(-40.0..40.0));
+            let axis = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            let mut rays: Vec<Ray> = (0..61)
+                .map(|_| {
+                    let jitter = vec3(rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1));
+                    Ray { pos, dir: (axis.normalize_or_zero() + jitter).normalize() }
+                })
+                .collect();
+            rays.push(Ray { pos, dir: Vec3::ZERO });
+
+            for (watertight, cull_backfaces) in itertools::iproduct!([false, true], [false, true]) {
+                let test = TriangleTest { watertight, cull_backfaces };
+                let expected: Vec<Option<Vec3>> =
+                    rays.iter().map(|ray| single.raycast_with(*ray, 200.0, test)).collect();
+                assert_eq!(bundled.raycast_bundle(&rays, 200.0, test), expected);
+                hits += expected.iter().flatten().count();
+            }
+        }
+        assert!(hits > 200, "only {} hits", hits);
+        let down = Ray { pos: Vec3::Y, dir: -Vec3::Y };
+        assert_eq!(
+            Plane::new().raycast_bundle(&[down], -1.0, TriangleTest::default()),
+            [Plane::new().raycast(down, -1.0)]
+        );
+    }
+
+    #[test]
+    fn batched_sampling_meshes_the_same_triangles_as_single_voxels() {
+        let generator = NoiseGenerator::new(3);
+        let (min, dims) = (DVec3::new(-40.0, 12.5, 1e6), [7, 5, 40]);
+        let mut levels = vec![0.0; dims.iter().product()];
+        generator.sample_grid(min, 2.5, dims, &mut levels);
+        for (i, level) in levels.iter().enumerate() {
+            let index = DVec3::new((i % 7) as f64, (i / 7 % 5) as f64, (i / 35) as f64);
+            assert_eq!(*level, generator.surface_level(min + 2.5 * index));
+        }
+
+        let (mut batched, mut single) = (World::with_seed(3), World::with_seed(3));
+        batched.edit_density(vec3(4.0, -3.0, 2.0), 12.0, 0.4);
+        single.edit_density(vec3(4.0, -3.0, 2.0), 12.0, 0.4);
+        let triangles = batched.retrieve_triangles(Vec3::ZERO, 20.0);
+        let base = Voxel::containing(Vec3::ZERO, VOXEL_SIZE).unwrap();
+        let expected: Vec<_> = itertools::iproduct!(-4..=4, -4..=4, -4..=4)
+            .flat_map(|(x, y, z)| single.voxel_triangles(base.offset([x, y, z])).to_vec())
+            .collect();
+        assert!(!triangles.is_empty());
+        assert_eq!(triangles.len(), expected.len());
+        for (triangle, expected) in triangles.iter().zip(&expected) {
+            assert!(triangle.a.distance(expected.a) < 1e-4 && triangle.c.distance(expected.c) < 1e-4);
+        }
+    }
+
+    #[test]
+    fn triangle_visits_cover_the_sphere_without_the_voxels_around_it() {
+        let mut world = World::with_generator(HeightmapGenerator::from_levels(4, 4, &[0.5; 16]));
+        let surface = world.raycast(Ray { pos: vec3(3.0, 200.0, -7.0), dir: -Vec3::Y }, -1.0).unwrap();
+        let nearby = world.retrieve_triangles(surface, 2.0);
+        let mut visited = Vec::new();
+        world.for_each_triangle_in_radius(surface, 2.0, &mut |triangle| visited.push(triangle.clone()));
+        assert!(!visited.is_empty() && visited.len() < nearby.len(), "{} of {}", visited.len(), nearby.len());
+        let corners = |triangle: &Triangle| [triangle.a, triangle.b, triangle.c];
+        for triangle in nearby.iter().filter(|triangle| corners(triangle).iter().any(|v| v.distance(surface) < 2.0)) {
+            assert!(visited.iter().any(|other| corners(other) == corners(triangle)));
+        }
+
+        let mut plane = Plane::new();
+        let mut visits = 0;
+        plane.for_each_triangle_in_radius(vec3(0.0, -49.0, 0.0), 2.0, &mut |_| visits += 1);
+        assert_eq!(visits, plane.retrieve_triangles(vec3(0.0, -49.0, 0.0), 2.0).len());
+        assert!(visits > 0);
+    }
+
+    #[test]
+    fn neighboring_voxels_share_corner_samples() {
+        let mut world = World::new();
+        assert_eq!(world.cache_stats().unwrap().hit_rate(), None);
+
+        // A region samples each of its lattice points once, and meshing it again reuses all of them.
+        world.retrieve_triangles(Vec3::ZERO, 20.0);
+        let stats = world.cache_stats().unwrap();
+        assert_eq!((stats.cached, stats.sampled, stats.reused), (10 * 10 * 10, 1000, 0));
+        world.lattice.triangle_cache.clear();
+        world.retrieve_triangles(Vec3::ZERO, 20.0);
+        assert_eq!(world.cache_stats().unwrap().sampled, 1000);
+
+        // A column of single voxels shares four corners between each pair of neighbors.
+        let base = Voxel::from_index([100, 0, 0]);
+        for y in 0..10 {
+            world.voxel_triangles(base.offset([0, y, 0]));
+        }
+        let stats = world.cache_stats().unwrap();
+        assert_eq!(stats.sampled, 1000 + 4 * 11);
+        assert_eq!(stats.cached, 1000 + 4 * 11);
+    }
+
+    #[test]
+    fn prefetched_blocks_match_synchronous_meshing_and_drop_when_stale() {
+        let wait_for = |world: &mut World, voxels: usize| {
+            let start = std::time::Instant::now();
+            while world.lattice.triangle_cache.len() < voxels {
+                assert!(start.elapsed().as_secs() < 30, "prefetching stalled");
+                world.prefetch(Vec3::ZERO, Vec3::X);
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        };
+        let mut world = World::new();
+        world.prefetch(Vec3::ZERO, Vec3::X);
+        wait_for(&mut world, 4 * 8 * 8 * 8);
+
+        let mut fresh = World::new();
+        assert!(world.cache_stats().unwrap().sampled > 0);
+        let meshed: Vec<Voxel> = world.lattice.triangle_cache.keys().copied().take(200).collect();
+        for voxel in meshed {
+            let (prefetched, expected) = (world.voxel_triangles(voxel), fresh.voxel_triangles(voxel));
+            assert_eq!(prefetched.len(), expected.len());
+            for (triangle, expected) in prefetched.iter().zip(expected) {
+                assert!(triangle.a.distance(expected.a) < 1e-4 && triangle.b.distance(expected.b) < 1e-4);
+            }
+        }
+
+        // Blocks in flight during an edit were meshed from the terrain as it was, and must not bring it back.
+        let (mut edited, mut reference) = (World::new(), World::new());
+        edited.prefetch(Vec3::ZERO, Vec3::X);
+        edited.edit_density(Vec3::ZERO, 15.0, 1.0);
+        reference.edit_density(Vec3::ZERO, 15.0, 1.0);
+        wait_for(&mut edited, 8 * 8 * 8 * 8);
+        let base = Voxel::containing(Vec3::ZERO, VOXEL_SIZE).unwrap();
+        for (x, y, z) in itertools::iproduct!(-4..4, -4..4, -4..4) {
+            let voxel = base.offset([x, y, z]);
+            assert_eq!(edited.voxel_triangles(voxel).len(), reference.voxel_triangles(voxel).len(), "{:?}", voxel);
+        }
+    }
+
+    #[test]
+    fn rebased_terrains_match_the_original_around_the_new_origin() {
+        let shift = vec3(960.0 * 3.0, -960.0, 960.0 * 5.0);
+        let terrains: [fn() -> Box<dyn Terrain>; 3] =
+            [|| Box::new(World::new()), || Box::new(Heightfield::new()), || Box::new(Plane::new())];
+        for create in terrains {
+            let (mut original, mut rebased) = (create(), create());
+            rebased.rebase(shift);
+            let ray = Ray { pos: shift + vec3(3.0, 120.0, -7.0), dir: vec3(0.3, -1.0, 0.2).normalize() };
+            let hit = original.raycast(ray, -1.0);
+            let rebased_hit = rebased.raycast(Ray { pos: ray.pos - shift, ..ray }, -1.0);
+            let (hit, rebased_hit) = (hit.unwrap(), rebased_hit.unwrap());
+            assert!((hit - shift).distance(rebased_hit) < 0.01, "{} vs {}", hit - shift, rebased_hit);
+            assert!((original.surface_level(hit) - rebased.surface_level(rebased_hit)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn detached_terrains_hit_the_same_surface_from_another_thread() {
+        let terrains: [fn() -> Box<dyn Terrain>; 3] =
+            [|| Box::new(World::new()), || Box::new(Heightfield::new()), || Box::new(Plane::new())];
+        let ray = Ray { pos: vec3(3.0, 120.0, -7.0), dir: vec3(0.3, -1.0, 0.2).normalize() };
+        for create in terrains {
+            let mut original = create();
+            original.rebase(vec3(960.0, 0.0, -960.0));
+            let surface = original.raycast(ray, -1.0).unwrap();
+            // Edits made before detaching carry over, on terrains that can be edited.
+            original.edit_density(surface, 20.0, 0.5);
+            let hit = original.raycast(ray, -1.0);
+            let mut detached = original.detached();
+            assert_eq!(std::thread::spawn(move || detached.raycast(ray, -1.0)).join().unwrap(), hit);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use super::{ray_length, Terrain, SURFACE_THRESHOLD};
+use crate::util::{geom, Ray, Triangle};
+use glam::{vec3, Vec3};
+
+const PLANE_HEIGHT: f32 = -50.0;
+const DENSITY_FALLOFF: f32 = 0.05;
+
+/// Infinite horizontal plane, useful to check scanner behaviour against trivially predictable geometry.
+pub struct Plane {
+    height: f32,
+}
+
+impl Default for Plane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Plane {
+    pub fn new() -> Self {
+        Self { height: PLANE_HEIGHT }
+    }
+}
+
+impl Terrain for Plane {
+    fn raycast(&mut self, ray: Ray, dist: f32) -> Option<Vec3> {
+        let max_t = ray_length(dist);
+        if ray.pos.y <= self.height {
+            return Some(ray.pos);
+        }
+        if ray.dir.y >= 0.0 {
+            return None;
+        }
+
+        let t = (self.height - ray.pos.y) / ray.dir.y;
+        (t <= max_t).then(|| ray.pos + t * ray.dir)
+    }
+
+    fn retrieve_triangles(&mut self, center: Vec3, dist: f32) -> Vec<Triangle> {
+        if !geom::aabb_intersects_plane(center - dist, center + dist, Vec3::Y, self.height) {
+            return Vec::new();
+        }
+
+        let v00 = vec3(center.x - dist, self.height, center.z - dist);
+        let v01 = vec3(center.x - dist, self.height, center.z + dist);
+        let v10 = vec3(center.x + dist, self.height, center.z - dist);
+        let v11 = vec3(center.x + dist, self.height, center.z + dist);
+        vec![Triangle { a: v00, b: v01, c: v11 }, Triangle { a: v00, b: v11, c: v10 }]
+    }
+
+    fn surface_level(&self, pos: Vec3) -> f64 {
+        let depth = self.height - pos.y;
+        (SURFACE_THRESHOLD + (depth * DENSITY_FALLOFF) as f64).clamp(0.0, 1.0)
+    }
+
+    fn rebase(&mut self, shift: Vec3) {
+        self.height -= shift.y;
+    }
+
+    fn detached(&self) -> Box<dyn Terrain + Send> {
+        Box::new(Plane { height: self.height })
+    }
+}
@@ -0,0 +1,126 @@
+use super::{Field, Lattice, TerrainGenerator, Voxel};
+use glam::{DVec3, Vec3};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+/// Voxels along each edge of a block meshed as one background job.
+const BLOCK_VOXELS: i64 = 8;
+/// Blocks within this distance of the camera are meshed ahead of time.
+const PREFETCH_RADIUS: f32 = 100.0;
+/// How much closer a block counts for lying straight ahead, as a fraction of its distance.
+const HEADING_BIAS: f32 = 0.5;
+/// Blocks being meshed at once; more would hold back the rayon pool for little gain.
+const MAX_IN_FLIGHT: usize = 4;
+
+/// What a background job meshes from: everything [`Field`] borrows, owned so the job can outlive the frame.
+#[derive(Clone)]
+pub(super) struct Snapshot {
+    pub generator: Arc<dyn TerrainGenerator>,
+    pub edits: Arc<HashMap<Voxel, f64>>,
+    pub voxel_size: f32,
+    pub origin: DVec3,
+}
+
+/// A block meshed in the background.
+pub(super) struct Batch {
+    epoch: u64,
+    pub lattice: Lattice,
+}
+
+/// Meshes blocks of the terrain around the camera on the rayon pool before rays reach them, nearest and most
+/// directly ahead first, so turning to face new terrain does not stall on meshing it.
+pub(super) struct Prefetcher {
+    /// Bumped whenever the terrain changes, so blocks meshed from an older snapshot are dropped.
+    epoch: u64,
+    /// Blocks queued or meshed since the last change, by their minimum voxel.
+    requested: HashSet<Voxel>,
+    in_flight: usize,
+    sender: Sender<Batch>,
+    receiver: Receiver<Batch>,
+}
+
+impl Default for Prefetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Prefetcher {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self { epoch: 0, requested: HashSet::new(), in_flight: 0, sender, receiver }
+    }
+
+    /// Forgets every block meshed or in flight; call whenever the density field or the lattice changes.
+    pub fn invalidate(&mut self) {
+        self.epoch += 1;
+        self.requested.clear();
+    }
+
+    /// Blocks meshed since the last call that are still current.
+    pub fn receive(&mut self) -> Vec<Batch> {
+        let batches: Vec<Batch> = self.receiver.try_iter().collect();
+        self.in_flight -= batches.len();
+        batches.into_iter().filter(|batch| batch.epoch == self.epoch).collect()
+    }
+
+    /// Starts meshing the most pressing blocks around `center` that have not been requested yet.
+    pub fn request(&mut self, snapshot: &Snapshot, center: Vec3, heading: Vec3) {
+        let size = snapshot.voxel_size;
+        let block_size = size * BLOCK_VOXELS as f32;
+        let Some(base) = Voxel::containing(center, block_size) else {
+            return;
+        };
+        // Blocks left far behind may be requested again on the way back, in case they were evicted meanwhile.
+        self.requested.retain(|block| {
+            let corner = block.corner(size).as_vec3();
+            corner.distance(center) <= PREFETCH_RADIUS * 2.0 + block_size
+        });
+
+        let reach = (PREFETCH_RADIUS / block_size).ceil() as i64;
+        let mut pending: Vec<(f32, Voxel)> = itertools::iproduct!(-reach..=reach, -reach..=reach, -reach..=reach)
+            .map(|(x, y, z)| Voxel::from_index(base.offset([x, y, z]).index().map(|i| i * BLOCK_VOXELS)))
+            .filter(|block| !self.requested.contains(block))
+            .filter_map(|block| {
+                let offset = block.corner(size).as_vec3() + block_size * 0.5 - center;
+                let dist = offset.length();
+                let ahead = offset.dot(heading).max(0.0);
+                (dist <= PREFETCH_RADIUS).then_some((dist - ahead * HEADING_BIAS, block))
+            })
+            .collect();
+        pending.sort_unstable_by(|a, b| f32::total_cmp(&a.0, &b.0));
+
+        for (_, block) in pending.into_iter().take(MAX_IN_FLIGHT.saturating_sub(self.in_flight)) {
+            self.requested.insert(block);
+            self.in_flight += 1;
+            let (snapshot, sender, epoch) = (snapshot.clone(), self.sender.clone(), self.epoch);
+            rayon::spawn(move || {
+                let field = Field {
+                    generator: &*snapshot.generator,
+                    edits: &snapshot.edits,
+                    edit_size: snapshot.voxel_size,
+                    origin: snapshot.origin,
+                };
+                let mut lattice = Lattice::new(snapshot.voxel_size);
+                lattice.mesh_region(&field, block, block.offset([BLOCK_VOXELS - 1; 3]));
+                // The receiver only goes away with the world, which no longer wants the block then.
+                _ = sender.send(Batch { epoch, lattice });
+            });
+        }
+    }
+}
+
+impl Lattice {
+    /// Takes in the triangles and samples of a block meshed in the background, keeping whatever was meshed here in
+    /// the meantime.
+    pub(super) fn merge(&mut self, other: Lattice) {
+        for (voxel, triangles) in other.triangle_cache {
+            self.triangle_cache.entry(voxel).or_insert(triangles);
+        }
+        for (point, level) in other.corners {
+            self.corners.entry(point).or_insert(level);
+        }
+        self.stats.sampled += other.stats.sampled;
+    }
+}
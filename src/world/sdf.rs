@@ -0,0 +1,89 @@
+use super::generator::TerrainGenerator;
+use super::SURFACE_THRESHOLD;
+use glam::{DVec3, UVec3, Vec3};
+use std::io::Read;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"VSDF";
+const HEADER_SIZE: usize = 4 + 3 * 4 + 4 + 3 * 4;
+/// Density change per world unit of signed distance.
+const DENSITY_FALLOFF: f32 = 0.1;
+/// Largest grid accepted, in samples.
+const MAX_SAMPLES: u64 = 1 << 28;
+
+/// An authored environment stored as a grid of signed distances, negative inside. Space outside the grid is empty.
+///
+/// The file is little-endian: the magic `VSDF`, the sample counts along x, y and z as `u32`s, the spacing between
+/// samples and the world position of the first sample as `f32`s, then one `f32` distance per sample with x varying
+/// fastest, then y.
+pub struct SdfGenerator {
+    size: UVec3,
+    spacing: f32,
+    origin: Vec3,
+    distances: Vec<f32>,
+}
+
+impl SdfGenerator {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut bytes = Vec::new();
+        std::fs::File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err("not a voxel SDF file".to_string());
+        }
+        let header: [u32; 7] = bytemuck::pod_read_unaligned(&bytes[4..HEADER_SIZE]);
+        let size = UVec3::new(header[0], header[1], header[2]);
+        let spacing = f32::from_bits(header[3]);
+        let origin = Vec3::new(f32::from_bits(header[4]), f32::from_bits(header[5]), f32::from_bits(header[6]));
+
+        let samples = size.x as u64 * size.y as u64 * size.z as u64;
+        if size.min_element() < 2 || samples > MAX_SAMPLES || spacing.is_nan() || spacing <= 0.0 {
+            return Err(format!("invalid grid of {:?} samples spaced {}", size.to_array(), spacing));
+        }
+        let data = &bytes[HEADER_SIZE..];
+        if data.len() as u64 != samples * 4 {
+            return Err(format!("expected {} samples but found {} bytes", samples, data.len()));
+        }
+        let distances = data.chunks_exact(4).map(|sample| f32::from_le_bytes(sample.try_into().unwrap())).collect();
+        Ok(Self { size, spacing, origin, distances })
+    }
+
+    fn sample(&self, x: u32, y: u32, z: u32) -> f32 {
+        self.distances[((z * self.size.y + y) * self.size.x + x) as usize]
+    }
+
+    /// Trilinearly interpolated distance at `pos`, or `None` outside the grid.
+    fn distance(&self, pos: Vec3) -> Option<f32> {
+        let grid = (pos - self.origin) / self.spacing;
+        let last = (self.size - 1).as_vec3();
+        if grid.cmplt(Vec3::ZERO).any() || grid.cmpgt(last).any() {
+            return None;
+        }
+
+        let base = grid.floor().min(last - 1.0);
+        let t = grid - base;
+        let base = base.as_uvec3();
+        let mut distance = 0.0;
+        for corner in 0..8 {
+            let offset = UVec3::new(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+            let weight = Vec3::select(offset.cmpeq(UVec3::ONE), t, 1.0 - t);
+            let p = base + offset;
+            distance += self.sample(p.x, p.y, p.z) * weight.x * weight.y * weight.z;
+        }
+        Some(distance)
+    }
+}
+
+impl TerrainGenerator for SdfGenerator {
+    fn surface_level(&self, pos: DVec3) -> f64 {
+        match self.distance(pos.as_vec3()) {
+            Some(distance) => (SURFACE_THRESHOLD - (distance * DENSITY_FALLOFF) as f64).clamp(0.0, 1.0),
+            None => 0.0,
+        }
+    }
+}
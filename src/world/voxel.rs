@@ -0,0 +1,95 @@
+use glam::{DVec3, Vec3};
+
+/// Voxels along each edge of a chunk.
+pub const CHUNK_VOXELS: i64 = 16;
+
+/// Address of a voxel, or of the lattice point at its minimum corner: the chunk holding it and its offset within
+/// the chunk. Chunks are counted in `i64`, so addresses don't overflow short of `1e19` units from the origin, and
+/// voxel indices are derived in `f64` to get the boundaries of negative and distant voxels right.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Voxel {
+    pub chunk: [i64; 3],
+    pub local: [u8; 3],
+}
+
+impl Voxel {
+    /// The voxel with the given index along each axis.
+    pub fn from_index(index: [i64; 3]) -> Self {
+        Self {
+            chunk: index.map(|i| i.div_euclid(CHUNK_VOXELS)),
+            local: index.map(|i| i.rem_euclid(CHUNK_VOXELS) as u8),
+        }
+    }
+
+    /// The voxel of edge `size` holding the local position `pos`, or `None` if `pos` is not finite.
+    pub fn containing(pos: Vec3, size: f32) -> Option<Self> {
+        pos.is_finite().then(|| Self::from_index((pos.as_dvec3() / size as f64).floor().to_array().map(|i| i as i64)))
+    }
+
+    /// Index of the voxel along each axis. Saturates for voxels beyond the range of `i64` indices.
+    pub fn index(self) -> [i64; 3] {
+        [0, 1, 2].map(|i| self.chunk[i].saturating_mul(CHUNK_VOXELS).saturating_add(self.local[i] as i64))
+    }
+
+    /// Cheap key of the voxel's index, equal for equal voxels. It wraps every 2^21 voxels along each axis, so distinct
+    /// voxels that far apart share a key.
+    pub fn key(self) -> u64 {
+        let [x, y, z] = self.index().map(|i| i as u64 & 0x1f_ffff);
+        x | y << 21 | z << 42
+    }
+
+    /// The voxel `delta` voxels away.
+    pub fn offset(self, delta: [i64; 3]) -> Self {
+        let index = self.index();
+        Self::from_index([0, 1, 2].map(|i| index[i].saturating_add(delta[i])))
+    }
+
+    /// Voxels from `from` to this one along each axis.
+    pub fn delta(self, from: Voxel) -> [i64; 3] {
+        let (index, from) = (self.index(), from.index());
+        [0, 1, 2].map(|i| index[i].saturating_sub(from[i]))
+    }
+
+    /// Local position of the minimum corner of the voxel on a lattice of edge `size`, in double precision.
+    pub fn corner(self, size: f32) -> DVec3 {
+        DVec3::from_array(self.index().map(|i| i as f64)) * size as f64
+    }
+
+    /// The voxel of edge `size` a rebase by the grid-aligned `shift` moves the origin voxel to.
+    pub fn of_shift(shift: Vec3, size: f32) -> Self {
+        Self::from_index((shift.as_dvec3() / size as f64).round().to_array().map(|i| i as i64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Voxel, CHUNK_VOXELS};
+    use crate::world::DEFAULT_VOXEL_SIZE as VOXEL_SIZE;
+    use glam::{vec3, Vec3};
+
+    #[test]
+    fn voxel_addresses_hold_at_chunk_and_sign_boundaries() {
+        for index in [0, 1, -1, CHUNK_VOXELS - 1, CHUNK_VOXELS, -CHUNK_VOXELS, -CHUNK_VOXELS - 1, i64::MAX, i64::MIN] {
+            let voxel = Voxel::from_index([index, -index.saturating_add(1), 7]);
+            assert_eq!(voxel.index(), [index, -index.saturating_add(1), 7]);
+            assert!(voxel.local.iter().all(|local| (*local as i64) < CHUNK_VOXELS));
+        }
+        assert_eq!(Voxel::from_index([-1, 0, 0]).chunk, [-1, 0, 0]);
+        assert_eq!(Voxel::from_index([-1, 0, 0]).local, [CHUNK_VOXELS as u8 - 1, 0, 0]);
+        assert_eq!(Voxel::from_index([5, 5, 5]).offset([-6, 11, 0]).index(), [-1, 16, 5]);
+        assert_eq!(Voxel::from_index([i64::MAX, 0, 0]).offset([1, 0, 0]).index()[0], i64::MAX);
+
+        // Just below and exactly on a negative voxel boundary, and on either side of zero.
+        let below = -VOXEL_SIZE - 1e-6;
+        assert_eq!(Voxel::containing(vec3(below, 0.0, -0.0), VOXEL_SIZE).unwrap().index(), [-2, 0, 0]);
+        assert_eq!(Voxel::containing(vec3(-VOXEL_SIZE, 0.0, 0.0), VOXEL_SIZE).unwrap().index(), [-1, 0, 0]);
+        assert_eq!(Voxel::containing(Vec3::splat(-1e-30), VOXEL_SIZE).unwrap().index(), [-1, -1, -1]);
+
+        // Far beyond where i32 voxel indices overflow.
+        let far = 2.0_f32.powi(50);
+        let expected = (far as f64 / VOXEL_SIZE as f64).floor() as i64;
+        assert_eq!(Voxel::containing(vec3(far, -far, 0.0), VOXEL_SIZE).unwrap().index(), [expected, -expected - 1, 0]);
+        assert!(Voxel::containing(vec3(f32::NAN, 0.0, 0.0), VOXEL_SIZE).is_none());
+        assert!(Voxel::containing(vec3(f32::INFINITY, 0.0, 0.0), VOXEL_SIZE).is_none());
+    }
+}